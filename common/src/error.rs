@@ -1,6 +1,108 @@
+use std::{error::Error, fmt};
+
+use arrow::error::ArrowError;
+use cranelift_module::ModuleError;
+
+pub type Result<T, E = ServerError> = std::result::Result<T, E>;
+
 #[derive(Debug)]
 pub enum ServerError {
-    NotImplemented(String),
     NotSupported(String),
     ArgumentError(String),
+    /// an input does not have the type an expression or operator expects.
+    TypeError(String),
+    ArrowError(ArrowError),
+    /// cranelift failed to declare, compile or link a generated function. Boxed, as it
+    /// is far larger than the other variants.
+    CodegenError(Box<ModuleError>),
+    /// LLVM failed to optimize or compile a generated function.
+    LlvmError(String),
+    /// something the engine relies on didn't hold, which is a bug rather than a problem
+    /// with the query or its input.
+    Internal(String),
+    /// an arithmetic result did not fit its type at `row` of the input.
+    ArithmeticOverflow {
+        row: usize,
+    },
+    /// a division or remainder by zero at `row` of the input.
+    DivideByZero {
+        row: usize,
+    },
+    /// a value at `row` of the input could not be cast to the target type.
+    InvalidCast {
+        row: usize,
+    },
+    /// a function was called with an argument it doesn't accept at `row` of the input.
+    InvalidArgument {
+        row: usize,
+    },
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::NotSupported(msg) => write!(f, "not supported: {}", msg),
+            ServerError::ArgumentError(msg) => write!(f, "invalid argument: {}", msg),
+            ServerError::TypeError(msg) => write!(f, "type error: {}", msg),
+            ServerError::ArrowError(err) => write!(f, "arrow error: {}", err),
+            ServerError::CodegenError(err) => write!(f, "codegen error: {}", err),
+            ServerError::LlvmError(msg) => write!(f, "llvm error: {}", msg),
+            ServerError::Internal(msg) => write!(f, "internal error: {}", msg),
+            ServerError::ArithmeticOverflow { row } => {
+                write!(f, "arithmetic overflow at row {}", row)
+            }
+            ServerError::DivideByZero { row } => write!(f, "division by zero at row {}", row),
+            ServerError::InvalidCast { row } => write!(f, "invalid cast at row {}", row),
+            ServerError::InvalidArgument { row } => {
+                write!(f, "invalid function argument at row {}", row)
+            }
+        }
+    }
+}
+
+impl Error for ServerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServerError::ArrowError(err) => Some(err),
+            ServerError::CodegenError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArrowError> for ServerError {
+    fn from(err: ArrowError) -> Self {
+        ServerError::ArrowError(err)
+    }
+}
+
+impl From<ModuleError> for ServerError {
+    fn from(err: ModuleError) -> Self {
+        ServerError::CodegenError(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use arrow::error::ArrowError;
+    use cranelift_module::ModuleError;
+
+    use super::ServerError;
+
+    #[test]
+    fn test_display_and_source() {
+        let err = ServerError::ArithmeticOverflow { row: 3 };
+        assert_eq!(err.to_string(), "arithmetic overflow at row 3");
+        assert!(err.source().is_none());
+
+        let err = ServerError::from(ArrowError::DivideByZero);
+        assert_eq!(err.to_string(), "arrow error: Divide by zero error");
+        assert!(err.source().is_some());
+
+        let err = ServerError::from(ModuleError::Undeclared("f".to_string()));
+        assert!(matches!(err, ServerError::CodegenError(_)));
+        assert!(err.source().is_some());
+    }
 }
@@ -1,2 +1,2 @@
 mod error;
-pub use error::ServerError;
+pub use error::{Result, ServerError};
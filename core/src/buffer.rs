@@ -0,0 +1,73 @@
+use arrow::{
+    array::{Array, ArrayRef, AsArray},
+    buffer::{BooleanBuffer, Buffer},
+    datatypes::DataType,
+};
+
+/// values buffer of a primitive or boolean array, starting at its first element.
+pub fn values_buffer(array: &ArrayRef) -> Buffer {
+    match array.data_type() {
+        DataType::Boolean => array.as_boolean().values().sliced(),
+        data_type => {
+            let width = data_type.primitive_width().unwrap();
+            let data = array.to_data();
+            data.buffers()[0].slice_with_length(data.offset() * width, data.len() * width)
+        }
+    }
+}
+
+/// buffers generated code reads the values of an array from: its values buffer, or for
+/// strings the offsets of its elements followed by the data they index into.
+pub fn value_buffers(array: &ArrayRef) -> Vec<Buffer> {
+    match array.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            let width = match array.data_type() {
+                DataType::Utf8 => 4,
+                _ => 8,
+            };
+            let data = array.to_data();
+            let offsets = data.buffers()[0]
+                .slice_with_length(data.offset() * width, (data.len() + 1) * width);
+            vec![offsets, data.buffers()[1].clone()]
+        }
+        _ => vec![values_buffer(array)],
+    }
+}
+
+/// validity bitmap of an array starting at its first element, all set when it has no nulls.
+pub fn validity_buffer(array: &ArrayRef) -> Buffer {
+    match array.nulls() {
+        Some(nulls) => nulls.inner().sliced(),
+        None => BooleanBuffer::new_set(array.len()).into_inner(),
+    }
+}
+
+/// Raw pointers handed to a generated kernel, keeping the buffers behind them alive.
+pub struct KernelArgs {
+    // only held so the pointers stay valid.
+    _buffers: Vec<Buffer>,
+    ptrs: Vec<*const u8>,
+}
+
+impl KernelArgs {
+    pub fn new(buffers: Vec<Buffer>) -> Self {
+        let ptrs = buffers.iter().map(|buffer| buffer.as_ptr()).collect();
+        Self {
+            _buffers: buffers,
+            ptrs,
+        }
+    }
+
+    /// the buffers of [`value_buffers`] for each array, so string arrays take two slots.
+    pub fn values(arrays: &[ArrayRef]) -> Self {
+        Self::new(arrays.iter().flat_map(value_buffers).collect())
+    }
+
+    pub fn validity(arrays: &[ArrayRef]) -> Self {
+        Self::new(arrays.iter().map(validity_buffer).collect())
+    }
+
+    pub fn as_ptr(&self) -> *const *const u8 {
+        self.ptrs.as_ptr()
+    }
+}
@@ -0,0 +1,314 @@
+use arrow::datatypes::DataType;
+use cranelift::prelude::*;
+
+use crate::gen::{FuncGenContext, GenValue, KernelErrorCode, StringType};
+use crate::jit::native_opcall::{NativeKind, NativeOp};
+
+/// How generated integer and decimal arithmetic treats results that don't fit their type
+/// and zero divisors. Float arithmetic follows IEEE 754 in every mode, so zero divisors
+/// give inf or NaN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// overflow wraps around and a zero divisor gives null.
+    Wrapping,
+    /// overflow and zero divisors stop the kernel with an error naming the row, like the
+    /// arrow kernels used outside of generated code.
+    #[default]
+    Checked,
+    /// overflow clamps to the bounds of the type and a zero divisor gives null.
+    Saturating,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// `lhs op rhs` over two values of `data_type`, null if either side is. Overflow and
+    /// zero divisors are handled by the current [`ArithmeticMode`].
+    pub fn arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: GenValue,
+        rhs: GenValue,
+    ) -> GenValue {
+        let valid = self.and_valid(lhs.valid, rhs.valid);
+        let (lhs, rhs) = (lhs.value, rhs.value);
+        if matches!(op, ArithOp::Div | ArithOp::Mod) {
+            return self.div_rem(op == ArithOp::Mod, data_type, lhs, rhs, valid);
+        }
+        if data_type.is_floating() {
+            let value = match op {
+                ArithOp::Add => self.builder.ins().fadd(lhs, rhs),
+                ArithOp::Sub => self.builder.ins().fsub(lhs, rhs),
+                _ => self.builder.ins().fmul(lhs, rhs),
+            };
+            return GenValue::new(value, valid);
+        }
+
+        let wrapped = match op {
+            ArithOp::Add => self.builder.ins().iadd(lhs, rhs),
+            ArithOp::Sub => self.builder.ins().isub(lhs, rhs),
+            _ => self.builder.ins().imul(lhs, rhs),
+        };
+        let value = match self.arithmetic_mode() {
+            ArithmeticMode::Wrapping => wrapped,
+            ArithmeticMode::Checked => {
+                let overflow = self.overflows(op, data_type, lhs, rhs, wrapped);
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                wrapped
+            }
+            ArithmeticMode::Saturating => {
+                let overflow = self.overflows(op, data_type, lhs, rhs, wrapped);
+                let bound = self.saturated(op, data_type, lhs, rhs);
+                self.builder.ins().select(overflow, bound, wrapped)
+            }
+        };
+        GenValue::new(value, valid)
+    }
+
+    /// an integer constant of `_type`, I128 included.
+    pub fn int_const(&mut self, _type: Type, value: i128) -> Value {
+        if _type == types::I128 {
+            return self.i128_const(value);
+        }
+        // narrow constants are given by their zero-extended bits.
+        let mask = u64::MAX >> (64 - _type.bits());
+        self.builder
+            .ins()
+            .iconst(_type, (value as u64 & mask) as i64)
+    }
+
+    /// compare two values of `data_type` by the signed form of `cond`, giving 0 or 1.
+    /// Floats compare in arrow's total order, where -0 < +0 and NaN sorts last, strings
+    /// by their bytes.
+    pub fn compare(&mut self, cond: IntCC, data_type: &DataType, lhs: Value, rhs: Value) -> Value {
+        if StringType::from_data_type(data_type).is_some() {
+            return self.string_compare(cond, lhs, rhs);
+        }
+        if data_type.is_floating() {
+            let lhs = self.total_order_key(lhs);
+            let rhs = self.total_order_key(rhs);
+            return self.builder.ins().icmp(cond, lhs, rhs);
+        }
+        let cond = match data_type {
+            DataType::Boolean => cond.unsigned(),
+            _ if data_type.is_unsigned_integer() => cond.unsigned(),
+            _ => cond,
+        };
+        self.builder.ins().icmp(cond, lhs, rhs)
+    }
+
+    // `lhs / rhs`, or `lhs % rhs` when `rem`.
+    fn div_rem(
+        &mut self,
+        rem: bool,
+        data_type: &DataType,
+        lhs: Value,
+        rhs: Value,
+        valid: Option<Value>,
+    ) -> GenValue {
+        let _type = self.builder.func.dfg.value_type(rhs);
+        let op = if rem {
+            NativeOp::ModWrapping
+        } else {
+            NativeOp::DivWrapping
+        };
+        if _type.is_float() {
+            // a zero divisor gives inf or NaN like any other IEEE 754 result.
+            let value = if rem {
+                // cranelift has no float remainder.
+                let kind = NativeKind::from_data_type(data_type).unwrap();
+                self.call_native(op, kind, lhs, rhs)
+            } else {
+                self.builder.ins().fdiv(lhs, rhs)
+            };
+            return GenValue::new(value, valid);
+        }
+
+        let zero = self.int_const(_type, 0);
+        let is_zero = self.builder.ins().icmp(IntCC::Equal, rhs, zero);
+        let valid = match self.arithmetic_mode() {
+            ArithmeticMode::Checked => {
+                self.fail_if(is_zero, valid, KernelErrorCode::DivideByZero);
+                valid
+            }
+            _ => {
+                let nonzero = self.builder.ins().bxor_imm(is_zero, 1);
+                self.and_valid(valid, Some(nonzero))
+            }
+        };
+
+        let signed = !data_type.is_unsigned_integer();
+        let wrapped = if _type == types::I128 {
+            // nor 128 bit division, the native function guards the zero divisor itself.
+            let kind = NativeKind::from_data_type(data_type).unwrap();
+            self.call_native(op, kind, lhs, rhs)
+        } else {
+            self.int_div_rem(rem, signed, lhs, rhs, is_zero)
+        };
+        // only `MIN / -1` overflows, its remainder is 0 like any `x % 1`.
+        if rem || !signed || self.arithmetic_mode() == ArithmeticMode::Wrapping {
+            return GenValue::new(wrapped, valid);
+        }
+        let min = self.int_const(_type, i128::MIN >> (128 - _type.bits()));
+        let neg_one = self.int_const(_type, -1);
+        let is_min = self.builder.ins().icmp(IntCC::Equal, lhs, min);
+        let is_neg_one = self.builder.ins().icmp(IntCC::Equal, rhs, neg_one);
+        let overflow = self.builder.ins().band(is_min, is_neg_one);
+        let value = match self.arithmetic_mode() {
+            ArithmeticMode::Checked => {
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                wrapped
+            }
+            _ => {
+                let max = self.int_const(_type, i128::MAX >> (128 - _type.bits()));
+                self.builder.ins().select(overflow, max, wrapped)
+            }
+        };
+        GenValue::new(value, valid)
+    }
+
+    // division of integers up to 64 bits, which must not trap on any input.
+    fn int_div_rem(
+        &mut self,
+        rem: bool,
+        signed: bool,
+        lhs: Value,
+        rhs: Value,
+        is_zero: Value,
+    ) -> Value {
+        let _type = self.builder.func.dfg.value_type(rhs);
+        let one = self.builder.ins().iconst(_type, 1);
+        // division by zero traps, so divide by one instead.
+        let rhs = self.builder.ins().select(is_zero, one, rhs);
+        if !signed {
+            return if rem {
+                self.builder.ins().urem(lhs, rhs)
+            } else {
+                self.builder.ins().udiv(lhs, rhs)
+            };
+        }
+        // so does `MIN / -1`, which wraps to `MIN`.
+        let is_neg_one = self.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+        let safe_rhs = self.builder.ins().select(is_neg_one, one, rhs);
+        if rem {
+            self.builder.ins().srem(lhs, safe_rhs)
+        } else {
+            let quotient = self.builder.ins().sdiv(lhs, safe_rhs);
+            let negated = self.builder.ins().ineg(lhs);
+            self.builder.ins().select(is_neg_one, negated, quotient)
+        }
+    }
+
+    // whether `wrapped`, the wrapping result of `lhs op rhs`, differs from the exact one.
+    pub(crate) fn overflows(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: Value,
+        rhs: Value,
+        wrapped: Value,
+    ) -> Value {
+        let _type = self.builder.func.dfg.value_type(lhs);
+        let signed = !data_type.is_unsigned_integer();
+        match op {
+            ArithOp::Add if signed => {
+                // both operands have the sign the result lacks.
+                let lhs_flipped = self.builder.ins().bxor(lhs, wrapped);
+                let rhs_flipped = self.builder.ins().bxor(rhs, wrapped);
+                let both = self.builder.ins().band(lhs_flipped, rhs_flipped);
+                self.is_negative(both)
+            }
+            ArithOp::Add => self
+                .builder
+                .ins()
+                .icmp(IntCC::UnsignedLessThan, wrapped, lhs),
+            ArithOp::Sub if signed => {
+                // the operands' signs differ and the result lacks the sign of lhs.
+                let differ = self.builder.ins().bxor(lhs, rhs);
+                let flipped = self.builder.ins().bxor(lhs, wrapped);
+                let both = self.builder.ins().band(differ, flipped);
+                self.is_negative(both)
+            }
+            ArithOp::Sub => self.builder.ins().icmp(IntCC::UnsignedLessThan, lhs, rhs),
+            _ if _type == types::I128 => {
+                let kind = NativeKind::from_data_type(data_type).unwrap();
+                self.call_native(NativeOp::MulOverflows, kind, lhs, rhs)
+            }
+            _ if _type == types::I64 => {
+                // the high half of the product must only repeat the sign of the low half.
+                let (high, sign) = if signed {
+                    let high = self.builder.ins().smulhi(lhs, rhs);
+                    (high, self.builder.ins().sshr_imm(wrapped, 63))
+                } else {
+                    let high = self.builder.ins().umulhi(lhs, rhs);
+                    (high, self.builder.ins().iconst(types::I64, 0))
+                };
+                self.builder.ins().icmp(IntCC::NotEqual, high, sign)
+            }
+            _ => {
+                // the exact product of narrower types fits twice their width.
+                let wide = _type.double_width().unwrap();
+                let extend = |ctx: &mut Self, value: Value| {
+                    if signed {
+                        ctx.builder.ins().sextend(wide, value)
+                    } else {
+                        ctx.builder.ins().uextend(wide, value)
+                    }
+                };
+                let (wide_lhs, wide_rhs) = (extend(self, lhs), extend(self, rhs));
+                let product = self.builder.ins().imul(wide_lhs, wide_rhs);
+                let wrapped = extend(self, wrapped);
+                self.builder.ins().icmp(IntCC::NotEqual, product, wrapped)
+            }
+        }
+    }
+
+    // the bound an overflowing `lhs op rhs` saturates to.
+    fn saturated(&mut self, op: ArithOp, data_type: &DataType, lhs: Value, rhs: Value) -> Value {
+        let _type = self.builder.func.dfg.value_type(lhs);
+        let shift = 128 - _type.bits();
+        if data_type.is_unsigned_integer() {
+            let bound = match op {
+                ArithOp::Sub => 0,
+                _ => (u128::MAX >> shift) as i128,
+            };
+            return self.int_const(_type, bound);
+        }
+        // sums overflow towards the sign of lhs, products towards the sign of the product.
+        let sign = match op {
+            ArithOp::Mul => self.builder.ins().bxor(lhs, rhs),
+            _ => lhs,
+        };
+        let negative = self.is_negative(sign);
+        let min = self.int_const(_type, i128::MIN >> shift);
+        let max = self.int_const(_type, i128::MAX >> shift);
+        self.builder.ins().select(negative, min, max)
+    }
+
+    fn is_negative(&mut self, value: Value) -> Value {
+        let _type = self.builder.func.dfg.value_type(value);
+        let zero = self.int_const(_type, 0);
+        self.builder.ins().icmp(IntCC::SignedLessThan, value, zero)
+    }
+
+    // the float's bits as a signed integer ordered like `total_cmp` orders the float.
+    pub(crate) fn total_order_key(&mut self, value: Value) -> Value {
+        let _type = self.builder.func.dfg.value_type(value);
+        let int_type = _type.as_int();
+        let bits = self.builder.ins().bitcast(int_type, MemFlags::new(), value);
+        let sign = self
+            .builder
+            .ins()
+            .sshr_imm(bits, int_type.lane_bits() as i64 - 1);
+        let mask = self.builder.ins().ushr_imm(sign, 1);
+        self.builder.ins().bxor(bits, mask)
+    }
+}
@@ -0,0 +1,189 @@
+use cranelift::prelude::*;
+
+use crate::gen::{FuncGenContext, StringType};
+
+/// Arrays walked by [`FuncGenContext::build_array_loop`].
+pub struct ArrayLoop {
+    /// pointer and element type of each input array.
+    pub inputs: Vec<(Value, Type)>,
+    /// arrays written by every iteration, one per value returned by the body.
+    pub outputs: Vec<LoopOutput>,
+    /// number of elements, any value including 0 and odd lengths.
+    pub len: Value,
+    /// use SIMD lanes for the main body when all inputs share one element type.
+    pub vectorize: bool,
+}
+
+/// An output array of an [`ArrayLoop`].
+pub struct LoopOutput {
+    pub ptr: Value,
+    pub lane_type: Type,
+    /// write booleans as one byte of 0 or 1 per element instead of vector masks.
+    pub is_bool: bool,
+    /// append strings to the arrow string builder at `ptr` instead, which only works in
+    /// scalar loops writing every row in order.
+    pub string: Option<StringType>,
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// Emit a loop over `lp.len` elements starting from the current block.
+    ///
+    /// `body` receives the row index of the first lane and the loaded inputs, and returns
+    /// the values to store, one per output. It is generated for the SIMD main body with
+    /// [`FuncGenContext::lanes`] lanes, once per vector of a target register (see
+    /// [`FuncGenContext::simd_vectors`]), and once for the scalar remainder. Code
+    /// generation continues in the exit block once this returns.
+    pub fn build_array_loop<F>(&mut self, lp: ArrayLoop, mut body: F)
+    where
+        F: FnMut(&mut Self, Value, &[Value]) -> Vec<Value>,
+    {
+        let lanes = self.loop_lanes(&lp);
+        // rows one iteration of the main body covers, a whole target register per input.
+        let step = lanes * self.simd_vectors();
+        let mut start = self.builder.ins().iconst(types::I64, 0);
+
+        if lanes > 1 {
+            let vector_header = self.builder.create_block();
+            let vector_body = self.builder.create_block();
+            let vector_exit = self.builder.create_block();
+            self.builder.append_block_param(vector_header, types::I64);
+            let vector_len = self.builder.ins().band_imm(lp.len, -(step as i64));
+            self.builder.ins().jump(vector_header, &[start]);
+
+            self.builder.switch_to_block(vector_header);
+            let row = self.builder.block_params(vector_header)[0];
+            let cond = self
+                .builder
+                .ins()
+                .icmp(IntCC::SignedLessThan, row, vector_len);
+            self.builder
+                .ins()
+                .brif(cond, vector_body, &[], vector_exit, &[]);
+
+            self.builder.switch_to_block(vector_body);
+            self.set_lanes(lanes);
+            for offset in (0..step).step_by(lanes as usize) {
+                let row = self.builder.ins().iadd_imm(row, offset as i64);
+                self.emit_loop_body(&lp, row, &mut body);
+            }
+            self.set_lanes(1);
+            let next_row = self.builder.ins().iadd_imm(row, step as i64);
+            self.builder.ins().jump(vector_header, &[next_row]);
+
+            self.builder.switch_to_block(vector_exit);
+            start = row;
+        }
+
+        let len = lp.len;
+        self.build_row_loop(start, len, |ctx, row| {
+            ctx.emit_loop_body(&lp, row, &mut body);
+        });
+    }
+
+    /// Emit a scalar loop over rows `start..len` whose body only has side effects, such
+    /// as updating accumulators through loaded pointers. Code generation continues in
+    /// the exit block once this returns.
+    pub fn build_row_loop<F>(&mut self, start: Value, len: Value, mut body: F)
+    where
+        F: FnMut(&mut Self, Value),
+    {
+        let header_block = self.builder.create_block();
+        let body_block = self.builder.create_block();
+        let exit_block = self.builder.create_block();
+        self.builder.append_block_param(header_block, types::I64);
+        self.builder.ins().jump(header_block, &[start]);
+
+        self.builder.switch_to_block(header_block);
+        let row = self.builder.block_params(header_block)[0];
+        let cond = self.builder.ins().icmp(IntCC::SignedLessThan, row, len);
+        self.builder
+            .ins()
+            .brif(cond, body_block, &[], exit_block, &[]);
+
+        self.builder.switch_to_block(body_block);
+        body(self, row);
+        let next_row = self.builder.ins().iadd_imm(row, 1);
+        self.builder.ins().jump(header_block, &[next_row]);
+
+        self.builder.switch_to_block(exit_block);
+    }
+
+    fn loop_lanes(&self, lp: &ArrayLoop) -> u32 {
+        let Some((_, lane_type)) = lp.inputs.first() else {
+            return 1;
+        };
+        if !lp.vectorize || lp.inputs.iter().any(|(_, _type)| _type != lane_type) {
+            return 1;
+        }
+        self.simd_lanes(*lane_type)
+    }
+
+    fn emit_loop_body<F>(&mut self, lp: &ArrayLoop, row: Value, body: &mut F)
+    where
+        F: FnMut(&mut Self, Value, &[Value]) -> Vec<Value>,
+    {
+        let lanes = self.lanes();
+        let inputs: Vec<Value> = lp
+            .inputs
+            .iter()
+            .map(|(ptr, lane_type)| {
+                let addr = self.element_addr(*ptr, row, lane_type.bytes());
+                let _type = lane_type.by(lanes).unwrap();
+                // arrow buffers are not guaranteed to be aligned to the vector width.
+                self.builder
+                    .ins()
+                    .load(_type, MemFlags::new().with_notrap(), addr, 0)
+            })
+            .collect();
+        let results = body(self, row, &inputs);
+        assert_eq!(results.len(), lp.outputs.len());
+        for (output, result) in lp.outputs.iter().zip(results) {
+            self.store_loop_output(output, row, result);
+        }
+    }
+
+    /// store `result` for `row` into `output`, narrowing it to the output element type.
+    pub fn store_loop_output(&mut self, output: &LoopOutput, row: Value, result: Value) {
+        let lanes = self.lanes();
+        if let Some(string_type) = output.string {
+            assert_eq!(lanes, 1, "strings are only generated in scalar loops");
+            self.append_string(output.ptr, result, string_type);
+            return;
+        }
+        let output_type = output.lane_type;
+        let result_type = self.builder.func.dfg.value_type(result);
+        if lanes == 1 {
+            let result = if result_type.bits() > output_type.bits() {
+                self.builder.ins().ireduce(output_type, result)
+            } else {
+                result
+            };
+            self.store_element(result, output.ptr, row);
+            return;
+        }
+
+        // vector comparisons yield all-ones lanes as wide as their operands.
+        let result = if output.is_bool {
+            let one = self.builder.ins().iconst(result_type.lane_type(), 1);
+            let one = self.builder.ins().splat(result_type, one);
+            self.builder.ins().band(result, one)
+        } else {
+            result
+        };
+        let addr = self.element_addr(output.ptr, row, output_type.bytes());
+        if result_type.lane_type() == output_type {
+            self.builder
+                .ins()
+                .store(MemFlags::new().with_notrap(), result, addr, 0);
+            return;
+        }
+        for lane in 0..lanes {
+            let value = self.builder.ins().extractlane(result, lane as u8);
+            let value = self.builder.ins().ireduce(output_type, value);
+            let offset = (lane * output_type.bytes()) as i32;
+            self.builder
+                .ins()
+                .store(MemFlags::trusted(), value, addr, offset);
+        }
+    }
+}
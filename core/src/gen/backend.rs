@@ -0,0 +1,230 @@
+use arrow::datatypes::{DataType, SchemaRef};
+use cranelift::prelude::*;
+
+use crate::gen::{
+    can_gen_cast, cast_can_fail, native_type, ArithOp, ArithmeticMode, FuncGenContext, GenValue,
+};
+
+/// A value generated by a [`CodegenBackend`], only meaningful to the backend that made it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackendValue(pub u32);
+
+/// A [`BackendValue`] together with its validity, like a [`GenValue`].
+#[derive(Clone, Copy, Debug)]
+pub struct BackendGenValue {
+    pub value: BackendValue,
+    pub valid: Option<BackendValue>,
+}
+
+impl BackendGenValue {
+    pub fn new(value: BackendValue, valid: Option<BackendValue>) -> Self {
+        Self { value, valid }
+    }
+
+    pub fn non_null(value: BackendValue) -> Self {
+        Self { value, valid: None }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+/// The code generator kernels are compiled with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// fast to compile, for kernels that only run a few times.
+    #[default]
+    Cranelift,
+    /// optimized and vectorized by LLVM at -O3, slower to compile but faster to run.
+    Llvm,
+    /// lowered into the [query IR](crate::ir), optimized there and compiled by cranelift.
+    /// Opt-in, and limited to what [`CodegenBackend`] covers like [`Backend::Llvm`].
+    Ir,
+}
+
+/// The operations expression code is generated from, independent of the code generator.
+/// Values follow the conventions of [`GenValue`]: booleans and validity flags are 0 or 1
+/// in a byte, dates and timestamps are their integer offset from the epoch.
+///
+/// Operations return `None` for types or cases a backend doesn't generate code for.
+pub trait CodegenBackend {
+    fn schema(&self) -> SchemaRef;
+
+    fn arithmetic_mode(&self) -> ArithmeticMode;
+
+    /// the value of a column for the current row.
+    fn column(&self, index: usize) -> Option<BackendGenValue>;
+
+    /// an integer, boolean or temporal constant of `data_type`.
+    fn int_const(&mut self, data_type: &DataType, value: i128) -> Option<BackendValue>;
+
+    fn float_const(&mut self, data_type: &DataType, value: f64) -> Option<BackendValue>;
+
+    /// `lhs op rhs` as [`FuncGenContext::arith`] computes it.
+    fn arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: BackendGenValue,
+        rhs: BackendGenValue,
+    ) -> Option<BackendGenValue>;
+
+    /// `lhs op rhs` as [`FuncGenContext::compare`] computes it, giving 0 or 1.
+    fn compare(
+        &mut self,
+        op: CompareOp,
+        data_type: &DataType,
+        lhs: BackendValue,
+        rhs: BackendValue,
+    ) -> Option<BackendValue>;
+
+    /// convert `value` from `from` to `to`, for casts that can't fail.
+    fn cast(&mut self, value: BackendValue, from: &DataType, to: &DataType)
+        -> Option<BackendValue>;
+
+    fn and_valid(
+        &mut self,
+        lhs: Option<BackendValue>,
+        rhs: Option<BackendValue>,
+    ) -> Option<BackendValue>;
+
+    fn kleene_not(&mut self, input: BackendGenValue) -> BackendGenValue;
+
+    fn kleene_and(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue;
+
+    fn kleene_or(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue;
+
+    fn is_null(&mut self, input: BackendGenValue) -> BackendGenValue;
+
+    fn is_not_null(&mut self, input: BackendGenValue) -> BackendGenValue;
+}
+
+// cranelift values are entity indices, which fit a backend value as they are.
+fn to_backend(value: Value) -> BackendValue {
+    BackendValue(value.as_u32())
+}
+
+fn from_backend(value: BackendValue) -> Value {
+    Value::from_u32(value.0)
+}
+
+fn gen_to_backend(value: GenValue) -> BackendGenValue {
+    BackendGenValue::new(to_backend(value.value), value.valid.map(to_backend))
+}
+
+fn gen_from_backend(value: BackendGenValue) -> GenValue {
+    GenValue::new(from_backend(value.value), value.valid.map(from_backend))
+}
+
+impl<'long, 'short> CodegenBackend for FuncGenContext<'long, 'short> {
+    fn schema(&self) -> SchemaRef {
+        FuncGenContext::schema(self)
+    }
+
+    fn arithmetic_mode(&self) -> ArithmeticMode {
+        FuncGenContext::arithmetic_mode(self)
+    }
+
+    fn column(&self, index: usize) -> Option<BackendGenValue> {
+        Some(gen_to_backend(FuncGenContext::column(self, index)))
+    }
+
+    fn int_const(&mut self, data_type: &DataType, value: i128) -> Option<BackendValue> {
+        let _type = native_type(data_type).filter(|_type| _type.is_int())?;
+        let value = FuncGenContext::int_const(self, _type, value);
+        Some(to_backend(self.broadcast(value)))
+    }
+
+    fn float_const(&mut self, data_type: &DataType, value: f64) -> Option<BackendValue> {
+        let value = match data_type {
+            DataType::Float32 => self.builder.ins().f32const(value as f32),
+            DataType::Float64 => self.builder.ins().f64const(value),
+            _ => return None,
+        };
+        Some(to_backend(self.broadcast(value)))
+    }
+
+    fn arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: BackendGenValue,
+        rhs: BackendGenValue,
+    ) -> Option<BackendGenValue> {
+        native_type(data_type)?;
+        let (lhs, rhs) = (gen_from_backend(lhs), gen_from_backend(rhs));
+        let result = FuncGenContext::arith(self, op, data_type, lhs, rhs);
+        Some(gen_to_backend(result))
+    }
+
+    fn compare(
+        &mut self,
+        op: CompareOp,
+        data_type: &DataType,
+        lhs: BackendValue,
+        rhs: BackendValue,
+    ) -> Option<BackendValue> {
+        let cond = match op {
+            CompareOp::Eq => IntCC::Equal,
+            CompareOp::NotEq => IntCC::NotEqual,
+            CompareOp::Lt => IntCC::SignedLessThan,
+            CompareOp::LtEq => IntCC::SignedLessThanOrEqual,
+            CompareOp::Gt => IntCC::SignedGreaterThan,
+            CompareOp::GtEq => IntCC::SignedGreaterThanOrEqual,
+        };
+        let (lhs, rhs) = (from_backend(lhs), from_backend(rhs));
+        let value = FuncGenContext::compare(self, cond, data_type, lhs, rhs);
+        Some(to_backend(value))
+    }
+
+    fn cast(
+        &mut self,
+        value: BackendValue,
+        from: &DataType,
+        to: &DataType,
+    ) -> Option<BackendValue> {
+        if !can_gen_cast(from, to) || cast_can_fail(from, to) {
+            return None;
+        }
+        let (value, _) = FuncGenContext::cast(self, from_backend(value), from, to);
+        Some(to_backend(value))
+    }
+
+    fn and_valid(
+        &mut self,
+        lhs: Option<BackendValue>,
+        rhs: Option<BackendValue>,
+    ) -> Option<BackendValue> {
+        let (lhs, rhs) = (lhs.map(from_backend), rhs.map(from_backend));
+        FuncGenContext::and_valid(self, lhs, rhs).map(to_backend)
+    }
+
+    fn kleene_not(&mut self, input: BackendGenValue) -> BackendGenValue {
+        gen_to_backend(FuncGenContext::kleene_not(self, gen_from_backend(input)))
+    }
+
+    fn kleene_and(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs, rhs) = (gen_from_backend(lhs), gen_from_backend(rhs));
+        gen_to_backend(FuncGenContext::kleene_and(self, lhs, rhs))
+    }
+
+    fn kleene_or(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs, rhs) = (gen_from_backend(lhs), gen_from_backend(rhs));
+        gen_to_backend(FuncGenContext::kleene_or(self, lhs, rhs))
+    }
+
+    fn is_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        gen_to_backend(FuncGenContext::is_null(self, gen_from_backend(input)))
+    }
+
+    fn is_not_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        gen_to_backend(FuncGenContext::is_not_null(self, gen_from_backend(input)))
+    }
+}
@@ -1,21 +1,55 @@
 use std::collections::HashMap;
 
+use common::{Result, ServerError};
+
 use crate::gen::ctx::CodegenContext;
-use crate::jit::build::create_jit_module;
+use crate::gen::{NativeFunction, TypedNativeFn};
+use crate::jit::build::{create_jit_module, host_abi};
 use crate::jit::native_opcall::NativeOpCall;
+use crate::jit::native_parse::NativeParse;
+use crate::jit::native_set::NativeSet;
+use crate::jit::native_string::NativeString;
 use cranelift::codegen::ir::Signature;
 use cranelift::frontend::FunctionBuilderContext;
-use cranelift_module::Module;
+use cranelift_module::{Linkage, Module};
 
+/// A rust function generated code can call by `name`.
 pub struct FuncRegister {
-    pub(crate) name: &'static str,
+    pub(crate) name: String,
     pub(crate) address: *const u8,
     pub(crate) sig: Option<Signature>,
 }
 
+impl FuncRegister {
+    pub fn new(name: impl Into<String>, address: *const u8) -> Self {
+        Self {
+            name: name.into(),
+            address,
+            sig: None,
+        }
+    }
+}
+
+impl FuncRegister {
+    /// the native function `func` called `name`, with the signature of its rust types.
+    pub fn typed<F: TypedNativeFn>(name: impl Into<String>, func: F) -> Result<Self> {
+        Ok(FuncRegister::from(&NativeFunction::typed(name, func)?))
+    }
+}
+
+impl From<&NativeFunction> for FuncRegister {
+    fn from(func: &NativeFunction) -> Self {
+        let (ptype, call_conv) = host_abi();
+        Self {
+            sig: Some(func.signature(ptype, call_conv)),
+            ..FuncRegister::new(func.name(), func.address())
+        }
+    }
+}
+
 pub struct CodegenContextBuilder {
     debug: bool,
-    register_funcs: HashMap<&'static str, FuncRegister>,
+    register_funcs: HashMap<String, FuncRegister>,
 }
 
 impl Default for CodegenContextBuilder {
@@ -26,17 +60,25 @@ impl Default for CodegenContextBuilder {
 
 impl CodegenContextBuilder {
     pub fn new() -> Self {
-        let mut register_funcs = HashMap::new();
-        for op in NativeOpCall::all_opcalls() {
-            register_funcs.insert(
-                op.name(),
-                FuncRegister {
-                    name: op.name(),
-                    address: op.addr(),
-                    sig: None,
-                },
+        let natives = NativeOpCall::all_opcalls()
+            .into_iter()
+            .map(|op| FuncRegister::new(op.name(), op.addr()))
+            .chain(
+                NativeParse::ALL
+                    .iter()
+                    .map(|parse| FuncRegister::new(parse.name(), parse.addr())),
+            )
+            .chain(
+                NativeString::ALL
+                    .iter()
+                    .map(|op| FuncRegister::new(op.name(), op.addr())),
+            )
+            .chain(
+                NativeSet::ALL
+                    .iter()
+                    .map(|op| FuncRegister::new(op.name(), op.addr())),
             );
-        }
+        let register_funcs = natives.map(|func| (func.name.clone(), func)).collect();
         Self {
             debug: false,
             register_funcs,
@@ -48,17 +90,56 @@ impl CodegenContextBuilder {
         self
     }
 
+    /// panics if a function of the same name is already registered.
     pub fn register_func(mut self, fun: FuncRegister) -> Self {
-        let name = fun.name;
-        let result = self.register_funcs.insert(name, fun);
-        match result {
-            Some(_) => self,
-            None => panic!("can't register two functions with same name: {}", name),
+        let name = fun.name.clone();
+        if self.register_funcs.insert(name.clone(), fun).is_some() {
+            panic!("can't register two functions with same name: {}", name);
+        }
+        self
+    }
+
+    /// register `fun` unless the same function is already registered under its name,
+    /// failing if another one is.
+    pub fn try_register_func(mut self, fun: FuncRegister) -> Result<Self> {
+        match self.register_funcs.get(&fun.name) {
+            Some(registered) if registered.address == fun.address => Ok(self),
+            Some(_) => Err(ServerError::ArgumentError(format!(
+                "another function is already registered as {}",
+                fun.name
+            ))),
+            None => {
+                self.register_funcs.insert(fun.name.clone(), fun);
+                Ok(self)
+            }
         }
     }
 
+    /// register the native function `func` as `name`, e.g.
+    /// `builder.register_native("geohash", geohash as extern "C" fn(f64, f64) -> i64)`.
+    pub fn register_native<F: TypedNativeFn>(
+        self,
+        name: impl Into<String>,
+        func: F,
+    ) -> Result<Self> {
+        self.try_register_func(FuncRegister::typed(name, func)?)
+    }
+
+    /// whether a function called `name` is registered.
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.register_funcs.contains_key(name)
+    }
+
     pub fn finish(mut self) -> CodegenContext {
-        let module = create_jit_module(&self.register_funcs);
+        let mut module = create_jit_module(&self.register_funcs);
+        // calls of functions with a known signature must match it.
+        for func in self.register_funcs.values() {
+            if let Some(sig) = &func.sig {
+                module
+                    .declare_function(&func.name, Linkage::Import, sig)
+                    .unwrap();
+            }
+        }
         let mut ctx = module.make_context();
         if self.debug {
             ctx.set_disasm(true);
@@ -0,0 +1,533 @@
+use std::cmp::Ordering;
+
+use arrow::datatypes::{DataType, TimeUnit};
+use cranelift::prelude::*;
+
+use crate::gen::{native_type, ArithOp, FuncGenContext, StringType};
+use crate::jit::native_opcall::{NativeKind, NativeOp};
+use crate::jit::native_parse::NativeParse;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// whether [`FuncGenContext::cast`] converts `from` to `to`. Casts between dates and
+/// timestamps are only generated where no time zone has to be applied, and strings are
+/// only parsed to numbers.
+pub fn can_gen_cast(from: &DataType, to: &DataType) -> bool {
+    let is_number = |data_type: &DataType| data_type.is_integer() || data_type.is_floating();
+    match (
+        StringType::from_data_type(from),
+        StringType::from_data_type(to),
+    ) {
+        (Some(_), Some(_)) => return true,
+        (Some(_), None) => return is_number(to),
+        (None, Some(_)) => return false,
+        (None, None) => {}
+    }
+    if native_type(from).is_none() || native_type(to).is_none() {
+        return false;
+    }
+    match (from, to) {
+        _ if from == to => true,
+        (DataType::Timestamp(_, from_tz), DataType::Timestamp(_, to_tz)) => {
+            from_tz.is_some() || to_tz.is_none()
+        }
+        (DataType::Timestamp(_, tz), DataType::Date32) => tz.is_none(),
+        (DataType::Date32 | DataType::Date64, DataType::Timestamp(_, tz)) => tz.is_none(),
+        (DataType::Timestamp(_, _), DataType::Date64)
+        | (DataType::Date32, DataType::Date64)
+        | (DataType::Date64, DataType::Date32) => true,
+        _ if temporal_int(from).is_some() => can_gen_cast(&temporal_int(from).unwrap(), to),
+        _ if temporal_int(to).is_some() => can_gen_cast(from, &temporal_int(to).unwrap()),
+        (DataType::Decimal128(_, from_scale), DataType::Decimal128(_, to_scale)) => {
+            *from_scale >= 0 && *to_scale >= 0
+        }
+        (DataType::Decimal128(_, scale), _) => *scale >= 0 && is_number(to),
+        (_, DataType::Decimal128(_, scale)) => *scale >= 0 && is_number(from),
+        _ => is_number(from) && is_number(to),
+    }
+}
+
+/// whether [`FuncGenContext::cast`] reports values of `from` that don't fit `to`.
+pub fn cast_can_fail(from: &DataType, to: &DataType) -> bool {
+    match (from, to) {
+        _ if from == to => false,
+        (DataType::Utf8 | DataType::LargeUtf8, DataType::Utf8 | DataType::LargeUtf8) => false,
+        (DataType::Utf8 | DataType::LargeUtf8, _) => true,
+        (DataType::Timestamp(from_unit, _), DataType::Timestamp(to_unit, _)) => {
+            ticks_per_second(from_unit) < ticks_per_second(to_unit)
+        }
+        (DataType::Timestamp(unit, _), DataType::Date64) => ticks_per_second(unit) < 1_000,
+        (DataType::Date64, DataType::Timestamp(unit, _)) => ticks_per_second(unit) > 1_000,
+        (DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64, _)
+            if matches!(
+                to,
+                DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64
+            ) =>
+        {
+            false
+        }
+        _ if temporal_int(from).is_some() => cast_can_fail(&temporal_int(from).unwrap(), to),
+        _ if temporal_int(to).is_some() => cast_can_fail(from, &temporal_int(to).unwrap()),
+        (
+            DataType::Decimal128(from_precision, from_scale),
+            DataType::Decimal128(precision, scale),
+        ) => {
+            let digits = *scale as i32 - *from_scale as i32;
+            (*precision as i32 - digits.max(0)) < *from_precision as i32
+        }
+        (DataType::Decimal128(_, _), _) => !to.is_floating(),
+        (_, DataType::Decimal128(precision, scale)) => {
+            from.is_floating() || !fits_digits(from, *precision as i32 - *scale as i32)
+        }
+        _ if to.is_floating() => false,
+        _ if from.is_floating() => true,
+        _ => {
+            let signed = |data_type: &DataType| !data_type.is_unsigned_integer();
+            let (from_min, from_max) = int_range(native_type(from).unwrap(), signed(from));
+            let (to_min, to_max) = int_range(native_type(to).unwrap(), signed(to));
+            from_min < to_min || from_max > to_max
+        }
+    }
+}
+
+// the integer type dates and timestamps convert to and from numbers as.
+fn temporal_int(data_type: &DataType) -> Option<DataType> {
+    match data_type {
+        DataType::Date32 => Some(DataType::Int32),
+        DataType::Date64 | DataType::Timestamp(_, _) => Some(DataType::Int64),
+        _ => None,
+    }
+}
+
+fn ticks_per_second(unit: &TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    }
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// convert `value` from `from` to `to` as arrow's cast does. The second value is 1 if
+    /// the value could be represented in `to`, it is `None` for casts that can't fail.
+    /// What the converted value holds for other values is unspecified.
+    pub fn cast(&mut self, value: Value, from: &DataType, to: &DataType) -> (Value, Option<Value>) {
+        if from == to {
+            return (value, None);
+        }
+        if StringType::from_data_type(from).is_some() {
+            // both string types share one view of their values.
+            if StringType::from_data_type(to).is_some() {
+                return (value, None);
+            }
+            let (ptr, len) = self.string_parts(value);
+            let (value, ok) = self.parse_number(ptr, len, to);
+            return (value, Some(ok));
+        }
+        let to_native = native_type(to).unwrap();
+        match (from, to) {
+            (DataType::Timestamp(from_unit, _), DataType::Timestamp(to_unit, _)) => self.rescale(
+                value,
+                ticks_per_second(from_unit),
+                ticks_per_second(to_unit),
+            ),
+            (DataType::Timestamp(unit, _), DataType::Date32) => {
+                // days are floored, times before the epoch belong to the previous day.
+                let per_day = self
+                    .builder
+                    .ins()
+                    .iconst(types::I64, ticks_per_second(unit) * 86_400);
+                let days = self.builder.ins().sdiv(value, per_day);
+                let rem = self.builder.ins().srem(value, per_day);
+                let before = self.builder.ins().icmp_imm(IntCC::SignedLessThan, rem, 0);
+                let before = self.builder.ins().uextend(types::I64, before);
+                let days = self.builder.ins().isub(days, before);
+                (self.builder.ins().ireduce(types::I32, days), None)
+            }
+            (DataType::Timestamp(unit, _), DataType::Date64) => {
+                self.rescale(value, ticks_per_second(unit), 1_000)
+            }
+            (DataType::Date32, DataType::Timestamp(unit, _)) => {
+                let days = self.builder.ins().sextend(types::I64, value);
+                let ticks = self
+                    .builder
+                    .ins()
+                    .imul_imm(days, ticks_per_second(unit) * 86_400);
+                (ticks, None)
+            }
+            (DataType::Date64, DataType::Timestamp(unit, _)) => {
+                self.rescale(value, 1_000, ticks_per_second(unit))
+            }
+            (DataType::Date32, DataType::Date64) => {
+                let days = self.builder.ins().sextend(types::I64, value);
+                (self.builder.ins().imul_imm(days, MILLIS_PER_DAY), None)
+            }
+            (DataType::Date64, DataType::Date32) => {
+                let per_day = self.builder.ins().iconst(types::I64, MILLIS_PER_DAY);
+                let days = self.builder.ins().sdiv(value, per_day);
+                (self.builder.ins().ireduce(types::I32, days), None)
+            }
+            _ if temporal_int(from).is_some() => self.cast(value, &temporal_int(from).unwrap(), to),
+            _ if temporal_int(to).is_some() => self.cast(value, from, &temporal_int(to).unwrap()),
+            (DataType::Decimal128(_, _), DataType::Decimal128(_, _)) => {
+                self.rescale_decimal(value, from, to)
+            }
+            (DataType::Decimal128(_, scale), _) if to.is_floating() => {
+                // the magnitude as hi * 2^64 + lo, signed and then divided by 10^scale,
+                // so the sum is rounded once.
+                let negative = self.builder.ins().icmp_imm(IntCC::SignedLessThan, value, 0);
+                let negated = self.builder.ins().ineg(value);
+                let magnitude = self.builder.ins().select(negative, negated, value);
+                let (lo, hi) = self.builder.ins().isplit(magnitude);
+                let lo = self.builder.ins().fcvt_from_uint(types::F64, lo);
+                let hi = self.builder.ins().fcvt_from_uint(types::F64, hi);
+                let shift = self.builder.ins().f64const(2f64.powi(64));
+                let hi = self.builder.ins().fmul(hi, shift);
+                let unscaled = self.builder.ins().fadd(hi, lo);
+                let negated = self.builder.ins().fneg(unscaled);
+                let unscaled = self.builder.ins().select(negative, negated, unscaled);
+                let divisor = self.builder.ins().f64const(10f64.powi(*scale as i32));
+                let value = self.builder.ins().fdiv(unscaled, divisor);
+                match to_native {
+                    types::F32 => (self.builder.ins().fdemote(types::F32, value), None),
+                    _ => (value, None),
+                }
+            }
+            (DataType::Decimal128(_, scale), _) => {
+                // the fraction is truncated.
+                let value = if *scale > 0 {
+                    let divisor = self.i128_const(10i128.pow(*scale as u32));
+                    self.call_native(NativeOp::DivWrapping, NativeKind::Int128, value, divisor)
+                } else {
+                    value
+                };
+                self.int_to_int(value, true, to)
+            }
+            (_, DataType::Decimal128(precision, scale)) if from.is_floating() => {
+                self.float_to_decimal(value, *precision, *scale)
+            }
+            (_, DataType::Decimal128(precision, scale)) => {
+                let digits = *precision as i32 - *scale as i32;
+                let value = self.int_cast(value, from, types::I128);
+                let ok = if fits_digits(from, digits) {
+                    None
+                } else {
+                    Some(self.within_digits(value, digits))
+                };
+                (self.scale_up(value, *scale as u32), ok)
+            }
+            _ if from.is_floating() && to.is_floating() => {
+                let from_native = native_type(from).unwrap();
+                let value = match from_native.bits().cmp(&to_native.bits()) {
+                    Ordering::Less => self.builder.ins().fpromote(to_native, value),
+                    Ordering::Equal => value,
+                    Ordering::Greater => self.builder.ins().fdemote(to_native, value),
+                };
+                (value, None)
+            }
+            _ if from.is_floating() => {
+                let signed = !to.is_unsigned_integer();
+                let ok = self.float_fits(value, to_native, signed);
+                // x64 only converts to 32 and 64 bit integers, narrower ones are reduced
+                // from 32 bits where the value fits.
+                let convert_type = if to_native.bits() < 32 {
+                    types::I32
+                } else {
+                    to_native
+                };
+                let value = if signed {
+                    self.builder.ins().fcvt_to_sint_sat(convert_type, value)
+                } else {
+                    self.builder.ins().fcvt_to_uint_sat(convert_type, value)
+                };
+                let value = if convert_type == to_native {
+                    value
+                } else {
+                    self.builder.ins().ireduce(to_native, value)
+                };
+                (value, Some(ok))
+            }
+            _ if to.is_floating() && from.is_unsigned_integer() => {
+                (self.builder.ins().fcvt_from_uint(to_native, value), None)
+            }
+            _ if to.is_floating() => (self.builder.ins().fcvt_from_sint(to_native, value), None),
+            _ => self.int_to_int(value, !from.is_unsigned_integer(), to),
+        }
+    }
+
+    /// parse the utf8 string of `len` bytes at `ptr` to a number of type `to`, returning
+    /// the number and 1 if the string was one that fits `to`.
+    pub fn parse_number(&mut self, ptr: Value, len: Value, to: &DataType) -> (Value, Value) {
+        let (parse, parsed_type) = if to.is_floating() {
+            (NativeParse::Float64, DataType::Float64)
+        } else if to.is_unsigned_integer() {
+            (NativeParse::UInt64, DataType::UInt64)
+        } else {
+            (NativeParse::Int64, DataType::Int64)
+        };
+        let (bits, parsed) = self.call_parse(parse, ptr, len);
+        let parsed = self.builder.ins().ireduce(types::I8, parsed);
+        let value = match parse {
+            NativeParse::Float64 => self
+                .builder
+                .ins()
+                .bitcast(types::F64, MemFlags::new(), bits),
+            _ => bits,
+        };
+        let (value, fits) = self.cast(value, &parsed_type, to);
+        let ok = self.and_valid(Some(parsed), fits).unwrap();
+        (value, ok)
+    }
+
+    /// an i128 constant, built from its halves.
+    pub fn i128_const(&mut self, value: i128) -> Value {
+        let lo = self.builder.ins().iconst(types::I64, value as i64);
+        let hi = self.builder.ins().iconst(types::I64, (value >> 64) as i64);
+        self.builder.ins().iconcat(lo, hi)
+    }
+
+    // widen an integer, which can't fail.
+    fn int_cast(&mut self, value: Value, from: &DataType, to: Type) -> Value {
+        let from_native = self.builder.func.dfg.value_type(value);
+        match from_native.bits().cmp(&to.bits()) {
+            Ordering::Less if from.is_unsigned_integer() => self.builder.ins().uextend(to, value),
+            Ordering::Less => self.builder.ins().sextend(to, value),
+            Ordering::Equal => value,
+            Ordering::Greater => self.builder.ins().ireduce(to, value),
+        }
+    }
+
+    // convert an integer to the integer type `to`, checking the value is in its range.
+    fn int_to_int(&mut self, value: Value, signed: bool, to: &DataType) -> (Value, Option<Value>) {
+        let from_native = self.builder.func.dfg.value_type(value);
+        let to_native = native_type(to).unwrap();
+        let (from_min, from_max) = int_range(from_native, signed);
+        let (to_min, to_max) = int_range(to_native, !to.is_unsigned_integer());
+        let (ge, le) = if signed {
+            (
+                IntCC::SignedGreaterThanOrEqual,
+                IntCC::SignedLessThanOrEqual,
+            )
+        } else {
+            (
+                IntCC::UnsignedGreaterThanOrEqual,
+                IntCC::UnsignedLessThanOrEqual,
+            )
+        };
+        // only bounds narrower than the source's are checked, so they fit the source type.
+        let mut ok = None;
+        if to_min > from_min {
+            let min = self.int_const(from_native, to_min);
+            let above = self.builder.ins().icmp(ge, value, min);
+            ok = self.and_valid(ok, Some(above));
+        }
+        if to_max < from_max {
+            let max = self.int_const(from_native, to_max);
+            let below = self.builder.ins().icmp(le, value, max);
+            ok = self.and_valid(ok, Some(below));
+        }
+        let value = match from_native.bits().cmp(&to_native.bits()) {
+            Ordering::Less if signed => self.builder.ins().sextend(to_native, value),
+            Ordering::Less => self.builder.ins().uextend(to_native, value),
+            Ordering::Equal => value,
+            Ordering::Greater => self.builder.ins().ireduce(to_native, value),
+        };
+        (value, ok)
+    }
+
+    // whether a float truncates to an integer of type `to`, NaN never does.
+    fn float_fits(&mut self, value: Value, to: Type, signed: bool) -> Value {
+        let value = match self.builder.func.dfg.value_type(value) {
+            types::F32 => self.builder.ins().fpromote(types::F64, value),
+            _ => value,
+        };
+        let bits = to.bits() as i32;
+        let (min, min_cc, max) = match signed {
+            // MIN - 1 can't be told apart from MIN for 64 bits, which fits.
+            true if bits == 64 => (-(2f64.powi(63)), FloatCC::GreaterThanOrEqual, 2f64.powi(63)),
+            true => {
+                let max = 2f64.powi(bits - 1);
+                (-max - 1.0, FloatCC::GreaterThan, max)
+            }
+            false => (-1.0, FloatCC::GreaterThan, 2f64.powi(bits)),
+        };
+        let min = self.builder.ins().f64const(min);
+        let max = self.builder.ins().f64const(max);
+        let above = self.builder.ins().fcmp(min_cc, value, min);
+        let below = self.builder.ins().fcmp(FloatCC::LessThan, value, max);
+        self.builder.ins().band(above, below)
+    }
+
+    // `value * 10^scale` rounded half away from zero, which must have at most
+    // `precision` digits.
+    fn float_to_decimal(
+        &mut self,
+        value: Value,
+        precision: u8,
+        scale: i8,
+    ) -> (Value, Option<Value>) {
+        let value = match self.builder.func.dfg.value_type(value) {
+            types::F32 => self.builder.ins().fpromote(types::F64, value),
+            _ => value,
+        };
+        let factor = self.builder.ins().f64const(10f64.powi(scale as i32));
+        let scaled = self.builder.ins().fmul(value, factor);
+        let truncated = self.builder.ins().trunc(scaled);
+        let fraction = self.builder.ins().fsub(scaled, truncated);
+        let fraction = self.builder.ins().fabs(fraction);
+        let half = self.builder.ins().f64const(0.5);
+        let round_away = self
+            .builder
+            .ins()
+            .fcmp(FloatCC::GreaterThanOrEqual, fraction, half);
+        let one = self.builder.ins().f64const(1.0);
+        let away = self.builder.ins().fcopysign(one, scaled);
+        let zero = self.builder.ins().f64const(0.0);
+        let step = self.builder.ins().select(round_away, away, zero);
+        let rounded = self.builder.ins().fadd(truncated, step);
+
+        let magnitude = self.builder.ins().fabs(rounded);
+        let bound = self.builder.ins().f64const(10f64.powi(precision as i32));
+        let ok = self.builder.ins().fcmp(FloatCC::LessThan, magnitude, bound);
+
+        // the magnitude as hi * 2^64 + lo, both exact since a magnitude of 2^64 or more
+        // has no bits below 2^11, then negated for negative values.
+        let shift = self.builder.ins().f64const(2f64.powi(64));
+        let inverse = self.builder.ins().f64const(2f64.powi(-64));
+        let hi = self.builder.ins().fmul(magnitude, inverse);
+        let hi = self.builder.ins().floor(hi);
+        let high_part = self.builder.ins().fmul(hi, shift);
+        let lo = self.builder.ins().fsub(magnitude, high_part);
+        let hi = self.builder.ins().fcvt_to_uint_sat(types::I64, hi);
+        let lo = self.builder.ins().fcvt_to_uint_sat(types::I64, lo);
+        let magnitude = self.builder.ins().iconcat(lo, hi);
+        let negated = self.builder.ins().ineg(magnitude);
+        let negative = self.builder.ins().fcmp(FloatCC::LessThan, rounded, zero);
+        let value = self.builder.ins().select(negative, negated, magnitude);
+        (value, Some(ok))
+    }
+
+    // change the scale of a decimal, rounding half away from zero when it shrinks.
+    fn rescale_decimal(
+        &mut self,
+        value: Value,
+        from: &DataType,
+        to: &DataType,
+    ) -> (Value, Option<Value>) {
+        let (
+            DataType::Decimal128(from_precision, from_scale),
+            DataType::Decimal128(precision, scale),
+        ) = (from, to)
+        else {
+            unreachable!("decimal rescale from {} to {}", from, to)
+        };
+        let (from_precision, precision) = (*from_precision as i32, *precision as i32);
+        if scale >= from_scale {
+            let digits = (scale - from_scale) as i32;
+            let ok = if precision - digits >= from_precision {
+                None
+            } else {
+                Some(self.within_digits(value, precision - digits))
+            };
+            return (self.scale_up(value, digits as u32), ok);
+        }
+
+        let divisor = 10i128.pow((from_scale - scale) as u32);
+        let divisor_value = self.i128_const(divisor);
+        let quotient = self.call_native(
+            NativeOp::DivWrapping,
+            NativeKind::Int128,
+            value,
+            divisor_value,
+        );
+        let rem = self.call_native(
+            NativeOp::ModWrapping,
+            NativeKind::Int128,
+            value,
+            divisor_value,
+        );
+        // the remainder has the sign of the value.
+        let half = self.i128_const(divisor / 2);
+        let neg_half = self.i128_const(-(divisor / 2));
+        let up = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedGreaterThanOrEqual, rem, half);
+        let down = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedLessThanOrEqual, rem, neg_half);
+        let up = self.builder.ins().uextend(types::I128, up);
+        let down = self.builder.ins().uextend(types::I128, down);
+        let rounded = self.builder.ins().iadd(quotient, up);
+        let rounded = self.builder.ins().isub(rounded, down);
+        let ok = if precision >= from_precision {
+            None
+        } else {
+            Some(self.within_digits(rounded, precision))
+        };
+        (rounded, ok)
+    }
+
+    // whether an i128 has at most `digits` decimal digits.
+    fn within_digits(&mut self, value: Value, digits: i32) -> Value {
+        let bound = 10i128.pow(digits.max(0) as u32);
+        let max = self.i128_const(bound);
+        let min = self.i128_const(-bound);
+        let below = self.builder.ins().icmp(IntCC::SignedLessThan, value, max);
+        let above = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedGreaterThan, value, min);
+        self.builder.ins().band(below, above)
+    }
+
+    // convert an i64 count of `from` ticks per second to `to` ticks per second, coarser
+    // units truncate and finer ones fail on overflow.
+    fn rescale(&mut self, value: Value, from: i64, to: i64) -> (Value, Option<Value>) {
+        match from.cmp(&to) {
+            Ordering::Equal => (value, None),
+            Ordering::Greater => {
+                let divisor = self.builder.ins().iconst(types::I64, from / to);
+                (self.builder.ins().sdiv(value, divisor), None)
+            }
+            Ordering::Less => {
+                let factor = self.builder.ins().iconst(types::I64, to / from);
+                let scaled = self.builder.ins().imul(value, factor);
+                let overflow =
+                    self.overflows(ArithOp::Mul, &DataType::Int64, value, factor, scaled);
+                (scaled, Some(self.builder.ins().bxor_imm(overflow, 1)))
+            }
+        }
+    }
+
+    /// multiply a decimal by 10^digits.
+    fn scale_up(&mut self, value: Value, digits: u32) -> Value {
+        if digits == 0 {
+            return value;
+        }
+        let factor = self.i128_const(10i128.pow(digits));
+        self.builder.ins().imul(value, factor)
+    }
+}
+
+// smallest and largest value of an integer type.
+fn int_range(_type: Type, signed: bool) -> (i128, i128) {
+    let shift = 128 - _type.bits();
+    if signed {
+        (i128::MIN >> shift, i128::MAX >> shift)
+    } else {
+        (0, (u128::MAX >> shift).min(i128::MAX as u128) as i128)
+    }
+}
+
+// whether every value of the integer type `data_type` has at most `digits` digits.
+fn fits_digits(data_type: &DataType, digits: i32) -> bool {
+    let (min, max) = int_range(
+        native_type(data_type).unwrap(),
+        !data_type.is_unsigned_integer(),
+    );
+    let largest = min.unsigned_abs().max(max as u128);
+    (0..=38).contains(&digits) && largest < 10u128.pow(digits as u32)
+}
@@ -0,0 +1,111 @@
+use cranelift::prelude::*;
+
+use crate::gen::{FuncGenContext, GenValue};
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// whether a condition holds, null conditions don't.
+    pub fn is_true(&mut self, cond: GenValue) -> Value {
+        match cond.valid {
+            Some(valid) => self.builder.ins().band(cond.value, valid),
+            None => cond.value,
+        }
+    }
+
+    /// a null of `_type`, whose value is zero.
+    pub fn null_value(&mut self, _type: Type) -> GenValue {
+        let value = match _type {
+            types::F32 => self.builder.ins().f32const(0.0),
+            types::F64 => self.builder.ins().f64const(0.0),
+            _ => self.int_const(_type, 0),
+        };
+        let valid = self.builder.ins().iconst(types::I8, 0);
+        GenValue::new(value, Some(valid))
+    }
+
+    /// `then` where `cond` is 1 and `otherwise` elsewhere, both computed for every row.
+    pub fn select(&mut self, cond: Value, then: GenValue, otherwise: GenValue) -> GenValue {
+        let value = self.builder.ins().select(cond, then.value, otherwise.value);
+        if then.valid.is_none() && otherwise.valid.is_none() {
+            return GenValue::non_null(value);
+        }
+        let then_valid = self.valid_or_true(then.valid);
+        let otherwise_valid = self.valid_or_true(otherwise.valid);
+        let valid = self.builder.ins().select(cond, then_valid, otherwise_valid);
+        GenValue::new(value, Some(valid))
+    }
+
+    /// `then` where `cond` is 1 and `otherwise` elsewhere, each generated into its own
+    /// block so rows only run the one they take. Both must produce values of `_type`.
+    pub fn branch<T, E>(&mut self, cond: Value, _type: Type, then: T, otherwise: E) -> GenValue
+    where
+        T: FnOnce(&mut Self) -> GenValue,
+        E: FnOnce(&mut Self) -> GenValue,
+    {
+        let then_block = self.builder.create_block();
+        let otherwise_block = self.builder.create_block();
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, _type);
+        self.builder.append_block_param(merge_block, types::I8);
+        self.builder
+            .ins()
+            .brif(cond, then_block, &[], otherwise_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let then = then(self);
+        let then_valid = self.valid_or_true(then.valid);
+        self.builder
+            .ins()
+            .jump(merge_block, &[then.value, then_valid]);
+
+        self.builder.switch_to_block(otherwise_block);
+        let otherwise = otherwise(self);
+        let otherwise_valid = self.valid_or_true(otherwise.valid);
+        self.builder
+            .ins()
+            .jump(merge_block, &[otherwise.value, otherwise_valid]);
+
+        self.builder.switch_to_block(merge_block);
+        let params = self.builder.block_params(merge_block);
+        let (value, valid) = (params[0], params[1]);
+        if then.valid.is_none() && otherwise.valid.is_none() {
+            GenValue::non_null(value)
+        } else {
+            GenValue::new(value, Some(valid))
+        }
+    }
+
+    /// SQL `AND` whose `rhs` only runs for rows where `lhs` isn't false.
+    pub fn short_circuit_and<F>(&mut self, lhs: GenValue, rhs: F) -> GenValue
+    where
+        F: FnOnce(&mut Self) -> GenValue,
+    {
+        let lhs_false = self.builder.ins().bxor_imm(lhs.value, 1);
+        let decided = self.is_true(GenValue::new(lhs_false, lhs.valid));
+        self.branch(
+            decided,
+            types::I8,
+            |ctx| GenValue::non_null(ctx.builder.ins().iconst(types::I8, 0)),
+            |ctx| {
+                let rhs = rhs(ctx);
+                ctx.kleene_and(lhs, rhs)
+            },
+        )
+    }
+
+    /// SQL `OR` whose `rhs` only runs for rows where `lhs` isn't true.
+    pub fn short_circuit_or<F>(&mut self, lhs: GenValue, rhs: F) -> GenValue
+    where
+        F: FnOnce(&mut Self) -> GenValue,
+    {
+        let decided = self.is_true(lhs);
+        self.branch(
+            decided,
+            types::I8,
+            |ctx| GenValue::non_null(ctx.builder.ins().iconst(types::I8, 1)),
+            |ctx| {
+                let rhs = rhs(ctx);
+                ctx.kleene_or(lhs, rhs)
+            },
+        )
+    }
+}
@@ -2,20 +2,27 @@ use std::collections::HashMap;
 
 use crate::gen::build::CodegenContextBuilder;
 use crate::gen::build::FuncRegister;
-use crate::jit::native_opcall::NativeOpCall;
+use crate::gen::{native_type, ArithmeticMode, GenValue, NativeFunction};
+use crate::jit::code::JitCode;
+use crate::jit::native_opcall::{NativeKind, NativeOp, NativeOpCall};
+use crate::jit::native_parse::NativeParse;
+use crate::jit::native_set::NativeSet;
+use crate::jit::native_string::NativeString;
+use arrow::datatypes::{DataType, SchemaRef};
+use common::Result;
 use cranelift::codegen::ir::stackslot::StackSize;
 use cranelift::codegen::ir::StackSlot;
 use cranelift::{
     codegen::{ir::UserFuncName, isa::CallConv},
     prelude::*,
 };
-use cranelift_module::{FuncId, Linkage, Module};
+use cranelift_module::{DataDescription, FuncId, Linkage, Module, ModuleError};
 
 pub struct CodegenContext {
     pub(crate) func_ctx: FunctionBuilderContext,
     pub module: cranelift_jit::JITModule,
     pub ctx: codegen::Context,
-    pub(crate) register_funcs: HashMap<&'static str, FuncRegister>,
+    pub(crate) register_funcs: HashMap<String, FuncRegister>,
 }
 
 impl CodegenContext {
@@ -27,11 +34,21 @@ impl CodegenContext {
         self.module.target_config().pointer_type()
     }
 
-    pub fn finalize(mut self, func_id: FuncId) -> *const u8 {
-        self.module.define_function(func_id, &mut self.ctx).unwrap();
+    pub fn finalize(mut self, func_id: FuncId) -> Result<*const u8> {
+        self.module.define_function(func_id, &mut self.ctx)?;
         self.module.clear_context(&mut self.ctx);
-        self.module.finalize_definitions().unwrap();
-        self.module.get_finalized_function(func_id)
+        self.module.finalize_definitions()?;
+        Ok(self.module.get_finalized_function(func_id))
+    }
+
+    /// like [`CodegenContext::finalize`], but the code is freed once it isn't used anymore
+    /// instead of living as long as the process.
+    pub fn finalize_code(mut self, func_id: FuncId) -> Result<JitCode> {
+        self.module.define_function(func_id, &mut self.ctx)?;
+        self.module.clear_context(&mut self.ctx);
+        self.module.finalize_definitions()?;
+        let address = self.module.get_finalized_function(func_id);
+        Ok(JitCode::new(self.module, address))
     }
 
     pub fn create_func_gen_ctx(
@@ -39,23 +56,20 @@ impl CodegenContext {
         name: &str,
         params: Vec<AbiParam>,
         returns: Vec<AbiParam>,
-    ) -> FuncGenContext {
+    ) -> Result<FuncGenContext> {
         let sig = Signature {
             call_conv: CallConv::Fast,
             params,
             returns,
         };
 
-        let func_id = self
-            .module
-            .declare_function(name, Linkage::Local, &sig)
-            .unwrap();
+        let func_id = self.module.declare_function(name, Linkage::Local, &sig)?;
 
         self.ctx.func.signature = sig;
         self.ctx.func.name = UserFuncName::user(0, func_id.as_u32());
         let ptype = self.module.target_config().pointer_type();
 
-        FuncGenContext {
+        Ok(FuncGenContext {
             func_id,
             ptype,
             builder: FunctionBuilder::new(&mut self.ctx.func, &mut self.func_ctx),
@@ -63,7 +77,15 @@ impl CodegenContext {
             stack: None,
             stack_len: 0,
             stack_value_map: HashMap::new(),
-        }
+            schema: None,
+            columns: HashMap::new(),
+            lanes: 1,
+            num_vars: 0,
+            arithmetic_mode: ArithmeticMode::Wrapping,
+            error_record: None,
+            row: None,
+            module_error: None,
+        })
     }
 }
 
@@ -83,44 +105,288 @@ pub struct FuncGenContext<'long, 'short> {
     stack: Option<StackSlot>,
     stack_len: StackSize,
     stack_value_map: HashMap<&'static str, StackValueInfo>,
+    schema: Option<SchemaRef>,
+    // column index in schema -> value loaded for the current row.
+    columns: HashMap<usize, GenValue>,
+    // lanes of the values currently being generated, 1 outside of vector loops.
+    lanes: u32,
+    num_vars: usize,
+    arithmetic_mode: ArithmeticMode,
+    // where failing generated code reports its error, see `bind_error_record`.
+    pub(crate) error_record: Option<Value>,
+    pub(crate) row: Option<Value>,
+    // the first failure to declare an import or data, reported by `finalize` since code
+    // generation itself can't fail.
+    module_error: Option<ModuleError>,
 }
 
-impl<'long: 'short, 'short> FuncGenContext<'long, 'short> {
+impl<'long, 'short> FuncGenContext<'long, 'short> {
     pub fn call_f64_add_wrapping(&mut self, lhs: Value, rhs: Value) -> Value {
-        let op = NativeOpCall::Float64AddWrapping;
-        self.call_binary(op, lhs, rhs)
+        self.call_native(NativeOp::AddWrapping, NativeKind::Float64, lhs, rhs)
     }
 
     pub fn call_f64_div_wrapping(&mut self, lhs: Value, rhs: Value) -> Value {
-        let op = NativeOpCall::Float64DivWrapping;
-
-        self.call_binary(op, lhs, rhs)
+        self.call_native(NativeOp::DivWrapping, NativeKind::Float64, lhs, rhs)
     }
 
     pub fn call_f64_lt(&mut self, lhs: Value, rhs: Value) -> Value {
-        let op = NativeOpCall::Float64Lt;
-        self.call_binary(op, lhs, rhs)
+        self.call_native(NativeOp::Lt, NativeKind::Float64, lhs, rhs)
+    }
+
+    /// call the rust function registered for `op` over two values of `kind`.
+    pub fn call_native(&mut self, op: NativeOp, kind: NativeKind, lhs: Value, rhs: Value) -> Value {
+        let op = NativeOpCall::new(op, kind);
+        let sig = op.signature(self.module.isa().default_call_conv());
+        let result = self.call_import(op.name(), &sig, &[lhs, rhs]);
+        assert_eq!(result.len(), 1);
+        result[0]
+    }
+
+    /// call the rust function registered to parse a string of `len` bytes at `ptr`,
+    /// returning the parsed bits as an i64 and 1 if the string was a number.
+    pub(crate) fn call_parse(
+        &mut self,
+        parse: NativeParse,
+        ptr: Value,
+        len: Value,
+    ) -> (Value, Value) {
+        let sig = parse.signature(self.ptype, self.module.isa().default_call_conv());
+        let result = self.call_import(parse.name(), &sig, &[ptr, len]);
+        assert_eq!(result.len(), 2);
+        (result[0], result[1])
+    }
+
+    /// call the rust function registered for a string operation.
+    pub(crate) fn call_string(&mut self, op: NativeString, args: &[Value]) -> Option<Value> {
+        let sig = op.signature(self.ptype, self.module.isa().default_call_conv());
+        self.call_import(op.name(), &sig, args).first().copied()
+    }
+
+    /// call the rust function registered for a set membership test, giving 0 or 1.
+    pub(crate) fn call_set(&mut self, op: NativeSet, args: &[Value]) -> Value {
+        let sig = op.signature(self.ptype, self.module.isa().default_call_conv());
+        self.call_import(op.name(), &sig, args)[0]
+    }
+
+    /// call a registered [`NativeFunction`] with its parameters, strings already split
+    /// into their address and length.
+    pub(crate) fn call_native_function(
+        &mut self,
+        func: &NativeFunction,
+        params: &[Value],
+    ) -> Vec<Value> {
+        let sig = func.signature(self.ptype, self.module.isa().default_call_conv());
+        self.call_import(func.name(), &sig, params)
+    }
+
+    /// address of a read-only copy of `bytes` that lives as long as the generated code.
+    pub(crate) fn static_data(&mut self, bytes: &[u8]) -> Value {
+        let mut desc = DataDescription::new();
+        // empty data can't be defined, its address is never read from anyway.
+        let contents = if bytes.is_empty() { &[0][..] } else { bytes };
+        desc.define(contents.into());
+        let defined = match self.module.declare_anonymous_data(false, false) {
+            Ok(data_id) => self.module.define_data(data_id, &desc).map(|_| data_id),
+            err => err,
+        };
+        match defined {
+            Ok(data_id) => {
+                let data = self.module.declare_data_in_func(data_id, self.builder.func);
+                self.builder.ins().symbol_value(self.ptype, data)
+            }
+            Err(err) => {
+                self.module_error.get_or_insert(err);
+                self.builder.ins().iconst(self.ptype, 0)
+            }
+        }
     }
 
-    fn call_binary(&mut self, op: NativeOpCall, lhs: Value, rhs: Value) -> Value {
-        let sig = op.signature(self.ptype);
+    fn call_import(&mut self, name: &str, sig: &Signature, args: &[Value]) -> Vec<Value> {
         // FIXME this don't generate new func id during every call.
-        let func_id = self
+        let func_id = match self.module.declare_function(name, Linkage::Import, sig) {
+            Ok(func_id) => func_id,
+            Err(err) => {
+                self.module_error.get_or_insert(err);
+                // generation goes on with zeros, the function is never finalized.
+                return sig
+                    .returns
+                    .iter()
+                    .map(|param| match param.value_type {
+                        types::F32 => self.builder.ins().f32const(0.0),
+                        types::F64 => self.builder.ins().f64const(0.0),
+                        _type => self.int_const(_type, 0),
+                    })
+                    .collect();
+            }
+        };
+        let func = self.module.declare_func_in_func(func_id, self.builder.func);
+        let call = self.builder.ins().call(func, args);
+        self.builder.inst_results(call).to_vec()
+    }
+
+    pub fn bind_schema(&mut self, schema: SchemaRef) {
+        self.schema = Some(schema);
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema
+            .clone()
+            .expect("schema must be bound before generating expressions")
+    }
+
+    pub fn bind_column(&mut self, index: usize, value: GenValue) {
+        self.columns.insert(index, value);
+    }
+
+    pub fn column(&self, index: usize) -> GenValue {
+        *self
+            .columns
+            .get(&index)
+            .unwrap_or_else(|| panic!("column {} is not bound", index))
+    }
+
+    /// a mutable variable starting as `init`, for state carried across loop iterations.
+    pub fn declare_var(&mut self, init: Value) -> Variable {
+        let var = Variable::new(self.num_vars);
+        self.num_vars += 1;
+        let _type = self.builder.func.dfg.value_type(init);
+        self.builder.declare_var(var, _type);
+        self.builder.def_var(var, init);
+        var
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    /// how integer arithmetic generated from now on treats overflow and zero divisors.
+    /// Wrapping until set, as only kernels with an error record can check.
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) {
+        self.arithmetic_mode = mode;
+    }
+
+    pub fn lanes(&self) -> u32 {
+        self.lanes
+    }
+
+    pub(crate) fn set_lanes(&mut self, lanes: u32) {
+        self.lanes = lanes;
+    }
+
+    /// lanes of `lane_type` in one vector value. cranelift only lowers 128-bit vectors,
+    /// so this is F64X2 on every target.
+    pub fn simd_lanes(&self, lane_type: Type) -> u32 {
+        let bytes = self.module.isa().dynamic_vector_bytes(lane_type);
+        (bytes / lane_type.bytes()).max(1)
+    }
+
+    /// vectors of [`FuncGenContext::simd_lanes`] lanes that fill one SIMD register of the
+    /// target: two on x86_64 with AVX2, whose 256-bit registers hold F64X4, one elsewhere.
+    pub fn simd_vectors(&self) -> u32 {
+        let has_avx2 = self
             .module
-            .declare_function(op.name(), Linkage::Import, &sig)
+            .isa()
+            .isa_flags()
+            .iter()
+            .any(|flag| flag.name == "has_avx2" && flag.as_bool() == Some(true));
+        if has_avx2 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// splat a scalar into the current lane width, no-op outside of vector loops.
+    pub fn broadcast(&mut self, value: Value) -> Value {
+        if self.lanes == 1 {
+            return value;
+        }
+        let _type = self
+            .builder
+            .func
+            .dfg
+            .value_type(value)
+            .by(self.lanes)
             .unwrap();
-        let func = self.module.declare_func_in_func(func_id, self.builder.func);
-        let call = self.builder.ins().call(func, &[lhs, rhs]);
-        let result = self.builder.inst_results(call);
-        assert_eq!(result.len(), 1);
-        result[0]
+        self.builder.ins().splat(_type, value)
+    }
+
+    /// address of the `index`th element with `width` bytes starting from `ptr`.
+    pub fn element_addr(&mut self, ptr: Value, index: Value, width: u32) -> Value {
+        let offset = self.builder.ins().imul_imm(index, width as i64);
+        self.builder.ins().iadd(ptr, offset)
+    }
+
+    pub fn load_element(&mut self, _type: Type, ptr: Value, index: Value) -> Value {
+        let addr = self.element_addr(ptr, index, _type.bytes());
+        self.builder.ins().load(_type, MemFlags::trusted(), addr, 0)
+    }
+
+    pub fn store_element(&mut self, value: Value, ptr: Value, index: Value) {
+        let _type = self.builder.func.dfg.value_type(value);
+        let addr = self.element_addr(ptr, index, _type.bytes());
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, addr, 0);
+    }
+
+    /// load `count` pointers stored one after another from `base`.
+    pub fn load_ptrs(&mut self, base: Value, count: usize) -> Vec<Value> {
+        let ptype = self.ptype;
+        (0..count)
+            .map(|slot| {
+                let offset = (slot as u32 * ptype.bytes()) as i32;
+                self.builder
+                    .ins()
+                    .load(ptype, MemFlags::trusted(), base, offset)
+            })
+            .collect()
+    }
+
+    /// load row `index` of an arrow array of `data_type`, with its validity when
+    /// `validity_ptr` points at a validity bitmap.
+    pub fn load_array_value(
+        &mut self,
+        data_type: &DataType,
+        ptr: Value,
+        validity_ptr: Option<Value>,
+        index: Value,
+    ) -> GenValue {
+        let value = match data_type {
+            DataType::Boolean => self.load_bit(ptr, index),
+            _ => {
+                let _type = native_type(data_type)
+                    .unwrap_or_else(|| panic!("{} can't be loaded natively", data_type));
+                self.load_element(_type, ptr, index)
+            }
+        };
+        let valid = validity_ptr.map(|validity_ptr| self.load_bit(validity_ptr, index));
+        GenValue::new(value, valid)
     }
 
-    pub fn finalize(mut self, results: &[Value]) -> FuncId {
+    /// load the `index`th bit of a bitmap as an i8 of 0 or 1.
+    pub fn load_bit(&mut self, ptr: Value, index: Value) -> Value {
+        let byte_index = self.builder.ins().ushr_imm(index, 3);
+        let addr = self.builder.ins().iadd(ptr, byte_index);
+        let byte = self
+            .builder
+            .ins()
+            .load(types::I8, MemFlags::trusted(), addr, 0);
+        let bit_index = self.builder.ins().band_imm(index, 7);
+        let bit = self.builder.ins().ushr(byte, bit_index);
+        self.builder.ins().band_imm(bit, 1)
+    }
+
+    /// return `results` and finish the function, failing if an import or data it uses
+    /// couldn't be declared.
+    pub fn finalize(mut self, results: &[Value]) -> Result<FuncId> {
         self.builder.ins().return_(results);
         self.builder.seal_all_blocks();
         self.builder.finalize();
-        self.func_id
+        match self.module_error {
+            Some(err) => Err(err.into()),
+            None => Ok(self.func_id),
+        }
     }
 
     pub fn make_static_stack(&mut self, capacity: StackSize) {
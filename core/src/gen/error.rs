@@ -0,0 +1,116 @@
+use common::ServerError;
+use cranelift::prelude::*;
+
+use crate::gen::FuncGenContext;
+
+/// Why a kernel stopped before its last row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i64)]
+pub enum KernelErrorCode {
+    Overflow = 1,
+    DivideByZero = 2,
+    InvalidCast = 3,
+    InvalidArgument = 4,
+}
+
+/// Filled in by a kernel that stops early, at the address bound by
+/// [`FuncGenContext::bind_error_record`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KernelError {
+    /// a [`KernelErrorCode`], 0 while no error occurred.
+    pub code: i64,
+    /// input row whose values were being computed.
+    pub row: i64,
+}
+
+impl From<KernelError> for ServerError {
+    fn from(err: KernelError) -> Self {
+        let row = err.row as usize;
+        match err.code {
+            code if code == KernelErrorCode::Overflow as i64 => {
+                ServerError::ArithmeticOverflow { row }
+            }
+            code if code == KernelErrorCode::DivideByZero as i64 => {
+                ServerError::DivideByZero { row }
+            }
+            code if code == KernelErrorCode::InvalidCast as i64 => ServerError::InvalidCast { row },
+            code if code == KernelErrorCode::InvalidArgument as i64 => {
+                ServerError::InvalidArgument { row }
+            }
+            code => ServerError::Internal(format!(
+                "kernel failed at row {} with unknown error code {}",
+                row, code
+            )),
+        }
+    }
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// let generated code fail: errors are written to the [`KernelError`] at `ptr` and
+    /// the function returns -1, so it must return a single i64.
+    pub fn bind_error_record(&mut self, ptr: Value) {
+        self.error_record = Some(ptr);
+    }
+
+    /// the input row errors are reported for.
+    pub fn bind_row(&mut self, row: Value) {
+        self.row = Some(row);
+    }
+
+    /// leave the function with `code` when `cond` is 1.
+    pub fn error_if(&mut self, cond: Value, code: KernelErrorCode) {
+        let ptr = self
+            .error_record
+            .expect("generated code can only fail with an error record bound");
+        let row = self.row.expect("generated code can only fail within a row");
+        let error_block = self.builder.create_block();
+        let next_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(cond, error_block, &[], next_block, &[]);
+
+        self.builder.switch_to_block(error_block);
+        // failing is the unlikely path, keep it out of the loop body.
+        self.builder.set_cold_block(error_block);
+        let code = self.builder.ins().iconst(types::I64, code as i64);
+        self.builder.ins().store(MemFlags::trusted(), code, ptr, 0);
+        self.builder.ins().store(MemFlags::trusted(), row, ptr, 8);
+        let failed = self.builder.ins().iconst(types::I64, -1);
+        self.builder.ins().return_(&[failed]);
+
+        self.builder.switch_to_block(next_block);
+    }
+
+    /// stop with `code` when `cond` holds for a row that isn't null.
+    pub fn fail_if(&mut self, cond: Value, valid: Option<Value>, code: KernelErrorCode) {
+        let cond = match valid {
+            Some(valid) => self.builder.ins().band(cond, valid),
+            None => cond,
+        };
+        self.error_if(cond, code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::ServerError;
+
+    use super::{KernelError, KernelErrorCode};
+
+    #[test]
+    fn test_kernel_error_into_server_error() {
+        let error = |code, row| ServerError::from(KernelError { code, row });
+        let overflow = KernelErrorCode::Overflow as i64;
+        assert!(matches!(
+            error(overflow, 4),
+            ServerError::ArithmeticOverflow { row: 4 }
+        ));
+        let cast = KernelErrorCode::InvalidCast as i64;
+        assert!(matches!(
+            error(cast, 0),
+            ServerError::InvalidCast { row: 0 }
+        ));
+        assert!(matches!(error(99, 1), ServerError::Internal(_)));
+    }
+}
@@ -0,0 +1,235 @@
+use std::cell::RefCell;
+
+use arrow::datatypes::DataType;
+use common::{Result, ServerError};
+use cranelift::codegen::ir::Signature;
+use cranelift::codegen::isa::CallConv;
+use cranelift::prelude::*;
+
+use crate::gen::{native_type, FuncGenContext, GenValue, KernelErrorCode, StringType};
+
+/// A rust `extern "C" fn` generated code calls through its symbol, for rows whose
+/// arguments aren't null. Numbers and booleans are passed as their [`native_type`],
+/// strings as their address and length in bytes. A string is returned as a
+/// [`StringResult`], and a function that can fail returns a [`NativeResult`] instead of
+/// its value.
+#[derive(Clone, Debug)]
+pub struct NativeFunction {
+    name: String,
+    address: *const u8,
+    arg_types: Vec<DataType>,
+    return_type: DataType,
+    error: Option<KernelErrorCode>,
+}
+
+// Safety: the address is of a function, which every thread can call.
+unsafe impl Send for NativeFunction {}
+unsafe impl Sync for NativeFunction {}
+
+impl NativeFunction {
+    /// `address` must be a function taking `arg_types` and returning `return_type` as
+    /// described above. Fails for types generated code can't pass.
+    pub fn try_new(
+        name: impl Into<String>,
+        address: *const u8,
+        arg_types: Vec<DataType>,
+        return_type: DataType,
+    ) -> Result<Self> {
+        let name = name.into();
+        for data_type in arg_types.iter().chain([&return_type]) {
+            if native_type(data_type).is_none() && StringType::from_data_type(data_type).is_none() {
+                return Err(ServerError::NotSupported(format!(
+                    "{} in native function {}",
+                    data_type, name
+                )));
+            }
+        }
+        Ok(Self {
+            name,
+            address,
+            arg_types,
+            return_type,
+            error: None,
+        })
+    }
+
+    /// the function returns a [`NativeResult`], and stops the kernel with `code` when it
+    /// fails. Only values of up to 64 bits can be returned this way.
+    pub fn with_error(mut self, code: KernelErrorCode) -> Result<Self> {
+        match native_type(&self.return_type) {
+            Some(_type) if _type.bits() <= 64 => {
+                self.error = Some(code);
+                Ok(self)
+            }
+            _ => Err(ServerError::NotSupported(format!(
+                "native function {} failing with a {} result",
+                self.name, self.return_type
+            ))),
+        }
+    }
+
+    /// the symbol generated code calls the function by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> *const u8 {
+        self.address
+    }
+
+    pub fn arg_types(&self) -> &[DataType] {
+        &self.arg_types
+    }
+
+    pub fn return_type(&self) -> &DataType {
+        &self.return_type
+    }
+
+    /// the error code the function stops the kernel with, if it can fail.
+    pub fn error(&self) -> Option<KernelErrorCode> {
+        self.error
+    }
+
+    pub fn signature(&self, pointer_type: Type, call_conv: CallConv) -> Signature {
+        let mut params = vec![];
+        for data_type in &self.arg_types {
+            match native_type(data_type) {
+                Some(_type) => params.push(AbiParam::new(_type)),
+                None => {
+                    params.push(AbiParam::new(pointer_type));
+                    params.push(AbiParam::new(types::I64));
+                }
+            }
+        }
+        let returns = match native_type(&self.return_type) {
+            _ if self.error.is_some() => vec![AbiParam::new(types::I64); 2],
+            Some(_type) => vec![AbiParam::new(_type)],
+            None => vec![AbiParam::new(pointer_type), AbiParam::new(types::I64)],
+        };
+        Signature {
+            params,
+            returns,
+            call_conv,
+        }
+    }
+}
+
+/// A string returned by a [`NativeFunction`]. Two 64 bit integers are returned in
+/// registers by every C ABI we target.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct StringResult {
+    pub ptr: *const u8,
+    pub len: u64,
+}
+
+impl StringResult {
+    /// `value` must outlive the kernel call, like a slice of an argument does. Strings
+    /// the function creates are kept alive by [`return_string`].
+    pub fn borrowed(value: &str) -> Self {
+        Self {
+            ptr: value.as_ptr(),
+            len: value.len() as u64,
+        }
+    }
+}
+
+thread_local! {
+    // strings created by native functions for the kernel running on this thread.
+    static RETURNED_STRINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// return a string a native function created, which stays alive until
+/// [`clear_returned_strings`] is called on the same thread.
+pub fn return_string(value: String) -> StringResult {
+    let result = StringResult::borrowed(&value);
+    // the bytes don't move when the string does.
+    RETURNED_STRINGS.with(|strings| strings.borrow_mut().push(value));
+    result
+}
+
+/// free the strings native functions returned on this thread, once the kernel that
+/// called them has copied them into its outputs.
+pub fn clear_returned_strings() {
+    RETURNED_STRINGS.with(|strings| strings.borrow_mut().clear());
+}
+
+/// Returned by a [`NativeFunction`] that can fail: the bits of its value, zero or sign
+/// extended integers and floats as `to_bits`, and 1 if it failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NativeResult {
+    pub bits: u64,
+    pub failed: u64,
+}
+
+impl NativeResult {
+    pub const FAILED: NativeResult = NativeResult { bits: 0, failed: 1 };
+
+    pub fn ok(bits: u64) -> Self {
+        Self { bits, failed: 0 }
+    }
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// call `func` with `args` of its argument types, only for rows where `valid`. Other
+    /// rows are null. The function must be registered with the
+    /// [`CodegenContextBuilder`](crate::gen::CodegenContextBuilder).
+    pub fn call_function(
+        &mut self,
+        func: &NativeFunction,
+        args: &[Value],
+        valid: Option<Value>,
+    ) -> GenValue {
+        let _type = native_type(func.return_type()).unwrap_or(types::I128);
+        let Some(valid) = valid else {
+            return GenValue::non_null(self.gen_call_function(func, args));
+        };
+        // null rows may hold any bytes, which the function must not see.
+        let value = self.branch(
+            valid,
+            _type,
+            |ctx| GenValue::non_null(ctx.gen_call_function(func, args)),
+            |ctx| ctx.null_value(_type),
+        );
+        GenValue::new(value.value, Some(valid))
+    }
+
+    fn gen_call_function(&mut self, func: &NativeFunction, args: &[Value]) -> Value {
+        let mut params = vec![];
+        for (arg, data_type) in args.iter().zip(func.arg_types()) {
+            if StringType::from_data_type(data_type).is_some() {
+                let (ptr, len) = self.string_parts(*arg);
+                params.extend([ptr, len]);
+            } else {
+                params.push(*arg);
+            }
+        }
+        let results = self.call_native_function(func, &params);
+        let return_type = func.return_type();
+        let Some(code) = func.error() else {
+            return match native_type(return_type) {
+                Some(_) => results[0],
+                None => self.string_view(results[0], results[1]),
+            };
+        };
+        let failed = self.builder.ins().icmp_imm(IntCC::NotEqual, results[1], 0);
+        self.error_if(failed, code);
+        let _type = native_type(return_type).unwrap();
+        let bits = results[0];
+        match _type {
+            types::I64 => bits,
+            types::F64 => self
+                .builder
+                .ins()
+                .bitcast(types::F64, MemFlags::new(), bits),
+            types::F32 => {
+                let bits = self.builder.ins().ireduce(types::I32, bits);
+                self.builder
+                    .ins()
+                    .bitcast(types::F32, MemFlags::new(), bits)
+            }
+            _ => self.builder.ins().ireduce(_type, bits),
+        }
+    }
+}
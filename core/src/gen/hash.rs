@@ -0,0 +1,64 @@
+use cranelift::prelude::*;
+
+use crate::gen::{FuncGenContext, GenValue};
+
+/// initial hash of a row before any key is combined into it.
+pub const HASH_SEED: i64 = 0x2d35_8dcc_aa6c_78a5;
+// FxHash multiplier, cheap and good enough for hash table buckets.
+const HASH_MULTIPLIER: i64 = 0x517c_c1b7_2722_0a95;
+// stands in for the bits of null keys so that they hash alike.
+const NULL_BITS: i64 = 0x7f4a_7c15_9e37_79b9;
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// Combine one key into a running i64 `hash`.
+    ///
+    /// Keys hash by their bit pattern, so equal keys of the same type always collide.
+    /// Floats are normalized first: -0 and +0 compare equal and hash alike, and every NaN
+    /// hashes like the canonical one.
+    pub fn hash_combine(&mut self, hash: Value, key: GenValue) -> Value {
+        let bits = self.hash_bits(key.value);
+        let bits = match key.valid {
+            Some(valid) => {
+                let null_bits = self.builder.ins().iconst(types::I64, NULL_BITS);
+                self.builder.ins().select(valid, bits, null_bits)
+            }
+            None => bits,
+        };
+        let hash = self.builder.ins().rotl_imm(hash, 5);
+        let hash = self.builder.ins().bxor(hash, bits);
+        self.builder.ins().imul_imm(hash, HASH_MULTIPLIER)
+    }
+
+    fn hash_bits(&mut self, value: Value) -> Value {
+        let _type = self.builder.func.dfg.value_type(value);
+        let value = if _type.is_float() {
+            let (zero, nan) = match _type {
+                types::F32 => (
+                    self.builder.ins().f32const(0.0),
+                    self.builder.ins().f32const(f32::NAN),
+                ),
+                _ => (
+                    self.builder.ins().f64const(0.0),
+                    self.builder.ins().f64const(f64::NAN),
+                ),
+            };
+            // -0 + +0 is +0.
+            let value = self.builder.ins().fadd(value, zero);
+            let is_nan = self.builder.ins().fcmp(FloatCC::Unordered, value, value);
+            let value = self.builder.ins().select(is_nan, nan, value);
+            let int_type = Type::int(_type.bits() as u16).unwrap();
+            self.builder.ins().bitcast(int_type, MemFlags::new(), value)
+        } else {
+            value
+        };
+        match _type.bits() {
+            bits if bits < 64 => self.builder.ins().uextend(types::I64, value),
+            64 => value,
+            _ => {
+                // fold wider values, i.e. decimals, into 64 bits.
+                let (lo, hi) = self.builder.ins().isplit(value);
+                self.builder.ins().bxor(lo, hi)
+            }
+        }
+    }
+}
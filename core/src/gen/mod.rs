@@ -1,11 +1,57 @@
-use cranelift::prelude::Value;
-
+mod arith;
+mod array_loop;
+mod backend;
 mod build;
+mod cast;
+mod cond;
 mod ctx;
+mod error;
+mod func;
+mod hash;
+mod null;
+mod set;
+mod string;
+mod types;
+mod udf;
 
+pub use arith::*;
+pub use array_loop::*;
+pub use backend::*;
 pub use build::*;
+pub use cast::*;
 pub use ctx::*;
+pub use error::*;
+pub use func::*;
+pub use hash::*;
+pub use null::*;
+pub use set::*;
+pub use string::*;
+pub use types::*;
+pub use udf::*;
 
 pub trait ExprGen {
-    fn gen(&self, ctx: &mut FuncGenContext) -> Value;
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue;
+
+    /// whether `gen` also emits correct code for SIMD lanes of non-null inputs.
+    fn vectorizable(&self) -> bool {
+        false
+    }
+
+    /// rough number of instructions `gen` emits for this node alone. Conditional code
+    /// computes cheap expressions for every row instead of branching around them.
+    fn gen_cost(&self, _ctx: &FuncGenContext) -> usize {
+        1
+    }
+
+    /// whether code `gen` emits for this node alone can stop the kernel with an error, so
+    /// it must only run for rows whose result needs it.
+    fn gen_may_fail(&self, _ctx: &FuncGenContext) -> bool {
+        false
+    }
+
+    /// generate this node through a [`CodegenBackend`], `None` if the node or a type it
+    /// uses isn't supported that way.
+    fn gen_backend(&self, _backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        None
+    }
 }
@@ -0,0 +1,122 @@
+use cranelift::prelude::*;
+
+use crate::gen::FuncGenContext;
+
+/// A value produced by generated code together with its validity.
+///
+/// Booleans and validity flags are i8 values of 0 or 1. `valid` is `None` when the
+/// value can never be null, which lets non-nullable inputs skip validity tracking.
+#[derive(Clone, Copy, Debug)]
+pub struct GenValue {
+    pub value: Value,
+    pub valid: Option<Value>,
+}
+
+impl GenValue {
+    pub fn new(value: Value, valid: Option<Value>) -> Self {
+        Self { value, valid }
+    }
+
+    pub fn non_null(value: Value) -> Self {
+        Self { value, valid: None }
+    }
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// validity of a value computed from both inputs, null if either input is null.
+    pub fn and_valid(&mut self, lhs: Option<Value>, rhs: Option<Value>) -> Option<Value> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(self.builder.ins().band(lhs, rhs)),
+            (Some(valid), None) | (None, Some(valid)) => Some(valid),
+            (None, None) => None,
+        }
+    }
+
+    fn or_valid(&mut self, lhs: Option<Value>, rhs: Option<Value>) -> Option<Value> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(self.builder.ins().bor(lhs, rhs)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn valid_or_true(&mut self, valid: Option<Value>) -> Value {
+        match valid {
+            Some(valid) => valid,
+            None => self.builder.ins().iconst(types::I8, 1),
+        }
+    }
+
+    /// SQL `NOT`, null stays null.
+    pub fn kleene_not(&mut self, input: GenValue) -> GenValue {
+        let value = self.builder.ins().bxor_imm(input.value, 1);
+        GenValue::new(value, input.valid)
+    }
+
+    /// SQL `AND`: false if either side is false, even when the other side is null.
+    pub fn kleene_and(&mut self, lhs: GenValue, rhs: GenValue) -> GenValue {
+        let value = self.builder.ins().band(lhs.value, rhs.value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return GenValue::non_null(value);
+        }
+        let lhs_false = self.builder.ins().bxor_imm(lhs.value, 1);
+        let rhs_false = self.builder.ins().bxor_imm(rhs.value, 1);
+        let valid = self.kleene_valid(lhs, lhs_false, rhs, rhs_false);
+        GenValue::new(value, Some(valid))
+    }
+
+    /// SQL `OR`: true if either side is true, even when the other side is null.
+    pub fn kleene_or(&mut self, lhs: GenValue, rhs: GenValue) -> GenValue {
+        let value = self.builder.ins().bor(lhs.value, rhs.value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return GenValue::non_null(value);
+        }
+        let valid = self.kleene_valid(lhs, lhs.value, rhs, rhs.value);
+        GenValue::new(value, Some(valid))
+    }
+
+    // the result is valid when both sides are valid, or when one valid side decides it.
+    fn kleene_valid(
+        &mut self,
+        lhs: GenValue,
+        lhs_decides: Value,
+        rhs: GenValue,
+        rhs_decides: Value,
+    ) -> Value {
+        let lhs_valid = self.valid_or_true(lhs.valid);
+        let rhs_valid = self.valid_or_true(rhs.valid);
+        let both = self.builder.ins().band(lhs_valid, rhs_valid);
+        let by_lhs = self.builder.ins().band(lhs_valid, lhs_decides);
+        let by_rhs = self.builder.ins().band(rhs_valid, rhs_decides);
+        let valid = self.builder.ins().bor(both, by_lhs);
+        self.builder.ins().bor(valid, by_rhs)
+    }
+
+    /// `IS NULL`, never null itself.
+    pub fn is_null(&mut self, input: GenValue) -> GenValue {
+        let value = match input.valid {
+            Some(valid) => self.builder.ins().bxor_imm(valid, 1),
+            None => self.builder.ins().iconst(types::I8, 0),
+        };
+        GenValue::non_null(value)
+    }
+
+    /// `IS NOT NULL`, never null itself.
+    pub fn is_not_null(&mut self, input: GenValue) -> GenValue {
+        let value = self.valid_or_true(input.valid);
+        GenValue::non_null(value)
+    }
+
+    /// first non-null value of `inputs`, null if all of them are null.
+    pub fn coalesce(&mut self, inputs: &[GenValue]) -> GenValue {
+        let mut result = inputs[0];
+        for input in &inputs[1..] {
+            let Some(valid) = result.valid else {
+                break;
+            };
+            let value = self.builder.ins().select(valid, result.value, input.value);
+            let valid = self.or_valid(result.valid, input.valid);
+            result = GenValue::new(value, valid);
+        }
+        result
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeSet, HashSet};
+
+use arrow::datatypes::DataType;
+use cranelift::frontend::Switch;
+use cranelift::prelude::*;
+
+use crate::gen::{FuncGenContext, StringType};
+use crate::jit::native_set::NativeSet;
+
+/// Constant values generated code tests membership in with one call, for lists too long
+/// to compare one by one.
+#[derive(Clone, Debug)]
+pub enum ValueSet {
+    /// integers, dates and timestamps widened to i64, floats as their [`float_key`].
+    Int64(HashSet<i64>),
+    Utf8(HashSet<String>),
+}
+
+/// the key a float64 is looked up by in a [`ValueSet`], which is equal for floats arrow's
+/// comparisons consider equal.
+pub fn float_key(value: f64) -> i64 {
+    let bits = value.to_bits() as i64;
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
+}
+
+/// like [`float_key`] for a float32.
+pub fn float32_key(value: f32) -> i64 {
+    let bits = value.to_bits() as i32;
+    (bits ^ (((bits >> 31) as u32) >> 1) as i32) as i64
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// whether `set` holds a value of `data_type`. Generated code refers to `set` by its
+    /// address, so it must not move or be dropped while the code runs.
+    pub fn in_set(&mut self, set: &ValueSet, data_type: &DataType, value: Value) -> Value {
+        let set = self
+            .builder
+            .ins()
+            .iconst(self.ptype, set as *const ValueSet as i64);
+        if StringType::from_data_type(data_type).is_some() {
+            let (ptr, len) = self.string_parts(value);
+            return self.call_set(NativeSet::ContainsUtf8, &[set, ptr, len]);
+        }
+        let key = if data_type.is_floating() {
+            self.total_order_key(value)
+        } else {
+            value
+        };
+        let _type = self.builder.func.dfg.value_type(key);
+        let key = match _type {
+            types::I64 => key,
+            _ if data_type.is_unsigned_integer() => self.builder.ins().uextend(types::I64, key),
+            _ => self.builder.ins().sextend(types::I64, key),
+        };
+        self.call_set(NativeSet::ContainsInt64, &[set, key])
+    }
+
+    /// whether an integer equals one of `values`, branching through a switch on its
+    /// distance from the smallest of them. Dense values become a jump table.
+    pub fn switch_contains(&mut self, value: Value, values: &[i128]) -> Value {
+        let _type = self.builder.func.dfg.value_type(value);
+        let base = *values.iter().min().expect("switch over no values");
+        // values below the base wrap around past every entry.
+        let offsets: BTreeSet<u128> = values.iter().map(|v| (v - base) as u128).collect();
+        let found_block = self.builder.create_block();
+        let missing_block = self.builder.create_block();
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, types::I8);
+
+        let mut switch = Switch::new();
+        for offset in offsets {
+            switch.set_entry(offset, found_block);
+        }
+        let base = self.int_const(_type, base);
+        let offset = self.builder.ins().isub(value, base);
+        switch.emit(&mut self.builder, offset, missing_block);
+
+        self.builder.switch_to_block(found_block);
+        let found = self.builder.ins().iconst(types::I8, 1);
+        self.builder.ins().jump(merge_block, &[found]);
+
+        self.builder.switch_to_block(missing_block);
+        let missing = self.builder.ins().iconst(types::I8, 0);
+        self.builder.ins().jump(merge_block, &[missing]);
+
+        self.builder.switch_to_block(merge_block);
+        self.builder.block_params(merge_block)[0]
+    }
+}
@@ -0,0 +1,221 @@
+use arrow::datatypes::DataType;
+use cranelift::prelude::*;
+use regex::Regex;
+
+use crate::gen::{FuncGenContext, GenValue};
+use crate::jit::native_string::NativeString;
+
+/// Arrow string arrays generated code reads and writes: an offsets buffer of one more
+/// element than rows, indexing into a buffer of utf8 data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringType {
+    Utf8,
+    LargeUtf8,
+}
+
+impl StringType {
+    pub fn from_data_type(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Utf8 => Some(StringType::Utf8),
+            DataType::LargeUtf8 => Some(StringType::LargeUtf8),
+            _ => None,
+        }
+    }
+
+    /// cranelift type of one element of the offsets buffer.
+    pub fn offset_type(&self) -> Type {
+        match self {
+            StringType::Utf8 => types::I32,
+            StringType::LargeUtf8 => types::I64,
+        }
+    }
+}
+
+impl<'long, 'short> FuncGenContext<'long, 'short> {
+    /// a string value: the address of its first byte in the low half of an i128 and its
+    /// length in bytes in the high half. The bytes are owned by an input array or the
+    /// generated code, and outlive the kernel call.
+    pub fn string_view(&mut self, ptr: Value, len: Value) -> Value {
+        self.builder.ins().iconcat(ptr, len)
+    }
+
+    /// the address and length of a string built by [`FuncGenContext::string_view`].
+    pub fn string_parts(&mut self, string: Value) -> (Value, Value) {
+        let (ptr, len) = self.builder.ins().isplit(string);
+        (ptr, len)
+    }
+
+    /// load row `index` of a string array from its offsets and data buffers.
+    pub fn load_string(
+        &mut self,
+        string_type: StringType,
+        offsets_ptr: Value,
+        data_ptr: Value,
+        index: Value,
+    ) -> Value {
+        let offset_type = string_type.offset_type();
+        let start_addr = self.element_addr(offsets_ptr, index, offset_type.bytes());
+        let start = self
+            .builder
+            .ins()
+            .load(offset_type, MemFlags::trusted(), start_addr, 0);
+        let end = self.builder.ins().load(
+            offset_type,
+            MemFlags::trusted(),
+            start_addr,
+            offset_type.bytes() as i32,
+        );
+        let (start, end) = match string_type {
+            StringType::Utf8 => (
+                self.builder.ins().uextend(types::I64, start),
+                self.builder.ins().uextend(types::I64, end),
+            ),
+            StringType::LargeUtf8 => (start, end),
+        };
+        let ptr = self.builder.ins().iadd(data_ptr, start);
+        let len = self.builder.ins().isub(end, start);
+        self.string_view(ptr, len)
+    }
+
+    /// a constant string, stored with the generated code.
+    pub fn string_const(&mut self, value: &str) -> Value {
+        let ptr = self.static_data(value.as_bytes());
+        let len = self.builder.ins().iconst(types::I64, value.len() as i64);
+        self.string_view(ptr, len)
+    }
+
+    /// length of a string in bytes, as an i64.
+    pub fn string_len(&mut self, string: Value) -> Value {
+        self.string_parts(string).1
+    }
+
+    /// compare two strings bytewise by the signed form of `cond`, giving 0 or 1.
+    pub fn string_compare(&mut self, cond: IntCC, lhs: Value, rhs: Value) -> Value {
+        let (lhs_ptr, lhs_len) = self.string_parts(lhs);
+        let (rhs_ptr, rhs_len) = self.string_parts(rhs);
+        let order = self
+            .call_string(NativeString::Compare, &[lhs_ptr, lhs_len, rhs_ptr, rhs_len])
+            .unwrap();
+        self.builder.ins().icmp_imm(cond, order, 0)
+    }
+
+    /// whether a string begins with `prefix`, compared inline.
+    pub fn starts_with(&mut self, string: Value, prefix: &str) -> Value {
+        let (ptr, len) = self.string_parts(string);
+        self.match_at(ptr, len, prefix.as_bytes(), |_, ptr, _| ptr)
+    }
+
+    /// whether a string ends with `suffix`, compared inline.
+    pub fn ends_with(&mut self, string: Value, suffix: &str) -> Value {
+        let (ptr, len) = self.string_parts(string);
+        let width = suffix.len() as i64;
+        self.match_at(ptr, len, suffix.as_bytes(), |ctx, ptr, len| {
+            let start = ctx.builder.ins().iadd(ptr, len);
+            ctx.builder.ins().iadd_imm(start, -width)
+        })
+    }
+
+    /// whether `needle` occurs anywhere in a string.
+    pub fn string_contains(&mut self, string: Value, needle: &str) -> Value {
+        let needle = self.string_const(needle);
+        self.call_string_predicate(NativeString::Contains, string, needle)
+    }
+
+    /// whether a string matches the SQL LIKE `pattern`, ignoring case for ILIKE.
+    pub fn like(&mut self, string: Value, pattern: Value, case_insensitive: bool) -> Value {
+        let op = if case_insensitive {
+            NativeString::ILike
+        } else {
+            NativeString::Like
+        };
+        self.call_string_predicate(op, string, pattern)
+    }
+
+    /// whether `regex` matches anywhere in a string. Generated code refers to `regex` by
+    /// its address, so it must not move or be dropped while the code runs.
+    pub fn regex_match(&mut self, regex: &Regex, string: Value) -> Value {
+        let regex = self
+            .builder
+            .ins()
+            .iconst(self.ptype, regex as *const Regex as i64);
+        let (ptr, len) = self.string_parts(string);
+        self.call_string(NativeString::RegexMatch, &[regex, ptr, len])
+            .unwrap()
+    }
+
+    /// append a string to the arrow `GenericStringBuilder` at `builder`, whose offsets
+    /// are those of `string_type`.
+    pub fn append_string(&mut self, builder: Value, string: Value, string_type: StringType) {
+        let (ptr, len) = self.string_parts(string);
+        let op = match string_type {
+            StringType::Utf8 => NativeString::AppendUtf8,
+            StringType::LargeUtf8 => NativeString::AppendLargeUtf8,
+        };
+        self.call_string(op, &[builder, ptr, len]);
+    }
+
+    fn call_string_predicate(&mut self, op: NativeString, lhs: Value, rhs: Value) -> Value {
+        let (lhs_ptr, lhs_len) = self.string_parts(lhs);
+        let (rhs_ptr, rhs_len) = self.string_parts(rhs);
+        self.call_string(op, &[lhs_ptr, lhs_len, rhs_ptr, rhs_len])
+            .unwrap()
+    }
+
+    // whether the string of `len` bytes at `ptr` is long enough for `bytes`, and holds them
+    // at the address `start` computes. Bytes are only loaded from long enough strings.
+    fn match_at<F>(&mut self, ptr: Value, len: Value, bytes: &[u8], start: F) -> Value
+    where
+        F: FnOnce(&mut Self, Value, Value) -> Value,
+    {
+        if bytes.is_empty() {
+            return self.builder.ins().iconst(types::I8, 1);
+        }
+        let long_enough =
+            self.builder
+                .ins()
+                .icmp_imm(IntCC::SignedGreaterThanOrEqual, len, bytes.len() as i64);
+        let matched = self.branch(
+            long_enough,
+            types::I8,
+            |ctx| {
+                let addr = start(ctx, ptr, len);
+                GenValue::non_null(ctx.bytes_equal(addr, bytes))
+            },
+            |ctx| GenValue::non_null(ctx.builder.ins().iconst(types::I8, 0)),
+        );
+        matched.value
+    }
+
+    // whether memory at `addr` holds `bytes`, compared in the widest loads that fit.
+    fn bytes_equal(&mut self, addr: Value, bytes: &[u8]) -> Value {
+        let mut equal = None;
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let width = [8, 4, 2, 1]
+                .into_iter()
+                .find(|width| offset + width <= bytes.len())
+                .unwrap();
+            let chunk = &bytes[offset..offset + width];
+            // what a load of the chunk reads on this target, which is the host.
+            let expected = match width {
+                8 => u64::from_ne_bytes(chunk.try_into().unwrap()),
+                4 => u32::from_ne_bytes(chunk.try_into().unwrap()) as u64,
+                2 => u16::from_ne_bytes(chunk.try_into().unwrap()) as u64,
+                _ => chunk[0] as u64,
+            };
+            let _type = Type::int_with_byte_size(width as u16).unwrap();
+            let loaded =
+                self.builder
+                    .ins()
+                    .load(_type, MemFlags::new().with_notrap(), addr, offset as i32);
+            let expected = self.int_const(_type, expected as i128);
+            let chunk_equal = self.builder.ins().icmp(IntCC::Equal, loaded, expected);
+            equal = Some(match equal {
+                Some(equal) => self.builder.ins().band(equal, chunk_equal),
+                None => chunk_equal,
+            });
+            offset += width;
+        }
+        equal.unwrap()
+    }
+}
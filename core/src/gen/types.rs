@@ -0,0 +1,44 @@
+use arrow::datatypes::DataType;
+use common::{Result, ServerError};
+use cranelift::prelude::*;
+
+/// cranelift type used to hold one value of `data_type` in generated code.
+/// boolean values are represented as an i8 of 0 or 1, dates and timestamps as their
+/// integer offset from the epoch and decimals as their unscaled value.
+pub fn native_type(data_type: &DataType) -> Option<Type> {
+    match data_type {
+        DataType::Boolean | DataType::Int8 | DataType::UInt8 => Some(types::I8),
+        DataType::Int16 | DataType::UInt16 => Some(types::I16),
+        DataType::Int32 | DataType::UInt32 | DataType::Date32 => Some(types::I32),
+        DataType::Int64 | DataType::UInt64 | DataType::Date64 | DataType::Timestamp(_, _) => {
+            Some(types::I64)
+        }
+        DataType::Decimal128(_, _) => Some(types::I128),
+        DataType::Float32 => Some(types::F32),
+        DataType::Float64 => Some(types::F64),
+        _ => None,
+    }
+}
+
+/// like [`native_type`], failing for types generated code cannot hold.
+pub fn try_native_type(data_type: &DataType) -> Result<Type> {
+    native_type(data_type)
+        .ok_or_else(|| ServerError::NotSupported(format!("{} in generated code", data_type)))
+}
+
+/// cranelift type of a value of `data_type` in expression code: its [`native_type`], or
+/// for strings the view built by [`FuncGenContext::string_view`].
+///
+/// [`FuncGenContext::string_view`]: crate::gen::FuncGenContext::string_view
+pub fn gen_type(data_type: &DataType) -> Option<Type> {
+    match data_type {
+        DataType::Utf8 | DataType::LargeUtf8 => Some(types::I128),
+        _ => native_type(data_type),
+    }
+}
+
+/// like [`gen_type`], failing for types expression code cannot hold.
+pub fn try_gen_type(data_type: &DataType) -> Result<Type> {
+    gen_type(data_type)
+        .ok_or_else(|| ServerError::NotSupported(format!("{} in generated code", data_type)))
+}
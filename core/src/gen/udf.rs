@@ -0,0 +1,337 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, BooleanArray, PrimitiveArray, StringArray},
+    datatypes::{
+        DataType, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+};
+use common::{Result, ServerError};
+
+use crate::gen::{
+    clear_returned_strings, KernelErrorCode, NativeFunction, NativeResult, StringResult,
+};
+
+/// integer and pointer parameters every C ABI we target passes in registers, so a
+/// [`NativeStr`] is passed like its two fields.
+const MAX_INTEGER_PARAMS: usize = 6;
+
+/// A rust type a native function takes values of an arrow type as.
+pub trait NativeArg: Copy {
+    fn data_type() -> DataType;
+
+    /// the value at a non-null `row` of an array of [`NativeArg::data_type`].
+    fn from_array(array: &ArrayRef, row: usize) -> Self;
+}
+
+/// A rust type a native function returns values of an arrow type as.
+pub trait NativeReturn: Copy {
+    fn data_type() -> DataType;
+
+    /// the error code a function returning this stops the kernel with when it fails.
+    fn error() -> Option<KernelErrorCode> {
+        None
+    }
+
+    /// an array of one value per row, failing for the first failed value.
+    fn collect(values: Vec<Option<Self>>) -> Result<ArrayRef>;
+}
+
+/// Numbers and booleans, which a [`Fallible`] result can hold.
+pub trait NativePrimitive: NativeArg + NativeReturn {
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! native_primitive {
+    ($native:ty, $arrow:ty, $data_type:expr, $to_bits:expr, $from_bits:expr) => {
+        impl NativeArg for $native {
+            fn data_type() -> DataType {
+                $data_type
+            }
+
+            fn from_array(array: &ArrayRef, row: usize) -> Self {
+                array.as_primitive::<$arrow>().value(row)
+            }
+        }
+
+        impl NativeReturn for $native {
+            fn data_type() -> DataType {
+                $data_type
+            }
+
+            fn collect(values: Vec<Option<Self>>) -> Result<ArrayRef> {
+                Ok(Arc::new(PrimitiveArray::<$arrow>::from(values)))
+            }
+        }
+
+        impl NativePrimitive for $native {
+            fn to_bits(self) -> u64 {
+                $to_bits(self)
+            }
+
+            fn from_bits(bits: u64) -> Self {
+                $from_bits(bits)
+            }
+        }
+    };
+}
+
+native_primitive!(i8, Int8Type, DataType::Int8, |v| v as u64, |b| b as i8);
+native_primitive!(i16, Int16Type, DataType::Int16, |v| v as u64, |b| b as i16);
+native_primitive!(i32, Int32Type, DataType::Int32, |v| v as u64, |b| b as i32);
+native_primitive!(i64, Int64Type, DataType::Int64, |v| v as u64, |b| b as i64);
+native_primitive!(u8, UInt8Type, DataType::UInt8, |v| v as u64, |b| b as u8);
+native_primitive!(u16, UInt16Type, DataType::UInt16, |v| v as u64, |b| b
+    as u16);
+native_primitive!(u32, UInt32Type, DataType::UInt32, |v| v as u64, |b| b
+    as u32);
+native_primitive!(u64, UInt64Type, DataType::UInt64, |v| v, |b| b);
+native_primitive!(
+    f32,
+    Float32Type,
+    DataType::Float32,
+    |v: f32| v.to_bits() as u64,
+    |b| f32::from_bits(b as u32)
+);
+native_primitive!(
+    f64,
+    Float64Type,
+    DataType::Float64,
+    f64::to_bits,
+    f64::from_bits
+);
+
+impl NativeArg for bool {
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn from_array(array: &ArrayRef, row: usize) -> Self {
+        array.as_boolean().value(row)
+    }
+}
+
+impl NativeReturn for bool {
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn collect(values: Vec<Option<Self>>) -> Result<ArrayRef> {
+        Ok(Arc::new(BooleanArray::from(values)))
+    }
+}
+
+impl NativePrimitive for bool {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        bits & 1 == 1
+    }
+}
+
+/// A Utf8 argument of a native function: the address and length in bytes of a string
+/// that lives at least as long as the call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NativeStr {
+    ptr: *const u8,
+    len: i64,
+}
+
+impl NativeStr {
+    pub fn as_str(&self) -> &str {
+        if self.len == 0 {
+            return "";
+        }
+        // generated code and `from_array` only pass valid utf8.
+        unsafe {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.ptr, self.len as usize))
+        }
+    }
+}
+
+impl NativeArg for NativeStr {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn from_array(array: &ArrayRef, row: usize) -> Self {
+        let value = array.as_string::<i32>().value(row);
+        NativeStr {
+            ptr: value.as_ptr(),
+            len: value.len() as i64,
+        }
+    }
+}
+
+impl NativeReturn for StringResult {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn collect(values: Vec<Option<Self>>) -> Result<ArrayRef> {
+        let strings: StringArray = values
+            .iter()
+            .map(|value| {
+                value.map(|value| {
+                    let bytes = if value.len == 0 {
+                        &[][..]
+                    } else {
+                        unsafe { std::slice::from_raw_parts(value.ptr, value.len as usize) }
+                    };
+                    // native functions only return valid utf8.
+                    unsafe { std::str::from_utf8_unchecked(bytes) }
+                })
+            })
+            .collect();
+        // the array holds copies of the strings.
+        clear_returned_strings();
+        Ok(Arc::new(strings))
+    }
+}
+
+/// The result of a native function that can fail, which stops the kernel with
+/// [`KernelErrorCode::InvalidArgument`] when it does.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug)]
+pub struct Fallible<T> {
+    result: NativeResult,
+    _type: PhantomData<T>,
+}
+
+impl<T: NativePrimitive> Fallible<T> {
+    pub fn ok(value: T) -> Self {
+        Self {
+            result: NativeResult::ok(value.to_bits()),
+            _type: PhantomData,
+        }
+    }
+
+    pub fn failed() -> Self {
+        Self {
+            result: NativeResult::FAILED,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn value(&self) -> Option<T> {
+        (self.result.failed == 0).then(|| T::from_bits(self.result.bits))
+    }
+}
+
+impl<T: NativePrimitive> NativeReturn for Fallible<T> {
+    fn data_type() -> DataType {
+        <T as NativeReturn>::data_type()
+    }
+
+    fn error() -> Option<KernelErrorCode> {
+        Some(KernelErrorCode::InvalidArgument)
+    }
+
+    fn collect(values: Vec<Option<Self>>) -> Result<ArrayRef> {
+        let values = values
+            .into_iter()
+            .enumerate()
+            .map(|(row, value)| match value {
+                Some(value) => value
+                    .value()
+                    .map(Some)
+                    .ok_or(ServerError::InvalidArgument { row }),
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        T::collect(values)
+    }
+}
+
+/// An `extern "C" fn` generated code can call, whose signature is given by the rust
+/// types of its arguments and result.
+pub trait TypedNativeFn: Copy + Send + Sync + 'static {
+    fn arg_types() -> Vec<DataType>;
+
+    fn return_type() -> DataType;
+
+    fn error() -> Option<KernelErrorCode>;
+
+    fn address(self) -> *const u8;
+
+    /// call the function for every row of `args`, arrays of [`TypedNativeFn::arg_types`]
+    /// the function isn't called for rows where any of them is null, which are null in
+    /// the result.
+    fn eval(self, args: &[ArrayRef]) -> Result<ArrayRef>;
+}
+
+macro_rules! typed_native_fn {
+    ($($arg:ident),+) => {
+        impl<R: NativeReturn + 'static, $($arg: NativeArg + 'static),+> TypedNativeFn
+            for extern "C" fn($($arg),+) -> R
+        {
+            fn arg_types() -> Vec<DataType> {
+                vec![$($arg::data_type()),+]
+            }
+
+            fn return_type() -> DataType {
+                R::data_type()
+            }
+
+            fn error() -> Option<KernelErrorCode> {
+                R::error()
+            }
+
+            fn address(self) -> *const u8 {
+                self as *const u8
+            }
+
+            fn eval(self, args: &[ArrayRef]) -> Result<ArrayRef> {
+                let len = args[0].len();
+                let values = (0..len)
+                    .map(|row| {
+                        if args.iter().any(|arg| arg.is_null(row)) {
+                            return None;
+                        }
+                        let mut args = args.iter();
+                        Some(self($($arg::from_array(args.next().unwrap(), row)),+))
+                    })
+                    .collect();
+                R::collect(values)
+            }
+        }
+    };
+}
+
+typed_native_fn!(A);
+typed_native_fn!(A, B);
+typed_native_fn!(A, B, C);
+typed_native_fn!(A, B, C, D);
+
+impl NativeFunction {
+    /// the native function `func`, typed by its rust signature, e.g.
+    /// `geohash as extern "C" fn(f64, f64) -> i64`.
+    pub fn typed<F: TypedNativeFn>(name: impl Into<String>, func: F) -> Result<Self> {
+        let name = name.into();
+        let arg_types = F::arg_types();
+        let integer_params: usize = arg_types
+            .iter()
+            .map(|data_type| match data_type {
+                DataType::Utf8 => 2,
+                DataType::Float32 | DataType::Float64 => 0,
+                _ => 1,
+            })
+            .sum();
+        if integer_params > MAX_INTEGER_PARAMS {
+            return Err(ServerError::NotSupported(format!(
+                "native function {} with more than {} integer and string parameters",
+                name, MAX_INTEGER_PARAMS
+            )));
+        }
+        let func = NativeFunction::try_new(name, func.address(), arg_types, F::return_type())?;
+        match F::error() {
+            Some(code) => func.with_error(code),
+            None => Ok(func),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::sync::Arc;
+
+use common::{Result, ServerError};
+
+use crate::gen::{KernelErrorCode, NativeFunction};
+use crate::ir::{
+    BinaryOp, CastKind, Cond, Const, Function, Inst, Op, Region, ScalarType, Type, UnaryOp, Value,
+};
+
+/// Builds a [`Function`] one instruction at a time, appending to the innermost region
+/// being built. Result types follow from the operands, [`Function::verify`] checks
+/// they're used consistently.
+pub struct Builder {
+    func: Function,
+    // regions being built, the body of the function first.
+    regions: Vec<Region>,
+    // true branches of the ifs whose false branch is being built.
+    branches: Vec<Region>,
+}
+
+impl Builder {
+    /// a function reading input columns of `columns` lane types.
+    pub fn new(name: impl Into<String>, columns: Vec<Type>) -> Self {
+        Self {
+            func: Function {
+                name: name.into(),
+                columns,
+                outputs: vec![],
+                types: vec![],
+                body: Region::default(),
+            },
+            regions: vec![Region::default()],
+            branches: vec![],
+        }
+    }
+
+    /// add an output column of lane type `_type`, giving its slot.
+    pub fn add_output(&mut self, _type: Type) -> usize {
+        self.func.outputs.push(_type);
+        self.func.outputs.len() - 1
+    }
+
+    /// the function, which yields `rows` as the number of rows it wrote.
+    pub fn finish(mut self, rows: Value) -> Function {
+        let mut body = self
+            .regions
+            .pop()
+            .expect("the function body is always open");
+        assert!(
+            self.regions.is_empty() && self.branches.is_empty(),
+            "a nested region is still open"
+        );
+        body.yields = vec![rows];
+        self.func.body = body;
+        self.func
+    }
+
+    pub fn value_type(&self, value: Value) -> Type {
+        self.func.value_type(value)
+    }
+
+    fn push(&mut self, op: Op, result_types: &[Type]) -> Vec<Value> {
+        let results: Vec<Value> = result_types
+            .iter()
+            .map(|_type| self.func.add_value(*_type))
+            .collect();
+        let region = self.regions.last_mut().unwrap();
+        region.insts.push(Inst {
+            results: results.clone(),
+            op,
+        });
+        results
+    }
+
+    fn push_value(&mut self, op: Op, _type: Type) -> Value {
+        self.push(op, &[_type])[0]
+    }
+
+    /// an integer or boolean constant, truncated to `_type`.
+    pub fn iconst(&mut self, _type: Type, value: i64) -> Value {
+        self.push_value(Op::Const(Const::Int(normalize(value, _type))), _type)
+    }
+
+    pub fn fconst(&mut self, _type: Type, value: f64) -> Value {
+        self.push_value(Op::Const(Const::float(value)), _type)
+    }
+
+    pub fn len(&mut self) -> Value {
+        self.push_value(Op::Len, Type::I64)
+    }
+
+    /// row `row` of input column `slot`, or the `lanes` rows from it.
+    pub fn column(&mut self, slot: usize, row: Value, lanes: u32) -> Value {
+        let _type = self.func.columns[slot].with_lanes(lanes);
+        self.push_value(Op::Column { slot, row }, _type)
+    }
+
+    pub fn column_valid(&mut self, slot: usize, row: Value) -> Value {
+        self.push_value(Op::ColumnValid { slot, row }, Type::BOOL)
+    }
+
+    pub fn store(&mut self, slot: usize, row: Value, value: Value) {
+        self.push(Op::Store { slot, row, value }, &[]);
+    }
+
+    pub fn store_valid(&mut self, slot: usize, row: Value, valid: Value) {
+        self.push(Op::StoreValid { slot, row, valid }, &[]);
+    }
+
+    pub fn unary(&mut self, op: UnaryOp, value: Value) -> Value {
+        let _type = self.value_type(value);
+        self.push_value(Op::Unary(op, value), _type)
+    }
+
+    pub fn not(&mut self, value: Value) -> Value {
+        self.unary(UnaryOp::Not, value)
+    }
+
+    pub fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        let _type = self.value_type(lhs);
+        self.push_value(Op::Binary(op, lhs, rhs), _type)
+    }
+
+    pub fn and(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::And, lhs, rhs)
+    }
+
+    pub fn or(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Or, lhs, rhs)
+    }
+
+    pub fn overflows(&mut self, op: BinaryOp, signed: bool, lhs: Value, rhs: Value) -> Value {
+        let _type = self.mask_type(lhs);
+        let op = Op::Overflows {
+            op,
+            signed,
+            lhs,
+            rhs,
+        };
+        self.push_value(op, _type)
+    }
+
+    pub fn compare(&mut self, cond: Cond, lhs: Value, rhs: Value) -> Value {
+        let _type = self.mask_type(lhs);
+        self.push_value(Op::Compare(cond, lhs, rhs), _type)
+    }
+
+    // a boolean, or a mask with a lane for every lane of `value`.
+    fn mask_type(&self, value: Value) -> Type {
+        Type::BOOL.with_lanes(self.value_type(value).lanes)
+    }
+
+    pub fn select(&mut self, cond: Value, then: Value, otherwise: Value) -> Value {
+        let _type = self.value_type(then);
+        let op = Op::Select {
+            cond,
+            then,
+            otherwise,
+        };
+        self.push_value(op, _type)
+    }
+
+    pub fn cast(&mut self, kind: CastKind, value: Value, to: Type) -> Value {
+        self.push_value(Op::Cast(kind, value), to)
+    }
+
+    pub fn splat(&mut self, value: Value, lanes: u32) -> Value {
+        let _type = self.value_type(value).with_lanes(lanes);
+        self.push_value(Op::Splat(value), _type)
+    }
+
+    pub fn any_true(&mut self, mask: Value) -> Value {
+        self.push_value(Op::AnyTrue(mask), Type::BOOL)
+    }
+
+    pub fn all_true(&mut self, mask: Value) -> Value {
+        self.push_value(Op::AllTrue(mask), Type::BOOL)
+    }
+
+    /// call `func`, which must take and return numbers or booleans.
+    pub fn call(&mut self, func: &Arc<NativeFunction>, args: &[Value]) -> Result<Value> {
+        let not_supported =
+            || ServerError::NotSupported(format!("calling {} from the query ir", func.name()));
+        let _type = Type::from_data_type(func.return_type()).ok_or_else(not_supported)?;
+        if func
+            .arg_types()
+            .iter()
+            .any(|data_type| Type::from_data_type(data_type).is_none())
+        {
+            return Err(not_supported());
+        }
+        let op = Op::Call {
+            func: func.clone(),
+            args: args.to_vec(),
+        };
+        Ok(self.push_value(op, _type))
+    }
+
+    /// stop the kernel with `code` for `row` when `cond` is true.
+    pub fn fail(&mut self, code: KernelErrorCode, cond: Value, row: Value) {
+        self.push(Op::Fail { code, cond, row }, &[]);
+    }
+
+    /// start building the body of a loop carrying values like `carried`, giving its row
+    /// and carried parameters. Instructions go to the body until [`Builder::end_loop`].
+    pub fn begin_loop(&mut self, carried: &[Value]) -> (Value, Vec<Value>) {
+        let row = self.func.add_value(Type::I64);
+        let params: Vec<Value> = carried
+            .iter()
+            .map(|value| {
+                let _type = self.value_type(*value);
+                self.func.add_value(_type)
+            })
+            .collect();
+        self.regions.push(Region {
+            params: [row].into_iter().chain(params.iter().copied()).collect(),
+            ..Region::default()
+        });
+        (row, params)
+    }
+
+    /// finish the loop body begun last, which yields the next carried values, and loop
+    /// over rows `start..end`, `step` at a time. Gives the first row not run and the
+    /// final carried values.
+    pub fn end_loop(
+        &mut self,
+        start: Value,
+        end: Value,
+        step: u32,
+        carried: &[Value],
+        yields: Vec<Value>,
+    ) -> (Value, Vec<Value>) {
+        let mut body = self.regions.pop().expect("no loop body is being built");
+        body.yields = yields;
+        let result_types: Vec<Type> = body
+            .params
+            .iter()
+            .map(|value| self.value_type(*value))
+            .collect();
+        let op = Op::Loop {
+            start,
+            end,
+            step,
+            carried: carried.to_vec(),
+            body,
+        };
+        let results = self.push(op, &result_types);
+        (results[0], results[1..].to_vec())
+    }
+
+    /// [`Builder::begin_loop`] and [`Builder::end_loop`] with the body built by `body`,
+    /// given the row and the carried values, which returns their next values.
+    pub fn build_loop(
+        &mut self,
+        start: Value,
+        end: Value,
+        step: u32,
+        carried: &[Value],
+        body: impl FnOnce(&mut Self, Value, &[Value]) -> Vec<Value>,
+    ) -> (Value, Vec<Value>) {
+        let (row, params) = self.begin_loop(carried);
+        let yields = body(self, row, &params);
+        self.end_loop(start, end, step, carried, yields)
+    }
+
+    /// start building the branch run when the condition of an `if` is true, until
+    /// [`Builder::begin_else`].
+    pub fn begin_if(&mut self) {
+        self.regions.push(Region::default());
+    }
+
+    /// finish the true branch, which yields `yields`, and start building the false one.
+    pub fn begin_else(&mut self, yields: Vec<Value>) {
+        let mut then = self.regions.pop().expect("no if is being built");
+        then.yields = yields;
+        self.branches.push(then);
+        self.regions.push(Region::default());
+    }
+
+    /// finish the false branch, which yields values of the same types as the true one,
+    /// giving the values yielded by the branch `cond` chose.
+    pub fn end_if(&mut self, cond: Value, yields: Vec<Value>) -> Vec<Value> {
+        let mut otherwise = self.regions.pop().expect("no if is being built");
+        otherwise.yields = yields;
+        let then = self.branches.pop().expect("no if is being built");
+        let result_types: Vec<Type> = then
+            .yields
+            .iter()
+            .map(|value| self.value_type(*value))
+            .collect();
+        let op = Op::If {
+            cond,
+            then,
+            otherwise,
+        };
+        self.push(op, &result_types)
+    }
+
+    /// an `if` with branches built by `then` and `otherwise`, which return the values
+    /// they yield.
+    pub fn build_if(
+        &mut self,
+        cond: Value,
+        then: impl FnOnce(&mut Self) -> Vec<Value>,
+        otherwise: impl FnOnce(&mut Self) -> Vec<Value>,
+    ) -> Vec<Value> {
+        self.begin_if();
+        let yields = then(self);
+        self.begin_else(yields);
+        let yields = otherwise(self);
+        self.end_if(cond, yields)
+    }
+}
+
+/// `value` truncated to a lane of `_type` and sign extended back, how integer
+/// constants are held.
+pub(crate) fn normalize(value: i64, _type: Type) -> i64 {
+    match _type.scalar {
+        ScalarType::Bool => value & 1,
+        ScalarType::I8 => value as i8 as i64,
+        ScalarType::I16 => value as i16 as i64,
+        ScalarType::I32 => value as i32 as i64,
+        _ => value,
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+
+use arrow::datatypes::DataType;
+use common::{Result, ServerError};
+use cranelift::prelude::*;
+
+use crate::gen::{ArithOp, CodegenContext, FuncGenContext, FuncRegister};
+use crate::ir::{self, BinaryOp, CastKind, Cond, Const, Function, Op, Region, ScalarType, UnaryOp};
+use crate::jit::code::JitCode;
+use crate::jit::native_opcall::{NativeKind, NativeOp};
+
+/// compile `func` with cranelift into a kernel called like the ones
+/// [`compile_llvm_kernel`] compiles: (column pointers, validity bitmap pointers, output
+/// pointers, output validity pointers, error record, row count) -> rows written, or -1
+/// after writing the error record. Fails with [`ServerError::NotSupported`] for vector
+/// operations cranelift doesn't lower.
+///
+/// [`compile_llvm_kernel`]: crate::compile_llvm_kernel
+pub fn compile_ir_kernel(func: &Function) -> Result<JitCode> {
+    func.verify()?;
+    let mut builder = CodegenContext::builder();
+    for native in func.calls() {
+        builder = builder.try_register_func(FuncRegister::from(native.as_ref()))?;
+    }
+    let mut ctx = builder.finish();
+    let ptype = ctx.ptype();
+    let mut func_ctx = ctx.create_func_gen_ctx(
+        &func.name,
+        vec![
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(types::I64),
+        ],
+        vec![AbiParam::new(types::I64)],
+    )?;
+
+    let entry_block = func_ctx.builder.create_block();
+    func_ctx.builder.switch_to_block(entry_block);
+    func_ctx
+        .builder
+        .append_block_params_for_function_params(entry_block);
+    let params = func_ctx.builder.block_params(entry_block).to_vec();
+    func_ctx.bind_error_record(params[4]);
+    // failing outside of a loop reports the first row.
+    let first_row = func_ctx.builder.ins().iconst(types::I64, 0);
+    func_ctx.bind_row(first_row);
+    let mut lowering = Lowering {
+        func,
+        values: HashMap::new(),
+        len: params[5],
+        column_ptrs: func_ctx.load_ptrs(params[0], func.columns.len()),
+        validity_ptrs: func_ctx.load_ptrs(params[1], func.columns.len()),
+        output_ptrs: func_ctx.load_ptrs(params[2], func.outputs.len()),
+        output_validity_ptrs: func_ctx.load_ptrs(params[3], func.outputs.len()),
+    };
+    let rows = lowering.region(&mut func_ctx, &func.body, &[])?;
+    let func_id = func_ctx.finalize(&rows)?;
+    ctx.finalize_code(func_id)
+}
+
+fn not_supported(what: &str, _type: ir::Type) -> ServerError {
+    ServerError::NotSupported(format!("{} of {} with cranelift", what, _type))
+}
+
+// cranelift type of values of `_type`, booleans as an i8 of 0 or 1. Masks take the type
+// of the comparison making them, so they have none of their own.
+fn cranelift_type(_type: ir::Type) -> Type {
+    let lane = match _type.scalar {
+        ScalarType::Bool | ScalarType::I8 => types::I8,
+        ScalarType::I16 => types::I16,
+        ScalarType::I32 => types::I32,
+        ScalarType::I64 => types::I64,
+        ScalarType::F32 => types::F32,
+        ScalarType::F64 => types::F64,
+    };
+    lane.by(_type.lanes).unwrap()
+}
+
+// the arrow type of integer lanes of `_type`, for helpers that take one.
+fn int_data_type(_type: ir::Type, signed: bool) -> DataType {
+    match (_type.scalar.bits(), signed) {
+        (8, true) => DataType::Int8,
+        (16, true) => DataType::Int16,
+        (32, true) => DataType::Int32,
+        (64, true) => DataType::Int64,
+        (8, false) => DataType::UInt8,
+        (16, false) => DataType::UInt16,
+        (32, false) => DataType::UInt32,
+        _ => DataType::UInt64,
+    }
+}
+
+struct Lowering<'a> {
+    func: &'a Function,
+    values: HashMap<ir::Value, Value>,
+    len: Value,
+    column_ptrs: Vec<Value>,
+    validity_ptrs: Vec<Value>,
+    output_ptrs: Vec<Value>,
+    output_validity_ptrs: Vec<Value>,
+}
+
+impl<'a> Lowering<'a> {
+    fn value(&self, value: ir::Value) -> Value {
+        self.values[&value]
+    }
+
+    fn type_of(&self, value: ir::Value) -> ir::Type {
+        self.func.value_type(value)
+    }
+
+    // lower the instructions of `region`, given values for its parameters, giving the
+    // values it yields.
+    fn region(
+        &mut self,
+        ctx: &mut FuncGenContext,
+        region: &Region,
+        params: &[Value],
+    ) -> Result<Vec<Value>> {
+        for (param, value) in region.params.iter().zip(params) {
+            self.values.insert(*param, *value);
+        }
+        for inst in &region.insts {
+            let results = self.inst(ctx, &inst.op, &inst.results)?;
+            for (result, value) in inst.results.iter().zip(results) {
+                self.values.insert(*result, value);
+            }
+        }
+        Ok(region
+            .yields
+            .iter()
+            .map(|value| self.value(*value))
+            .collect())
+    }
+
+    fn inst(
+        &mut self,
+        ctx: &mut FuncGenContext,
+        op: &Op,
+        results: &[ir::Value],
+    ) -> Result<Vec<Value>> {
+        let result_type = results.first().map(|value| self.type_of(*value));
+        let value = match op {
+            Op::Const(value) => {
+                let _type = result_type.unwrap();
+                match (value, _type.scalar) {
+                    (Const::Float(_), ScalarType::F32) => {
+                        let value = value.as_float().unwrap();
+                        ctx.builder.ins().f32const(value as f32)
+                    }
+                    (Const::Float(_), _) => ctx.builder.ins().f64const(value.as_float().unwrap()),
+                    (Const::Int(value), _) => ctx.int_const(cranelift_type(_type), *value as i128),
+                }
+            }
+            Op::Len => self.len,
+            Op::Column { slot, row } => {
+                let (_type, ptr, row) = (
+                    result_type.unwrap(),
+                    self.column_ptrs[*slot],
+                    self.value(*row),
+                );
+                if _type.is_bool() {
+                    ctx.load_bit(ptr, row)
+                } else {
+                    let _type = cranelift_type(_type);
+                    let addr = ctx.element_addr(ptr, row, _type.lane_type().bytes());
+                    ctx.builder.ins().load(_type, MemFlags::trusted(), addr, 0)
+                }
+            }
+            Op::ColumnValid { slot, row } => {
+                let row = self.value(*row);
+                ctx.load_bit(self.validity_ptrs[*slot], row)
+            }
+            Op::Store { slot, row, value } => {
+                let (ptr, row, value) = (
+                    self.output_ptrs[*slot],
+                    self.value(*row),
+                    self.value(*value),
+                );
+                let _type = ctx.builder.func.dfg.value_type(value);
+                let addr = ctx.element_addr(ptr, row, _type.lane_type().bytes());
+                ctx.builder.ins().store(MemFlags::trusted(), value, addr, 0);
+                return Ok(vec![]);
+            }
+            Op::StoreValid { slot, row, valid } => {
+                let ptr = self.output_validity_ptrs[*slot];
+                ctx.store_element(self.value(*valid), ptr, self.value(*row));
+                return Ok(vec![]);
+            }
+            Op::Unary(op, value) => {
+                let (_type, value) = (self.type_of(*value), self.value(*value));
+                match op {
+                    UnaryOp::Not if _type == ir::Type::BOOL => ctx.builder.ins().bxor_imm(value, 1),
+                    UnaryOp::Not => ctx.builder.ins().bnot(value),
+                    UnaryOp::Neg if _type.is_float() => ctx.builder.ins().fneg(value),
+                    UnaryOp::Neg => ctx.builder.ins().ineg(value),
+                }
+            }
+            Op::Binary(op, lhs, rhs) => self.binary(ctx, *op, *lhs, *rhs)?,
+            Op::Overflows {
+                op,
+                signed,
+                lhs,
+                rhs,
+            } => {
+                let _type = self.type_of(*lhs);
+                if _type.is_vector() {
+                    return Err(not_supported("overflow checks", _type));
+                }
+                let (lhs, rhs) = (self.value(*lhs), self.value(*rhs));
+                let (op, wrapped) = match op {
+                    BinaryOp::Add => (ArithOp::Add, ctx.builder.ins().iadd(lhs, rhs)),
+                    BinaryOp::Sub => (ArithOp::Sub, ctx.builder.ins().isub(lhs, rhs)),
+                    _ => (ArithOp::Mul, ctx.builder.ins().imul(lhs, rhs)),
+                };
+                ctx.overflows(op, &int_data_type(_type, *signed), lhs, rhs, wrapped)
+            }
+            Op::Compare(cond, lhs, rhs) => {
+                let _type = self.type_of(*lhs);
+                let (mut lhs, mut rhs) = (self.value(*lhs), self.value(*rhs));
+                if _type.is_float() {
+                    if _type.is_vector() {
+                        return Err(not_supported("comparisons", _type));
+                    }
+                    lhs = ctx.total_order_key(lhs);
+                    rhs = ctx.total_order_key(rhs);
+                }
+                let cond = match cond {
+                    Cond::Eq => IntCC::Equal,
+                    Cond::Ne => IntCC::NotEqual,
+                    Cond::SLt => IntCC::SignedLessThan,
+                    Cond::SLe => IntCC::SignedLessThanOrEqual,
+                    Cond::SGt => IntCC::SignedGreaterThan,
+                    Cond::SGe => IntCC::SignedGreaterThanOrEqual,
+                    Cond::ULt => IntCC::UnsignedLessThan,
+                    Cond::ULe => IntCC::UnsignedLessThanOrEqual,
+                    Cond::UGt => IntCC::UnsignedGreaterThan,
+                    Cond::UGe => IntCC::UnsignedGreaterThanOrEqual,
+                };
+                ctx.builder.ins().icmp(cond, lhs, rhs)
+            }
+            Op::Select {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let cond_type = self.type_of(*cond);
+                let (cond, then, otherwise) =
+                    (self.value(*cond), self.value(*then), self.value(*otherwise));
+                if !cond_type.is_vector() {
+                    ctx.builder.ins().select(cond, then, otherwise)
+                } else if ctx.builder.func.dfg.value_type(cond)
+                    == ctx.builder.func.dfg.value_type(then)
+                {
+                    // comparisons of vectors give all ones or zeros in every lane.
+                    ctx.builder.ins().bitselect(cond, then, otherwise)
+                } else {
+                    return Err(not_supported("selecting by a mask", result_type.unwrap()));
+                }
+            }
+            Op::Cast(kind, value) => {
+                let from = self.type_of(*value);
+                let to = result_type.unwrap();
+                if from.is_vector() {
+                    return Err(not_supported("casts", from));
+                }
+                let (value, _type) = (self.value(*value), cranelift_type(to));
+                match kind {
+                    _ if cranelift_type(from) == _type => value,
+                    CastKind::SExtend => ctx.builder.ins().sextend(_type, value),
+                    CastKind::UExtend => ctx.builder.ins().uextend(_type, value),
+                    CastKind::Reduce => ctx.builder.ins().ireduce(_type, value),
+                    CastKind::SIntToFloat => ctx.builder.ins().fcvt_from_sint(_type, value),
+                    CastKind::UIntToFloat => ctx.builder.ins().fcvt_from_uint(_type, value),
+                    CastKind::FloatPromote => ctx.builder.ins().fpromote(_type, value),
+                    CastKind::FloatDemote => ctx.builder.ins().fdemote(_type, value),
+                }
+            }
+            Op::Splat(value) => {
+                let _type = cranelift_type(result_type.unwrap());
+                ctx.builder.ins().splat(_type, self.value(*value))
+            }
+            Op::AnyTrue(mask) => ctx.builder.ins().vany_true(self.value(*mask)),
+            Op::AllTrue(mask) => ctx.builder.ins().vall_true(self.value(*mask)),
+            Op::Call { func, args } => {
+                let args: Vec<Value> = args.iter().map(|arg| self.value(*arg)).collect();
+                ctx.call_function(func, &args, None).value
+            }
+            Op::Fail { code, cond, row } => {
+                let (cond, row) = (self.value(*cond), self.value(*row));
+                let outer_row = ctx.row;
+                ctx.bind_row(row);
+                ctx.error_if(cond, *code);
+                ctx.row = outer_row;
+                return Ok(vec![]);
+            }
+            Op::Loop {
+                start,
+                end,
+                step,
+                carried,
+                body,
+            } => return self.lower_loop(ctx, *start, *end, *step, carried, body),
+            Op::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let cond = self.value(*cond);
+                let then_block = ctx.builder.create_block();
+                let else_block = ctx.builder.create_block();
+                let merge_block = ctx.builder.create_block();
+                for result in results {
+                    let _type = cranelift_type(self.type_of(*result));
+                    ctx.builder.append_block_param(merge_block, _type);
+                }
+                ctx.builder
+                    .ins()
+                    .brif(cond, then_block, &[], else_block, &[]);
+                for (block, region) in [(then_block, then), (else_block, otherwise)] {
+                    ctx.builder.switch_to_block(block);
+                    let yields = self.region(ctx, region, &[])?;
+                    ctx.builder.ins().jump(merge_block, &yields);
+                }
+                ctx.builder.switch_to_block(merge_block);
+                return Ok(ctx.builder.block_params(merge_block).to_vec());
+            }
+        };
+        Ok(vec![value])
+    }
+
+    fn binary(
+        &mut self,
+        ctx: &mut FuncGenContext,
+        op: BinaryOp,
+        lhs: ir::Value,
+        rhs: ir::Value,
+    ) -> Result<Value> {
+        let _type = self.type_of(lhs);
+        let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+        let ins = ctx.builder.ins();
+        let value = match op {
+            BinaryOp::Add if _type.is_float() => ins.fadd(lhs, rhs),
+            BinaryOp::Sub if _type.is_float() => ins.fsub(lhs, rhs),
+            BinaryOp::Mul if _type.is_float() => ins.fmul(lhs, rhs),
+            BinaryOp::Add => ins.iadd(lhs, rhs),
+            BinaryOp::Sub => ins.isub(lhs, rhs),
+            BinaryOp::Mul => ins.imul(lhs, rhs),
+            BinaryOp::SDiv => ins.sdiv(lhs, rhs),
+            BinaryOp::UDiv => ins.udiv(lhs, rhs),
+            BinaryOp::SRem => ins.srem(lhs, rhs),
+            BinaryOp::URem => ins.urem(lhs, rhs),
+            BinaryOp::FDiv => ins.fdiv(lhs, rhs),
+            BinaryOp::And => ins.band(lhs, rhs),
+            BinaryOp::Or => ins.bor(lhs, rhs),
+            BinaryOp::Xor => ins.bxor(lhs, rhs),
+            _ if _type.is_vector() => return Err(not_supported("shifts and remainders", _type)),
+            BinaryOp::Shl => ins.ishl(lhs, rhs),
+            BinaryOp::SShr => ins.sshr(lhs, rhs),
+            BinaryOp::UShr => ins.ushr(lhs, rhs),
+            BinaryOp::FRem => {
+                // cranelift has no float remainder.
+                let data_type = match _type.scalar {
+                    ScalarType::F32 => DataType::Float32,
+                    _ => DataType::Float64,
+                };
+                let kind = NativeKind::from_data_type(&data_type).unwrap();
+                ctx.call_native(NativeOp::ModWrapping, kind, lhs, rhs)
+            }
+        };
+        Ok(value)
+    }
+
+    // the loop as a header block checking the bound and a body block jumping back to it,
+    // carrying the row and the carried values as parameters of the header.
+    fn lower_loop(
+        &mut self,
+        ctx: &mut FuncGenContext,
+        start: ir::Value,
+        end: ir::Value,
+        step: u32,
+        carried: &[ir::Value],
+        body: &Region,
+    ) -> Result<Vec<Value>> {
+        let header_block = ctx.builder.create_block();
+        let body_block = ctx.builder.create_block();
+        let exit_block = ctx.builder.create_block();
+        ctx.builder.append_block_param(header_block, types::I64);
+        for value in carried {
+            let _type = cranelift_type(self.type_of(*value));
+            ctx.builder.append_block_param(header_block, _type);
+        }
+        let inits: Vec<Value> = [start]
+            .iter()
+            .chain(carried)
+            .map(|value| self.value(*value))
+            .collect();
+        ctx.builder.ins().jump(header_block, &inits);
+
+        ctx.builder.switch_to_block(header_block);
+        let params = ctx.builder.block_params(header_block).to_vec();
+        let (row, end) = (params[0], self.value(end));
+        let last = ctx.builder.ins().iadd_imm(row, step as i64);
+        let more = ctx
+            .builder
+            .ins()
+            .icmp(IntCC::SignedLessThanOrEqual, last, end);
+        ctx.builder
+            .ins()
+            .brif(more, body_block, &[], exit_block, &[]);
+
+        ctx.builder.switch_to_block(body_block);
+        let outer_row = ctx.row;
+        ctx.bind_row(row);
+        let yields = self.region(ctx, body, &params)?;
+        ctx.row = outer_row;
+        let next: Vec<Value> = [last].into_iter().chain(yields).collect();
+        ctx.builder.ins().jump(header_block, &next);
+
+        ctx.builder.switch_to_block(exit_block);
+        Ok(params)
+    }
+}
@@ -0,0 +1,520 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::gen::{KernelErrorCode, NativeFunction};
+use crate::ir::Type;
+
+/// An SSA value of a [`Function`], defined once by an instruction or as a region
+/// parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub(crate) u32);
+
+impl Value {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+/// A constant lane. Integers are held sign extended from their type's width, floats as
+/// the bits of an f64, so that equal constants compare equal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Const {
+    Int(i64),
+    Float(u64),
+}
+
+impl Const {
+    pub fn float(value: f64) -> Self {
+        Const::Float(value.to_bits())
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Const::Float(bits) => Some(f64::from_bits(*bits)),
+            Const::Int(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// logical not of booleans and masks, bitwise not of integers.
+    Not,
+    Neg,
+}
+
+/// Lane-wise binary operations, both operands and the result have the same type.
+/// Divisions and remainders of integers are undefined for zero divisors and signed
+/// `MIN / -1`, whoever emits them guards against both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    FDiv,
+    FRem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    SShr,
+    UShr,
+}
+
+/// Comparison conditions. Floats compare in total order with the signed conditions,
+/// so NaNs are equal to themselves and greater than everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Cond {
+    Eq,
+    Ne,
+    SLt,
+    SLe,
+    SGt,
+    SGe,
+    ULt,
+    ULe,
+    UGt,
+    UGe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CastKind {
+    SExtend,
+    UExtend,
+    Reduce,
+    SIntToFloat,
+    UIntToFloat,
+    FloatPromote,
+    FloatDemote,
+}
+
+/// A sequence of instructions, run with values for its parameters, that passes its
+/// `yields` to the instruction owning it. Values defined in a region are visible in the
+/// regions nested in it, but not after it.
+#[derive(Clone, Debug, Default)]
+pub struct Region {
+    pub params: Vec<Value>,
+    pub insts: Vec<Inst>,
+    pub yields: Vec<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Inst {
+    pub results: Vec<Value>,
+    pub op: Op,
+}
+
+#[derive(Clone, Debug)]
+pub enum Op {
+    /// the constant lane of the result's type.
+    Const(Const),
+    /// number of input rows of the kernel.
+    Len,
+    /// row `row` of input column `slot`, or the `lanes` rows starting at `row` for a
+    /// vector result. Boolean columns are read one row at a time.
+    Column {
+        slot: usize,
+        row: Value,
+    },
+    /// whether row `row` of input column `slot` isn't null.
+    ColumnValid {
+        slot: usize,
+        row: Value,
+    },
+    /// write `value` to row `row` of output `slot`, or `lanes` rows for a vector.
+    Store {
+        slot: usize,
+        row: Value,
+        value: Value,
+    },
+    /// write whether row `row` of output `slot` isn't null.
+    StoreValid {
+        slot: usize,
+        row: Value,
+        valid: Value,
+    },
+    Unary(UnaryOp, Value),
+    Binary(BinaryOp, Value, Value),
+    /// whether the integer `lhs op rhs` doesn't fit its type, for `Add`, `Sub` and
+    /// `Mul` of signed or unsigned operands.
+    Overflows {
+        op: BinaryOp,
+        signed: bool,
+        lhs: Value,
+        rhs: Value,
+    },
+    Compare(Cond, Value, Value),
+    /// `then` where `cond` is true, else `otherwise`, lane by lane for masks.
+    Select {
+        cond: Value,
+        then: Value,
+        otherwise: Value,
+    },
+    /// convert to the result's type.
+    Cast(CastKind, Value),
+    /// a vector of the result's type with every lane set to the scalar.
+    Splat(Value),
+    /// whether any lane of a mask is true.
+    AnyTrue(Value),
+    /// whether every lane of a mask is true.
+    AllTrue(Value),
+    /// call a native function taking and returning numbers or booleans, which stops the
+    /// kernel if the function can fail and does.
+    Call {
+        func: Arc<NativeFunction>,
+        args: Vec<Value>,
+    },
+    /// stop the kernel with `code`, reporting `row`, when `cond` is true.
+    Fail {
+        code: KernelErrorCode,
+        cond: Value,
+        row: Value,
+    },
+    /// run `body` with the row and the carried values for rows `start`, `start + step`,
+    /// ... as long as `step` rows are left before `end`, carrying what it yields to the
+    /// next iteration. Results are the first row not run and the final carried values.
+    Loop {
+        start: Value,
+        end: Value,
+        step: u32,
+        carried: Vec<Value>,
+        body: Region,
+    },
+    /// run `then` if `cond` is true, else `otherwise`, giving what the region run yields.
+    If {
+        cond: Value,
+        then: Region,
+        otherwise: Region,
+    },
+}
+
+impl Op {
+    /// values the instruction reads, besides those of its regions.
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Op::Const(_) | Op::Len => vec![],
+            Op::Column { row, .. } | Op::ColumnValid { row, .. } => vec![*row],
+            Op::Store { row, value, .. } => vec![*row, *value],
+            Op::StoreValid { row, valid, .. } => vec![*row, *valid],
+            Op::Unary(_, value)
+            | Op::Cast(_, value)
+            | Op::Splat(value)
+            | Op::AnyTrue(value)
+            | Op::AllTrue(value) => vec![*value],
+            Op::Binary(_, lhs, rhs) | Op::Overflows { lhs, rhs, .. } | Op::Compare(_, lhs, rhs) => {
+                vec![*lhs, *rhs]
+            }
+            Op::Select {
+                cond,
+                then,
+                otherwise,
+            } => vec![*cond, *then, *otherwise],
+            Op::Call { args, .. } => args.clone(),
+            Op::Fail { cond, row, .. } => vec![*cond, *row],
+            Op::Loop {
+                start,
+                end,
+                carried,
+                ..
+            } => [*start, *end].into_iter().chain(carried.clone()).collect(),
+            Op::If { cond, .. } => vec![*cond],
+        }
+    }
+
+    /// replace every operand, besides those of its regions, by `f` of it.
+    pub fn map_operands(&mut self, mut f: impl FnMut(Value) -> Value) {
+        match self {
+            Op::Const(_) | Op::Len => {}
+            Op::Column { row, .. } | Op::ColumnValid { row, .. } => *row = f(*row),
+            Op::Store { row, value, .. } => {
+                *row = f(*row);
+                *value = f(*value);
+            }
+            Op::StoreValid { row, valid, .. } => {
+                *row = f(*row);
+                *valid = f(*valid);
+            }
+            Op::Unary(_, value)
+            | Op::Cast(_, value)
+            | Op::Splat(value)
+            | Op::AnyTrue(value)
+            | Op::AllTrue(value) => *value = f(*value),
+            Op::Binary(_, lhs, rhs) | Op::Overflows { lhs, rhs, .. } | Op::Compare(_, lhs, rhs) => {
+                *lhs = f(*lhs);
+                *rhs = f(*rhs);
+            }
+            Op::Select {
+                cond,
+                then,
+                otherwise,
+            } => {
+                *cond = f(*cond);
+                *then = f(*then);
+                *otherwise = f(*otherwise);
+            }
+            Op::Call { args, .. } => args.iter_mut().for_each(|arg| *arg = f(*arg)),
+            Op::Fail { cond, row, .. } => {
+                *cond = f(*cond);
+                *row = f(*row);
+            }
+            Op::Loop {
+                start,
+                end,
+                carried,
+                ..
+            } => {
+                *start = f(*start);
+                *end = f(*end);
+                carried.iter_mut().for_each(|value| *value = f(*value));
+            }
+            Op::If { cond, .. } => *cond = f(*cond),
+        }
+    }
+
+    pub fn regions(&self) -> Vec<&Region> {
+        match self {
+            Op::Loop { body, .. } => vec![body],
+            Op::If {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            _ => vec![],
+        }
+    }
+
+    pub fn regions_mut(&mut self) -> Vec<&mut Region> {
+        match self {
+            Op::Loop { body, .. } => vec![body],
+            Op::If {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            _ => vec![],
+        }
+    }
+
+    /// whether the instruction only computes its results, so it can be removed when
+    /// they aren't used or merged with an identical one.
+    pub fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Op::Store { .. }
+                | Op::StoreValid { .. }
+                | Op::Call { .. }
+                | Op::Fail { .. }
+                | Op::Loop { .. }
+                | Op::If { .. }
+        )
+    }
+
+    /// whether running the instruction, or any in its regions, does more than computing
+    /// its results.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Op::Loop { .. } | Op::If { .. } => self
+                .regions()
+                .into_iter()
+                .flat_map(|region| &region.insts)
+                .any(|inst| inst.op.has_side_effects()),
+            _ => !self.is_pure(),
+        }
+    }
+}
+
+/// A kernel in the query IR: it reads input columns and writes output columns, one
+/// row or one vector of rows at a time, and yields the number of rows written.
+/// Booleans are read from bitmaps, but written one byte per row like validity.
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    /// lane type of every input column.
+    pub columns: Vec<Type>,
+    /// lane type of every output column.
+    pub outputs: Vec<Type>,
+    /// type of every value, by its index.
+    pub(crate) types: Vec<Type>,
+    pub body: Region,
+}
+
+impl Function {
+    pub fn value_type(&self, value: Value) -> Type {
+        self.types[value.index()]
+    }
+
+    pub(crate) fn add_value(&mut self, _type: Type) -> Value {
+        self.types.push(_type);
+        Value(self.types.len() as u32 - 1)
+    }
+
+    /// whether the kernel writes the validity of output `slot`, so it can be null.
+    pub fn writes_validity(&self, slot: usize) -> bool {
+        fn find(region: &Region, slot: usize) -> bool {
+            region.insts.iter().any(|inst| match &inst.op {
+                Op::StoreValid { slot: stored, .. } => *stored == slot,
+                op => op.regions().into_iter().any(|region| find(region, slot)),
+            })
+        }
+        find(&self.body, slot)
+    }
+
+    /// the native functions the kernel calls.
+    pub fn calls(&self) -> Vec<Arc<NativeFunction>> {
+        fn find(region: &Region, funcs: &mut Vec<Arc<NativeFunction>>) {
+            for inst in &region.insts {
+                if let Op::Call { func, .. } = &inst.op {
+                    funcs.push(func.clone());
+                }
+                inst.op
+                    .regions()
+                    .into_iter()
+                    .for_each(|region| find(region, funcs));
+            }
+        }
+        let mut funcs = vec![];
+        find(&self.body, &mut funcs);
+        funcs
+    }
+}
+
+// lower case name of an operation, e.g. `sdiv` for `BinaryOp::SDiv`.
+fn name(op: &impl fmt::Debug) -> String {
+    format!("{:?}", op).to_lowercase()
+}
+
+fn write_list(
+    f: &mut fmt::Formatter,
+    items: impl IntoIterator<Item = impl fmt::Display>,
+) -> fmt::Result {
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Function {
+    fn write_region(&self, f: &mut fmt::Formatter, region: &Region, depth: usize) -> fmt::Result {
+        for inst in &region.insts {
+            self.write_inst(f, inst, depth)?;
+        }
+        write!(f, "{:1$}yield ", "", depth * 4)?;
+        write_list(f, &region.yields)?;
+        writeln!(f)
+    }
+
+    fn write_inst(&self, f: &mut fmt::Formatter, inst: &Inst, depth: usize) -> fmt::Result {
+        let indent = depth * 4;
+        write!(f, "{:1$}", "", indent)?;
+        if !inst.results.is_empty() {
+            write_list(f, &inst.results)?;
+            write!(f, " = ")?;
+        }
+        let result_type = inst.results.first().map(|value| self.value_type(*value));
+        let suffix = result_type
+            .map(|_type| format!(".{}", _type))
+            .unwrap_or_default();
+        match &inst.op {
+            Op::Const(Const::Int(value)) => write!(f, "const{} {}", suffix, value)?,
+            Op::Const(Const::Float(bits)) => {
+                write!(f, "const{} {:?}", suffix, f64::from_bits(*bits))?
+            }
+            Op::Len => write!(f, "len")?,
+            Op::Column { slot, row } => write!(f, "column{} {}[{}]", suffix, slot, row)?,
+            Op::ColumnValid { slot, row } => write!(f, "column_valid {}[{}]", slot, row)?,
+            Op::Store { slot, row, value } => write!(f, "store {}[{}], {}", slot, row, value)?,
+            Op::StoreValid { slot, row, valid } => {
+                write!(f, "store_valid {}[{}], {}", slot, row, valid)?
+            }
+            Op::Unary(op, value) => write!(f, "{}{} {}", name(op), suffix, value)?,
+            Op::Binary(op, lhs, rhs) => write!(f, "{}{} {}, {}", name(op), suffix, lhs, rhs)?,
+            Op::Overflows {
+                op,
+                signed,
+                lhs,
+                rhs,
+            } => {
+                let sign = if *signed { "s" } else { "u" };
+                write!(f, "overflows.{}{} {}, {}", sign, name(op), lhs, rhs)?
+            }
+            Op::Compare(cond, lhs, rhs) => write!(f, "compare.{} {}, {}", name(cond), lhs, rhs)?,
+            Op::Select {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "select{} {}, {}, {}", suffix, cond, then, otherwise)?,
+            Op::Cast(kind, value) => write!(f, "{}{} {}", name(kind), suffix, value)?,
+            Op::Splat(value) => write!(f, "splat{} {}", suffix, value)?,
+            Op::AnyTrue(value) => write!(f, "any_true {}", value)?,
+            Op::AllTrue(value) => write!(f, "all_true {}", value)?,
+            Op::Call { func, args } => {
+                write!(f, "call{} {}(", suffix, func.name())?;
+                write_list(f, args)?;
+                write!(f, ")")?;
+            }
+            Op::Fail { code, cond, row } => write!(f, "fail.{} {}, {}", name(code), cond, row)?,
+            Op::Loop {
+                start,
+                end,
+                step,
+                carried,
+                body,
+            } => {
+                write!(f, "loop {}..{} step {} (", start, end, step)?;
+                write_list(f, carried)?;
+                write!(f, ") |")?;
+                write_list(f, &body.params)?;
+                writeln!(f, "| {{")?;
+                self.write_region(f, body, depth + 1)?;
+                write!(f, "{:1$}}}", "", indent)?;
+            }
+            Op::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                writeln!(f, "if {} {{", cond)?;
+                self.write_region(f, then, depth + 1)?;
+                writeln!(f, "{:1$}}} else {{", "", indent)?;
+                self.write_region(f, otherwise, depth + 1)?;
+                write!(f, "{:1$}}}", "", indent)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+/// prints the kernel like
+///
+/// ```text
+/// function add(i32, i32) -> (i32) {
+///     v0 = len
+///     v1 = const.i64 0
+///     v2 = loop v1..v0 step 1 () |v3| {
+///         v4 = column.i32 0[v3]
+///         ...
+///         yield
+///     }
+///     yield v0
+/// }
+/// ```
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "function {}(", self.name)?;
+        write_list(f, &self.columns)?;
+        write!(f, ") -> (")?;
+        write_list(f, &self.outputs)?;
+        writeln!(f, ") {{")?;
+        self.write_region(f, &self.body, 1)?;
+        writeln!(f, "}}")
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, SchemaRef};
+use common::{Result, ServerError};
+
+use crate::gen::{
+    can_gen_cast, cast_can_fail, ArithOp, ArithmeticMode, BackendGenValue, BackendValue,
+    CodegenBackend, CompareOp, ExprGen, KernelErrorCode,
+};
+use crate::ir::{BinaryOp, Builder, CastKind, Cond, Function, Type, UnaryOp, Value};
+
+const KERNEL_NAME: &str = "compiled_exprs";
+
+// ir values are indices, which fit a backend value as they are.
+fn to_backend(value: Value) -> BackendValue {
+    BackendValue(value.0)
+}
+
+fn from_backend(value: BackendValue) -> Value {
+    Value(value.0)
+}
+
+/// Lowers expressions into the function a [`Builder`] builds, through
+/// [`ExprGen::gen_backend`]. Values follow the conventions of [`CodegenBackend`], with
+/// booleans and validity as [`Type::BOOL`].
+pub struct IrGenContext {
+    pub builder: Builder,
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+    // column index in schema -> value read for the current row.
+    columns: HashMap<usize, BackendGenValue>,
+    row: Option<Value>,
+}
+
+impl IrGenContext {
+    pub fn new(builder: Builder, schema: SchemaRef, mode: ArithmeticMode) -> Self {
+        Self {
+            builder,
+            schema,
+            mode,
+            columns: HashMap::new(),
+            row: None,
+        }
+    }
+
+    pub fn bind_column(&mut self, index: usize, value: Value, valid: Option<Value>) {
+        let value = BackendGenValue::new(to_backend(value), valid.map(to_backend));
+        self.columns.insert(index, value);
+    }
+
+    /// the input row errors are reported for, which checked arithmetic needs.
+    pub fn bind_row(&mut self, row: Value) {
+        self.row = Some(row);
+    }
+
+    /// lower `expr`, giving its value and validity.
+    pub fn gen<E: ExprGen + ?Sized>(&mut self, expr: &E) -> Result<(Value, Option<Value>)> {
+        let result = expr
+            .gen_backend(self)
+            .ok_or_else(|| ServerError::NotSupported("expression in the query ir".to_string()))?;
+        Ok((from_backend(result.value), result.valid.map(from_backend)))
+    }
+
+    fn int_type(&self, data_type: &DataType) -> Option<Type> {
+        Type::from_data_type(data_type).filter(|_type| !_type.is_float())
+    }
+
+    // stop the kernel with `code` when `cond` holds for a row that isn't null.
+    fn fail_if(&mut self, cond: Value, valid: Option<Value>, code: KernelErrorCode) {
+        let row = self
+            .row
+            .expect("lowered code can only fail within a row, see `bind_row`");
+        let cond = match valid {
+            Some(valid) => self.builder.and(cond, valid),
+            None => cond,
+        };
+        self.builder.fail(code, cond, row);
+    }
+
+    fn and_valid(&mut self, lhs: Option<Value>, rhs: Option<Value>) -> Option<Value> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(self.builder.and(lhs, rhs)),
+            (Some(valid), None) | (None, Some(valid)) => Some(valid),
+            (None, None) => None,
+        }
+    }
+
+    fn valid_or_true(&mut self, valid: Option<Value>) -> Value {
+        match valid {
+            Some(valid) => valid,
+            None => self.builder.iconst(Type::BOOL, 1),
+        }
+    }
+
+    // `lhs / rhs`, or `lhs % rhs` when `rem`, like `FuncGenContext::div_rem`.
+    fn div_rem(
+        &mut self,
+        rem: bool,
+        data_type: &DataType,
+        lhs: Value,
+        rhs: Value,
+        valid: Option<Value>,
+    ) -> (Value, Option<Value>) {
+        let b = &mut self.builder;
+        let _type = b.value_type(rhs);
+        // floats follow IEEE 754, so zero divisors give inf or NaN in every mode.
+        if _type.is_float() {
+            let op = if rem { BinaryOp::FRem } else { BinaryOp::FDiv };
+            return (b.binary(op, lhs, rhs), valid);
+        }
+        let zero = b.iconst(_type, 0);
+        let is_zero = b.compare(Cond::Eq, rhs, zero);
+        let valid = match self.mode {
+            ArithmeticMode::Checked => {
+                self.fail_if(is_zero, valid, KernelErrorCode::DivideByZero);
+                valid
+            }
+            _ => {
+                let nonzero = self.builder.not(is_zero);
+                self.and_valid(valid, Some(nonzero))
+            }
+        };
+
+        let b = &mut self.builder;
+        // division by zero is undefined, so divide by one instead.
+        let one = b.iconst(_type, 1);
+        let rhs = b.select(is_zero, one, rhs);
+        if data_type.is_unsigned_integer() {
+            let op = if rem { BinaryOp::URem } else { BinaryOp::UDiv };
+            return (b.binary(op, lhs, rhs), valid);
+        }
+        // so is `MIN / -1`, which wraps to `MIN`.
+        let neg_one = b.iconst(_type, -1);
+        let is_neg_one = b.compare(Cond::Eq, rhs, neg_one);
+        let rhs = b.select(is_neg_one, one, rhs);
+        if rem {
+            return (b.binary(BinaryOp::SRem, lhs, rhs), valid);
+        }
+        let quotient = b.binary(BinaryOp::SDiv, lhs, rhs);
+        let negated = b.unary(UnaryOp::Neg, lhs);
+        let wrapped = b.select(is_neg_one, negated, quotient);
+        if self.mode == ArithmeticMode::Wrapping {
+            return (wrapped, valid);
+        }
+        let bits = _type.scalar.bits();
+        let min = b.iconst(_type, i64::MIN >> (64 - bits));
+        let is_min = b.compare(Cond::Eq, lhs, min);
+        let overflow = b.and(is_min, is_neg_one);
+        let value = match self.mode {
+            ArithmeticMode::Checked => {
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                wrapped
+            }
+            _ => {
+                let max = self.builder.iconst(_type, i64::MAX >> (64 - bits));
+                self.builder.select(overflow, max, wrapped)
+            }
+        };
+        (value, valid)
+    }
+
+    // the bound an overflowing `lhs op rhs` saturates to.
+    fn saturated(&mut self, op: BinaryOp, signed: bool, lhs: Value, rhs: Value) -> Value {
+        let b = &mut self.builder;
+        let _type = b.value_type(lhs);
+        if !signed {
+            let bound = if op == BinaryOp::Sub { 0 } else { -1 };
+            return b.iconst(_type, bound);
+        }
+        // sums overflow towards the sign of lhs, products towards the sign of the product.
+        let sign = match op {
+            BinaryOp::Mul => b.binary(BinaryOp::Xor, lhs, rhs),
+            _ => lhs,
+        };
+        let zero = b.iconst(_type, 0);
+        let negative = b.compare(Cond::SLt, sign, zero);
+        let bits = _type.scalar.bits();
+        let min = b.iconst(_type, i64::MIN >> (64 - bits));
+        let max = b.iconst(_type, i64::MAX >> (64 - bits));
+        b.select(negative, min, max)
+    }
+}
+
+impl CodegenBackend for IrGenContext {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.mode
+    }
+
+    fn column(&self, index: usize) -> Option<BackendGenValue> {
+        self.columns.get(&index).copied()
+    }
+
+    fn int_const(&mut self, data_type: &DataType, value: i128) -> Option<BackendValue> {
+        let _type = self.int_type(data_type)?;
+        Some(to_backend(self.builder.iconst(_type, value as i64)))
+    }
+
+    fn float_const(&mut self, data_type: &DataType, value: f64) -> Option<BackendValue> {
+        let _type = Type::from_data_type(data_type).filter(Type::is_float)?;
+        let value = match _type {
+            Type::F32 => value as f32 as f64,
+            _ => value,
+        };
+        Some(to_backend(self.builder.fconst(_type, value)))
+    }
+
+    fn arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: BackendGenValue,
+        rhs: BackendGenValue,
+    ) -> Option<BackendGenValue> {
+        let _type = Type::from_data_type(data_type).filter(|_type| !_type.is_bool())?;
+        let valid = self.and_valid(lhs.valid.map(from_backend), rhs.valid.map(from_backend));
+        let (lhs, rhs) = (from_backend(lhs.value), from_backend(rhs.value));
+        let op = match op {
+            ArithOp::Add => BinaryOp::Add,
+            ArithOp::Sub => BinaryOp::Sub,
+            ArithOp::Mul => BinaryOp::Mul,
+            ArithOp::Div | ArithOp::Mod => {
+                let (value, valid) = self.div_rem(op == ArithOp::Mod, data_type, lhs, rhs, valid);
+                return Some(BackendGenValue::new(
+                    to_backend(value),
+                    valid.map(to_backend),
+                ));
+            }
+        };
+        let wrapped = self.builder.binary(op, lhs, rhs);
+        let signed = !data_type.is_unsigned_integer();
+        let value = match self.mode {
+            _ if _type.is_float() => wrapped,
+            ArithmeticMode::Wrapping => wrapped,
+            ArithmeticMode::Checked => {
+                let overflow = self.builder.overflows(op, signed, lhs, rhs);
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                wrapped
+            }
+            ArithmeticMode::Saturating => {
+                let overflow = self.builder.overflows(op, signed, lhs, rhs);
+                let bound = self.saturated(op, signed, lhs, rhs);
+                self.builder.select(overflow, bound, wrapped)
+            }
+        };
+        Some(BackendGenValue::new(
+            to_backend(value),
+            valid.map(to_backend),
+        ))
+    }
+
+    fn compare(
+        &mut self,
+        op: CompareOp,
+        data_type: &DataType,
+        lhs: BackendValue,
+        rhs: BackendValue,
+    ) -> Option<BackendValue> {
+        Type::from_data_type(data_type)?;
+        let signed = data_type.is_floating()
+            || (data_type != &DataType::Boolean && !data_type.is_unsigned_integer());
+        let cond = match (op, signed) {
+            (CompareOp::Eq, _) => Cond::Eq,
+            (CompareOp::NotEq, _) => Cond::Ne,
+            (CompareOp::Lt, true) => Cond::SLt,
+            (CompareOp::Lt, false) => Cond::ULt,
+            (CompareOp::LtEq, true) => Cond::SLe,
+            (CompareOp::LtEq, false) => Cond::ULe,
+            (CompareOp::Gt, true) => Cond::SGt,
+            (CompareOp::Gt, false) => Cond::UGt,
+            (CompareOp::GtEq, true) => Cond::SGe,
+            (CompareOp::GtEq, false) => Cond::UGe,
+        };
+        let value = self
+            .builder
+            .compare(cond, from_backend(lhs), from_backend(rhs));
+        Some(to_backend(value))
+    }
+
+    fn cast(
+        &mut self,
+        value: BackendValue,
+        from: &DataType,
+        to: &DataType,
+    ) -> Option<BackendValue> {
+        if from == to {
+            return Some(value);
+        }
+        if !can_gen_cast(from, to) || cast_can_fail(from, to) {
+            return None;
+        }
+        // dates and timestamps convert to each other by scaling, which isn't supported.
+        if from.is_temporal() && to.is_temporal() {
+            return None;
+        }
+        let (from_type, to_type) = (Type::from_data_type(from)?, Type::from_data_type(to)?);
+        let value = from_backend(value);
+        let signed = !from.is_unsigned_integer();
+        let is_int = |_type: Type| _type.is_int();
+        let kind = match (is_int(from_type), is_int(to_type)) {
+            (true, true) if from_type == to_type => return Some(to_backend(value)),
+            (true, true) if from_type.scalar.bits() > to_type.scalar.bits() => return None,
+            (true, true) if signed => CastKind::SExtend,
+            (true, true) => CastKind::UExtend,
+            (true, false) if to_type.is_float() && signed => CastKind::SIntToFloat,
+            (true, false) if to_type.is_float() => CastKind::UIntToFloat,
+            (false, false) if from_type.is_float() && to_type.is_float() => {
+                match (from_type, to_type) {
+                    (Type::F32, Type::F64) => CastKind::FloatPromote,
+                    _ => CastKind::FloatDemote,
+                }
+            }
+            _ => return None,
+        };
+        Some(to_backend(self.builder.cast(kind, value, to_type)))
+    }
+
+    fn and_valid(
+        &mut self,
+        lhs: Option<BackendValue>,
+        rhs: Option<BackendValue>,
+    ) -> Option<BackendValue> {
+        IrGenContext::and_valid(self, lhs.map(from_backend), rhs.map(from_backend)).map(to_backend)
+    }
+
+    fn kleene_not(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let value = self.builder.not(from_backend(input.value));
+        BackendGenValue::new(to_backend(value), input.valid)
+    }
+
+    fn kleene_and(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs_value, rhs_value) = (from_backend(lhs.value), from_backend(rhs.value));
+        let value = self.builder.and(lhs_value, rhs_value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return BackendGenValue::non_null(to_backend(value));
+        }
+        let lhs_false = self.builder.not(lhs_value);
+        let rhs_false = self.builder.not(rhs_value);
+        let valid = self.kleene_valid(lhs.valid, lhs_false, rhs.valid, rhs_false);
+        BackendGenValue::new(to_backend(value), Some(to_backend(valid)))
+    }
+
+    fn kleene_or(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs_value, rhs_value) = (from_backend(lhs.value), from_backend(rhs.value));
+        let value = self.builder.or(lhs_value, rhs_value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return BackendGenValue::non_null(to_backend(value));
+        }
+        let valid = self.kleene_valid(lhs.valid, lhs_value, rhs.valid, rhs_value);
+        BackendGenValue::new(to_backend(value), Some(to_backend(valid)))
+    }
+
+    fn is_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let value = match input.valid {
+            Some(valid) => self.builder.not(from_backend(valid)),
+            None => self.builder.iconst(Type::BOOL, 0),
+        };
+        BackendGenValue::non_null(to_backend(value))
+    }
+
+    fn is_not_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let value = self.valid_or_true(input.valid.map(from_backend));
+        BackendGenValue::non_null(to_backend(value))
+    }
+}
+
+impl IrGenContext {
+    // the result is valid when both sides are valid, or when one valid side decides it.
+    fn kleene_valid(
+        &mut self,
+        lhs_valid: Option<BackendValue>,
+        lhs_decides: Value,
+        rhs_valid: Option<BackendValue>,
+        rhs_decides: Value,
+    ) -> Value {
+        let lhs_valid = self.valid_or_true(lhs_valid.map(from_backend));
+        let rhs_valid = self.valid_or_true(rhs_valid.map(from_backend));
+        let b = &mut self.builder;
+        let both = b.and(lhs_valid, rhs_valid);
+        let by_lhs = b.and(lhs_valid, lhs_decides);
+        let by_rhs = b.and(rhs_valid, rhs_decides);
+        let valid = b.or(both, by_lhs);
+        b.or(valid, by_rhs)
+    }
+}
+
+/// lower `exprs` over `columns` of `schema` into a kernel called like the ones
+/// [`compile_llvm_kernel`] compiles, one row at a time. With a predicate, `exprs` are
+/// only computed for rows where it's true and written densely. Fails with
+/// [`ServerError::NotSupported`] for expressions [`ExprGen::gen_backend`] doesn't lower.
+///
+/// [`compile_llvm_kernel`]: crate::compile_llvm_kernel
+pub fn lower_kernel<E: ExprGen + ?Sized>(
+    predicate: Option<&E>,
+    exprs: &[Arc<E>],
+    schema: SchemaRef,
+    columns: &[usize],
+    mode: ArithmeticMode,
+) -> Result<Function> {
+    let mut column_types = vec![];
+    for index in columns {
+        let data_type = schema.field(*index).data_type();
+        let _type = Type::from_data_type(data_type).ok_or_else(|| {
+            ServerError::NotSupported(format!("{} columns in the query ir", data_type))
+        })?;
+        column_types.push(_type);
+    }
+    let mut ctx = IrGenContext::new(
+        Builder::new(KERNEL_NAME, column_types),
+        schema.clone(),
+        mode,
+    );
+    let len = ctx.builder.len();
+    let zero = ctx.builder.iconst(Type::I64, 0);
+    // the next output row is carried when rows can be dropped.
+    let carried = if predicate.is_some() {
+        vec![zero]
+    } else {
+        vec![]
+    };
+
+    let (row, params) = ctx.builder.begin_loop(&carried);
+    ctx.bind_row(row);
+    for (slot, index) in columns.iter().enumerate() {
+        let value = ctx.builder.column(slot, row, 1);
+        let valid = schema
+            .field(*index)
+            .is_nullable()
+            .then(|| ctx.builder.column_valid(slot, row));
+        ctx.bind_column(*index, value, valid);
+    }
+    let yields = match predicate {
+        None => {
+            store_outputs(&mut ctx, exprs, row)?;
+            vec![]
+        }
+        Some(predicate) => {
+            let (keep, valid) = ctx.gen(predicate)?;
+            // rows where the predicate is null are dropped like false ones.
+            let keep = match valid {
+                Some(valid) => ctx.builder.and(keep, valid),
+                None => keep,
+            };
+            let out_row = params[0];
+            ctx.builder.begin_if();
+            store_outputs(&mut ctx, exprs, out_row)?;
+            let one = ctx.builder.iconst(Type::I64, 1);
+            let next_out = ctx.builder.binary(BinaryOp::Add, out_row, one);
+            ctx.builder.begin_else(vec![next_out]);
+            ctx.builder.end_if(keep, vec![out_row])
+        }
+    };
+    let (_, carried) = ctx.builder.end_loop(zero, len, 1, &carried, yields);
+    let rows = carried.first().copied().unwrap_or(len);
+    let func = ctx.builder.finish(rows);
+    func.verify()?;
+    Ok(func)
+}
+
+fn store_outputs<E: ExprGen + ?Sized>(
+    ctx: &mut IrGenContext,
+    exprs: &[Arc<E>],
+    out_row: Value,
+) -> Result<()> {
+    for expr in exprs {
+        let (value, valid) = ctx.gen(expr.as_ref())?;
+        let slot = ctx.builder.add_output(ctx.builder.value_type(value));
+        ctx.builder.store(slot, out_row, value);
+        if let Some(valid) = valid {
+            ctx.builder.store_valid(slot, out_row, valid);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+//! A typed SSA query IR between expressions and code generators.
+//!
+//! Expressions lower into a [`Function`] through [`IrGenContext`], which implements
+//! [`CodegenBackend`](crate::gen::CodegenBackend). Passes simplify the function with no
+//! machine code involved, and [`compile_ir_kernel`] lowers it to cranelift. Loops and
+//! branches are instructions owning regions, like structured control flow in MLIR, so
+//! passes walk a tree instead of a control flow graph.
+//!
+//! The IR is one backend among others, chosen with [`Backend::Ir`](crate::Backend::Ir).
+//! Kernels compiled with the default [`Backend::Cranelift`](crate::Backend::Cranelift)
+//! are still generated by [`ExprGen::gen`](crate::ExprGen::gen) straight from the
+//! expressions, as are operator kernels such as hashing and aggregation. Only what
+//! [`CodegenBackend`](crate::gen::CodegenBackend) covers lowers into the IR, expressions
+//! beyond it, such as strings, fail to compile with this backend.
+
+mod builder;
+mod cranelift;
+mod function;
+mod lower;
+mod pass;
+mod types;
+mod verify;
+
+pub use self::cranelift::compile_ir_kernel;
+pub use builder::Builder;
+pub use function::*;
+pub use lower::{lower_kernel, IrGenContext};
+pub use pass::*;
+pub use types::*;
@@ -0,0 +1,642 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use crate::ir::builder::normalize;
+use crate::ir::{
+    BinaryOp, CastKind, Cond, Const, Function, Inst, Op, Region, ScalarType, Type, UnaryOp, Value,
+};
+
+/// run every pass, simplifying the function without changing what it computes.
+pub fn optimize(func: &mut Function) {
+    fold_constants(func);
+    eliminate_common_subexprs(func);
+    hoist_loop_invariants(func);
+    eliminate_dead_code(func);
+}
+
+// replaces uses of values by the values they were found equal to.
+#[derive(Default)]
+struct Substitution(HashMap<Value, Value>);
+
+impl Substitution {
+    fn resolve(&self, value: Value) -> Value {
+        self.0.get(&value).copied().unwrap_or(value)
+    }
+
+    fn apply(&self, inst: &mut Inst) {
+        inst.op.map_operands(|value| self.resolve(value));
+    }
+
+    fn apply_yields(&self, region: &mut Region) {
+        for value in &mut region.yields {
+            *value = self.resolve(*value);
+        }
+    }
+}
+
+/// compute instructions whose operands are constants, drop failures that can't happen,
+/// inline branches whose condition is known and skip operations with an identity
+/// operand, like `x + 0`.
+pub fn fold_constants(func: &mut Function) {
+    let mut body = mem::take(&mut func.body);
+    let mut folder = ConstantFolder {
+        types: &func.types,
+        consts: HashMap::new(),
+        subst: Substitution::default(),
+    };
+    folder.region(&mut body);
+    func.body = body;
+}
+
+enum Folded {
+    Const(Const),
+    Value(Value),
+    Remove,
+    Inline(Region),
+}
+
+struct ConstantFolder<'a> {
+    types: &'a [Type],
+    consts: HashMap<Value, Const>,
+    subst: Substitution,
+}
+
+impl<'a> ConstantFolder<'a> {
+    fn region(&mut self, region: &mut Region) {
+        let insts = mem::take(&mut region.insts);
+        self.insts(insts, &mut region.insts);
+        self.subst.apply_yields(region);
+    }
+
+    fn insts(&mut self, insts: Vec<Inst>, out: &mut Vec<Inst>) {
+        for mut inst in insts {
+            self.subst.apply(&mut inst);
+            match self.fold(&inst) {
+                Some(Folded::Const(value)) => {
+                    self.consts.insert(inst.results[0], value);
+                    out.push(Inst {
+                        results: inst.results,
+                        op: Op::Const(value),
+                    });
+                }
+                Some(Folded::Value(value)) => {
+                    self.subst.0.insert(inst.results[0], value);
+                }
+                Some(Folded::Remove) => {}
+                Some(Folded::Inline(region)) => {
+                    self.insts(region.insts, out);
+                    for (result, value) in inst.results.iter().zip(region.yields) {
+                        let value = self.subst.resolve(value);
+                        self.subst.0.insert(*result, value);
+                    }
+                }
+                None => {
+                    if let Op::Const(value) = inst.op {
+                        self.consts.insert(inst.results[0], value);
+                    }
+                    for region in inst.op.regions_mut() {
+                        self.region(region);
+                    }
+                    out.push(inst);
+                }
+            }
+        }
+    }
+
+    fn int(&self, value: Value) -> Option<i64> {
+        match self.consts.get(&value) {
+            Some(Const::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn float(&self, value: Value) -> Option<f64> {
+        self.consts.get(&value).and_then(Const::as_float)
+    }
+
+    fn fold(&self, inst: &Inst) -> Option<Folded> {
+        let _type = inst.results.first().map(|value| self.types[value.index()]);
+        match &inst.op {
+            Op::Unary(op, value) => {
+                let _type = _type?;
+                if let Some(value) = self.int(*value) {
+                    let folded = match op {
+                        UnaryOp::Not if _type.is_bool() => 1 - value,
+                        UnaryOp::Not => !value,
+                        UnaryOp::Neg => value.wrapping_neg(),
+                    };
+                    return Some(Folded::Const(Const::Int(normalize(folded, _type))));
+                }
+                let value = self.float(*value)?;
+                Some(Folded::Const(float_const(_type, -value)))
+            }
+            Op::Binary(op, lhs, rhs) => self.fold_binary(*op, _type?, *lhs, *rhs),
+            Op::Overflows {
+                op,
+                signed,
+                lhs,
+                rhs,
+            } => {
+                let lhs_type = self.types[lhs.index()];
+                let (lhs, rhs) = (self.int(*lhs)?, self.int(*rhs)?);
+                let (lhs, rhs) = if *signed {
+                    (lhs as i128, rhs as i128)
+                } else {
+                    (
+                        unsigned(lhs, lhs_type) as i128,
+                        unsigned(rhs, lhs_type) as i128,
+                    )
+                };
+                let exact = match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    _ => lhs * rhs,
+                };
+                let bits = lhs_type.scalar.bits();
+                let fits = if *signed {
+                    exact >= -(1 << (bits - 1)) && exact < 1 << (bits - 1)
+                } else {
+                    exact >= 0 && exact < 1 << bits
+                };
+                Some(Folded::Const(Const::Int(!fits as i64)))
+            }
+            Op::Compare(cond, lhs, rhs) => {
+                let lhs_type = self.types[lhs.index()];
+                let ordering = match (self.int(*lhs), self.int(*rhs)) {
+                    (Some(lhs), Some(rhs)) if is_unsigned(*cond) => {
+                        unsigned(lhs, lhs_type).cmp(&unsigned(rhs, lhs_type))
+                    }
+                    (Some(lhs), Some(rhs)) => lhs.cmp(&rhs),
+                    _ if lhs_type.scalar == ScalarType::F32 => {
+                        let (lhs, rhs) = (self.float(*lhs)? as f32, self.float(*rhs)? as f32);
+                        lhs.total_cmp(&rhs)
+                    }
+                    _ => self.float(*lhs)?.total_cmp(&self.float(*rhs)?),
+                };
+                let holds = match cond {
+                    Cond::Eq => ordering == Ordering::Equal,
+                    Cond::Ne => ordering != Ordering::Equal,
+                    Cond::SLt | Cond::ULt => ordering == Ordering::Less,
+                    Cond::SLe | Cond::ULe => ordering != Ordering::Greater,
+                    Cond::SGt | Cond::UGt => ordering == Ordering::Greater,
+                    Cond::SGe | Cond::UGe => ordering != Ordering::Less,
+                };
+                Some(Folded::Const(Const::Int(holds as i64)))
+            }
+            Op::Select {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if then == otherwise {
+                    return Some(Folded::Value(*then));
+                }
+                match self.int(*cond)? {
+                    0 => Some(Folded::Value(*otherwise)),
+                    _ => Some(Folded::Value(*then)),
+                }
+            }
+            Op::Cast(kind, value) => {
+                let (from, to) = (self.types[value.index()], _type?);
+                let folded = match kind {
+                    CastKind::SExtend | CastKind::Reduce => {
+                        Const::Int(normalize(self.int(*value)?, to))
+                    }
+                    CastKind::UExtend => {
+                        Const::Int(normalize(unsigned(self.int(*value)?, from) as i64, to))
+                    }
+                    CastKind::SIntToFloat => {
+                        let value = self.int(*value)?;
+                        match to.scalar {
+                            ScalarType::F32 => Const::float(value as f32 as f64),
+                            _ => Const::float(value as f64),
+                        }
+                    }
+                    CastKind::UIntToFloat => {
+                        let value = unsigned(self.int(*value)?, from);
+                        match to.scalar {
+                            ScalarType::F32 => Const::float(value as f32 as f64),
+                            _ => Const::float(value as f64),
+                        }
+                    }
+                    // f32 constants already hold an f64 of the same value.
+                    CastKind::FloatPromote => Const::float(self.float(*value)?),
+                    CastKind::FloatDemote => float_const(to, self.float(*value)?),
+                };
+                Some(Folded::Const(folded))
+            }
+            Op::Fail { cond, .. } => match self.int(*cond)? {
+                0 => Some(Folded::Remove),
+                _ => None,
+            },
+            Op::If {
+                cond,
+                then,
+                otherwise,
+            } => match self.int(*cond)? {
+                0 => Some(Folded::Inline(otherwise.clone())),
+                _ => Some(Folded::Inline(then.clone())),
+            },
+            _ => None,
+        }
+    }
+
+    fn fold_binary(&self, op: BinaryOp, _type: Type, lhs: Value, rhs: Value) -> Option<Folded> {
+        if let (Some(l), Some(r)) = (self.float(lhs), self.float(rhs)) {
+            let value = match op {
+                BinaryOp::Add => l + r,
+                BinaryOp::Sub => l - r,
+                BinaryOp::Mul => l * r,
+                BinaryOp::FDiv => l / r,
+                BinaryOp::FRem => l % r,
+                _ => return None,
+            };
+            return Some(Folded::Const(float_const(_type, value)));
+        }
+        let (l, r) = match (self.int(lhs), self.int(rhs)) {
+            (Some(l), Some(r)) => (l, r),
+            // `x op c` or `c op x` that gives `x`, or `c` itself.
+            (None, Some(c)) => return self.identity(op, _type, lhs, rhs, c, false),
+            (Some(c), None) => return self.identity(op, _type, rhs, lhs, c, true),
+            (None, None) => return None,
+        };
+        let shift = (r as u32) & (_type.scalar.bits() - 1);
+        let value = match op {
+            BinaryOp::Add => l.wrapping_add(r),
+            BinaryOp::Sub => l.wrapping_sub(r),
+            BinaryOp::Mul => l.wrapping_mul(r),
+            // divisions stay as they are where they'd be undefined.
+            BinaryOp::SDiv => l.checked_div(r).filter(|_| !overflows_div(l, r, _type))?,
+            BinaryOp::SRem => l.checked_rem(r).filter(|_| !overflows_div(l, r, _type))?,
+            BinaryOp::UDiv => unsigned(l, _type).checked_div(unsigned(r, _type))? as i64,
+            BinaryOp::URem => unsigned(l, _type).checked_rem(unsigned(r, _type))? as i64,
+            BinaryOp::And => l & r,
+            BinaryOp::Or => l | r,
+            BinaryOp::Xor => l ^ r,
+            BinaryOp::Shl => l.wrapping_shl(shift),
+            BinaryOp::SShr => l >> shift,
+            BinaryOp::UShr => (unsigned(l, _type) >> shift) as i64,
+            BinaryOp::FDiv | BinaryOp::FRem => return None,
+        };
+        Some(Folded::Const(Const::Int(normalize(value, _type))))
+    }
+
+    // `value op c`, or `c op value` when `commuted`, if it's `value` or `c`.
+    fn identity(
+        &self,
+        op: BinaryOp,
+        _type: Type,
+        value: Value,
+        constant: Value,
+        c: i64,
+        commuted: bool,
+    ) -> Option<Folded> {
+        let all_ones = normalize(-1, _type);
+        match op {
+            BinaryOp::Add | BinaryOp::Or | BinaryOp::Xor if c == 0 => Some(Folded::Value(value)),
+            BinaryOp::Sub | BinaryOp::Shl | BinaryOp::SShr | BinaryOp::UShr
+                if c == 0 && !commuted =>
+            {
+                Some(Folded::Value(value))
+            }
+            BinaryOp::Mul if c == 1 => Some(Folded::Value(value)),
+            BinaryOp::And if c == all_ones => Some(Folded::Value(value)),
+            BinaryOp::And | BinaryOp::Mul if c == 0 => Some(Folded::Value(constant)),
+            BinaryOp::Or if c == all_ones => Some(Folded::Value(constant)),
+            _ => None,
+        }
+    }
+}
+
+// the lane of an integer constant of `_type` read as unsigned.
+fn unsigned(value: i64, _type: Type) -> u64 {
+    match _type.scalar.bits() {
+        64 => value as u64,
+        bits => value as u64 & ((1 << bits) - 1),
+    }
+}
+
+fn overflows_div(lhs: i64, rhs: i64, _type: Type) -> bool {
+    rhs == -1 && lhs == normalize(1 << (_type.scalar.bits() - 1), _type)
+}
+
+fn is_unsigned(cond: Cond) -> bool {
+    matches!(cond, Cond::ULt | Cond::ULe | Cond::UGt | Cond::UGe)
+}
+
+// `value` rounded to the precision of `_type`.
+fn float_const(_type: Type, value: f64) -> Const {
+    match _type.scalar {
+        ScalarType::F32 => Const::float(value as f32 as f64),
+        _ => Const::float(value),
+    }
+}
+
+/// replace pure instructions computing what an earlier one visible to them computes,
+/// and drop failures an earlier one already checked.
+pub fn eliminate_common_subexprs(func: &mut Function) {
+    let mut body = mem::take(&mut func.body);
+    let mut subst = Substitution::default();
+    let mut scopes = vec![];
+    value_number(func, &mut body, &mut scopes, &mut subst);
+    func.body = body;
+}
+
+fn value_number(
+    func: &Function,
+    region: &mut Region,
+    scopes: &mut Vec<HashMap<String, Option<Value>>>,
+    subst: &mut Substitution,
+) {
+    scopes.push(HashMap::new());
+    let insts = mem::take(&mut region.insts);
+    for mut inst in insts {
+        subst.apply(&mut inst);
+        // operands are already replaced, so equal instructions print the same.
+        let key = match &inst.op {
+            Op::Fail { .. } => Some(format!("{:?}", inst.op)),
+            op if op.is_pure() && inst.results.len() == 1 => {
+                let _type = func.value_type(inst.results[0]);
+                Some(format!("{:?}: {}", op, _type))
+            }
+            _ => None,
+        };
+        if let Some(key) = key {
+            match scopes.iter().rev().find_map(|scope| scope.get(&key)) {
+                Some(found) => {
+                    if let (Some(result), Some(found)) = (inst.results.first(), found) {
+                        subst.0.insert(*result, *found);
+                    }
+                    continue;
+                }
+                None => {
+                    let result = inst.results.first().copied();
+                    scopes.last_mut().unwrap().insert(key, result);
+                }
+            }
+        }
+        for nested in inst.op.regions_mut() {
+            value_number(func, nested, scopes, subst);
+        }
+        region.insts.push(inst);
+    }
+    subst.apply_yields(region);
+    scopes.pop();
+}
+
+/// move pure instructions of loop bodies that don't depend on the iteration before
+/// the loop. Column reads stay, as the loop may not run at all.
+pub fn hoist_loop_invariants(func: &mut Function) {
+    hoist_region(&mut func.body);
+}
+
+fn hoist_region(region: &mut Region) {
+    let insts = mem::take(&mut region.insts);
+    for mut inst in insts {
+        for nested in inst.op.regions_mut() {
+            hoist_region(nested);
+        }
+        if let Op::Loop { body, .. } = &mut inst.op {
+            let mut variant: HashSet<Value> = body.params.iter().copied().collect();
+            let body_insts = mem::take(&mut body.insts);
+            for body_inst in body_insts {
+                let invariant = body_inst.op.is_pure()
+                    && !matches!(body_inst.op, Op::Column { .. } | Op::ColumnValid { .. })
+                    && body_inst
+                        .op
+                        .operands()
+                        .iter()
+                        .all(|value| !variant.contains(value));
+                if invariant {
+                    region.insts.push(body_inst);
+                } else {
+                    variant.extend(&body_inst.results);
+                    body.insts.push(body_inst);
+                }
+            }
+        }
+        region.insts.push(inst);
+    }
+}
+
+/// remove instructions without side effects whose results aren't used.
+pub fn eliminate_dead_code(func: &mut Function) {
+    loop {
+        let mut used = HashSet::new();
+        collect_uses(&func.body, &mut used);
+        if !remove_unused(&mut func.body, &used) {
+            break;
+        }
+    }
+}
+
+fn collect_uses(region: &Region, used: &mut HashSet<Value>) {
+    used.extend(&region.yields);
+    for inst in &region.insts {
+        used.extend(inst.op.operands());
+        for nested in inst.op.regions() {
+            collect_uses(nested, used);
+        }
+    }
+}
+
+// whether any instruction was removed.
+fn remove_unused(region: &mut Region, used: &HashSet<Value>) -> bool {
+    let before = region.insts.len();
+    region.insts.retain(|inst| {
+        inst.op.has_side_effects() || inst.results.iter().any(|value| used.contains(value))
+    });
+    let mut removed = region.insts.len() < before;
+    for inst in &mut region.insts {
+        for nested in inst.op.regions_mut() {
+            removed |= remove_unused(nested, used);
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gen::KernelErrorCode;
+    use crate::ir::{BinaryOp, Builder, Cond, Function, Type};
+
+    use super::{
+        eliminate_common_subexprs, eliminate_dead_code, fold_constants, hoist_loop_invariants,
+        optimize,
+    };
+
+    // `out[row] = col[row] * (2 + 3) + col[row] * (2 + 3)` for every row, failing when
+    // the sum overflows, with an unused comparison and a failure that can't happen.
+    fn kernel() -> Function {
+        let mut b = Builder::new("kernel", vec![Type::I32]);
+        let out = b.add_output(Type::I32);
+        let start = b.iconst(Type::I64, 0);
+        let len = b.len();
+        let (rows, _) = b.build_loop(start, len, 1, &[], |b, row, _| {
+            let value = b.column(0, row, 1);
+            let (two, three) = (b.iconst(Type::I32, 2), b.iconst(Type::I32, 3));
+            let five = b.binary(BinaryOp::Add, two, three);
+            let lhs = b.binary(BinaryOp::Mul, value, five);
+            let rhs = b.binary(BinaryOp::Mul, value, five);
+            let zero = b.iconst(Type::I32, 0);
+            b.compare(Cond::SLt, value, zero);
+            let no = b.iconst(Type::BOOL, 0);
+            b.fail(KernelErrorCode::InvalidCast, no, row);
+            let overflows = b.overflows(BinaryOp::Add, true, lhs, rhs);
+            b.fail(KernelErrorCode::Overflow, overflows, row);
+            let sum = b.binary(BinaryOp::Add, lhs, rhs);
+            b.store(out, row, sum);
+            vec![]
+        });
+        b.finish(rows)
+    }
+
+    // `kernel()` after `pass`, which must leave it valid.
+    fn run(pass: fn(&mut Function)) -> String {
+        let mut func = kernel();
+        func.verify().unwrap();
+        pass(&mut func);
+        func.verify().unwrap();
+        func.to_string()
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_eq!(
+            run(fold_constants),
+            "function kernel(i32) -> (i32) {
+    v0 = const.i64 0
+    v1 = len
+    v14 = loop v0..v1 step 1 () |v2| {
+        v3 = column.i32 0[v2]
+        v4 = const.i32 2
+        v5 = const.i32 3
+        v6 = const.i32 5
+        v7 = mul.i32 v3, v6
+        v8 = mul.i32 v3, v6
+        v9 = const.i32 0
+        v10 = compare.slt v3, v9
+        v11 = const.bool 0
+        v12 = overflows.sadd v7, v8
+        fail.overflow v12, v2
+        v13 = add.i32 v7, v8
+        store 0[v2], v13
+        yield 
+    }
+    yield v14
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_eliminate_common_subexprs() {
+        assert_eq!(
+            run(eliminate_common_subexprs),
+            "function kernel(i32) -> (i32) {
+    v0 = const.i64 0
+    v1 = len
+    v14 = loop v0..v1 step 1 () |v2| {
+        v3 = column.i32 0[v2]
+        v4 = const.i32 2
+        v5 = const.i32 3
+        v6 = add.i32 v4, v5
+        v7 = mul.i32 v3, v6
+        v9 = const.i32 0
+        v10 = compare.slt v3, v9
+        v11 = const.bool 0
+        fail.invalidcast v11, v2
+        v12 = overflows.sadd v7, v7
+        fail.overflow v12, v2
+        v13 = add.i32 v7, v7
+        store 0[v2], v13
+        yield 
+    }
+    yield v14
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_hoist_loop_invariants() {
+        assert_eq!(
+            run(hoist_loop_invariants),
+            "function kernel(i32) -> (i32) {
+    v0 = const.i64 0
+    v1 = len
+    v4 = const.i32 2
+    v5 = const.i32 3
+    v6 = add.i32 v4, v5
+    v9 = const.i32 0
+    v11 = const.bool 0
+    v14 = loop v0..v1 step 1 () |v2| {
+        v3 = column.i32 0[v2]
+        v7 = mul.i32 v3, v6
+        v8 = mul.i32 v3, v6
+        v10 = compare.slt v3, v9
+        fail.invalidcast v11, v2
+        v12 = overflows.sadd v7, v8
+        fail.overflow v12, v2
+        v13 = add.i32 v7, v8
+        store 0[v2], v13
+        yield 
+    }
+    yield v14
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_eliminate_dead_code() {
+        assert_eq!(
+            run(eliminate_dead_code),
+            "function kernel(i32) -> (i32) {
+    v0 = const.i64 0
+    v1 = len
+    v14 = loop v0..v1 step 1 () |v2| {
+        v3 = column.i32 0[v2]
+        v4 = const.i32 2
+        v5 = const.i32 3
+        v6 = add.i32 v4, v5
+        v7 = mul.i32 v3, v6
+        v8 = mul.i32 v3, v6
+        v11 = const.bool 0
+        fail.invalidcast v11, v2
+        v12 = overflows.sadd v7, v8
+        fail.overflow v12, v2
+        v13 = add.i32 v7, v8
+        store 0[v2], v13
+        yield 
+    }
+    yield v14
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_optimize() {
+        assert_eq!(
+            run(optimize),
+            "function kernel(i32) -> (i32) {
+    v0 = const.i64 0
+    v1 = len
+    v6 = const.i32 5
+    v14 = loop v0..v1 step 1 () |v2| {
+        v3 = column.i32 0[v2]
+        v7 = mul.i32 v3, v6
+        v12 = overflows.sadd v7, v7
+        fail.overflow v12, v2
+        v13 = add.i32 v7, v7
+        store 0[v2], v13
+        yield 
+    }
+    yield v14
+}
+"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+
+use arrow::datatypes::DataType;
+
+/// Type of one lane of an IR value. Booleans are 0 or 1, integers carry no sign, the
+/// operations using them say how they're interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScalarType {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    pub fn bits(&self) -> u32 {
+        match self {
+            ScalarType::Bool | ScalarType::I8 => 8,
+            ScalarType::I16 => 16,
+            ScalarType::I32 | ScalarType::F32 => 32,
+            ScalarType::I64 | ScalarType::F64 => 64,
+        }
+    }
+}
+
+/// Type of an IR value: a scalar, or a vector of `lanes` scalars. Boolean vectors are
+/// masks, which select lanes of other vectors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Type {
+    pub scalar: ScalarType,
+    pub lanes: u32,
+}
+
+impl Type {
+    pub const BOOL: Type = Type::scalar(ScalarType::Bool);
+    pub const I8: Type = Type::scalar(ScalarType::I8);
+    pub const I16: Type = Type::scalar(ScalarType::I16);
+    pub const I32: Type = Type::scalar(ScalarType::I32);
+    pub const I64: Type = Type::scalar(ScalarType::I64);
+    pub const F32: Type = Type::scalar(ScalarType::F32);
+    pub const F64: Type = Type::scalar(ScalarType::F64);
+
+    pub const fn scalar(scalar: ScalarType) -> Self {
+        Self { scalar, lanes: 1 }
+    }
+
+    /// `lanes` values of this type's lane type.
+    pub fn with_lanes(self, lanes: u32) -> Self {
+        Self { lanes, ..self }
+    }
+
+    pub fn lane_type(self) -> Type {
+        self.with_lanes(1)
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.lanes > 1
+    }
+
+    pub fn is_bool(&self) -> bool {
+        self.scalar == ScalarType::Bool
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(
+            self.scalar,
+            ScalarType::I8 | ScalarType::I16 | ScalarType::I32 | ScalarType::I64
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self.scalar, ScalarType::F32 | ScalarType::F64)
+    }
+
+    /// type of one value of `data_type`, like [`native_type`] without decimals.
+    ///
+    /// [`native_type`]: crate::gen::native_type
+    pub fn from_data_type(data_type: &DataType) -> Option<Type> {
+        match data_type {
+            DataType::Boolean => Some(Type::BOOL),
+            DataType::Int8 | DataType::UInt8 => Some(Type::I8),
+            DataType::Int16 | DataType::UInt16 => Some(Type::I16),
+            DataType::Int32 | DataType::UInt32 | DataType::Date32 => Some(Type::I32),
+            DataType::Int64 | DataType::UInt64 | DataType::Date64 | DataType::Timestamp(_, _) => {
+                Some(Type::I64)
+            }
+            DataType::Float32 => Some(Type::F32),
+            DataType::Float64 => Some(Type::F64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.scalar {
+            ScalarType::Bool => "bool",
+            ScalarType::I8 => "i8",
+            ScalarType::I16 => "i16",
+            ScalarType::I32 => "i32",
+            ScalarType::I64 => "i64",
+            ScalarType::F32 => "f32",
+            ScalarType::F64 => "f64",
+        };
+        if self.is_vector() {
+            write!(f, "{}x{}", name, self.lanes)
+        } else {
+            write!(f, "{}", name)
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+
+use common::{Result, ServerError};
+
+use crate::ir::{BinaryOp, CastKind, Const, Function, Inst, Op, Region, Type, UnaryOp, Value};
+
+impl Function {
+    /// check that every value is defined once before it's used and only used where it's
+    /// visible, and that every instruction gets operands of the types it takes.
+    pub fn verify(&self) -> Result<()> {
+        let mut verifier = Verifier {
+            func: self,
+            defined: HashSet::new(),
+            scopes: vec![],
+        };
+        if !self.body.params.is_empty() {
+            return Err(verifier.error("the function body takes no parameters"));
+        }
+        let yields = verifier.region(&self.body)?;
+        if yields != [Type::I64] {
+            return Err(verifier.error("the function body must yield the rows written"));
+        }
+        Ok(())
+    }
+}
+
+struct Verifier<'a> {
+    func: &'a Function,
+    // values defined anywhere, each may only be defined once.
+    defined: HashSet<Value>,
+    // values visible in every region being checked, the body's first.
+    scopes: Vec<HashSet<Value>>,
+}
+
+impl<'a> Verifier<'a> {
+    fn error(&self, msg: impl AsRef<str>) -> ServerError {
+        ServerError::TypeError(format!(
+            "invalid ir in {}: {}",
+            self.func.name,
+            msg.as_ref()
+        ))
+    }
+
+    fn inst_error(&self, inst: &Inst, msg: &str) -> ServerError {
+        self.error(format!("{:?} {}", inst.op, msg))
+    }
+
+    fn define(&mut self, value: Value) -> Result<()> {
+        if value.index() >= self.func.types.len() || !self.defined.insert(value) {
+            return Err(self.error(format!("{} is defined twice or has no type", value)));
+        }
+        self.scopes.last_mut().unwrap().insert(value);
+        Ok(())
+    }
+
+    fn use_value(&self, value: Value) -> Result<Type> {
+        if !self.scopes.iter().any(|scope| scope.contains(&value)) {
+            return Err(self.error(format!("{} is used where it isn't defined", value)));
+        }
+        Ok(self.func.value_type(value))
+    }
+
+    // check `region` with its parameters defined, giving the types it yields.
+    fn region(&mut self, region: &Region) -> Result<Vec<Type>> {
+        self.scopes.push(HashSet::new());
+        for param in &region.params {
+            self.define(*param)?;
+        }
+        for inst in &region.insts {
+            self.inst(inst)?;
+        }
+        let yields = region
+            .yields
+            .iter()
+            .map(|value| self.use_value(*value))
+            .collect::<Result<Vec<_>>>()?;
+        self.scopes.pop();
+        Ok(yields)
+    }
+
+    fn inst(&mut self, inst: &Inst) -> Result<()> {
+        let operands = inst
+            .op
+            .operands()
+            .iter()
+            .map(|value| self.use_value(*value))
+            .collect::<Result<Vec<_>>>()?;
+        let results: Vec<Type> = inst
+            .results
+            .iter()
+            .map(|value| self.func.value_type(*value))
+            .collect();
+        let expected = self.result_types(inst, &operands)?;
+        if results != expected {
+            return Err(self.inst_error(inst, "has results of the wrong types"));
+        }
+        match &inst.op {
+            Op::Loop { body, .. } => {
+                if body.params.len() != inst.results.len() {
+                    return Err(self.inst_error(inst, "has a body taking the wrong parameters"));
+                }
+                for (param, _type) in body.params.iter().zip(&results) {
+                    if self.func.value_type(*param) != *_type {
+                        return Err(self.inst_error(inst, "has a body taking the wrong parameters"));
+                    }
+                }
+                if self.region(body)? != results[1..] {
+                    return Err(self.inst_error(inst, "has a body yielding the wrong types"));
+                }
+            }
+            Op::If {
+                then, otherwise, ..
+            } => {
+                if !then.params.is_empty() || !otherwise.params.is_empty() {
+                    return Err(self.inst_error(inst, "has branches taking parameters"));
+                }
+                if self.region(then)? != results || self.region(otherwise)? != results {
+                    return Err(self.inst_error(inst, "has branches yielding different types"));
+                }
+            }
+            _ => {}
+        }
+        for result in &inst.results {
+            self.define(*result)?;
+        }
+        Ok(())
+    }
+
+    // the result types of `inst` given the types of its operands.
+    fn result_types(&self, inst: &Inst, operands: &[Type]) -> Result<Vec<Type>> {
+        let func = self.func;
+        let invalid = |msg: &str| Err(self.inst_error(inst, msg));
+        let result = inst.results.first().map(|value| func.value_type(*value));
+        let mask = |_type: Type| Type::BOOL.with_lanes(_type.lanes);
+        let row_is_i64 = operands.first() == Some(&Type::I64);
+        let _type = match &inst.op {
+            Op::Const(value) => {
+                let Some(_type) = result.filter(|_type| !_type.is_vector()) else {
+                    return invalid("must be a scalar");
+                };
+                match value {
+                    Const::Float(_) if _type.is_float() => _type,
+                    Const::Int(_) if !_type.is_float() => _type,
+                    _ => return invalid("doesn't fit its type"),
+                }
+            }
+            Op::Len => Type::I64,
+            Op::Column { slot, .. } => {
+                let Some(column) = func.columns.get(*slot) else {
+                    return invalid("reads a column the function doesn't have");
+                };
+                let lanes = result.map_or(1, |_type| _type.lanes);
+                if !row_is_i64 || (column.is_bool() && lanes > 1) {
+                    return invalid("reads an invalid row");
+                }
+                column.with_lanes(lanes)
+            }
+            Op::ColumnValid { slot, .. } => {
+                if *slot >= func.columns.len() || !row_is_i64 {
+                    return invalid("reads an invalid row");
+                }
+                Type::BOOL
+            }
+            Op::Store { slot, .. } | Op::StoreValid { slot, .. } => {
+                let Some(output) = func.outputs.get(*slot) else {
+                    return invalid("writes an output the function doesn't have");
+                };
+                let value = operands[1];
+                let expected = match &inst.op {
+                    Op::Store { .. } if output.is_bool() => *output,
+                    Op::Store { .. } => output.with_lanes(value.lanes),
+                    _ => Type::BOOL,
+                };
+                if !row_is_i64 || value != expected {
+                    return invalid("writes an invalid row or value");
+                }
+                return Ok(vec![]);
+            }
+            Op::Unary(op, _) => {
+                let value = operands[0];
+                let valid = match op {
+                    UnaryOp::Not => !value.is_float(),
+                    UnaryOp::Neg => !value.is_bool(),
+                };
+                if !valid {
+                    return invalid("has an operand of the wrong type");
+                }
+                value
+            }
+            Op::Binary(op, _, _) => {
+                let (lhs, rhs) = (operands[0], operands[1]);
+                let valid = match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => !lhs.is_bool(),
+                    BinaryOp::FDiv | BinaryOp::FRem => lhs.is_float(),
+                    BinaryOp::And | BinaryOp::Or | BinaryOp::Xor => !lhs.is_float(),
+                    _ => lhs.is_int(),
+                };
+                if !valid || lhs != rhs {
+                    return invalid("has operands of the wrong types");
+                }
+                lhs
+            }
+            Op::Overflows { op, .. } => {
+                let (lhs, rhs) = (operands[0], operands[1]);
+                let arith = matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul);
+                if !arith || !lhs.is_int() || lhs != rhs {
+                    return invalid("has operands of the wrong types");
+                }
+                mask(lhs)
+            }
+            Op::Compare(_, _, _) => {
+                if operands[0] != operands[1] {
+                    return invalid("compares values of different types");
+                }
+                mask(operands[0])
+            }
+            Op::Select { .. } => {
+                let (cond, then, otherwise) = (operands[0], operands[1], operands[2]);
+                if (cond != Type::BOOL && cond != mask(then)) || then != otherwise {
+                    return invalid("has operands of the wrong types");
+                }
+                then
+            }
+            Op::Cast(kind, _) => {
+                let (from, Some(to)) = (operands[0], result) else {
+                    return invalid("has no result");
+                };
+                let (from_bits, to_bits) = (from.scalar.bits(), to.scalar.bits());
+                let valid = from.lanes == to.lanes
+                    && match kind {
+                        CastKind::SExtend | CastKind::UExtend => {
+                            (from.is_int() && to.is_int() && from_bits < to_bits)
+                                || (from.is_bool() && to.is_int())
+                        }
+                        CastKind::Reduce => from.is_int() && to.is_int() && from_bits > to_bits,
+                        CastKind::SIntToFloat => from.is_int() && to.is_float(),
+                        CastKind::UIntToFloat => !from.is_float() && to.is_float(),
+                        CastKind::FloatPromote => {
+                            from == Type::F32.with_lanes(from.lanes)
+                                && to.is_float()
+                                && to_bits == 64
+                        }
+                        CastKind::FloatDemote => {
+                            from.is_float()
+                                && from_bits == 64
+                                && to == Type::F32.with_lanes(to.lanes)
+                        }
+                    };
+                if !valid {
+                    return invalid("converts between the wrong types");
+                }
+                to
+            }
+            Op::Splat(_) => {
+                let (value, Some(to)) = (operands[0], result) else {
+                    return invalid("has no result");
+                };
+                if value.is_vector() || value.is_bool() || !to.is_vector() {
+                    return invalid("splats a vector or boolean");
+                }
+                value.with_lanes(to.lanes)
+            }
+            Op::AnyTrue(_) | Op::AllTrue(_) => {
+                if !operands[0].is_bool() || !operands[0].is_vector() {
+                    return invalid("reduces a value that isn't a mask");
+                }
+                Type::BOOL
+            }
+            Op::Call { func: native, .. } => {
+                let args: Option<Vec<Type>> = native
+                    .arg_types()
+                    .iter()
+                    .map(Type::from_data_type)
+                    .collect();
+                if args.as_deref() != Some(operands) {
+                    return invalid("passes arguments of the wrong types");
+                }
+                match Type::from_data_type(native.return_type()) {
+                    Some(_type) => _type,
+                    None => return invalid("returns a type the ir doesn't have"),
+                }
+            }
+            Op::Fail { .. } => {
+                if operands != [Type::BOOL, Type::I64] {
+                    return invalid("has operands of the wrong types");
+                }
+                return Ok(vec![]);
+            }
+            Op::Loop { step, .. } => {
+                let carried = &operands[2..];
+                if operands[..2] != [Type::I64, Type::I64] || *step == 0 {
+                    return invalid("has invalid bounds");
+                }
+                if carried
+                    .iter()
+                    .any(|_type| _type.is_bool() && _type.is_vector())
+                {
+                    return invalid("carries a mask");
+                }
+                return Ok([Type::I64].into_iter().chain(carried.to_vec()).collect());
+            }
+            Op::If { .. } => {
+                let results: Vec<Type> = inst
+                    .results
+                    .iter()
+                    .map(|value| func.value_type(*value))
+                    .collect();
+                if operands[0] != Type::BOOL {
+                    return invalid("has a condition that isn't a boolean");
+                }
+                if results
+                    .iter()
+                    .any(|_type| _type.is_bool() && _type.is_vector())
+                {
+                    return invalid("gives a mask");
+                }
+                return Ok(results);
+            }
+        };
+        Ok(vec![_type])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{Result, ServerError};
+
+    use crate::ir::{BinaryOp, Builder, Type};
+
+    // the message of the verifier's error.
+    fn error(result: Result<()>) -> String {
+        match result {
+            Err(ServerError::TypeError(msg)) => msg,
+            result => panic!("expected a type error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        // copies the column into the output and counts the rows.
+        let mut b = Builder::new("copy", vec![Type::I64]);
+        let out = b.add_output(Type::I64);
+        let (start, len) = (b.iconst(Type::I64, 0), b.len());
+        let (rows, _) = b.build_loop(start, len, 1, &[], |b, row, _| {
+            let value = b.column(0, row, 1);
+            b.store(out, row, value);
+            vec![]
+        });
+        let func = b.finish(rows);
+        func.verify().unwrap();
+
+        // a row used after its loop.
+        let mut b = Builder::new("escape", vec![Type::I64]);
+        let (start, len) = (b.iconst(Type::I64, 0), b.len());
+        let mut escaped = None;
+        b.build_loop(start, len, 1, &[], |_, row, _| {
+            escaped = Some(row);
+            vec![]
+        });
+        let func = b.finish(escaped.unwrap());
+        assert_eq!(
+            error(func.verify()),
+            "invalid ir in escape: v2 is used where it isn't defined"
+        );
+
+        // operands of different types.
+        let mut b = Builder::new("mixed", vec![]);
+        let (lhs, rhs) = (b.iconst(Type::I64, 1), b.iconst(Type::I32, 2));
+        let sum = b.binary(BinaryOp::Add, lhs, rhs);
+        let func = b.finish(sum);
+        assert!(error(func.verify()).contains("has operands of the wrong types"));
+
+        // the body must yield the number of rows written.
+        let mut b = Builder::new("rows", vec![]);
+        let rows = b.iconst(Type::I32, 0);
+        let func = b.finish(rows);
+        assert_eq!(
+            error(func.verify()),
+            "invalid ir in rows: the function body must yield the rows written"
+        );
+    }
+}
@@ -1,10 +1,13 @@
-use cranelift::{codegen::isa::TargetIsa, prelude::*};
+use cranelift::{
+    codegen::isa::{CallConv, TargetIsa},
+    prelude::*,
+};
 use cranelift_jit::{JITBuilder, JITModule};
 use std::{collections::HashMap, sync::Arc};
 
 use crate::gen::FuncRegister;
 
-pub fn create_jit_module(funcs: &HashMap<&str, FuncRegister>) -> JITModule {
+pub fn create_jit_module(funcs: &HashMap<String, FuncRegister>) -> JITModule {
     let flags = build_flags();
     let isa = build_isa(flags);
     build_jit_module(isa, funcs)
@@ -24,6 +27,19 @@ fn build_flags() -> settings::Flags {
     flags
 }
 
+/// pointer type and default calling convention of the host, which native functions
+/// generated code calls are compiled for.
+pub(crate) fn host_abi() -> (Type, CallConv) {
+    let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
+        panic!("host machine is not supported: {}", msg);
+    });
+    let triple = isa_builder.triple();
+    (
+        Type::triple_pointer_type(triple),
+        CallConv::triple_default(triple),
+    )
+}
+
 fn build_isa(flags: settings::Flags) -> Arc<dyn TargetIsa> {
     let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
         panic!("host machine is not supported: {}", msg);
@@ -32,9 +48,9 @@ fn build_isa(flags: settings::Flags) -> Arc<dyn TargetIsa> {
     isa_builder.finish(flags).unwrap()
 }
 
-fn build_jit_module(isa: Arc<dyn TargetIsa>, funcs: &HashMap<&str, FuncRegister>) -> JITModule {
+fn build_jit_module(isa: Arc<dyn TargetIsa>, funcs: &HashMap<String, FuncRegister>) -> JITModule {
     let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
-    for (&k, v) in funcs {
+    for (k, v) in funcs {
         builder.symbol(k, v.address);
     }
     JITModule::new(builder)
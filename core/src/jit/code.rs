@@ -0,0 +1,50 @@
+use cranelift_jit::JITModule;
+
+use crate::llvm::LlvmCode;
+
+/// Finalized machine code of a generated function, whose memory is freed when this is
+/// dropped.
+pub struct JitCode {
+    address: *const u8,
+    // the cranelift module the code lives in, freed on drop.
+    module: Option<JITModule>,
+    // or the llvm engine, which frees the code itself.
+    _llvm: Option<LlvmCode>,
+}
+
+// the module is only touched again when it's dropped, and the code only reads memory it
+// is given.
+unsafe impl Send for JitCode {}
+unsafe impl Sync for JitCode {}
+
+impl JitCode {
+    pub(crate) fn new(module: JITModule, address: *const u8) -> Self {
+        Self {
+            address,
+            module: Some(module),
+            _llvm: None,
+        }
+    }
+
+    pub(crate) fn llvm(code: LlvmCode, address: *const u8) -> Self {
+        Self {
+            address,
+            module: None,
+            _llvm: Some(code),
+        }
+    }
+
+    /// address of the function, valid as long as this is alive.
+    pub fn address(&self) -> *const u8 {
+        self.address
+    }
+}
+
+impl Drop for JitCode {
+    fn drop(&mut self) {
+        // whoever holds the address holds this too, so the code can't run anymore.
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
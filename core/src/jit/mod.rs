@@ -1,2 +1,6 @@
 pub(crate) mod build;
+pub(crate) mod code;
 pub(crate) mod native_opcall;
+pub(crate) mod native_parse;
+pub(crate) mod native_set;
+pub(crate) mod native_string;
@@ -1,55 +1,385 @@
-use arrow::datatypes::ArrowNativeTypeOp;
+use std::ops::{BitAnd, BitOr, BitXor};
+
+use arrow::datatypes::{ArrowNativeTypeOp, DataType};
 use cranelift::codegen::ir::Signature;
 use cranelift::codegen::isa::CallConv;
 use cranelift::prelude::*;
 
-pub enum NativeOpCall {
-    Float64AddWrapping,
-    Float64DivWrapping,
-    Float64Lt,
+/// Native value types operations can be called for. Decimals are their unscaled `Int128`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeKind {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Int128,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
 }
 
-impl NativeOpCall {
-    pub(crate) fn all_opcalls() -> &'static [NativeOpCall] {
-        use NativeOpCall::*;
-        &[Float64AddWrapping, Float64DivWrapping, Float64Lt]
+/// Binary operations backed by a rust function. Arithmetic wraps on overflow and yields
+/// zero for a zero divisor, comparisons and `MulOverflows` return 0 or 1, and floats are
+/// ordered like arrow does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeOp {
+    AddWrapping,
+    SubWrapping,
+    MulWrapping,
+    DivWrapping,
+    ModWrapping,
+    /// whether the product doesn't fit the type, which is never the case for floats.
+    MulOverflows,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+/// A rust function generated code calls for `op` over two values of `kind`, used for
+/// what cranelift has no instruction for, such as float remainders or 128 bit division.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NativeOpCall {
+    pub op: NativeOp,
+    pub kind: NativeKind,
+}
+
+const KINDS: [NativeKind; 11] = [
+    NativeKind::Int8,
+    NativeKind::Int16,
+    NativeKind::Int32,
+    NativeKind::Int64,
+    NativeKind::Int128,
+    NativeKind::UInt8,
+    NativeKind::UInt16,
+    NativeKind::UInt32,
+    NativeKind::UInt64,
+    NativeKind::Float32,
+    NativeKind::Float64,
+];
+
+const OPS: [NativeOp; 17] = [
+    NativeOp::AddWrapping,
+    NativeOp::SubWrapping,
+    NativeOp::MulWrapping,
+    NativeOp::DivWrapping,
+    NativeOp::ModWrapping,
+    NativeOp::MulOverflows,
+    NativeOp::Eq,
+    NativeOp::NotEq,
+    NativeOp::Lt,
+    NativeOp::LtEq,
+    NativeOp::Gt,
+    NativeOp::GtEq,
+    NativeOp::BitAnd,
+    NativeOp::BitOr,
+    NativeOp::BitXor,
+    NativeOp::ShiftLeft,
+    NativeOp::ShiftRight,
+];
+
+macro_rules! opcall_names {
+    ($($kind:ident),*) => {
+        [$([
+            concat!(stringify!($kind), "AddWrapping"),
+            concat!(stringify!($kind), "SubWrapping"),
+            concat!(stringify!($kind), "MulWrapping"),
+            concat!(stringify!($kind), "DivWrapping"),
+            concat!(stringify!($kind), "ModWrapping"),
+            concat!(stringify!($kind), "MulOverflows"),
+            concat!(stringify!($kind), "Eq"),
+            concat!(stringify!($kind), "NotEq"),
+            concat!(stringify!($kind), "Lt"),
+            concat!(stringify!($kind), "LtEq"),
+            concat!(stringify!($kind), "Gt"),
+            concat!(stringify!($kind), "GtEq"),
+            concat!(stringify!($kind), "BitAnd"),
+            concat!(stringify!($kind), "BitOr"),
+            concat!(stringify!($kind), "BitXor"),
+            concat!(stringify!($kind), "ShiftLeft"),
+            concat!(stringify!($kind), "ShiftRight"),
+        ]),*]
+    };
+}
+
+// indexed by `NativeKind` and then `NativeOp` discriminant.
+static OPCALL_NAMES: [[&str; OPS.len()]; KINDS.len()] = opcall_names!(
+    Int8, Int16, Int32, Int64, Int128, UInt8, UInt16, UInt32, UInt64, Float32, Float64
+);
+
+impl NativeKind {
+    pub fn from_data_type(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Int8 => Some(NativeKind::Int8),
+            DataType::Int16 => Some(NativeKind::Int16),
+            DataType::Int32 | DataType::Date32 => Some(NativeKind::Int32),
+            DataType::Int64 | DataType::Date64 | DataType::Timestamp(_, _) => {
+                Some(NativeKind::Int64)
+            }
+            DataType::Decimal128(_, _) => Some(NativeKind::Int128),
+            DataType::UInt8 => Some(NativeKind::UInt8),
+            DataType::UInt16 => Some(NativeKind::UInt16),
+            DataType::UInt32 => Some(NativeKind::UInt32),
+            DataType::UInt64 => Some(NativeKind::UInt64),
+            DataType::Float32 => Some(NativeKind::Float32),
+            DataType::Float64 => Some(NativeKind::Float64),
+            _ => None,
+        }
     }
 
-    pub(crate) fn signature(&self, pointer_type: Type) -> Signature {
-        use NativeOpCall::*;
+    pub fn native_type(&self) -> Type {
+        use NativeKind::*;
         match self {
-            Float64AddWrapping | Float64DivWrapping => Signature {
-                params: vec![AbiParam::new(types::F64), AbiParam::new(types::F64)],
-                returns: vec![AbiParam::new(types::F64)],
-                call_conv: CallConv::Fast,
-            },
-            Float64Lt => Signature {
-                params: vec![AbiParam::new(types::F64), AbiParam::new(types::F64)],
-                returns: vec![AbiParam::new(types::I8)],
-                call_conv: CallConv::Fast,
-            },
+            Int8 | UInt8 => types::I8,
+            Int16 | UInt16 => types::I16,
+            Int32 | UInt32 => types::I32,
+            Int64 | UInt64 => types::I64,
+            Int128 => types::I128,
+            Float32 => types::F32,
+            Float64 => types::F64,
         }
     }
 
-    pub(crate) fn name(&self) -> &str {
-        use NativeOpCall::*;
+    fn is_float(&self) -> bool {
+        matches!(self, NativeKind::Float32 | NativeKind::Float64)
+    }
+
+    /// parameter of this kind, narrow integers are extended as the C ABI expects.
+    fn abi_param(&self) -> AbiParam {
+        use NativeKind::*;
+        let param = AbiParam::new(self.native_type());
         match self {
-            Float64AddWrapping => "Float64AddWrapping",
-            Float64DivWrapping => "Float64DivWrapping",
-            Float64Lt => "Float64Lt",
+            Int8 | Int16 => param.sext(),
+            UInt8 | UInt16 => param.uext(),
+            _ => param,
         }
     }
+}
+
+impl NativeOp {
+    pub fn is_comparison(&self) -> bool {
+        use NativeOp::*;
+        matches!(self, Eq | NotEq | Lt | LtEq | Gt | GtEq)
+    }
+
+    pub fn is_bitwise(&self) -> bool {
+        use NativeOp::*;
+        matches!(self, BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight)
+    }
+}
+
+impl NativeOpCall {
+    pub fn new(op: NativeOp, kind: NativeKind) -> Self {
+        assert!(
+            !(op.is_bitwise() && kind.is_float()),
+            "{:?} over {:?}",
+            op,
+            kind
+        );
+        Self { op, kind }
+    }
+
+    pub(crate) fn all_opcalls() -> Vec<NativeOpCall> {
+        KINDS
+            .iter()
+            .flat_map(|kind| {
+                OPS.iter()
+                    .filter(|op| !(op.is_bitwise() && kind.is_float()))
+                    .map(|op| NativeOpCall::new(*op, *kind))
+            })
+            .collect()
+    }
+
+    pub(crate) fn signature(&self, call_conv: CallConv) -> Signature {
+        let param = self.kind.abi_param();
+        let result = if self.op.is_comparison() || self.op == NativeOp::MulOverflows {
+            AbiParam::new(types::I8).uext()
+        } else {
+            param
+        };
+        Signature {
+            params: vec![param, param],
+            returns: vec![result],
+            call_conv,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        OPCALL_NAMES[self.kind as usize][self.op as usize]
+    }
 
     pub(crate) fn addr(&self) -> *const u8 {
-        use NativeOpCall::*;
-        match self {
-            Float64AddWrapping => f64::add_wrapping as *const u8,
-            Float64DivWrapping => f64::div_wrapping as *const u8,
-            Float64Lt => lt_wrap as *const u8,
+        use NativeKind::*;
+        match self.kind {
+            Int8 => int_addr::<i8>(self.op),
+            Int16 => int_addr::<i16>(self.op),
+            Int32 => int_addr::<i32>(self.op),
+            Int64 => int_addr::<i64>(self.op),
+            Int128 => int_addr::<i128>(self.op),
+            UInt8 => int_addr::<u8>(self.op),
+            UInt16 => int_addr::<u16>(self.op),
+            UInt32 => int_addr::<u32>(self.op),
+            UInt64 => int_addr::<u64>(self.op),
+            Float32 => float_addr::<f32>(self.op),
+            Float64 => float_addr::<f64>(self.op),
         }
     }
 }
 
-fn lt_wrap(a: f64, b: f64) -> bool {
-    a < b
+fn numeric_addr<T: ArrowNativeTypeOp>(op: NativeOp) -> Option<*const u8> {
+    use NativeOp::*;
+    let addr = match op {
+        AddWrapping => add_wrapping::<T> as *const u8,
+        SubWrapping => sub_wrapping::<T> as *const u8,
+        MulWrapping => mul_wrapping::<T> as *const u8,
+        DivWrapping => div_wrapping::<T> as *const u8,
+        ModWrapping => mod_wrapping::<T> as *const u8,
+        MulOverflows => mul_overflows::<T> as *const u8,
+        Eq => eq::<T> as *const u8,
+        NotEq => not_eq::<T> as *const u8,
+        Lt => lt::<T> as *const u8,
+        LtEq => lt_eq::<T> as *const u8,
+        Gt => gt::<T> as *const u8,
+        GtEq => gt_eq::<T> as *const u8,
+        BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight => return None,
+    };
+    Some(addr)
+}
+
+fn float_addr<T: ArrowNativeTypeOp>(op: NativeOp) -> *const u8 {
+    match op {
+        // zero divisors give inf or NaN, there is nothing to guard.
+        NativeOp::DivWrapping => float_div::<T> as *const u8,
+        NativeOp::ModWrapping => float_mod::<T> as *const u8,
+        _ => numeric_addr::<T>(op).unwrap(),
+    }
+}
+
+fn int_addr<T: ArrowNativeTypeOp + IntOp>(op: NativeOp) -> *const u8 {
+    use NativeOp::*;
+    numeric_addr::<T>(op).unwrap_or_else(|| match op {
+        BitAnd => bit_and::<T> as *const u8,
+        BitOr => bit_or::<T> as *const u8,
+        BitXor => bit_xor::<T> as *const u8,
+        ShiftLeft => shift_left::<T> as *const u8,
+        ShiftRight => shift_right::<T> as *const u8,
+        _ => unreachable!(),
+    })
+}
+
+/// integer operations arrow's native type trait doesn't cover.
+trait IntOp: BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Sized {
+    fn shl_wrapping(self, rhs: Self) -> Self;
+    fn shr_wrapping(self, rhs: Self) -> Self;
+}
+
+macro_rules! impl_int_op {
+    ($($native:ty),*) => {
+        $(impl IntOp for $native {
+            fn shl_wrapping(self, rhs: Self) -> Self {
+                self.wrapping_shl(rhs as u32)
+            }
+
+            fn shr_wrapping(self, rhs: Self) -> Self {
+                self.wrapping_shr(rhs as u32)
+            }
+        })*
+    };
+}
+
+impl_int_op!(i8, i16, i32, i64, i128, u8, u16, u32, u64);
+
+extern "C" fn add_wrapping<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    lhs.add_wrapping(rhs)
+}
+
+extern "C" fn sub_wrapping<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    lhs.sub_wrapping(rhs)
+}
+
+extern "C" fn mul_wrapping<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    lhs.mul_wrapping(rhs)
+}
+
+// integer division by zero panics, which must not unwind into generated code.
+extern "C" fn div_wrapping<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    if rhs.is_zero() {
+        T::ZERO
+    } else {
+        lhs.div_wrapping(rhs)
+    }
+}
+
+extern "C" fn mod_wrapping<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    if rhs.is_zero() {
+        T::ZERO
+    } else {
+        lhs.mod_wrapping(rhs)
+    }
+}
+
+extern "C" fn float_div<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    lhs.div_wrapping(rhs)
+}
+
+extern "C" fn float_mod<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> T {
+    lhs.mod_wrapping(rhs)
+}
+
+extern "C" fn mul_overflows<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.mul_checked(rhs).is_err()
+}
+
+extern "C" fn eq<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_eq(rhs)
+}
+
+extern "C" fn not_eq<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_ne(rhs)
+}
+
+extern "C" fn lt<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_lt(rhs)
+}
+
+extern "C" fn lt_eq<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_le(rhs)
+}
+
+extern "C" fn gt<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_gt(rhs)
+}
+
+extern "C" fn gt_eq<T: ArrowNativeTypeOp>(lhs: T, rhs: T) -> bool {
+    lhs.is_ge(rhs)
+}
+
+extern "C" fn bit_and<T: IntOp>(lhs: T, rhs: T) -> T {
+    lhs & rhs
+}
+
+extern "C" fn bit_or<T: IntOp>(lhs: T, rhs: T) -> T {
+    lhs | rhs
+}
+
+extern "C" fn bit_xor<T: IntOp>(lhs: T, rhs: T) -> T {
+    lhs ^ rhs
+}
+
+extern "C" fn shift_left<T: IntOp>(lhs: T, rhs: T) -> T {
+    lhs.shl_wrapping(rhs)
+}
+
+extern "C" fn shift_right<T: IntOp>(lhs: T, rhs: T) -> T {
+    lhs.shr_wrapping(rhs)
 }
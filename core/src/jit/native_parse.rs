@@ -0,0 +1,92 @@
+use std::str::FromStr;
+
+use cranelift::codegen::ir::Signature;
+use cranelift::codegen::isa::CallConv;
+use cranelift::prelude::*;
+
+/// Numbers strings are parsed to by a rust function. Narrower targets are parsed as the
+/// 64 bit type of their kind and converted by generated code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeParse {
+    Int64,
+    UInt64,
+    Float64,
+}
+
+/// Returned by the parse functions: the parsed value's bits, and 1 if the whole string
+/// was a number. Two 64 bit integers are returned in registers by every C ABI we target,
+/// which isn't the case for a float next to a flag.
+#[repr(C)]
+#[allow(dead_code)] // read by generated code
+pub(crate) struct ParseResult {
+    bits: u64,
+    ok: u64,
+}
+
+impl NativeParse {
+    pub(crate) const ALL: [NativeParse; 3] = [
+        NativeParse::Int64,
+        NativeParse::UInt64,
+        NativeParse::Float64,
+    ];
+
+    /// takes the string's address and length in bytes.
+    pub(crate) fn signature(&self, pointer_type: Type, call_conv: CallConv) -> Signature {
+        Signature {
+            params: vec![AbiParam::new(pointer_type), AbiParam::new(types::I64)],
+            returns: vec![AbiParam::new(types::I64), AbiParam::new(types::I64)],
+            call_conv,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            NativeParse::Int64 => "ParseInt64",
+            NativeParse::UInt64 => "ParseUInt64",
+            NativeParse::Float64 => "ParseFloat64",
+        }
+    }
+
+    pub(crate) fn addr(&self) -> *const u8 {
+        match self {
+            NativeParse::Int64 => parse::<i64> as *const u8,
+            NativeParse::UInt64 => parse::<u64> as *const u8,
+            NativeParse::Float64 => parse::<f64> as *const u8,
+        }
+    }
+}
+
+trait ToBits {
+    fn to_bits(self) -> u64;
+}
+
+impl ToBits for i64 {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+}
+
+impl ToBits for u64 {
+    fn to_bits(self) -> u64 {
+        self
+    }
+}
+
+impl ToBits for f64 {
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+}
+
+extern "C" fn parse<T: FromStr + ToBits>(ptr: *const u8, len: i64) -> ParseResult {
+    // Safety: generated code passes the bytes of a valid utf8 value.
+    let text =
+        unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len as usize)) };
+    match text.parse::<T>() {
+        Ok(value) => ParseResult {
+            bits: value.to_bits(),
+            ok: 1,
+        },
+        Err(_) => ParseResult { bits: 0, ok: 0 },
+    }
+}
@@ -0,0 +1,74 @@
+use cranelift::codegen::ir::Signature;
+use cranelift::codegen::isa::CallConv;
+use cranelift::prelude::*;
+
+use crate::gen::ValueSet;
+
+/// Membership tests in a [`ValueSet`] backed by a rust function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeSet {
+    /// whether the set holds an i64 key.
+    ContainsInt64,
+    /// whether the set holds the string at an address and length in bytes.
+    ContainsUtf8,
+}
+
+impl NativeSet {
+    pub(crate) const ALL: [NativeSet; 2] = [NativeSet::ContainsInt64, NativeSet::ContainsUtf8];
+
+    /// takes the set's address first.
+    pub(crate) fn signature(&self, pointer_type: Type, call_conv: CallConv) -> Signature {
+        let params = match self {
+            NativeSet::ContainsInt64 => {
+                vec![AbiParam::new(pointer_type), AbiParam::new(types::I64)]
+            }
+            NativeSet::ContainsUtf8 => vec![
+                AbiParam::new(pointer_type),
+                AbiParam::new(pointer_type),
+                AbiParam::new(types::I64),
+            ],
+        };
+        Signature {
+            params,
+            returns: vec![AbiParam::new(types::I8)],
+            call_conv,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            NativeSet::ContainsInt64 => "SetContainsInt64",
+            NativeSet::ContainsUtf8 => "SetContainsUtf8",
+        }
+    }
+
+    pub(crate) fn addr(&self) -> *const u8 {
+        match self {
+            NativeSet::ContainsInt64 => contains_int64 as *const u8,
+            NativeSet::ContainsUtf8 => contains_utf8 as *const u8,
+        }
+    }
+}
+
+// Safety for both: the set is owned by the expression the code was generated for, which
+// outlives the code.
+
+extern "C" fn contains_int64(set: *const ValueSet, key: i64) -> bool {
+    match unsafe { &*set } {
+        ValueSet::Int64(set) => set.contains(&key),
+        ValueSet::Utf8(_) => false,
+    }
+}
+
+extern "C" fn contains_utf8(set: *const ValueSet, ptr: *const u8, len: i64) -> bool {
+    let bytes = if len == 0 {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(ptr, len as usize) }
+    };
+    match unsafe { &*set } {
+        // generated code only passes valid utf8.
+        ValueSet::Utf8(set) => set.contains(unsafe { std::str::from_utf8_unchecked(bytes) }),
+        ValueSet::Int64(_) => false,
+    }
+}
@@ -0,0 +1,190 @@
+use arrow::array::{GenericStringBuilder, OffsetSizeTrait};
+use cranelift::codegen::ir::Signature;
+use cranelift::codegen::isa::CallConv;
+use cranelift::prelude::*;
+use regex::Regex;
+
+/// String operations backed by a rust function. Strings are passed as their address and
+/// length in bytes, and always hold valid utf8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeString {
+    /// -1, 0 or 1 as the first string sorts before, equal to or after the second.
+    Compare,
+    /// whether the second string occurs in the first.
+    Contains,
+    /// whether the first string matches the second as a SQL LIKE pattern.
+    Like,
+    /// like `Like`, ignoring case.
+    ILike,
+    /// whether the `Regex` at the first parameter matches anywhere in a string.
+    RegexMatch,
+    /// append a string to the `GenericStringBuilder<i32>` at the first parameter.
+    AppendUtf8,
+    /// append a string to the `GenericStringBuilder<i64>` at the first parameter.
+    AppendLargeUtf8,
+}
+
+impl NativeString {
+    pub(crate) const ALL: [NativeString; 7] = [
+        NativeString::Compare,
+        NativeString::Contains,
+        NativeString::Like,
+        NativeString::ILike,
+        NativeString::RegexMatch,
+        NativeString::AppendUtf8,
+        NativeString::AppendLargeUtf8,
+    ];
+
+    pub(crate) fn signature(&self, pointer_type: Type, call_conv: CallConv) -> Signature {
+        let ptr = AbiParam::new(pointer_type);
+        let len = AbiParam::new(types::I64);
+        let (params, returns) = match self {
+            NativeString::Compare => (vec![ptr, len, ptr, len], vec![AbiParam::new(types::I32)]),
+            NativeString::Contains | NativeString::Like | NativeString::ILike => {
+                (vec![ptr, len, ptr, len], vec![AbiParam::new(types::I8)])
+            }
+            NativeString::RegexMatch => (vec![ptr, ptr, len], vec![AbiParam::new(types::I8)]),
+            NativeString::AppendUtf8 | NativeString::AppendLargeUtf8 => {
+                (vec![ptr, ptr, len], vec![])
+            }
+        };
+        Signature {
+            params,
+            returns,
+            call_conv,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            NativeString::Compare => "StringCompare",
+            NativeString::Contains => "StringContains",
+            NativeString::Like => "StringLike",
+            NativeString::ILike => "StringILike",
+            NativeString::RegexMatch => "StringRegexMatch",
+            NativeString::AppendUtf8 => "StringAppendUtf8",
+            NativeString::AppendLargeUtf8 => "StringAppendLargeUtf8",
+        }
+    }
+
+    pub(crate) fn addr(&self) -> *const u8 {
+        match self {
+            NativeString::Compare => compare as *const u8,
+            NativeString::Contains => contains as *const u8,
+            NativeString::Like => like as *const u8,
+            NativeString::ILike => ilike as *const u8,
+            NativeString::RegexMatch => regex_match as *const u8,
+            NativeString::AppendUtf8 => append::<i32> as *const u8,
+            NativeString::AppendLargeUtf8 => append::<i64> as *const u8,
+        }
+    }
+}
+
+// Safety: generated code passes the address and length of valid utf8. The address of an
+// empty string, such as the value of a null, may be 0.
+unsafe fn as_str<'a>(ptr: *const u8, len: i64) -> &'a str {
+    if len == 0 {
+        return "";
+    }
+    std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len as usize))
+}
+
+extern "C" fn compare(lhs: *const u8, lhs_len: i64, rhs: *const u8, rhs_len: i64) -> i32 {
+    let (lhs, rhs) = unsafe { (as_str(lhs, lhs_len), as_str(rhs, rhs_len)) };
+    lhs.cmp(rhs) as i32
+}
+
+extern "C" fn contains(ptr: *const u8, len: i64, needle: *const u8, needle_len: i64) -> bool {
+    let (text, needle) = unsafe { (as_str(ptr, len), as_str(needle, needle_len)) };
+    text.contains(needle)
+}
+
+extern "C" fn like(ptr: *const u8, len: i64, pattern: *const u8, pattern_len: i64) -> bool {
+    let (text, pattern) = unsafe { (as_str(ptr, len), as_str(pattern, pattern_len)) };
+    like_match(text.as_bytes(), pattern.as_bytes())
+}
+
+extern "C" fn ilike(ptr: *const u8, len: i64, pattern: *const u8, pattern_len: i64) -> bool {
+    let (text, pattern) = unsafe { (as_str(ptr, len), as_str(pattern, pattern_len)) };
+    like_match(
+        text.to_lowercase().as_bytes(),
+        pattern.to_lowercase().as_bytes(),
+    )
+}
+
+extern "C" fn regex_match(regex: *const Regex, ptr: *const u8, len: i64) -> bool {
+    // Safety: the regex is owned by the expression the code was generated for, which
+    // outlives the code.
+    let (regex, text) = unsafe { (&*regex, as_str(ptr, len)) };
+    regex.is_match(text)
+}
+
+extern "C" fn append<O: OffsetSizeTrait>(
+    builder: *mut GenericStringBuilder<O>,
+    ptr: *const u8,
+    len: i64,
+) {
+    // Safety: the kernel's caller passes a builder it owns for the duration of the call.
+    let (builder, value) = unsafe { (&mut *builder, as_str(ptr, len)) };
+    builder.append_value(value);
+}
+
+/// whether `text` matches a LIKE `pattern`: `%` matches any characters, `_` one character
+/// and `\` escapes the character after it. Literal characters compare as their bytes.
+pub(crate) fn like_match(text: &[u8], pattern: &[u8]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // where matching resumes when a literal fails: after the last `%` in the pattern, and
+    // one character further into the text than last time.
+    let mut resume: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'%' => {
+                    p += 1;
+                    resume = Some((p, t));
+                    continue;
+                }
+                b'_' => {
+                    t += char_width(text[t]);
+                    p += 1;
+                    continue;
+                }
+                b'\\' if p + 1 < pattern.len() && pattern[p + 1] == text[t] => {
+                    t += 1;
+                    p += 2;
+                    continue;
+                }
+                b'\\' if p + 1 < pattern.len() => {}
+                byte if byte == text[t] => {
+                    t += 1;
+                    p += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match resume {
+            Some((resume_p, resume_t)) => {
+                let resume_t = resume_t + char_width(text[resume_t]);
+                resume = Some((resume_p, resume_t));
+                p = resume_p;
+                t = resume_t;
+            }
+            None => return false,
+        }
+    }
+    while p < pattern.len() && pattern[p] == b'%' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+// bytes of the utf8 character starting with `first`.
+fn char_width(first: u8) -> usize {
+    match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
@@ -1,3 +1,10 @@
+mod buffer;
 mod gen;
+pub mod ir;
+pub use buffer::*;
 pub use gen::*;
 mod jit;
+pub use jit::code::JitCode;
+pub use jit::native_opcall::{NativeKind, NativeOp};
+mod llvm;
+pub use llvm::*;
@@ -0,0 +1,652 @@
+use std::collections::HashMap;
+
+use arrow::datatypes::{DataType, SchemaRef};
+use inkwell::{
+    builder::Builder,
+    context::Context,
+    intrinsics::Intrinsic,
+    module::Module,
+    types::{BasicTypeEnum, IntType},
+    values::{BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    IntPredicate,
+};
+
+use crate::gen::{
+    can_gen_cast, cast_can_fail, ArithOp, ArithmeticMode, BackendGenValue, BackendValue,
+    CodegenBackend, CompareOp, KernelErrorCode,
+};
+
+/// Generates the body of an LLVM function through [`CodegenBackend`]. Booleans and
+/// validity flags are i8 values of 0 or 1, like in cranelift code.
+pub(crate) struct LlvmGenContext<'ctx> {
+    pub(crate) context: &'ctx Context,
+    module: Module<'ctx>,
+    pub(crate) builder: Builder<'ctx>,
+    function: FunctionValue<'ctx>,
+    // every value handed out, indexed by its `BackendValue`.
+    values: Vec<BasicValueEnum<'ctx>>,
+    schema: SchemaRef,
+    // column index in schema -> value loaded for the current row.
+    columns: HashMap<usize, BackendGenValue>,
+    arithmetic_mode: ArithmeticMode,
+    error_record: Option<PointerValue<'ctx>>,
+    row: Option<IntValue<'ctx>>,
+}
+
+impl<'ctx> LlvmGenContext<'ctx> {
+    pub(crate) fn new(
+        context: &'ctx Context,
+        module: Module<'ctx>,
+        function: FunctionValue<'ctx>,
+        schema: SchemaRef,
+        arithmetic_mode: ArithmeticMode,
+    ) -> Self {
+        Self {
+            context,
+            module,
+            builder: context.create_builder(),
+            function,
+            values: vec![],
+            schema,
+            columns: HashMap::new(),
+            arithmetic_mode,
+            error_record: None,
+            row: None,
+        }
+    }
+
+    /// the module the function was generated in.
+    pub(crate) fn into_module(self) -> Module<'ctx> {
+        self.module
+    }
+
+    pub(crate) fn value(&self, value: BackendValue) -> BasicValueEnum<'ctx> {
+        self.values[value.0 as usize]
+    }
+
+    pub(crate) fn int_value(&self, value: BackendValue) -> IntValue<'ctx> {
+        self.value(value).into_int_value()
+    }
+
+    pub(crate) fn add_value(&mut self, value: impl Into<BasicValueEnum<'ctx>>) -> BackendValue {
+        self.values.push(value.into());
+        BackendValue(self.values.len() as u32 - 1)
+    }
+
+    pub(crate) fn bind_column(&mut self, index: usize, value: BackendGenValue) {
+        self.columns.insert(index, value);
+    }
+
+    /// let generated code fail, see [`FuncGenContext::bind_error_record`].
+    ///
+    /// [`FuncGenContext::bind_error_record`]: crate::gen::FuncGenContext::bind_error_record
+    pub(crate) fn bind_error_record(&mut self, ptr: PointerValue<'ctx>) {
+        self.error_record = Some(ptr);
+    }
+
+    pub(crate) fn bind_row(&mut self, row: IntValue<'ctx>) {
+        self.row = Some(row);
+    }
+
+    /// llvm type of one value of `data_type`. Decimals and strings aren't supported.
+    pub(crate) fn llvm_type(&self, data_type: &DataType) -> Option<BasicTypeEnum<'ctx>> {
+        let context = self.context;
+        match data_type {
+            DataType::Boolean | DataType::Int8 | DataType::UInt8 => Some(context.i8_type().into()),
+            DataType::Int16 | DataType::UInt16 => Some(context.i16_type().into()),
+            DataType::Int32 | DataType::UInt32 | DataType::Date32 => {
+                Some(context.i32_type().into())
+            }
+            DataType::Int64 | DataType::UInt64 | DataType::Date64 | DataType::Timestamp(_, _) => {
+                Some(context.i64_type().into())
+            }
+            DataType::Float32 => Some(context.f32_type().into()),
+            DataType::Float64 => Some(context.f64_type().into()),
+            _ => None,
+        }
+    }
+
+    // an i1 condition as a boolean byte.
+    pub(crate) fn to_bool(&self, cond: IntValue<'ctx>) -> IntValue<'ctx> {
+        self.builder
+            .build_int_z_extend(cond, self.context.i8_type(), "")
+            .unwrap()
+    }
+
+    // a boolean byte as an i1 condition.
+    pub(crate) fn to_cond(&self, value: IntValue<'ctx>) -> IntValue<'ctx> {
+        let zero = value.get_type().const_zero();
+        self.builder
+            .build_int_compare(IntPredicate::NE, value, zero, "")
+            .unwrap()
+    }
+
+    /// leave the function with `code` when the i1 `cond` holds for a row that isn't null.
+    pub(crate) fn fail_if(
+        &mut self,
+        cond: IntValue<'ctx>,
+        valid: Option<BackendValue>,
+        code: KernelErrorCode,
+    ) {
+        let cond = match valid {
+            Some(valid) => {
+                let valid = self.to_cond(self.int_value(valid));
+                self.builder.build_and(cond, valid, "").unwrap()
+            }
+            None => cond,
+        };
+        let ptr = self
+            .error_record
+            .expect("generated code can only fail with an error record bound");
+        let row = self.row.expect("generated code can only fail within a row");
+        let error_block = self.context.append_basic_block(self.function, "error");
+        let next_block = self.context.append_basic_block(self.function, "next");
+        self.builder
+            .build_conditional_branch(cond, error_block, next_block)
+            .unwrap();
+
+        self.builder.position_at_end(error_block);
+        let i64_type = self.context.i64_type();
+        let code = i64_type.const_int(code as u64, false);
+        self.builder.build_store(ptr, code).unwrap();
+        let row_ptr = unsafe {
+            self.builder
+                .build_gep(i64_type, ptr, &[i64_type.const_int(1, false)], "")
+                .unwrap()
+        };
+        self.builder.build_store(row_ptr, row).unwrap();
+        let failed = i64_type.const_all_ones();
+        self.builder.build_return(Some(&failed)).unwrap();
+
+        self.builder.position_at_end(next_block);
+    }
+
+    // the intrinsic `name` for the type of the operands. `*.with.overflow` return `{ result, i1 }`.
+    fn call_intrinsic(
+        &self,
+        name: &str,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let intrinsic = Intrinsic::find(name).unwrap_or_else(|| panic!("no intrinsic {}", name));
+        let func = intrinsic
+            .get_declaration(&self.module, &[lhs.get_type().into()])
+            .unwrap();
+        self.builder
+            .build_call(func, &[lhs.into(), rhs.into()], "")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    // the wrapped result of `lhs op rhs` and an i1 that is set if it overflowed.
+    fn with_overflow(
+        &self,
+        op: ArithOp,
+        signed: bool,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> (IntValue<'ctx>, IntValue<'ctx>) {
+        let name = match (op, signed) {
+            (ArithOp::Add, true) => "llvm.sadd.with.overflow",
+            (ArithOp::Add, false) => "llvm.uadd.with.overflow",
+            (ArithOp::Sub, true) => "llvm.ssub.with.overflow",
+            (ArithOp::Sub, false) => "llvm.usub.with.overflow",
+            (_, true) => "llvm.smul.with.overflow",
+            (_, false) => "llvm.umul.with.overflow",
+        };
+        let result = self.call_intrinsic(name, lhs, rhs).into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "").unwrap();
+        let overflow = self.builder.build_extract_value(result, 1, "").unwrap();
+        (value.into_int_value(), overflow.into_int_value())
+    }
+
+    fn int_bounds(&self, _type: IntType<'ctx>, signed: bool) -> (IntValue<'ctx>, IntValue<'ctx>) {
+        if !signed {
+            return (_type.const_zero(), _type.const_all_ones());
+        }
+        let bits = _type.get_bit_width();
+        let min = _type.const_int(1 << (bits - 1), false);
+        let max = _type.const_int(u64::MAX >> (65 - bits), false);
+        (min, max)
+    }
+
+    fn int_arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        valid: Option<BackendValue>,
+    ) -> IntValue<'ctx> {
+        let signed = !data_type.is_unsigned_integer();
+        let builder = &self.builder;
+        match self.arithmetic_mode {
+            ArithmeticMode::Wrapping => match op {
+                ArithOp::Add => builder.build_int_add(lhs, rhs, "").unwrap(),
+                ArithOp::Sub => builder.build_int_sub(lhs, rhs, "").unwrap(),
+                _ => builder.build_int_mul(lhs, rhs, "").unwrap(),
+            },
+            ArithmeticMode::Checked => {
+                let (value, overflow) = self.with_overflow(op, signed, lhs, rhs);
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                value
+            }
+            ArithmeticMode::Saturating if op != ArithOp::Mul => {
+                let name = match (op, signed) {
+                    (ArithOp::Add, true) => "llvm.sadd.sat",
+                    (ArithOp::Add, false) => "llvm.uadd.sat",
+                    (_, true) => "llvm.ssub.sat",
+                    (_, false) => "llvm.usub.sat",
+                };
+                self.call_intrinsic(name, lhs, rhs).into_int_value()
+            }
+            ArithmeticMode::Saturating => {
+                // products overflow towards the sign of the product.
+                let (value, overflow) = self.with_overflow(op, signed, lhs, rhs);
+                let (min, max) = self.int_bounds(lhs.get_type(), signed);
+                let bound = if signed {
+                    let sign = self.builder.build_xor(lhs, rhs, "").unwrap();
+                    let zero = lhs.get_type().const_zero();
+                    let negative = self
+                        .builder
+                        .build_int_compare(IntPredicate::SLT, sign, zero, "")
+                        .unwrap();
+                    self.builder.build_select(negative, min, max, "").unwrap()
+                } else {
+                    max.into()
+                };
+                self.builder
+                    .build_select(overflow, bound.into_int_value(), value, "")
+                    .unwrap()
+                    .into_int_value()
+            }
+        }
+    }
+
+    // `lhs / rhs`, or `lhs % rhs` when `rem`, like `FuncGenContext::arith` divides.
+    fn div_rem(
+        &mut self,
+        rem: bool,
+        data_type: &DataType,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        valid: Option<BackendValue>,
+    ) -> BackendGenValue {
+        // floats follow IEEE 754, so zero divisors give inf or NaN in every mode.
+        if data_type.is_floating() {
+            let (lhs, rhs) = (lhs.into_float_value(), rhs.into_float_value());
+            let value = if rem {
+                self.builder.build_float_rem(lhs, rhs, "").unwrap()
+            } else {
+                self.builder.build_float_div(lhs, rhs, "").unwrap()
+            };
+            return BackendGenValue::new(self.add_value(value), valid);
+        }
+        let zero = rhs.get_type().const_zero().into_int_value();
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs.into_int_value(), zero, "")
+            .unwrap();
+        let valid = match self.arithmetic_mode {
+            ArithmeticMode::Checked => {
+                self.fail_if(is_zero, valid, KernelErrorCode::DivideByZero);
+                valid
+            }
+            _ => {
+                let nonzero = self.builder.build_not(is_zero, "").unwrap();
+                let nonzero = self.to_bool(nonzero);
+                let nonzero = self.add_value(nonzero);
+                self.and_valid(valid, Some(nonzero))
+            }
+        };
+
+        let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+        let _type = rhs.get_type();
+        let one = _type.const_int(1, false);
+        // division by zero is undefined, so divide by one instead.
+        let rhs = self
+            .builder
+            .build_select(is_zero, one, rhs, "")
+            .unwrap()
+            .into_int_value();
+        let signed = !data_type.is_unsigned_integer();
+        if !signed {
+            let value = if rem {
+                self.builder.build_int_unsigned_rem(lhs, rhs, "").unwrap()
+            } else {
+                self.builder.build_int_unsigned_div(lhs, rhs, "").unwrap()
+            };
+            return BackendGenValue::new(self.add_value(value), valid);
+        }
+        // so is `MIN / -1`, which wraps to `MIN`.
+        let is_neg_one = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, _type.const_all_ones(), "")
+            .unwrap();
+        let safe_rhs = self
+            .builder
+            .build_select(is_neg_one, one, rhs, "")
+            .unwrap()
+            .into_int_value();
+        if rem {
+            // `x % -1` is 0 like any `x % 1`.
+            let value = self
+                .builder
+                .build_int_signed_rem(lhs, safe_rhs, "")
+                .unwrap();
+            return BackendGenValue::new(self.add_value(value), valid);
+        }
+        let quotient = self
+            .builder
+            .build_int_signed_div(lhs, safe_rhs, "")
+            .unwrap();
+        let negated = self.builder.build_int_neg(lhs, "").unwrap();
+        let wrapped = self
+            .builder
+            .build_select(is_neg_one, negated, quotient, "")
+            .unwrap()
+            .into_int_value();
+        if self.arithmetic_mode == ArithmeticMode::Wrapping {
+            return BackendGenValue::new(self.add_value(wrapped), valid);
+        }
+        let (min, max) = self.int_bounds(_type, true);
+        let is_min = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, lhs, min, "")
+            .unwrap();
+        let overflow = self.builder.build_and(is_min, is_neg_one, "").unwrap();
+        let value = match self.arithmetic_mode {
+            ArithmeticMode::Checked => {
+                self.fail_if(overflow, valid, KernelErrorCode::Overflow);
+                wrapped
+            }
+            _ => self
+                .builder
+                .build_select(overflow, max, wrapped, "")
+                .unwrap()
+                .into_int_value(),
+        };
+        BackendGenValue::new(self.add_value(value), valid)
+    }
+
+    // the float's bits as a signed integer ordered like `total_cmp` orders the float.
+    fn total_order_key(&self, value: BasicValueEnum<'ctx>) -> IntValue<'ctx> {
+        let value = value.into_float_value();
+        let int_type = if value.get_type() == self.context.f32_type() {
+            self.context.i32_type()
+        } else {
+            self.context.i64_type()
+        };
+        let bits = self
+            .builder
+            .build_bitcast(value, int_type, "")
+            .unwrap()
+            .into_int_value();
+        let shift = int_type.const_int(int_type.get_bit_width() as u64 - 1, false);
+        let sign = self
+            .builder
+            .build_right_shift(bits, shift, true, "")
+            .unwrap();
+        let one = int_type.const_int(1, false);
+        let mask = self
+            .builder
+            .build_right_shift(sign, one, false, "")
+            .unwrap();
+        self.builder.build_xor(bits, mask, "").unwrap()
+    }
+
+    fn kleene_valid(
+        &mut self,
+        lhs: BackendGenValue,
+        lhs_decides: IntValue<'ctx>,
+        rhs: BackendGenValue,
+        rhs_decides: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let lhs_valid = self.valid_or_true(lhs.valid);
+        let rhs_valid = self.valid_or_true(rhs.valid);
+        let builder = &self.builder;
+        let both = builder.build_and(lhs_valid, rhs_valid, "").unwrap();
+        let by_lhs = builder.build_and(lhs_valid, lhs_decides, "").unwrap();
+        let by_rhs = builder.build_and(rhs_valid, rhs_decides, "").unwrap();
+        let valid = builder.build_or(both, by_lhs, "").unwrap();
+        builder.build_or(valid, by_rhs, "").unwrap()
+    }
+
+    fn valid_or_true(&self, valid: Option<BackendValue>) -> IntValue<'ctx> {
+        match valid {
+            Some(valid) => self.int_value(valid),
+            None => self.context.i8_type().const_int(1, false),
+        }
+    }
+}
+
+// integers, dates and timestamps, all held as integers.
+fn is_int_like(data_type: &DataType) -> bool {
+    data_type.is_integer() || data_type.is_temporal()
+}
+
+impl<'ctx> CodegenBackend for LlvmGenContext<'ctx> {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    fn column(&self, index: usize) -> Option<BackendGenValue> {
+        self.columns.get(&index).copied()
+    }
+
+    fn int_const(&mut self, data_type: &DataType, value: i128) -> Option<BackendValue> {
+        let _type = self.llvm_type(data_type)?;
+        if !_type.is_int_type() {
+            return None;
+        }
+        // narrow constants are given by their zero-extended bits.
+        let value = _type.into_int_type().const_int(value as u64, false);
+        Some(self.add_value(value))
+    }
+
+    fn float_const(&mut self, data_type: &DataType, value: f64) -> Option<BackendValue> {
+        let value = match data_type {
+            DataType::Float32 => self.context.f32_type().const_float(value as f32 as f64),
+            DataType::Float64 => self.context.f64_type().const_float(value),
+            _ => return None,
+        };
+        Some(self.add_value(value))
+    }
+
+    fn arith(
+        &mut self,
+        op: ArithOp,
+        data_type: &DataType,
+        lhs: BackendGenValue,
+        rhs: BackendGenValue,
+    ) -> Option<BackendGenValue> {
+        self.llvm_type(data_type)?;
+        let valid = self.and_valid(lhs.valid, rhs.valid);
+        let (lhs, rhs) = (self.value(lhs.value), self.value(rhs.value));
+        if matches!(op, ArithOp::Div | ArithOp::Mod) {
+            return Some(self.div_rem(op == ArithOp::Mod, data_type, lhs, rhs, valid));
+        }
+        let value: BasicValueEnum = if data_type.is_floating() {
+            let (lhs, rhs) = (lhs.into_float_value(), rhs.into_float_value());
+            let builder = &self.builder;
+            match op {
+                ArithOp::Add => builder.build_float_add(lhs, rhs, "").unwrap(),
+                ArithOp::Sub => builder.build_float_sub(lhs, rhs, "").unwrap(),
+                _ => builder.build_float_mul(lhs, rhs, "").unwrap(),
+            }
+            .into()
+        } else {
+            let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+            self.int_arith(op, data_type, lhs, rhs, valid).into()
+        };
+        Some(BackendGenValue::new(self.add_value(value), valid))
+    }
+
+    fn compare(
+        &mut self,
+        op: CompareOp,
+        data_type: &DataType,
+        lhs: BackendValue,
+        rhs: BackendValue,
+    ) -> Option<BackendValue> {
+        self.llvm_type(data_type)?;
+        let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+        let (lhs, rhs, signed) = if data_type.is_floating() {
+            (self.total_order_key(lhs), self.total_order_key(rhs), true)
+        } else {
+            let signed = data_type != &DataType::Boolean && !data_type.is_unsigned_integer();
+            (lhs.into_int_value(), rhs.into_int_value(), signed)
+        };
+        let predicate = match (op, signed) {
+            (CompareOp::Eq, _) => IntPredicate::EQ,
+            (CompareOp::NotEq, _) => IntPredicate::NE,
+            (CompareOp::Lt, true) => IntPredicate::SLT,
+            (CompareOp::Lt, false) => IntPredicate::ULT,
+            (CompareOp::LtEq, true) => IntPredicate::SLE,
+            (CompareOp::LtEq, false) => IntPredicate::ULE,
+            (CompareOp::Gt, true) => IntPredicate::SGT,
+            (CompareOp::Gt, false) => IntPredicate::UGT,
+            (CompareOp::GtEq, true) => IntPredicate::SGE,
+            (CompareOp::GtEq, false) => IntPredicate::UGE,
+        };
+        let cond = self
+            .builder
+            .build_int_compare(predicate, lhs, rhs, "")
+            .unwrap();
+        let value = self.to_bool(cond);
+        Some(self.add_value(value))
+    }
+
+    fn cast(
+        &mut self,
+        value: BackendValue,
+        from: &DataType,
+        to: &DataType,
+    ) -> Option<BackendValue> {
+        if from == to {
+            return Some(value);
+        }
+        if !can_gen_cast(from, to) || cast_can_fail(from, to) {
+            return None;
+        }
+        // dates and timestamps convert to each other by scaling, which isn't supported.
+        if from.is_temporal() && to.is_temporal() {
+            return None;
+        }
+        let to_type = self.llvm_type(to)?;
+        self.llvm_type(from)?;
+        let value = self.value(value);
+        let signed = !from.is_unsigned_integer();
+        let builder = &self.builder;
+        let value: BasicValueEnum = match (is_int_like(from), is_int_like(to)) {
+            (true, true) => {
+                let (value, to_type) = (value.into_int_value(), to_type.into_int_type());
+                if value.get_type().get_bit_width() == to_type.get_bit_width() {
+                    value.into()
+                } else if signed {
+                    builder
+                        .build_int_s_extend(value, to_type, "")
+                        .unwrap()
+                        .into()
+                } else {
+                    builder
+                        .build_int_z_extend(value, to_type, "")
+                        .unwrap()
+                        .into()
+                }
+            }
+            (true, false) if to.is_floating() => {
+                let (value, to_type) = (value.into_int_value(), to_type.into_float_type());
+                if signed {
+                    builder
+                        .build_signed_int_to_float(value, to_type, "")
+                        .unwrap()
+                        .into()
+                } else {
+                    builder
+                        .build_unsigned_int_to_float(value, to_type, "")
+                        .unwrap()
+                        .into()
+                }
+            }
+            (false, false) if from.is_floating() && to.is_floating() => builder
+                .build_float_cast(value.into_float_value(), to_type.into_float_type(), "")
+                .unwrap()
+                .into(),
+            _ => return None,
+        };
+        Some(self.add_value(value))
+    }
+
+    fn and_valid(
+        &mut self,
+        lhs: Option<BackendValue>,
+        rhs: Option<BackendValue>,
+    ) -> Option<BackendValue> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => {
+                let (lhs, rhs) = (self.int_value(lhs), self.int_value(rhs));
+                let valid = self.builder.build_and(lhs, rhs, "").unwrap();
+                Some(self.add_value(valid))
+            }
+            (Some(valid), None) | (None, Some(valid)) => Some(valid),
+            (None, None) => None,
+        }
+    }
+
+    fn kleene_not(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let value = self.int_value(input.value);
+        let one = value.get_type().const_int(1, false);
+        let value = self.builder.build_xor(value, one, "").unwrap();
+        BackendGenValue::new(self.add_value(value), input.valid)
+    }
+
+    fn kleene_and(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs_value, rhs_value) = (self.int_value(lhs.value), self.int_value(rhs.value));
+        let value = self.builder.build_and(lhs_value, rhs_value, "").unwrap();
+        let value = self.add_value(value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return BackendGenValue::non_null(value);
+        }
+        let one = lhs_value.get_type().const_int(1, false);
+        let lhs_false = self.builder.build_xor(lhs_value, one, "").unwrap();
+        let rhs_false = self.builder.build_xor(rhs_value, one, "").unwrap();
+        let valid = self.kleene_valid(lhs, lhs_false, rhs, rhs_false);
+        BackendGenValue::new(value, Some(self.add_value(valid)))
+    }
+
+    fn kleene_or(&mut self, lhs: BackendGenValue, rhs: BackendGenValue) -> BackendGenValue {
+        let (lhs_value, rhs_value) = (self.int_value(lhs.value), self.int_value(rhs.value));
+        let value = self.builder.build_or(lhs_value, rhs_value, "").unwrap();
+        let value = self.add_value(value);
+        if lhs.valid.is_none() && rhs.valid.is_none() {
+            return BackendGenValue::non_null(value);
+        }
+        let valid = self.kleene_valid(lhs, lhs_value, rhs, rhs_value);
+        BackendGenValue::new(value, Some(self.add_value(valid)))
+    }
+
+    fn is_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let i8_type = self.context.i8_type();
+        let value = match input.valid {
+            Some(valid) => {
+                let valid = self.int_value(valid);
+                let one = i8_type.const_int(1, false);
+                self.builder.build_xor(valid, one, "").unwrap()
+            }
+            None => i8_type.const_zero(),
+        };
+        BackendGenValue::non_null(self.add_value(value))
+    }
+
+    fn is_not_null(&mut self, input: BackendGenValue) -> BackendGenValue {
+        let value = self.valid_or_true(input.valid);
+        BackendGenValue::non_null(self.add_value(value))
+    }
+}
@@ -0,0 +1,326 @@
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, SchemaRef};
+use common::{Result, ServerError};
+use inkwell::{
+    context::Context,
+    execution_engine::ExecutionEngine,
+    module::Module,
+    passes::PassBuilderOptions,
+    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine},
+    values::{IntValue, PointerValue},
+    AddressSpace, IntPredicate, OptimizationLevel,
+};
+
+use crate::gen::{ArithmeticMode, BackendGenValue, ExprGen};
+use crate::jit::code::JitCode;
+use crate::llvm::LlvmGenContext;
+
+const KERNEL_NAME: &str = "compiled_exprs";
+
+/// A kernel generated by LLVM, called like the kernels cranelift generates for
+/// expressions: (column pointers, validity bitmap pointers, output pointers, output
+/// validity pointers, error record, row count) -> rows written, or -1 after writing the
+/// error record.
+pub struct LlvmKernel {
+    pub code: JitCode,
+    /// whether the kernel writes an output validity buffer, for every output.
+    pub nullable: Vec<bool>,
+    /// the optimized IR of the kernel, to compare it with the code cranelift generates.
+    pub ir: String,
+}
+
+/// Owns the LLVM context, module and execution engine of a kernel, which hold its code.
+pub(crate) struct LlvmCode {
+    module: Option<Module<'static>>,
+    engine: Option<ExecutionEngine<'static>>,
+    context: *mut Context,
+}
+
+impl Drop for LlvmCode {
+    fn drop(&mut self) {
+        // the engine owns the module once it's created, and both live in the context.
+        self.module.take();
+        self.engine.take();
+        unsafe { drop(Box::from_raw(self.context)) };
+    }
+}
+
+fn llvm_error(err: impl ToString) -> ServerError {
+    ServerError::LlvmError(err.to_string())
+}
+
+/// compile `exprs` over `columns` of `schema`, each read by [`CodegenBackend::column`],
+/// into a kernel optimized like `-O3`. With a predicate, `exprs` are only computed for
+/// rows where it's true and written densely. Fails with [`ServerError::NotSupported`]
+/// for expressions [`ExprGen::gen_backend`] doesn't generate.
+///
+/// [`CodegenBackend::column`]: crate::gen::CodegenBackend::column
+pub fn compile_llvm_kernel<E: ExprGen + ?Sized>(
+    predicate: Option<&E>,
+    exprs: &[Arc<E>],
+    schema: SchemaRef,
+    columns: &[usize],
+    mode: ArithmeticMode,
+) -> Result<LlvmKernel> {
+    // declared first so it is dropped last, after everything living in the context.
+    let mut code = LlvmCode {
+        module: None,
+        engine: None,
+        context: Box::into_raw(Box::new(Context::create())),
+    };
+    let context: &'static Context = unsafe { &*code.context };
+
+    let i64_type = context.i64_type();
+    let ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+    let fn_type = i64_type.fn_type(
+        &[
+            ptr_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            i64_type.into(),
+        ],
+        false,
+    );
+    let module = context.create_module(KERNEL_NAME);
+    let function = module.add_function(KERNEL_NAME, fn_type, None);
+    let mut ctx = LlvmGenContext::new(context, module, function, schema.clone(), mode);
+    let entry_block = context.append_basic_block(function, "entry");
+    ctx.builder.position_at_end(entry_block);
+    let param = |i: u32| function.get_nth_param(i).unwrap();
+    let len = param(5).into_int_value();
+    // the error record is two i64s. The cast is a no-op with opaque pointers.
+    let error_record = ctx
+        .builder
+        .build_pointer_cast(
+            param(4).into_pointer_value(),
+            context.i64_type().ptr_type(AddressSpace::default()),
+            "",
+        )
+        .unwrap();
+    ctx.bind_error_record(error_record);
+
+    let mut column_types = vec![];
+    for index in columns {
+        let data_type = schema.field(*index).data_type();
+        let _type = ctx.llvm_type(data_type).ok_or_else(|| {
+            ServerError::NotSupported(format!("{} columns with the llvm backend", data_type))
+        })?;
+        column_types.push(_type);
+    }
+    let column_ptrs = load_ptrs(&ctx, param(0).into_pointer_value(), columns.len());
+    let validity_ptrs = load_ptrs(&ctx, param(1).into_pointer_value(), columns.len());
+    let output_ptrs = load_ptrs(&ctx, param(2).into_pointer_value(), exprs.len());
+    let output_validity_ptrs = load_ptrs(&ctx, param(3).into_pointer_value(), exprs.len());
+
+    // loop counters live on the stack, LLVM promotes them to registers.
+    let row_var = ctx.builder.build_alloca(i64_type, "row").unwrap();
+    let out_var = ctx.builder.build_alloca(i64_type, "out_row").unwrap();
+    ctx.builder
+        .build_store(row_var, i64_type.const_zero())
+        .unwrap();
+    ctx.builder
+        .build_store(out_var, i64_type.const_zero())
+        .unwrap();
+    let header_block = context.append_basic_block(function, "header");
+    let body_block = context.append_basic_block(function, "body");
+    let exit_block = context.append_basic_block(function, "exit");
+    ctx.builder
+        .build_unconditional_branch(header_block)
+        .unwrap();
+
+    ctx.builder.position_at_end(header_block);
+    let row = ctx
+        .builder
+        .build_load(i64_type, row_var, "")
+        .unwrap()
+        .into_int_value();
+    let more = ctx
+        .builder
+        .build_int_compare(IntPredicate::SLT, row, len, "")
+        .unwrap();
+    ctx.builder
+        .build_conditional_branch(more, body_block, exit_block)
+        .unwrap();
+
+    ctx.builder.position_at_end(body_block);
+    ctx.bind_row(row);
+    for (i, index) in columns.iter().enumerate() {
+        let field = schema.field(*index);
+        let value = match field.data_type() {
+            DataType::Boolean => load_bit(&ctx, column_ptrs[i], row).into(),
+            _ => {
+                let addr = unsafe {
+                    ctx.builder
+                        .build_gep(column_types[i], column_ptrs[i], &[row], "")
+                        .unwrap()
+                };
+                ctx.builder.build_load(column_types[i], addr, "").unwrap()
+            }
+        };
+        let value = ctx.add_value(value);
+        let valid = field.is_nullable().then(|| {
+            let valid = load_bit(&ctx, validity_ptrs[i], row);
+            ctx.add_value(valid)
+        });
+        ctx.bind_column(*index, BackendGenValue::new(value, valid));
+    }
+
+    let next_block = context.append_basic_block(function, "next");
+    let out_row = match predicate {
+        None => row,
+        Some(predicate) => {
+            let keep = gen_backend(predicate, &mut ctx)?;
+            // rows where the predicate is null are dropped like false ones.
+            let keep = match keep.valid {
+                Some(valid) => {
+                    let (value, valid) = (ctx.int_value(keep.value), ctx.int_value(valid));
+                    ctx.builder.build_and(value, valid, "").unwrap()
+                }
+                None => ctx.int_value(keep.value),
+            };
+            let keep = ctx.to_cond(keep);
+            let keep_block = context.append_basic_block(function, "keep");
+            ctx.builder
+                .build_conditional_branch(keep, keep_block, next_block)
+                .unwrap();
+            ctx.builder.position_at_end(keep_block);
+            ctx.builder
+                .build_load(i64_type, out_var, "")
+                .unwrap()
+                .into_int_value()
+        }
+    };
+    let mut nullable = vec![];
+    for (i, expr) in exprs.iter().enumerate() {
+        let result = gen_backend(expr.as_ref(), &mut ctx)?;
+        let value = ctx.value(result.value);
+        let addr = unsafe {
+            ctx.builder
+                .build_gep(value.get_type(), output_ptrs[i], &[out_row], "")
+                .unwrap()
+        };
+        ctx.builder.build_store(addr, value).unwrap();
+        if let Some(valid) = result.valid {
+            let valid = ctx.int_value(valid);
+            let addr = unsafe {
+                ctx.builder
+                    .build_gep(context.i8_type(), output_validity_ptrs[i], &[out_row], "")
+                    .unwrap()
+            };
+            ctx.builder.build_store(addr, valid).unwrap();
+        }
+        nullable.push(result.valid.is_some());
+    }
+    let one = i64_type.const_int(1, false);
+    let next_out = ctx.builder.build_int_add(out_row, one, "").unwrap();
+    ctx.builder.build_store(out_var, next_out).unwrap();
+    ctx.builder.build_unconditional_branch(next_block).unwrap();
+
+    ctx.builder.position_at_end(next_block);
+    let next_row = ctx.builder.build_int_add(row, one, "").unwrap();
+    ctx.builder.build_store(row_var, next_row).unwrap();
+    ctx.builder
+        .build_unconditional_branch(header_block)
+        .unwrap();
+
+    ctx.builder.position_at_end(exit_block);
+    let num_rows = ctx.builder.build_load(i64_type, out_var, "").unwrap();
+    ctx.builder.build_return(Some(&num_rows)).unwrap();
+
+    let module = ctx.into_module();
+    module.verify().map_err(llvm_error)?;
+    optimize(&module)?;
+    let ir = module.print_to_string().to_string();
+    let engine = module
+        .create_jit_execution_engine(OptimizationLevel::Aggressive)
+        .map_err(llvm_error)?;
+    let address = engine
+        .get_function_address(KERNEL_NAME)
+        .map_err(|err| llvm_error(format!("{:?}", err)))?;
+    code.module = Some(module);
+    code.engine = Some(engine);
+    Ok(LlvmKernel {
+        code: JitCode::llvm(code, address as *const u8),
+        nullable,
+        ir,
+    })
+}
+
+fn gen_backend<E: ExprGen + ?Sized>(expr: &E, ctx: &mut LlvmGenContext) -> Result<BackendGenValue> {
+    expr.gen_backend(ctx)
+        .ok_or_else(|| ServerError::NotSupported("expression with the llvm backend".to_string()))
+}
+
+/// run the `-O3` pipeline for the host, which vectorizes loops for its SIMD width.
+fn optimize(module: &Module) -> Result<()> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(llvm_error)?;
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple).map_err(llvm_error)?;
+    let machine = target
+        .create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            OptimizationLevel::Aggressive,
+            RelocMode::Default,
+            CodeModel::JITDefault,
+        )
+        .ok_or_else(|| llvm_error("no target machine for the host"))?;
+    module.set_triple(&triple);
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    module
+        .run_passes("default<O3>", &machine, PassBuilderOptions::create())
+        .map_err(llvm_error)
+}
+
+// `count` pointers stored one after another from `base`.
+fn load_ptrs<'ctx>(
+    ctx: &LlvmGenContext<'ctx>,
+    base: PointerValue<'ctx>,
+    count: usize,
+) -> Vec<PointerValue<'ctx>> {
+    let ptr_type = ctx.context.i8_type().ptr_type(AddressSpace::default());
+    let i64_type = ctx.context.i64_type();
+    (0..count)
+        .map(|slot| {
+            let index = i64_type.const_int(slot as u64, false);
+            let addr = unsafe { ctx.builder.build_gep(ptr_type, base, &[index], "").unwrap() };
+            ctx.builder
+                .build_load(ptr_type, addr, "")
+                .unwrap()
+                .into_pointer_value()
+        })
+        .collect()
+}
+
+// the `index`th bit of a bitmap as an i8 of 0 or 1.
+fn load_bit<'ctx>(
+    ctx: &LlvmGenContext<'ctx>,
+    ptr: PointerValue<'ctx>,
+    index: IntValue<'ctx>,
+) -> IntValue<'ctx> {
+    let builder = &ctx.builder;
+    let i8_type = ctx.context.i8_type();
+    let i64_type = ctx.context.i64_type();
+    let byte_index = builder
+        .build_right_shift(index, i64_type.const_int(3, false), false, "")
+        .unwrap();
+    let addr = unsafe { builder.build_gep(i8_type, ptr, &[byte_index], "").unwrap() };
+    let byte = builder
+        .build_load(i8_type, addr, "")
+        .unwrap()
+        .into_int_value();
+    let bit_index = builder
+        .build_and(index, i64_type.const_int(7, false), "")
+        .unwrap();
+    let bit_index = builder.build_int_truncate(bit_index, i8_type, "").unwrap();
+    let bit = builder
+        .build_right_shift(byte, bit_index, false, "")
+        .unwrap();
+    builder
+        .build_and(bit, i8_type.const_int(1, false), "")
+        .unwrap()
+}
@@ -0,0 +1,6 @@
+mod ctx;
+mod kernel;
+
+pub(crate) use ctx::LlvmGenContext;
+pub(crate) use kernel::LlvmCode;
+pub use kernel::{compile_llvm_kernel, LlvmKernel};
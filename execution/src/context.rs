@@ -1,25 +1,157 @@
-use std::sync::Arc;
+use common::{Result, ServerError};
+use core::{ArithmeticMode, TypedNativeFn};
+use physical_expr::{
+    expr::scalar_function::ScalarFunctionExpr,
+    function::{builtin_functions, ScalarFunction},
+    ExecutionMode, KernelCache, PhysicalExprRef,
+};
+use std::{collections::HashMap, sync::Arc};
 
-pub struct FuncRegistry {}
+/// Scalar functions queries can call by name, case insensitively. Starts out with the
+/// builtin functions.
+pub struct FuncRegistry {
+    functions: HashMap<String, Arc<ScalarFunction>>,
+}
+
+impl Default for FuncRegistry {
+    fn default() -> Self {
+        FuncRegistry::new()
+    }
+}
+
+impl FuncRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            functions: HashMap::new(),
+        };
+        for func in builtin_functions() {
+            registry
+                .register(func)
+                .expect("builtin functions have distinct names");
+        }
+        registry
+    }
+
+    /// fails if a function of the same name is already registered.
+    pub fn register(&mut self, func: ScalarFunction) -> Result<()> {
+        let name = func.name().to_lowercase();
+        if self.functions.contains_key(&name) {
+            return Err(ServerError::ArgumentError(format!(
+                "function {} is already registered",
+                name
+            )));
+        }
+        // native symbols are linked by name, so overloads of different functions
+        // mustn't share one.
+        for overload in func.overloads() {
+            let taken = self
+                .functions
+                .values()
+                .flat_map(|other| other.overloads())
+                .any(|other| other.name() == overload.name());
+            if taken {
+                return Err(ServerError::ArgumentError(format!(
+                    "native function {} is already registered",
+                    overload.name()
+                )));
+            }
+        }
+        self.functions.insert(name, Arc::new(func));
+        Ok(())
+    }
+
+    /// register the native function `func` as a user-defined function called `name`, e.g.
+    /// `registry.register_native("geohash", geohash as extern "C" fn(f64, f64) -> i64)`.
+    pub fn register_native<F: TypedNativeFn>(
+        &mut self,
+        name: impl Into<String>,
+        func: F,
+    ) -> Result<()> {
+        self.register(ScalarFunction::from_native(name, func)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<ScalarFunction>> {
+        self.functions.get(&name.to_lowercase()).cloned()
+    }
+
+    /// an expression calling the function `name` with `args`.
+    pub fn call(&self, name: &str, args: Vec<PhysicalExprRef>) -> Result<PhysicalExprRef> {
+        let func = self
+            .get(name)
+            .ok_or_else(|| ServerError::ArgumentError(format!("unknown function {}", name)))?;
+        Ok(Arc::new(ScalarFunctionExpr::new(func, args)))
+    }
+}
 
 pub struct MemoryPool {}
 
 pub struct ExecContext {
     func_registry: FuncRegistry,
+    kernel_cache: Arc<KernelCache>,
     memory_pool: MemoryPool,
+    arithmetic_mode: ArithmeticMode,
+    execution_mode: ExecutionMode,
 }
 
 pub type ExecContextRef = Arc<ExecContext>;
 
+impl Default for ExecContext {
+    fn default() -> Self {
+        ExecContext::new()
+    }
+}
+
 impl ExecContext {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            func_registry: FuncRegistry {},
+            func_registry: FuncRegistry::new(),
+            kernel_cache: Arc::new(KernelCache::default()),
             memory_pool: MemoryPool {},
+            arithmetic_mode: ArithmeticMode::default(),
+            execution_mode: ExecutionMode::default(),
         }
     }
 
-    fn as_ref(self) -> ExecContextRef {
+    /// how kernels generated for this query treat integer overflow and zero divisors.
+    pub fn with_arithmetic_mode(mut self, mode: ArithmeticMode) -> Self {
+        self.arithmetic_mode = mode;
+        self
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    /// whether operators of this query interpret or compile their expressions.
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.execution_mode = mode;
+        self
+    }
+
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.execution_mode
+    }
+
+    /// share `cache` with other contexts, so queries they run again reuse its kernels.
+    pub fn with_kernel_cache(mut self, cache: Arc<KernelCache>) -> Self {
+        self.kernel_cache = cache;
+        self
+    }
+
+    /// kernels compiled by operators of this context.
+    pub fn kernel_cache(&self) -> &Arc<KernelCache> {
+        &self.kernel_cache
+    }
+
+    pub fn func_registry(&self) -> &FuncRegistry {
+        &self.func_registry
+    }
+
+    pub fn func_registry_mut(&mut self) -> &mut FuncRegistry {
+        &mut self.func_registry
+    }
+
+    pub fn as_ref(self) -> ExecContextRef {
         Arc::new(self)
     }
 }
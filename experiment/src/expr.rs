@@ -1,4 +1,4 @@
-use core::CodegenContext;
+use core::{ArrayLoop, CodegenContext, LoopOutput};
 use std::{mem, simd::{f64x4, i8x4, mask8x4, Simd, cmp::SimdPartialOrd}};
 
 use arrow::{
@@ -9,8 +9,7 @@ use arrow::{
     error::ArrowError,
 };
 use cranelift::codegen::ir::{
-    condcodes::{FloatCC, IntCC},
-    types, AbiParam, ArgumentPurpose, InstBuilder, MemFlags,
+    condcodes::FloatCC, types, AbiParam, ArgumentPurpose, InstBuilder, MemFlags,
 };
 
 pub fn hardcode_expr(a: f64, b: f64, c: f64, d: f64) -> bool {
@@ -23,16 +22,18 @@ pub fn native_arrow_expr<T: ArrowNativeTypeOp>(a: T, b: T, c: T, d: T) -> bool {
 
 pub fn jit_expr_v1() -> fn(f64, f64, f64, f64) -> bool {
     let mut ctx = CodegenContext::builder().debug().finish();
-    let mut func_ctx = ctx.create_func_gen_ctx(
-        "op_v1",
-        vec![
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-        ],
-        vec![AbiParam::new(types::I8)],
-    );
+    let mut func_ctx = ctx
+        .create_func_gen_ctx(
+            "op_v1",
+            vec![
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+            ],
+            vec![AbiParam::new(types::I8)],
+        )
+        .unwrap();
 
     let entry_block = func_ctx.builder.create_block();
     func_ctx.builder.switch_to_block(entry_block);
@@ -52,23 +53,25 @@ pub fn jit_expr_v1() -> fn(f64, f64, f64, f64) -> bool {
     let to_lt = func_ctx.builder.block_params(entry_block)[3];
 
     let res = func_ctx.call_f64_lt(div_result, to_lt);
-    let func_id = func_ctx.finalize(&[res]);
-    let code = ctx.finalize(func_id);
+    let func_id = func_ctx.finalize(&[res]).unwrap();
+    let code = ctx.finalize(func_id).unwrap();
     unsafe { mem::transmute::<_, fn(f64, f64, f64, f64) -> bool>(code) }
 }
 
 pub fn jit_expr_v2() -> fn(f64, f64, f64, f64) -> bool {
     let mut ctx = CodegenContext::builder().debug().finish();
-    let mut func_ctx = ctx.create_func_gen_ctx(
-        "op_v2",
-        vec![
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-        ],
-        vec![AbiParam::new(types::I8)],
-    );
+    let mut func_ctx = ctx
+        .create_func_gen_ctx(
+            "op_v2",
+            vec![
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+            ],
+            vec![AbiParam::new(types::I8)],
+        )
+        .unwrap();
 
     let entry_block = func_ctx.builder.create_block();
     func_ctx.builder.switch_to_block(entry_block);
@@ -87,8 +90,8 @@ pub fn jit_expr_v2() -> fn(f64, f64, f64, f64) -> bool {
     // lt on res and rhs
     let rhs = func_ctx.builder.block_params(entry_block)[3];
     let res = func_ctx.builder.ins().fcmp(FloatCC::LessThan, res, rhs);
-    let func_id = func_ctx.finalize(&[res]);
-    let code = ctx.finalize(func_id);
+    let func_id = func_ctx.finalize(&[res]).unwrap();
+    let code = ctx.finalize(func_id).unwrap();
     unsafe { mem::transmute::<_, fn(f64, f64, f64, f64) -> bool>(code) }
 }
 
@@ -129,11 +132,13 @@ pub fn native_arrow_expr_on_array(
 
 pub fn jit_index_f64() -> fn(*const f64, i64) -> f64 {
     let mut ctx = CodegenContext::builder().debug().finish();
-    let mut func_ctx = ctx.create_func_gen_ctx(
-        "op_test",
-        vec![AbiParam::new(ctx.ptype()), AbiParam::new(types::I64)],
-        vec![AbiParam::new(types::F64)],
-    );
+    let mut func_ctx = ctx
+        .create_func_gen_ctx(
+            "op_test",
+            vec![AbiParam::new(ctx.ptype()), AbiParam::new(types::I64)],
+            vec![AbiParam::new(types::F64)],
+        )
+        .unwrap();
     let entry_block = func_ctx.builder.create_block();
     func_ctx.builder.switch_to_block(entry_block);
     func_ctx
@@ -153,138 +158,64 @@ pub fn jit_index_f64() -> fn(*const f64, i64) -> f64 {
         .ins()
         .load(types::F64, MemFlags::new(), array_ref, 0);
 
-    let func_id = func_ctx.finalize(&[value]);
-    let code = ctx.finalize(func_id);
+    let func_id = func_ctx.finalize(&[value]).unwrap();
+    let code = ctx.finalize(func_id).unwrap();
 
     unsafe { mem::transmute::<_, fn(*const f64, i64) -> f64>(code) }
 }
 
-pub fn jit_expr_v3() -> extern "C" fn(*const u8, *const u8, *const bool, f64, f64, i64, i64) {
+pub fn jit_expr_v3() -> extern "C" fn(*const u8, *const u8, *mut bool, f64, f64, i64) {
     let mut ctx = CodegenContext::builder().debug().finish();
-    let data_type = types::F64X2;
-    let result_type = types::I8X2;
-
-    let mut func_ctx = ctx.create_func_gen_ctx(
-        "op_v3",
-        vec![
-            AbiParam::new(ctx.ptype()),
-            AbiParam::new(ctx.ptype()),
-            AbiParam::new(ctx.ptype()),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::F64),
-            AbiParam::new(types::I64),
-            AbiParam::new(types::I64),
-        ],
-        vec![],
-    );
-    let entry_block = func_ctx.builder.create_block();
-    let body_block = func_ctx.builder.create_block();
-    let exit_block = func_ctx.builder.create_block();
 
+    let mut func_ctx = ctx
+        .create_func_gen_ctx(
+            "op_v3",
+            vec![
+                AbiParam::new(ctx.ptype()),
+                AbiParam::new(ctx.ptype()),
+                AbiParam::new(ctx.ptype()),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::F64),
+                AbiParam::new(types::I64),
+            ],
+            vec![],
+        )
+        .unwrap();
+    let entry_block = func_ctx.builder.create_block();
     func_ctx.builder.switch_to_block(entry_block);
     func_ctx
         .builder
         .append_block_params_for_function_params(entry_block);
-    let p1 = func_ctx.builder.block_params(entry_block)[0];
-    let p2 = func_ctx.builder.block_params(entry_block)[1];
-    let p3 = func_ctx.builder.block_params(entry_block)[2];
-    let p4 = func_ctx.builder.block_params(entry_block)[3];
-    let p5 = func_ctx.builder.block_params(entry_block)[4];
-    let p6 = func_ctx.builder.block_params(entry_block)[5];
-    let p7 = func_ctx.builder.block_params(entry_block)[6];
-
-    let p4 = func_ctx.builder.ins().splat(data_type, p4);
-    let p5 = func_ctx.builder.ins().splat(data_type, p5);
-
-    func_ctx
-        .builder
-        .append_block_param(body_block, func_ctx.ptype);
-    func_ctx
-        .builder
-        .append_block_param(body_block, func_ctx.ptype);
-    func_ctx
-        .builder
-        .append_block_param(body_block, func_ctx.ptype);
-    func_ctx.builder.append_block_param(body_block, data_type);
-    func_ctx.builder.append_block_param(body_block, data_type);
-    func_ctx.builder.append_block_param(body_block, types::I64);
-    func_ctx.builder.append_block_param(body_block, types::I64);
-    func_ctx
-        .builder
-        .ins()
-        .jump(body_block, &[p1, p2, p3, p4, p5, p6, p7]);
-    func_ctx.builder.seal_block(entry_block);
-
-    func_ctx.builder.switch_to_block(body_block);
-    let lhs_ref = func_ctx.builder.block_params(body_block)[0];
-    let rhs_ref = func_ctx.builder.block_params(body_block)[1];
-    let result_ref = func_ctx.builder.block_params(body_block)[2];
-    let to_div = func_ctx.builder.block_params(body_block)[3];
-    let to_lt = func_ctx.builder.block_params(body_block)[4];
-    let start = func_ctx.builder.block_params(body_block)[5];
-    let end = func_ctx.builder.block_params(body_block)[6];
-
-    let lhs = func_ctx
-        .builder
-        .ins()
-        .load(data_type, MemFlags::new(), lhs_ref, 0);
-    let rhs = func_ctx
-        .builder
-        .ins()
-        .load(data_type, MemFlags::new(), rhs_ref, 0);
-    let sum = func_ctx.builder.ins().fadd(lhs, rhs);
-    let div_result = func_ctx.builder.ins().fdiv(sum, to_div);
-    let result = func_ctx
-        .builder
-        .ins()
-        .fcmp(FloatCC::LessThan, div_result, to_lt);
-
-    func_ctx
-        .builder
-        .ins()
-        .store(MemFlags::new(), result, result_ref, 0);
-
-    let offset = func_ctx
-        .builder
-        .ins()
-        .iconst(types::I64, data_type.bytes() as i64);
-
-    let result_offset = func_ctx
-        .builder
-        .ins()
-        .iconst(types::I64, result_type.bytes() as i64);
-
-    let next_lhs_ref = func_ctx.builder.ins().iadd(offset, lhs_ref);
-    let next_rhs_ref = func_ctx.builder.ins().iadd(offset, rhs_ref);
-    let next_result_ref = func_ctx.builder.ins().iadd(result_offset, result_ref);
-
-    let next_start = func_ctx.builder.ins().iadd_imm(start, 1);
-    let cond = func_ctx
-        .builder
-        .ins()
-        .icmp(IntCC::SignedLessThan, next_start, end);
-    func_ctx.builder.ins().brif(
-        cond,
-        body_block,
-        &[
-            next_lhs_ref,
-            next_rhs_ref,
-            next_result_ref,
-            to_div,
-            to_lt,
-            next_start,
-            end,
-        ],
-        exit_block,
-        &[],
-    );
-    func_ctx.builder.switch_to_block(exit_block);
-    let func_id = func_ctx.finalize(&[]);
-    let code = ctx.finalize(func_id);
+    let lhs_ref = func_ctx.builder.block_params(entry_block)[0];
+    let rhs_ref = func_ctx.builder.block_params(entry_block)[1];
+    let result_ref = func_ctx.builder.block_params(entry_block)[2];
+    let to_div = func_ctx.builder.block_params(entry_block)[3];
+    let to_lt = func_ctx.builder.block_params(entry_block)[4];
+    let len = func_ctx.builder.block_params(entry_block)[5];
+
+    let array_loop = ArrayLoop {
+        inputs: vec![(lhs_ref, types::F64), (rhs_ref, types::F64)],
+        outputs: vec![LoopOutput {
+            ptr: result_ref,
+            lane_type: types::I8,
+            is_bool: true,
+            string: None,
+        }],
+        len,
+        vectorize: true,
+    };
+    func_ctx.build_array_loop(array_loop, |ctx, _, inputs| {
+        let to_div = ctx.broadcast(to_div);
+        let to_lt = ctx.broadcast(to_lt);
+        let sum = ctx.builder.ins().fadd(inputs[0], inputs[1]);
+        let div_result = ctx.builder.ins().fdiv(sum, to_div);
+        vec![ctx.builder.ins().fcmp(FloatCC::LessThan, div_result, to_lt)]
+    });
+
+    let func_id = func_ctx.finalize(&[]).unwrap();
+    let code = ctx.finalize(func_id).unwrap();
     unsafe {
-        mem::transmute::<_, extern "C" fn(*const u8, *const u8, *const bool, f64, f64, i64, i64)>(
-            code,
-        )
+        mem::transmute::<_, extern "C" fn(*const u8, *const u8, *mut bool, f64, f64, i64)>(code)
     }
 }
 
@@ -293,7 +224,7 @@ pub fn jit_expr_on_array_v3(
     b: &Float64Array,
     c: f64,
     d: f64,
-    op: extern "C" fn(*const u8, *const u8, *const bool, f64, f64, i64, i64),
+    op: extern "C" fn(*const u8, *const u8, *mut bool, f64, f64, i64),
 ) -> Result<BooleanArray, ArrowError> {
     if a.len() != b.len() {
         return Err(ArrowError::ComputeError(
@@ -301,20 +232,13 @@ pub fn jit_expr_on_array_v3(
         ));
     }
 
-    if a.is_empty() {
-        return Err(ArrowError::ComputeError(
-            "Cannot perform binary operation on arrays of different length".to_string(),
-        ));
-    }
     let nulls = NullBuffer::union(a.logical_nulls().as_ref(), b.logical_nulls().as_ref());
     let a_ptr = a.values().inner().as_ptr();
     let b_ptr = b.values().inner().as_ptr();
-    // there is a memory problem for cranelift jit.
-    let mut res: Vec<bool> = Vec::with_capacity((a.len() / 2) * 2 + 10);
-    let res_ptr = res.as_ptr();
-    op(a_ptr, b_ptr, res_ptr, c, d, 0, (a.len() / 2) as i64);
+    let mut res: Vec<bool> = Vec::with_capacity(a.len());
+    op(a_ptr, b_ptr, res.as_mut_ptr(), c, d, a.len() as i64);
     unsafe {
-        res.set_len((a.len() / 2) * 2);
+        res.set_len(a.len());
     }
     let buffer = BooleanBuffer::from_iter(res);
 
@@ -385,7 +309,9 @@ pub fn simd_expr(
 mod tests {
 
     use arrow::{
-        array::Float64Array, datatypes::Float64Type, util::bench_util::create_primitive_array,
+        array::{Array, Float64Array},
+        datatypes::Float64Type,
+        util::bench_util::create_primitive_array,
     };
 
     use crate::expr::{jit_expr_v1, jit_expr_v2};
@@ -418,6 +344,20 @@ mod tests {
         assert_eq!(values.values(), &[0b0011]);
     }
 
+    #[test]
+    fn test_jit_expr_on_array_v3_odd_length() {
+        let a = &Float64Array::from(vec![2.0_f64, 4.0_f64, 5.0_f64, 6.0_f64, 1.0_f64]);
+        let b = &Float64Array::from(vec![3.0_f64, 4.0_f64, 6.0_f64, 7.0_f64, 1.0_f64]);
+        let op = jit_expr_v3();
+        let res = jit_expr_on_array_v3(a, b, 3.0_f64, 3.0_f64, op).unwrap();
+        let (values, _) = res.into_parts();
+        assert_eq!(values.values(), &[0b10011]);
+
+        let empty = &Float64Array::from(Vec::<f64>::new());
+        let res = jit_expr_on_array_v3(empty, empty, 3.0_f64, 3.0_f64, op).unwrap();
+        assert!(res.is_empty());
+    }
+
     #[test]
     fn test_jit_expr_on_array_v3_64() {
         let BATCH_SIZE = 64;
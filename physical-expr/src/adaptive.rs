@@ -0,0 +1,447 @@
+use core::ArithmeticMode;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use arrow::{
+    array::{ArrayRef, AsArray},
+    compute::filter_record_batch,
+    datatypes::{DataType, SchemaRef},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use common::{Result, ServerError};
+
+use crate::{resolve_types, with_arithmetic_mode, CompiledExprs, KernelCache, PhysicalExprRef};
+
+/// How operators evaluate their expressions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExecutionMode {
+    /// interpret every batch with [`PhysicalExpr::eval`](crate::PhysicalExpr::eval).
+    Interpreted,
+    /// compile a kernel before the first batch.
+    #[default]
+    Compiled,
+    /// interpret the first batches while a kernel compiles in the background once the
+    /// policy decides it pays off, then switch to the kernel.
+    Adaptive(TieringPolicy),
+}
+
+/// When adaptive execution starts compiling. Compiling pays off once interpreting would
+/// have taken longer than compiling, which is unknown up front, so compiling starts once
+/// interpreting took as long as a compile is expected to. That costs at most twice the
+/// better of both choices, like buying skis once renting them cost as much.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TieringPolicy {
+    /// rows interpreted before compiling is considered, so tiny inputs never compile.
+    pub min_rows: usize,
+    /// how long a compile is expected to take before the kernel cache observed one.
+    pub default_compile_latency: Duration,
+    /// start compiling once interpreting took this many times the expected compile
+    /// latency.
+    pub compile_ratio: f64,
+}
+
+impl Default for TieringPolicy {
+    fn default() -> Self {
+        Self {
+            min_rows: 10_000,
+            default_compile_latency: Duration::from_millis(5),
+            compile_ratio: 1.0,
+        }
+    }
+}
+
+/// Expressions evaluated batch by batch the way an [`ExecutionMode`] says, with the
+/// semantics of [`CompiledExprs::eval_batch`] whether a batch is interpreted or not.
+pub struct AdaptiveExprs {
+    predicate: Option<PhysicalExprRef>,
+    exprs: Vec<PhysicalExprRef>,
+    // the expressions with resolved types and arithmetic in `mode`, which the interpreter
+    // evaluates.
+    resolved_predicate: Option<PhysicalExprRef>,
+    resolved_exprs: Vec<PhysicalExprRef>,
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+    cache: Arc<KernelCache>,
+    tier: Tier,
+}
+
+enum Tier {
+    Interpreted {
+        policy: Option<TieringPolicy>,
+        rows: usize,
+        elapsed: Duration,
+    },
+    Compiling(Receiver<Result<Arc<CompiledExprs>>>),
+    Compiled(Arc<CompiledExprs>),
+}
+
+impl AdaptiveExprs {
+    /// evaluates `exprs` over batches of `schema`, only for rows where `predicate` is true
+    /// if there is one. Kernels are compiled through `cache`.
+    pub fn try_new(
+        predicate: Option<PhysicalExprRef>,
+        exprs: Vec<PhysicalExprRef>,
+        schema: SchemaRef,
+        mode: ArithmeticMode,
+        execution: ExecutionMode,
+        cache: Arc<KernelCache>,
+    ) -> Result<Self> {
+        if let Some(predicate) = &predicate {
+            if predicate.output_type(schema.clone()) != DataType::Boolean {
+                return Err(ServerError::TypeError(
+                    "filter predicate must be boolean".to_string(),
+                ));
+            }
+        }
+        let tier = match execution {
+            ExecutionMode::Compiled => {
+                let kernel = match &predicate {
+                    Some(predicate) => {
+                        cache.compile_filtered_exprs(predicate, &exprs, schema.clone(), mode)?
+                    }
+                    None => cache.compile_exprs(&exprs, schema.clone(), mode)?,
+                };
+                Tier::Compiled(kernel)
+            }
+            ExecutionMode::Interpreted => Tier::Interpreted {
+                policy: None,
+                rows: 0,
+                elapsed: Duration::ZERO,
+            },
+            // a kernel compiled earlier is free to use.
+            ExecutionMode::Adaptive(policy) => {
+                match cache.lookup(predicate.as_ref(), &exprs, &schema, mode) {
+                    Some(kernel) => Tier::Compiled(kernel),
+                    None => Tier::Interpreted {
+                        policy: Some(policy),
+                        rows: 0,
+                        elapsed: Duration::ZERO,
+                    },
+                }
+            }
+        };
+        let resolved_predicate = predicate
+            .as_ref()
+            .map(|predicate| with_arithmetic_mode(&resolve_types(predicate, &schema)?, mode))
+            .transpose()?;
+        let resolved_exprs = exprs
+            .iter()
+            .map(|expr| with_arithmetic_mode(&resolve_types(expr, &schema)?, mode))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            predicate,
+            exprs,
+            resolved_predicate,
+            resolved_exprs,
+            schema,
+            mode,
+            cache,
+            tier,
+        })
+    }
+
+    /// whether batches are evaluated by a compiled kernel now.
+    pub fn is_compiled(&self) -> bool {
+        matches!(self.tier, Tier::Compiled(_))
+    }
+
+    /// evaluate into a batch of `schema`, see [`CompiledExprs::eval_batch`].
+    pub fn eval_batch(&mut self, batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch> {
+        if let Tier::Compiling(receiver) = &self.tier {
+            match receiver.try_recv() {
+                Ok(Ok(kernel)) => self.tier = Tier::Compiled(kernel),
+                // expressions the kernel can't be compiled for keep being interpreted.
+                Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                    self.tier = Tier::Interpreted {
+                        policy: None,
+                        rows: 0,
+                        elapsed: Duration::ZERO,
+                    }
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        if let Tier::Compiled(kernel) = &self.tier {
+            return kernel.eval_batch(batch, schema);
+        }
+
+        let start = Instant::now();
+        let output = self.interpret(batch, schema)?;
+        if let Tier::Interpreted {
+            policy: Some(policy),
+            rows,
+            elapsed,
+        } = &mut self.tier
+        {
+            *rows += batch.num_rows();
+            *elapsed += start.elapsed();
+            let compile_latency = self
+                .cache
+                .compile_latency()
+                .unwrap_or(policy.default_compile_latency);
+            if *rows >= policy.min_rows
+                && elapsed.as_secs_f64() >= compile_latency.as_secs_f64() * policy.compile_ratio
+            {
+                self.tier = Tier::Compiling(self.compile_in_background());
+            }
+        }
+        Ok(output)
+    }
+
+    fn compile_in_background(&self) -> Receiver<Result<Arc<CompiledExprs>>> {
+        let (sender, receiver) = mpsc::channel();
+        let predicate = self.predicate.clone();
+        let exprs = self.exprs.clone();
+        let schema = self.schema.clone();
+        let mode = self.mode;
+        let cache = self.cache.clone();
+        // the kernel lands in the cache even if this is dropped before it is ready.
+        thread::spawn(move || {
+            let kernel = match &predicate {
+                Some(predicate) => cache.compile_filtered_exprs(predicate, &exprs, schema, mode),
+                None => cache.compile_exprs(&exprs, schema, mode),
+            };
+            let _ = sender.send(kernel);
+        });
+        receiver
+    }
+
+    // rows the predicate is null for are dropped, and expressions only see the rows kept.
+    fn interpret(&self, batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch> {
+        let filtered;
+        let batch = match &self.resolved_predicate {
+            Some(predicate) => {
+                let keep = predicate.eval(batch)?.into_array(batch.num_rows());
+                filtered = filter_record_batch(batch, keep.as_boolean())?;
+                &filtered
+            }
+            None => batch,
+        };
+        let columns = self
+            .resolved_exprs
+            .iter()
+            .map(|expr| Ok(expr.eval(batch)?.into_array(batch.num_rows())))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        let options = RecordBatchOptions::default().with_row_count(Some(batch.num_rows()));
+        Ok(RecordBatch::try_new_with_options(
+            schema, columns, &options,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::{sync::Arc, thread, time::Duration};
+
+    use arrow::{
+        array::{Array, AsArray, Float64Array, Int64Array},
+        datatypes::{DataType, Field, Float64Type, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        KernelCache, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::{AdaptiveExprs, ExecutionMode, TieringPolicy};
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn binary(op: Op, lhs: PhysicalExprRef, rhs: PhysicalExprRef) -> PhysicalExprRef {
+        Arc::new(BinaryExpr::new(op, lhs, rhs))
+    }
+
+    fn output_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![Field::new("q", DataType::Int64, true)]))
+    }
+
+    // a is 6, null, 9, 4 and b is 3, 0, 0, 2.
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let a = Int64Array::from(vec![Some(6), None, Some(9), Some(4)]);
+        let b = Int64Array::from(vec![3, 0, 0, 2]);
+        RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b)]).unwrap()
+    }
+
+    // a / b where b != 0, which fails for the rows it drops.
+    fn adaptive(execution: ExecutionMode, cache: &Arc<KernelCache>) -> AdaptiveExprs {
+        let zero = Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(0))));
+        AdaptiveExprs::try_new(
+            Some(binary(Op::NotEq, column("b", 1), zero)),
+            vec![binary(Op::Div, column("a", 0), column("b", 1))],
+            batch().schema(),
+            ArithmeticMode::Checked,
+            execution,
+            cache.clone(),
+        )
+        .unwrap()
+    }
+
+    fn check(exprs: &mut AdaptiveExprs) {
+        let output = exprs.eval_batch(&batch(), output_schema()).unwrap();
+        let expected = Int64Array::from(vec![Some(2), Some(2)]);
+        assert_eq!(output.column(0).as_ref(), &expected);
+    }
+
+    #[test]
+    fn test_adaptive_exprs() {
+        let cache = Arc::new(KernelCache::default());
+        let mut interpreted = adaptive(ExecutionMode::Interpreted, &cache);
+        for _ in 0..3 {
+            check(&mut interpreted);
+        }
+        assert!(!interpreted.is_compiled());
+        assert!(cache.is_empty());
+
+        // too few rows to compile for.
+        let policy = TieringPolicy {
+            min_rows: 1_000,
+            default_compile_latency: Duration::ZERO,
+            compile_ratio: 1.0,
+        };
+        let mut adaptive_exprs = adaptive(ExecutionMode::Adaptive(policy), &cache);
+        for _ in 0..3 {
+            check(&mut adaptive_exprs);
+        }
+        assert!(!adaptive_exprs.is_compiled());
+
+        // interpreted batches until the kernel compiled in the background is ready.
+        let policy = TieringPolicy {
+            min_rows: 4,
+            ..policy
+        };
+        let mut adaptive_exprs = adaptive(ExecutionMode::Adaptive(policy), &cache);
+        check(&mut adaptive_exprs);
+        for _ in 0..10_000 {
+            if adaptive_exprs.is_compiled() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+            check(&mut adaptive_exprs);
+        }
+        assert!(adaptive_exprs.is_compiled());
+        check(&mut adaptive_exprs);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.compile_latency().is_some());
+
+        // the cached kernel is used from the first batch on.
+        let policy = TieringPolicy {
+            min_rows: usize::MAX,
+            ..policy
+        };
+        let cached = adaptive(ExecutionMode::Adaptive(policy), &cache);
+        assert!(cached.is_compiled());
+        let mut compiled = adaptive(ExecutionMode::Compiled, &cache);
+        assert!(compiled.is_compiled());
+        check(&mut compiled);
+        assert_eq!(cache.misses(), 1);
+    }
+
+    #[test]
+    fn test_adaptive_exprs_arithmetic_mode() {
+        let cache = Arc::new(KernelCache::default());
+        // zero divisors give null when arithmetic wraps, interpreted or not.
+        let mut outputs = vec![];
+        for execution in [ExecutionMode::Interpreted, ExecutionMode::Compiled] {
+            let mut exprs = AdaptiveExprs::try_new(
+                None,
+                vec![binary(Op::Div, column("a", 0), column("b", 1))],
+                batch().schema(),
+                ArithmeticMode::Wrapping,
+                execution,
+                cache.clone(),
+            )
+            .unwrap();
+            let output = exprs.eval_batch(&batch(), output_schema()).unwrap();
+            outputs.push(output.column(0).clone());
+        }
+        let expected = Int64Array::from(vec![Some(2), None, None, Some(2)]);
+        assert_eq!(outputs[0].as_ref(), &expected);
+        assert_eq!(outputs[1].as_ref(), &expected);
+    }
+
+    #[test]
+    fn test_interpreted_matches_compiled() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+            Field::new("x", DataType::Float64, true),
+            Field::new("y", DataType::Float64, false),
+        ]));
+        let columns = vec![
+            Arc::new(Int64Array::from(vec![
+                Some(6),
+                None,
+                Some(-9),
+                Some(4),
+                Some(1),
+            ])) as _,
+            Arc::new(Int64Array::from(vec![3, 0, 2, -2, 5])) as _,
+            Arc::new(Float64Array::from(vec![
+                Some(1.5),
+                Some(-2.0),
+                None,
+                Some(0.0),
+                Some(f64::NAN),
+            ])) as _,
+            Arc::new(Float64Array::from(vec![0.0, -0.0, 4.0, 0.0, 2.0])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+        let (a, b) = (column("a", 0), column("b", 1));
+        let (x, y) = (column("x", 2), column("y", 3));
+        let output_schema = Arc::new(Schema::new(vec![
+            Field::new("sum", DataType::Int64, true),
+            Field::new("product", DataType::Int64, true),
+            Field::new("quotient", DataType::Float64, true),
+            Field::new("remainder", DataType::Float64, true),
+            Field::new("less", DataType::Boolean, true),
+        ]));
+        let zero = Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(0))));
+        let predicate = binary(Op::NotEq, b.clone(), zero);
+        let exprs = vec![
+            binary(Op::Add, a.clone(), b.clone()),
+            binary(Op::Mul, a, b),
+            // float zero divisors give inf or NaN even in checked arithmetic.
+            binary(Op::Div, x.clone(), y.clone()),
+            binary(Op::Mod, x.clone(), y.clone()),
+            binary(Op::Lt, x, y),
+        ];
+
+        let cache = Arc::new(KernelCache::default());
+        let mut outputs = vec![];
+        for execution in [ExecutionMode::Interpreted, ExecutionMode::Compiled] {
+            let mut exprs = AdaptiveExprs::try_new(
+                Some(predicate.clone()),
+                exprs.clone(),
+                batch.schema(),
+                ArithmeticMode::Checked,
+                execution,
+                cache.clone(),
+            )
+            .unwrap();
+            outputs.push(exprs.eval_batch(&batch, output_schema.clone()).unwrap());
+        }
+        assert_eq!(outputs[0], outputs[1]);
+        let quotient = outputs[1].column(2).as_primitive::<Float64Type>();
+        assert_eq!(quotient.value(0), f64::INFINITY);
+        assert!(quotient.is_null(1));
+        assert!(quotient.value(2).is_nan());
+    }
+}
@@ -0,0 +1,297 @@
+use core::ArithmeticMode;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use arrow::datatypes::SchemaRef;
+use common::Result;
+
+use crate::{compile_exprs, compile_filtered_exprs, fingerprint, CompiledExprs, PhysicalExprRef};
+
+/// Kernels compiled for expressions over a schema, reused when the same expressions are
+/// compiled again, e.g. by a repeated query. Holds at most `capacity` kernels and evicts
+/// the least recently used one for a new kernel. A kernel's machine code is freed once
+/// it is evicted and no operator uses it anymore.
+pub struct KernelCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    // advanced by every lookup, so the least recently used entry has the smallest tick.
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    // moving average of how long successful compiles took.
+    compile_latency: Option<Duration>,
+}
+
+struct CacheEntry {
+    kernel: Arc<CompiledExprs>,
+    last_used: u64,
+}
+
+impl Default for KernelCache {
+    fn default() -> Self {
+        KernelCache::new(KernelCache::DEFAULT_CAPACITY)
+    }
+}
+
+impl KernelCache {
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "kernel cache needs room for a kernel");
+        Self {
+            capacity,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                tick: 0,
+                hits: 0,
+                misses: 0,
+                compile_latency: None,
+            }),
+        }
+    }
+
+    /// [`compile_exprs`], reusing the kernel of an earlier call with the same arguments.
+    pub fn compile_exprs(
+        &self,
+        exprs: &[PhysicalExprRef],
+        schema: SchemaRef,
+        mode: ArithmeticMode,
+    ) -> Result<Arc<CompiledExprs>> {
+        let key = cache_key(None, exprs, &schema, mode);
+        self.get_or_compile(key, || compile_exprs(exprs, schema, mode))
+    }
+
+    /// [`compile_filtered_exprs`], reusing the kernel of an earlier call with the same
+    /// arguments.
+    pub fn compile_filtered_exprs(
+        &self,
+        predicate: &PhysicalExprRef,
+        exprs: &[PhysicalExprRef],
+        schema: SchemaRef,
+        mode: ArithmeticMode,
+    ) -> Result<Arc<CompiledExprs>> {
+        let key = cache_key(Some(predicate), exprs, &schema, mode);
+        self.get_or_compile(key, || {
+            compile_filtered_exprs(predicate, exprs, schema, mode)
+        })
+    }
+
+    /// the kernel cached for the arguments of [`KernelCache::compile_exprs`] or, with a
+    /// predicate, of [`KernelCache::compile_filtered_exprs`], without compiling one.
+    pub fn lookup(
+        &self,
+        predicate: Option<&PhysicalExprRef>,
+        exprs: &[PhysicalExprRef],
+        schema: &SchemaRef,
+        mode: ArithmeticMode,
+    ) -> Option<Arc<CompiledExprs>> {
+        self.get(&cache_key(predicate, exprs, schema, mode))
+    }
+
+    /// how long compiling a kernel took recently, none before the first one compiled.
+    pub fn compile_latency(&self) -> Option<Duration> {
+        self.state.lock().unwrap().compile_latency
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// compiles that reused a cached kernel.
+    pub fn hits(&self) -> u64 {
+        self.state.lock().unwrap().hits
+    }
+
+    /// compiles that generated a new kernel, including failed ones.
+    pub fn misses(&self) -> u64 {
+        self.state.lock().unwrap().misses
+    }
+
+    /// evict every kernel.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().entries.clear();
+    }
+
+    // the kernel cached for `key`, now the most recently used one.
+    fn get(&self, key: &str) -> Option<Arc<CompiledExprs>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = tick;
+        let kernel = entry.kernel.clone();
+        state.hits += 1;
+        Some(kernel)
+    }
+
+    fn get_or_compile(
+        &self,
+        key: String,
+        compile: impl FnOnce() -> Result<CompiledExprs>,
+    ) -> Result<Arc<CompiledExprs>> {
+        if let Some(kernel) = self.get(&key) {
+            return Ok(kernel);
+        }
+        self.state.lock().unwrap().misses += 1;
+        // other kernels can be looked up while this one compiles. Failures aren't cached,
+        // so they are reported again by the next compile.
+        let start = Instant::now();
+        let kernel = Arc::new(compile()?);
+        let latency = start.elapsed();
+        let mut state = self.state.lock().unwrap();
+        // recent compiles count as much as all earlier ones together.
+        state.compile_latency = Some(match state.compile_latency {
+            Some(average) => (average + latency) / 2,
+            None => latency,
+        });
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some(entry) = state.entries.get_mut(&key) {
+            // compiled concurrently by another thread.
+            entry.last_used = tick;
+            return Ok(entry.kernel.clone());
+        }
+        if state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            state.entries.remove(&oldest);
+        }
+        state.entries.insert(
+            key,
+            CacheEntry {
+                kernel: kernel.clone(),
+                last_used: tick,
+            },
+        );
+        Ok(kernel)
+    }
+}
+
+// everything a compiled kernel depends on. Schema metadata doesn't change the kernel.
+fn cache_key(
+    predicate: Option<&PhysicalExprRef>,
+    exprs: &[PhysicalExprRef],
+    schema: &SchemaRef,
+    mode: ArithmeticMode,
+) -> String {
+    let mut key = format!("{:?} {:?}", mode, schema.fields());
+    if let Some(predicate) = predicate {
+        write!(key, " where {}", fingerprint(predicate)).unwrap();
+    }
+    for expr in exprs {
+        write!(key, " {}", fingerprint(expr)).unwrap();
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int64Array},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        PhysicalExprRef, ScalarValue,
+    };
+
+    use super::KernelCache;
+
+    // a + `value`, built anew every time.
+    fn add(value: i64) -> PhysicalExprRef {
+        Arc::new(BinaryExpr::new(
+            Op::Add,
+            Arc::new(ColumnExpr::new("a".to_string(), 0)),
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value)))),
+        ))
+    }
+
+    fn int_batch(nullable: bool) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "a",
+            DataType::Int64,
+            nullable,
+        )]));
+        let a = Int64Array::from(vec![1, 2, 3]);
+        RecordBatch::try_new(schema, vec![Arc::new(a)]).unwrap()
+    }
+
+    #[test]
+    fn test_kernel_cache() {
+        let cache = KernelCache::new(2);
+        let batch = int_batch(false);
+        let mode = ArithmeticMode::Checked;
+        let compile = |value: i64| {
+            cache
+                .compile_exprs(&[add(value)], batch.schema(), mode)
+                .unwrap()
+        };
+
+        let first = compile(1);
+        let again = compile(1);
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![2, 3, 4]));
+        assert_eq!(&again.eval(&batch).unwrap()[0], &expected);
+
+        // other constants, schemas, modes and predicates make other kernels.
+        assert!(!Arc::ptr_eq(&first, &compile(2)));
+        let nullable = cache
+            .compile_exprs(&[add(1)], int_batch(true).schema(), mode)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &nullable));
+        let wrapping = cache
+            .compile_exprs(&[add(1)], batch.schema(), ArithmeticMode::Wrapping)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &wrapping));
+        let predicate = Arc::new(BinaryExpr::new(Op::Gt, add(1), add(2))) as PhysicalExprRef;
+        let filtered = cache
+            .compile_filtered_exprs(&predicate, &[add(1)], batch.schema(), mode)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &filtered));
+        assert_eq!(cache.len(), 2);
+
+        // a + 1 was evicted, but the kernel still works while it's held.
+        assert!(!Arc::ptr_eq(&first, &compile(1)));
+        assert_eq!(&first.eval(&batch).unwrap()[0], &expected);
+
+        // looking a kernel up makes it the most recently used one.
+        let kept = compile(3);
+        compile(1);
+        compile(3);
+        compile(4);
+        assert!(Arc::ptr_eq(&kept, &compile(3)));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
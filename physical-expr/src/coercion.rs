@@ -0,0 +1,592 @@
+use std::sync::Arc;
+
+use arrow::{
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+
+use crate::{
+    expr::{
+        binary::{BinaryExpr, Op},
+        case::CaseExpr,
+        cast::CastExpr,
+        coalesce::CoalesceExpr,
+        in_list::InListExpr,
+        like::LikeExpr,
+        literal::LiteralExpr,
+        not::NotExpr,
+        regexp::RegexpMatchExpr,
+        scalar_function::ScalarFunctionExpr,
+    },
+    Datum, PhysicalExpr, PhysicalExprRef,
+};
+
+const DECIMAL128_MAX_PRECISION: u8 = 38;
+
+/// Types the operands of a binary expression are cast to, and the type it produces.
+pub(crate) struct BinarySignature {
+    pub lhs: DataType,
+    pub rhs: DataType,
+    pub output: DataType,
+}
+
+pub(crate) fn binary_signature(op: Op, lhs: &DataType, rhs: &DataType) -> Result<BinarySignature> {
+    let type_error =
+        || ServerError::TypeError(format!("{:?} can't be applied to {} and {}", op, lhs, rhs));
+    match op {
+        Op::And | Op::Or => {
+            let is_boolean =
+                |data_type: &DataType| matches!(data_type, DataType::Boolean | DataType::Null);
+            if !is_boolean(lhs) || !is_boolean(rhs) {
+                return Err(type_error());
+            }
+            Ok(BinarySignature {
+                lhs: DataType::Boolean,
+                rhs: DataType::Boolean,
+                output: DataType::Boolean,
+            })
+        }
+        _ if op.is_comparison() => {
+            let common = comparison_coercion(lhs, rhs).ok_or_else(type_error)?;
+            Ok(BinarySignature {
+                lhs: common.clone(),
+                rhs: common,
+                output: DataType::Boolean,
+            })
+        }
+        _ if op.is_bitwise() => {
+            let common = match (lhs, rhs) {
+                (DataType::Null, DataType::Null) => DataType::Int64,
+                (DataType::Null, other) | (other, DataType::Null) if other.is_integer() => {
+                    other.clone()
+                }
+                _ if lhs.is_integer() && rhs.is_integer() => {
+                    integer_coercion(lhs, rhs).ok_or_else(type_error)?
+                }
+                _ => return Err(type_error()),
+            };
+            Ok(BinarySignature {
+                lhs: common.clone(),
+                rhs: common.clone(),
+                output: common,
+            })
+        }
+        Op::Add | Op::Sub => match (lhs, rhs) {
+            (
+                DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _),
+                DataType::Interval(_),
+            ) => Ok(BinarySignature {
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+                output: lhs.clone(),
+            }),
+            _ => arithmetic_signature(op, lhs, rhs).ok_or_else(type_error),
+        },
+        _ => arithmetic_signature(op, lhs, rhs).ok_or_else(type_error),
+    }
+}
+
+// operands are cast to their numeric union, decimal results take arrow's precision and
+// scale for `op` over two decimals of the union type.
+fn arithmetic_signature(op: Op, lhs: &DataType, rhs: &DataType) -> Option<BinarySignature> {
+    let common = numeric_coercion(lhs, rhs)?;
+    let output = match common {
+        DataType::Decimal128(precision, scale) => {
+            let max = DECIMAL128_MAX_PRECISION as i16;
+            let (precision, scale) = (precision as i16, scale as i16);
+            let (precision, scale) = match op {
+                // one more integer digit for the carry.
+                Op::Add | Op::Sub => (precision + 1, scale),
+                Op::Mul if 2 * scale > max => return None,
+                Op::Mul => (2 * precision + 1, 2 * scale),
+                // four more fractional digits, like postgres and mysql.
+                Op::Div => {
+                    let result_scale = (scale + 4).min(max);
+                    (precision + result_scale, result_scale)
+                }
+                _ => (precision, scale),
+            };
+            DataType::Decimal128(precision.clamp(1, max) as u8, scale as i8)
+        }
+        ref common => common.clone(),
+    };
+    Some(BinarySignature {
+        lhs: common.clone(),
+        rhs: common,
+        output,
+    })
+}
+
+/// The type both sides of a comparison are cast to, if they can be compared at all.
+pub fn comparison_coercion(lhs: &DataType, rhs: &DataType) -> Option<DataType> {
+    match (lhs, rhs) {
+        _ if lhs == rhs => Some(lhs.clone()),
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        (DataType::Utf8 | DataType::LargeUtf8, DataType::Utf8 | DataType::LargeUtf8) => {
+            Some(DataType::LargeUtf8)
+        }
+        (DataType::Date32, DataType::Date64) | (DataType::Date64, DataType::Date32) => {
+            Some(DataType::Date64)
+        }
+        _ => numeric_coercion(lhs, rhs),
+    }
+}
+
+/// The type all of `types` are cast to, e.g. for the arguments of `coalesce`.
+pub(crate) fn common_type(types: &[DataType]) -> Option<DataType> {
+    types.iter().try_fold(DataType::Null, |common, data_type| {
+        comparison_coercion(&common, data_type)
+    })
+}
+
+/// The narrowest numeric type holding the values of both `lhs` and `rhs`: integers widen
+/// to a signed type when signedness differs, integers meet decimals as decimals, and
+/// anything meets floats as floats.
+fn numeric_coercion(lhs: &DataType, rhs: &DataType) -> Option<DataType> {
+    let is_numeric = |data_type: &DataType| {
+        data_type.is_integer()
+            || matches!(
+                data_type,
+                DataType::Float32 | DataType::Float64 | DataType::Decimal128(_, _) | DataType::Null
+            )
+    };
+    if !is_numeric(lhs) || !is_numeric(rhs) {
+        return None;
+    }
+    match (lhs, rhs) {
+        _ if lhs == rhs => Some(lhs.clone()),
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        (DataType::Float64, _) | (_, DataType::Float64) => Some(DataType::Float64),
+        (DataType::Decimal128(_, _), _) | (_, DataType::Decimal128(_, _))
+            if lhs.is_floating() || rhs.is_floating() =>
+        {
+            Some(DataType::Float64)
+        }
+        (DataType::Float32, other) | (other, DataType::Float32) => {
+            // a float32 mantissa holds integers of up to 16 bits exactly.
+            match other.primitive_width() {
+                Some(width) if width <= 2 => Some(DataType::Float32),
+                _ => Some(DataType::Float64),
+            }
+        }
+        (DataType::Decimal128(_, _), _) | (_, DataType::Decimal128(_, _)) => {
+            let (p1, s1) = decimal_of(lhs)?;
+            let (p2, s2) = decimal_of(rhs)?;
+            let scale = s1.max(s2);
+            let integer_digits = (p1 as i16 - s1 as i16).max(p2 as i16 - s2 as i16);
+            let precision = (integer_digits + scale as i16).min(DECIMAL128_MAX_PRECISION as i16);
+            Some(DataType::Decimal128(precision as u8, scale))
+        }
+        _ => integer_coercion(lhs, rhs),
+    }
+}
+
+/// precision and scale of the decimal exactly holding every value of `data_type`.
+fn decimal_of(data_type: &DataType) -> Option<(u8, i8)> {
+    match data_type {
+        DataType::Decimal128(precision, scale) => Some((*precision, *scale)),
+        DataType::Int8 | DataType::UInt8 => Some((3, 0)),
+        DataType::Int16 | DataType::UInt16 => Some((5, 0)),
+        DataType::Int32 | DataType::UInt32 => Some((10, 0)),
+        DataType::Int64 => Some((19, 0)),
+        DataType::UInt64 => Some((20, 0)),
+        _ => None,
+    }
+}
+
+fn integer_coercion(lhs: &DataType, rhs: &DataType) -> Option<DataType> {
+    let width = |data_type: &DataType| data_type.primitive_width().unwrap();
+    let (signed, width) = match (lhs.is_signed_integer(), rhs.is_signed_integer()) {
+        (true, true) => (true, width(lhs).max(width(rhs))),
+        (false, false) => (false, width(lhs).max(width(rhs))),
+        // the signed type must hold every value of the unsigned one.
+        (true, false) => (true, width(lhs).max(2 * width(rhs)).min(8)),
+        (false, true) => (true, width(rhs).max(2 * width(lhs)).min(8)),
+    };
+    Some(match (signed, width) {
+        (true, 1) => DataType::Int8,
+        (true, 2) => DataType::Int16,
+        (true, 4) => DataType::Int32,
+        (true, _) => DataType::Int64,
+        (false, 1) => DataType::UInt8,
+        (false, 2) => DataType::UInt16,
+        (false, 4) => DataType::UInt32,
+        (false, _) => DataType::UInt64,
+    })
+}
+
+/// Resolve the types of `expr` against `schema`: operands are cast to the type their
+/// operation is computed in, and ill-typed expressions are reported, before any code is
+/// generated for them.
+pub fn resolve_types(expr: &PhysicalExprRef, schema: &SchemaRef) -> Result<PhysicalExprRef> {
+    let children = expr.children();
+    let expr = if children.is_empty() {
+        expr.clone()
+    } else {
+        let children = children
+            .iter()
+            .map(|child| resolve_types(child, schema))
+            .collect::<Result<_>>()?;
+        expr.clone().with_new_children(children)?
+    };
+
+    let any = expr.as_any();
+    if let Some(binary) = any.downcast_ref::<BinaryExpr>() {
+        let lhs_type = binary.lhs().output_type(schema.clone());
+        let rhs_type = binary.rhs().output_type(schema.clone());
+        let signature = binary_signature(binary.op(), &lhs_type, &rhs_type)?;
+        let lhs = cast_to(binary.lhs(), &signature.lhs, schema)?;
+        let rhs = cast_to(binary.rhs(), &signature.rhs, schema)?;
+        let resolved = BinaryExpr::new(binary.op(), lhs, rhs);
+        return Ok(Arc::new(
+            resolved.with_arithmetic_mode(binary.arithmetic_mode()),
+        ));
+    }
+    if let Some(coalesce) = any.downcast_ref::<CoalesceExpr>() {
+        let args = coalesce.children();
+        let arg_types: Vec<DataType> = args
+            .iter()
+            .map(|arg| arg.output_type(schema.clone()))
+            .collect();
+        let common = common_type(&arg_types).ok_or_else(|| {
+            ServerError::TypeError(format!("coalesce of incompatible types {:?}", arg_types))
+        })?;
+        let args = args
+            .iter()
+            .map(|arg| cast_to(arg, &common, schema))
+            .collect::<Result<_>>()?;
+        return Ok(Arc::new(CoalesceExpr::new(args)));
+    }
+    if let Some(case) = any.downcast_ref::<CaseExpr>() {
+        let value_types: Vec<DataType> = case
+            .values()
+            .map(|value| value.output_type(schema.clone()))
+            .collect();
+        let common = common_type(&value_types).ok_or_else(|| {
+            ServerError::TypeError(format!("case of incompatible types {:?}", value_types))
+        })?;
+        let when_then = case
+            .when_then()
+            .iter()
+            .map(|(when, then)| {
+                let when = match when.output_type(schema.clone()) {
+                    DataType::Boolean | DataType::Null => {
+                        cast_to(when, &DataType::Boolean, schema)?
+                    }
+                    other => {
+                        return Err(ServerError::TypeError(format!(
+                            "case condition must be a boolean, got {}",
+                            other
+                        )))
+                    }
+                };
+                Ok((when, cast_to(then, &common, schema)?))
+            })
+            .collect::<Result<_>>()?;
+        let else_expr = case
+            .else_expr()
+            .map(|otherwise| cast_to(otherwise, &common, schema))
+            .transpose()?;
+        return Ok(Arc::new(CaseExpr::new(when_then, else_expr)));
+    }
+    if let Some(in_list) = any.downcast_ref::<InListExpr>() {
+        let types: Vec<DataType> = in_list
+            .children()
+            .iter()
+            .map(|child| child.output_type(schema.clone()))
+            .collect();
+        let common = match common_type(&types) {
+            // a list of only nulls is compared like booleans, which gives null.
+            Some(DataType::Null) => DataType::Boolean,
+            Some(common) => common,
+            None => {
+                return Err(ServerError::TypeError(format!(
+                    "IN of incompatible types {:?}",
+                    types
+                )))
+            }
+        };
+        let input = cast_to(in_list.expr(), &common, schema)?;
+        let list = in_list
+            .list()
+            .iter()
+            .map(|item| cast_to(item, &common, schema))
+            .collect::<Result<_>>()?;
+        return Ok(Arc::new(InListExpr::new(input, list, in_list.is_negated())));
+    }
+    if let Some(call) = any.downcast_ref::<ScalarFunctionExpr>() {
+        let overload = call.overload(schema)?;
+        let args = call
+            .args()
+            .iter()
+            .zip(overload.arg_types())
+            .map(|(arg, data_type)| cast_to(arg, data_type, schema))
+            .collect::<Result<_>>()?;
+        return Ok(Arc::new(ScalarFunctionExpr::new(call.func().clone(), args)));
+    }
+    if let Some(not) = any.downcast_ref::<NotExpr>() {
+        let input = not.children().remove(0);
+        return match input.output_type(schema.clone()) {
+            DataType::Boolean => Ok(expr),
+            DataType::Null => Ok(Arc::new(NotExpr::new(cast_to(
+                &input,
+                &DataType::Boolean,
+                schema,
+            )?))),
+            other => Err(ServerError::TypeError(format!(
+                "NOT can't be applied to {}",
+                other
+            ))),
+        };
+    }
+    if any.is::<LikeExpr>() || any.is::<RegexpMatchExpr>() {
+        let input = expr.children().remove(0);
+        let input = match input.output_type(schema.clone()) {
+            DataType::Utf8 | DataType::LargeUtf8 => input,
+            DataType::Null => cast_to(&input, &DataType::Utf8, schema)?,
+            other => {
+                return Err(ServerError::TypeError(format!(
+                    "patterns can't be matched against {}",
+                    other
+                )))
+            }
+        };
+        return expr.with_new_children(vec![input]);
+    }
+    Ok(expr)
+}
+
+/// `expr` converted to `to_type`, casts of literals are folded into a literal of that type.
+pub fn cast_to(
+    expr: &PhysicalExprRef,
+    to_type: &DataType,
+    schema: &SchemaRef,
+) -> Result<PhysicalExprRef> {
+    if &expr.output_type(schema.clone()) == to_type {
+        return Ok(expr.clone());
+    }
+    let cast = CastExpr::new(expr.clone(), to_type.clone());
+    if expr.as_any().is::<LiteralExpr>() {
+        if let Datum::Scalar(scalar) = cast.eval(&RecordBatch::new_empty(schema.clone()))? {
+            return Ok(Arc::new(LiteralExpr::new(scalar)));
+        }
+    }
+    Ok(Arc::new(cast))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Date32Array, Float64Array, Int32Array, IntervalDayTimeArray, UInt8Array},
+        datatypes::{DataType, Date32Type, Field, Float64Type, IntervalUnit, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        compile,
+        expr::{
+            binary::{BinaryExpr, Op},
+            case::CaseExpr,
+            cast::CastExpr,
+            column::ColumnExpr,
+            in_list::InListExpr,
+            like::LikeExpr,
+            literal::LiteralExpr,
+            not::NotExpr,
+            scalar_function::ScalarFunctionExpr,
+        },
+        function::builtin_functions,
+        Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::{binary_signature, comparison_coercion, resolve_types};
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("i", DataType::Int32, false),
+            Field::new("f", DataType::Float64, false),
+            Field::new("u", DataType::UInt8, false),
+            Field::new("d", DataType::Date32, false),
+            Field::new("iv", DataType::Interval(IntervalUnit::DayTime), false),
+        ]));
+        let columns = vec![
+            Arc::new(Int32Array::from(vec![1, -2, 3])) as _,
+            Arc::new(Float64Array::from(vec![0.5, 1.5, 2.5])) as _,
+            Arc::new(UInt8Array::from(vec![200, 1, 2])) as _,
+            Arc::new(Date32Array::from(vec![0, 1, 2])) as _,
+            Arc::new(IntervalDayTimeArray::from(vec![1 << 32, 2 << 32, 0])) as _,
+        ];
+        RecordBatch::try_new(schema, columns).unwrap()
+    }
+
+    #[test]
+    fn test_coercion_rules() {
+        let cases = [
+            (DataType::Int8, DataType::Int32, Some(DataType::Int32)),
+            (DataType::UInt8, DataType::Int8, Some(DataType::Int16)),
+            (DataType::UInt64, DataType::Int32, Some(DataType::Int64)),
+            (DataType::Int16, DataType::Float32, Some(DataType::Float32)),
+            (DataType::Int32, DataType::Float32, Some(DataType::Float64)),
+            (
+                DataType::Int32,
+                DataType::Decimal128(5, 2),
+                Some(DataType::Decimal128(12, 2)),
+            ),
+            (
+                DataType::Decimal128(10, 4),
+                DataType::Decimal128(12, 1),
+                Some(DataType::Decimal128(15, 4)),
+            ),
+            (
+                DataType::Decimal128(10, 2),
+                DataType::Float32,
+                Some(DataType::Float64),
+            ),
+            (DataType::Null, DataType::Date32, Some(DataType::Date32)),
+            (DataType::Utf8, DataType::Int64, None),
+            (DataType::Boolean, DataType::Int64, None),
+        ];
+        for (lhs, rhs, expected) in cases {
+            assert_eq!(comparison_coercion(&lhs, &rhs), expected);
+            assert_eq!(comparison_coercion(&rhs, &lhs), expected);
+        }
+    }
+
+    #[test]
+    fn test_binary_signatures() {
+        let decimal = DataType::Decimal128(10, 2);
+        let cases = [
+            (Op::Add, DataType::Decimal128(11, 2)),
+            (Op::Sub, DataType::Decimal128(11, 2)),
+            (Op::Mul, DataType::Decimal128(21, 4)),
+            (Op::Div, DataType::Decimal128(16, 6)),
+            (Op::Mod, DataType::Decimal128(10, 2)),
+            (Op::GtEq, DataType::Boolean),
+        ];
+        for (op, expected) in cases {
+            let signature = binary_signature(op, &decimal, &DataType::Int8).unwrap();
+            assert_eq!(signature.rhs, decimal);
+            assert_eq!(signature.output, expected);
+        }
+
+        let signature = binary_signature(Op::ShiftLeft, &DataType::UInt8, &DataType::Int8);
+        assert_eq!(signature.unwrap().output, DataType::Int16);
+        assert!(binary_signature(Op::BitOr, &decimal, &DataType::Int8).is_err());
+        assert!(binary_signature(Op::Mul, &DataType::Decimal128(38, 20), &decimal).is_err());
+    }
+
+    #[test]
+    fn test_resolve_inserts_casts() {
+        let batch = batch();
+        let schema = batch.schema();
+        // i + f
+        let expr = Arc::new(BinaryExpr::new(Op::Add, column("i", 0), column("f", 1))) as _;
+        let resolved = resolve_types(&expr, &schema).unwrap();
+        assert_eq!(resolved.output_type(schema.clone()), DataType::Float64);
+        let binary = resolved.as_any().downcast_ref::<BinaryExpr>().unwrap();
+        let cast = binary.lhs().as_any().downcast_ref::<CastExpr>().unwrap();
+        assert_eq!(cast.to_type(), &DataType::Float64);
+        let Datum::Array(result) = resolved.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(
+            result.as_primitive::<Float64Type>().values(),
+            &[1.5, -0.5, 5.5]
+        );
+        assert_eq!(
+            &compile(&resolved, schema.clone(), ArithmeticMode::Checked)
+                .unwrap()
+                .eval(&batch)
+                .unwrap(),
+            &result
+        );
+
+        // u < i, compared as Int32.
+        let expr = Arc::new(BinaryExpr::new(Op::Lt, column("u", 2), column("i", 0))) as _;
+        let resolved = resolve_types(&expr, &schema).unwrap();
+        let compiled = compile(&resolved, schema.clone(), ArithmeticMode::Checked).unwrap();
+        let Datum::Array(expected) = resolved.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&compiled.eval(&batch).unwrap(), &expected);
+
+        // f + 1, with the literal folded to Float64.
+        let one = Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(1)))) as _;
+        let expr = Arc::new(BinaryExpr::new(Op::Add, column("f", 1), one)) as _;
+        let resolved = resolve_types(&expr, &schema).unwrap();
+        let binary = resolved.as_any().downcast_ref::<BinaryExpr>().unwrap();
+        assert!(binary.rhs().as_any().is::<LiteralExpr>());
+        assert_eq!(binary.rhs().output_type(schema.clone()), DataType::Float64);
+    }
+
+    #[test]
+    fn test_resolve_date_plus_interval() {
+        let batch = batch();
+        let schema = batch.schema();
+        let expr = Arc::new(BinaryExpr::new(Op::Add, column("d", 3), column("iv", 4))) as _;
+        let resolved = resolve_types(&expr, &schema).unwrap();
+        assert_eq!(resolved.output_type(schema.clone()), DataType::Date32);
+        let Datum::Array(result) = resolved.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(result.as_primitive::<Date32Type>().values(), &[1, 3, 2]);
+    }
+
+    #[test]
+    fn test_resolve_case() {
+        let batch = batch();
+        let schema = batch.schema();
+        let zero = Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(0))));
+        let positive = Arc::new(BinaryExpr::new(Op::Gt, column("i", 0), zero));
+        let expr = Arc::new(CaseExpr::new(
+            vec![(positive, column("i", 0))],
+            Some(column("f", 1)),
+        )) as _;
+        let resolved = resolve_types(&expr, &schema).unwrap();
+        assert_eq!(resolved.output_type(schema.clone()), DataType::Float64);
+        let Datum::Array(result) = resolved.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(
+            result.as_primitive::<Float64Type>().values(),
+            &[1.0, 1.5, 3.0]
+        );
+    }
+
+    #[test]
+    fn test_resolve_reports_type_errors() {
+        let schema = batch().schema();
+        let abs = builtin_functions()
+            .into_iter()
+            .find(|func| func.name() == "abs")
+            .map(Arc::new)
+            .unwrap();
+        let errors: Vec<PhysicalExprRef> = vec![
+            Arc::new(BinaryExpr::new(Op::And, column("i", 0), column("f", 1))),
+            Arc::new(BinaryExpr::new(Op::Add, column("d", 3), column("i", 0))),
+            Arc::new(BinaryExpr::new(Op::BitAnd, column("i", 0), column("f", 1))),
+            Arc::new(BinaryExpr::new(Op::Mul, column("d", 3), column("d", 3))),
+            Arc::new(NotExpr::new(column("f", 1))),
+            Arc::new(CaseExpr::new(vec![(column("f", 1), column("i", 0))], None)),
+            Arc::new(LikeExpr::like(column("i", 0), "1%")),
+            Arc::new(InListExpr::new(
+                column("d", 3),
+                vec![column("i", 0), column("f", 1)],
+                false,
+            )),
+            Arc::new(ScalarFunctionExpr::new(abs, vec![column("d", 3)])),
+        ];
+        for expr in errors {
+            assert!(resolve_types(&expr, &schema).is_err());
+            assert!(compile(&expr, schema.clone(), ArithmeticMode::Checked).is_err());
+        }
+    }
+}
@@ -0,0 +1,1409 @@
+use common::{Result, ServerError};
+use core::{
+    clear_returned_strings, compile_llvm_kernel, gen_type, ir, native_type, try_gen_type,
+    ArithmeticMode, ArrayLoop, Backend, CodegenContext, CodegenContextBuilder, FuncGenContext,
+    FuncRegister, GenValue, JitCode, KernelArgs, KernelError, LoopOutput, StringType,
+};
+use std::{mem, slice, sync::Arc};
+
+use arrow::{
+    array::{
+        make_array, ArrayData, ArrayRef, BooleanArray, GenericStringBuilder, LargeStringArray,
+        StringArray,
+    },
+    buffer::{BooleanBuffer, MutableBuffer, NullBuffer},
+    datatypes::{DataType, SchemaRef},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use cranelift::prelude::*;
+
+use crate::{
+    expr::{binary::BinaryExpr, column::ColumnExpr, scalar_function::ScalarFunctionExpr},
+    resolve_types, PhysicalExprRef,
+};
+
+/// (column pointers, validity bitmap pointers, output pointers, output validity pointers,
+/// error record, row count) -> rows written, or -1 after writing the error record.
+/// String columns take two column pointers, string outputs point at an arrow builder.
+type KernelFn = extern "C" fn(
+    *const *const u8,
+    *const *const u8,
+    *const *mut u8,
+    *const *mut u8,
+    *mut KernelError,
+    i64,
+) -> i64;
+
+/// A whole expression tree fused into one generated loop over the input arrays.
+pub struct CompiledExpr(CompiledExprs);
+
+pub fn compile(
+    expr: &PhysicalExprRef,
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+) -> Result<CompiledExpr> {
+    compile_exprs(slice::from_ref(expr), schema, mode).map(CompiledExpr)
+}
+
+impl CompiledExpr {
+    pub fn output_type(&self) -> &DataType {
+        &self.0.outputs[0].data_type
+    }
+
+    pub fn eval(&self, batch: &RecordBatch) -> Result<ArrayRef> {
+        Ok(self.0.eval(batch)?.pop().unwrap())
+    }
+}
+
+/// Several expression trees evaluated by the same generated loop, so columns they share
+/// are loaded once per row.
+pub struct CompiledExprs {
+    kernel: KernelFn,
+    // the memory `kernel` lives in, freed with the compiled expressions.
+    _code: JitCode,
+    // the resolved expressions the kernel was generated from, whose state such as
+    // precompiled regexes it refers to by address.
+    _exprs: Vec<PhysicalExprRef>,
+    // schema indices of the columns read by the kernel, in parameter order.
+    columns: Vec<usize>,
+    outputs: Vec<CompiledOutput>,
+}
+
+struct CompiledOutput {
+    data_type: DataType,
+    // whether the kernel writes an output validity buffer.
+    nullable: bool,
+}
+
+pub fn compile_exprs(
+    exprs: &[PhysicalExprRef],
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+) -> Result<CompiledExprs> {
+    compile_kernel(None, exprs, schema, mode, Backend::Cranelift)
+}
+
+/// Like [`compile_exprs`], generating the kernel with `backend`. LLVM only generates
+/// expressions over numbers, booleans, dates and timestamps, and fails with
+/// [`ServerError::NotSupported`] for others.
+pub fn compile_exprs_with(
+    exprs: &[PhysicalExprRef],
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+    backend: Backend,
+) -> Result<CompiledExprs> {
+    compile_kernel(None, exprs, schema, mode, backend)
+}
+
+/// Like [`compile_exprs`], but `exprs` are only computed for rows where `predicate` is
+/// true and written densely, so filtering needs no selection vector in between.
+pub fn compile_filtered_exprs(
+    predicate: &PhysicalExprRef,
+    exprs: &[PhysicalExprRef],
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+) -> Result<CompiledExprs> {
+    compile_filtered_exprs_with(predicate, exprs, schema, mode, Backend::Cranelift)
+}
+
+/// Like [`compile_filtered_exprs`], generating the kernel with `backend`.
+pub fn compile_filtered_exprs_with(
+    predicate: &PhysicalExprRef,
+    exprs: &[PhysicalExprRef],
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+    backend: Backend,
+) -> Result<CompiledExprs> {
+    if predicate.output_type(schema.clone()) != DataType::Boolean {
+        return Err(ServerError::TypeError(
+            "filter predicate must be boolean".to_string(),
+        ));
+    }
+    compile_kernel(Some(predicate), exprs, schema, mode, backend)
+}
+
+fn compile_kernel(
+    predicate: Option<&PhysicalExprRef>,
+    exprs: &[PhysicalExprRef],
+    schema: SchemaRef,
+    mode: ArithmeticMode,
+    backend: Backend,
+) -> Result<CompiledExprs> {
+    let predicate = predicate
+        .map(|predicate| resolve_types(predicate, &schema))
+        .transpose()?;
+    let predicate = predicate.as_ref();
+    let exprs = exprs
+        .iter()
+        .map(|expr| resolve_types(expr, &schema))
+        .collect::<Result<Vec<_>>>()?;
+    let exprs = exprs.as_slice();
+
+    let mut columns = vec![];
+    if let Some(predicate) = predicate {
+        collect_columns(predicate, &mut columns);
+        check_gen(predicate, &schema)?;
+    }
+    let mut outputs = vec![];
+    for expr in exprs {
+        collect_columns(expr, &mut columns);
+        check_gen(expr, &schema)?;
+        outputs.push(CompiledOutput {
+            data_type: expr.output_type(schema.clone()),
+            nullable: false,
+        });
+    }
+    for index in &columns {
+        try_gen_type(schema.field(*index).data_type())?;
+    }
+    let generated = match backend {
+        Backend::Cranelift => None,
+        Backend::Llvm => {
+            let kernel = compile_llvm_kernel(
+                predicate.map(|predicate| predicate.as_ref()),
+                exprs,
+                schema.clone(),
+                &columns,
+                mode,
+            )?;
+            Some((kernel.code, kernel.nullable))
+        }
+        Backend::Ir => {
+            let mut func = ir::lower_kernel(
+                predicate.map(|predicate| predicate.as_ref()),
+                exprs,
+                schema.clone(),
+                &columns,
+                mode,
+            )?;
+            ir::optimize(&mut func);
+            let nullable = (0..exprs.len())
+                .map(|slot| func.writes_validity(slot))
+                .collect();
+            Some((ir::compile_ir_kernel(&func)?, nullable))
+        }
+    };
+    if let Some((code, nullable)) = generated {
+        for (output, nullable) in outputs.iter_mut().zip(nullable) {
+            output.nullable = nullable;
+        }
+        return Ok(CompiledExprs {
+            kernel: unsafe { mem::transmute::<*const u8, KernelFn>(code.address()) },
+            _code: code,
+            _exprs: predicate.into_iter().chain(exprs).cloned().collect(),
+            columns,
+            outputs,
+        });
+    }
+    let is_string =
+        |index: &usize| StringType::from_data_type(schema.field(*index).data_type()).is_some();
+    let num_value_ptrs = columns.len() + columns.iter().filter(|i| is_string(i)).count();
+
+    let mut builder = CodegenContext::builder();
+    for expr in predicate.into_iter().chain(exprs) {
+        builder = register_functions(builder, expr, &schema)?;
+    }
+    let mut ctx = builder.finish();
+    let ptype = ctx.ptype();
+    let mut func_ctx = ctx.create_func_gen_ctx(
+        "compiled_exprs",
+        vec![
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(types::I64),
+        ],
+        vec![AbiParam::new(types::I64)],
+    )?;
+    func_ctx.bind_schema(schema.clone());
+    func_ctx.set_arithmetic_mode(mode);
+
+    let entry_block = func_ctx.builder.create_block();
+    func_ctx.builder.switch_to_block(entry_block);
+    func_ctx
+        .builder
+        .append_block_params_for_function_params(entry_block);
+    let column_ptrs = func_ctx.builder.block_params(entry_block)[0];
+    let validity_ptrs = func_ctx.builder.block_params(entry_block)[1];
+    let output_ptrs = func_ctx.builder.block_params(entry_block)[2];
+    let output_validity_ptrs = func_ctx.builder.block_params(entry_block)[3];
+    let error_record = func_ctx.builder.block_params(entry_block)[4];
+    let len = func_ctx.builder.block_params(entry_block)[5];
+    func_ctx.bind_error_record(error_record);
+    let mut column_ptrs = func_ctx.load_ptrs(column_ptrs, num_value_ptrs).into_iter();
+    let validity_ptrs = func_ctx.load_ptrs(validity_ptrs, columns.len());
+    let output_ptrs = func_ctx.load_ptrs(output_ptrs, exprs.len());
+    let output_validity_ptrs = func_ctx.load_ptrs(output_validity_ptrs, exprs.len());
+    let ptrs: Vec<ColumnPtrs> = columns
+        .iter()
+        .zip(validity_ptrs)
+        .map(|(index, validity_ptr)| ColumnPtrs {
+            values: column_ptrs.next().unwrap(),
+            data: is_string(index).then(|| column_ptrs.next().unwrap()),
+            validity: schema.field(*index).is_nullable().then_some(validity_ptr),
+        })
+        .collect();
+    let loop_outputs: Vec<LoopOutput> = outputs
+        .iter()
+        .zip(&output_ptrs)
+        .map(|(output, ptr)| LoopOutput {
+            ptr: *ptr,
+            lane_type: gen_type(&output.data_type).unwrap(),
+            is_bool: output.data_type == DataType::Boolean,
+            string: StringType::from_data_type(&output.data_type),
+        })
+        .collect();
+
+    let in_loop =
+        |index: &usize| schema.field(*index).data_type() != &DataType::Boolean && !is_string(index);
+    let num_rows = match predicate {
+        None => {
+            // bit-packed booleans and strings are loaded by the body, everything else by
+            // the loop.
+            let inputs = columns
+                .iter()
+                .zip(&ptrs)
+                .filter(|(index, _)| in_loop(index))
+                .map(|(index, ptrs)| {
+                    let data_type = schema.field(*index).data_type();
+                    (ptrs.values, native_type(data_type).unwrap())
+                })
+                .collect();
+            let vectorize = exprs.iter().all(|expr| is_vectorizable(expr, mode))
+                && loop_outputs.iter().all(|output| output.string.is_none())
+                && columns
+                    .iter()
+                    .all(|index| in_loop(index) && !schema.field(*index).is_nullable());
+            let array_loop = ArrayLoop {
+                inputs,
+                outputs: loop_outputs,
+                len,
+                vectorize,
+            };
+            func_ctx.build_array_loop(array_loop, |ctx, row, inputs| {
+                ctx.bind_row(row);
+                bind_columns(ctx, &schema, &columns, &ptrs, row, Some(inputs));
+                gen_exprs(ctx, exprs, &mut outputs, &output_validity_ptrs, row)
+            });
+            len
+        }
+        Some(predicate) => {
+            let zero = func_ctx.builder.ins().iconst(types::I64, 0);
+            let out_row = func_ctx.declare_var(zero);
+            func_ctx.build_row_loop(zero, len, |ctx, row| {
+                ctx.bind_row(row);
+                bind_columns(ctx, &schema, &columns, &ptrs, row, None);
+                let keep = predicate.gen(ctx);
+                // rows where the predicate is null are dropped like false ones.
+                let keep = match keep.valid {
+                    Some(valid) => ctx.builder.ins().band(keep.value, valid),
+                    None => keep.value,
+                };
+                let keep_block = ctx.builder.create_block();
+                let next_block = ctx.builder.create_block();
+                ctx.builder
+                    .ins()
+                    .brif(keep, keep_block, &[], next_block, &[]);
+
+                ctx.builder.switch_to_block(keep_block);
+                let out = ctx.builder.use_var(out_row);
+                let values = gen_exprs(ctx, exprs, &mut outputs, &output_validity_ptrs, out);
+                for (output, value) in loop_outputs.iter().zip(values) {
+                    ctx.store_loop_output(output, out, value);
+                }
+                let next_out = ctx.builder.ins().iadd_imm(out, 1);
+                ctx.builder.def_var(out_row, next_out);
+                ctx.builder.ins().jump(next_block, &[]);
+
+                ctx.builder.switch_to_block(next_block);
+            });
+            func_ctx.builder.use_var(out_row)
+        }
+    };
+
+    let func_id = func_ctx.finalize(&[num_rows])?;
+    let code = ctx.finalize_code(func_id)?;
+    Ok(CompiledExprs {
+        kernel: unsafe { mem::transmute::<*const u8, KernelFn>(code.address()) },
+        _code: code,
+        _exprs: predicate.into_iter().chain(exprs).cloned().collect(),
+        columns,
+        outputs,
+    })
+}
+
+// pointers the kernel reads a column through.
+struct ColumnPtrs {
+    values: Value,
+    // data buffer of a string column, whose `values` are its offsets.
+    data: Option<Value>,
+    // validity is only tracked for columns the schema declares nullable.
+    validity: Option<Value>,
+}
+
+/// bind every column read by the kernel for `row`. `loaded` holds the columns other than
+/// booleans and strings already loaded by an array loop, in column order.
+fn bind_columns(
+    ctx: &mut FuncGenContext,
+    schema: &SchemaRef,
+    columns: &[usize],
+    ptrs: &[ColumnPtrs],
+    row: Value,
+    loaded: Option<&[Value]>,
+) {
+    let mut loaded = loaded.map(|loaded| loaded.iter());
+    for (index, ptrs) in columns.iter().zip(ptrs) {
+        let data_type = schema.field(*index).data_type();
+        let value = match (&mut loaded, StringType::from_data_type(data_type)) {
+            (_, Some(string_type)) => {
+                let value = ctx.load_string(string_type, ptrs.values, ptrs.data.unwrap(), row);
+                let valid = ptrs.validity.map(|validity| ctx.load_bit(validity, row));
+                GenValue::new(value, valid)
+            }
+            (Some(loaded), None) if data_type != &DataType::Boolean => {
+                let valid = ptrs.validity.map(|validity| ctx.load_bit(validity, row));
+                GenValue::new(*loaded.next().unwrap(), valid)
+            }
+            _ => ctx.load_array_value(data_type, ptrs.values, ptrs.validity, row),
+        };
+        ctx.bind_column(*index, value);
+    }
+}
+
+/// generate every expression, storing validity for `out_row` and returning the values.
+fn gen_exprs(
+    ctx: &mut FuncGenContext,
+    exprs: &[PhysicalExprRef],
+    outputs: &mut [CompiledOutput],
+    validity_ptrs: &[Value],
+    out_row: Value,
+) -> Vec<Value> {
+    let mut values = vec![];
+    for (i, expr) in exprs.iter().enumerate() {
+        let result = expr.gen(ctx);
+        if let Some(valid) = result.valid {
+            ctx.store_element(valid, validity_ptrs[i], out_row);
+            outputs[i].nullable = true;
+        }
+        values.push(result.value);
+    }
+    values
+}
+
+impl CompiledExprs {
+    pub fn output_types(&self) -> Vec<DataType> {
+        self.outputs
+            .iter()
+            .map(|output| output.data_type.clone())
+            .collect()
+    }
+
+    /// evaluate every expression over `batch`, in the order they were compiled. With a
+    /// predicate, only the rows it keeps are returned.
+    pub fn eval(&self, batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+        Ok(self.eval_rows(batch)?.0)
+    }
+
+    /// evaluate into a batch of `schema`, which keeps the row count even when there are
+    /// no expressions.
+    pub fn eval_batch(&self, batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch> {
+        let (columns, num_rows) = self.eval_rows(batch)?;
+        let options = RecordBatchOptions::default().with_row_count(Some(num_rows));
+        Ok(RecordBatch::try_new_with_options(
+            schema, columns, &options,
+        )?)
+    }
+
+    fn eval_rows(&self, batch: &RecordBatch) -> Result<(Vec<ArrayRef>, usize)> {
+        let len = batch.num_rows();
+        let inputs: Vec<ArrayRef> = self
+            .columns
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect();
+        let values = KernelArgs::values(&inputs);
+        let validity = KernelArgs::validity(&inputs);
+
+        let mut output_buffers = vec![];
+        // one byte per row, packed into a bitmap afterwards.
+        let mut output_validity = vec![];
+        for output in &self.outputs {
+            output_buffers.push(OutputBuffer::new(&output.data_type, len));
+            output_validity.push(MutableBuffer::from_len_zeroed(if output.nullable {
+                len
+            } else {
+                0
+            }));
+        }
+        let output_ptrs: Vec<*mut u8> = output_buffers
+            .iter_mut()
+            .map(OutputBuffer::as_mut_ptr)
+            .collect();
+        let output_validity_ptrs: Vec<*mut u8> = output_validity
+            .iter_mut()
+            .map(|buffer| buffer.as_mut_ptr())
+            .collect();
+        let mut error = KernelError::default();
+        let num_rows = (self.kernel)(
+            values.as_ptr(),
+            validity.as_ptr(),
+            output_ptrs.as_ptr(),
+            output_validity_ptrs.as_ptr(),
+            &mut error,
+            len as i64,
+        );
+        // outputs hold copies of the strings functions returned.
+        clear_returned_strings();
+        if num_rows < 0 {
+            return Err(error.into());
+        }
+        let num_rows = num_rows as usize;
+
+        let columns = self
+            .outputs
+            .iter()
+            .zip(output_buffers.into_iter().zip(output_validity))
+            .map(|(output, (values, validity))| {
+                let nulls = output.nullable.then(|| {
+                    NullBuffer::new(BooleanBuffer::from_iter(
+                        validity.as_slice()[..num_rows].iter().map(|b| *b != 0),
+                    ))
+                });
+                match values {
+                    OutputBuffer::Utf8(mut builder) => {
+                        let (offsets, values, _) = builder.finish().into_parts();
+                        Ok(Arc::new(StringArray::new(offsets, values, nulls)) as ArrayRef)
+                    }
+                    OutputBuffer::LargeUtf8(mut builder) => {
+                        let (offsets, values, _) = builder.finish().into_parts();
+                        Ok(Arc::new(LargeStringArray::new(offsets, values, nulls)) as ArrayRef)
+                    }
+                    OutputBuffer::Values(values) if output.data_type == DataType::Boolean => {
+                        let values = BooleanBuffer::from_iter(
+                            values.as_slice()[..num_rows].iter().map(|b| *b != 0),
+                        );
+                        Ok(Arc::new(BooleanArray::new(values, nulls)) as ArrayRef)
+                    }
+                    OutputBuffer::Values(mut values) => {
+                        let width = native_type(&output.data_type).unwrap().bytes() as usize;
+                        values.truncate(num_rows * width);
+                        let data = ArrayData::builder(output.data_type.clone())
+                            .len(num_rows)
+                            .add_buffer(values.into())
+                            .nulls(nulls)
+                            .build()?;
+                        Ok(make_array(data))
+                    }
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok((columns, num_rows))
+    }
+}
+
+/// Where a kernel writes one output: a buffer of one native value per row, or a builder
+/// generated code appends strings to.
+enum OutputBuffer {
+    Values(MutableBuffer),
+    Utf8(Box<GenericStringBuilder<i32>>),
+    LargeUtf8(Box<GenericStringBuilder<i64>>),
+}
+
+impl OutputBuffer {
+    fn new(data_type: &DataType, len: usize) -> Self {
+        match StringType::from_data_type(data_type) {
+            Some(StringType::Utf8) => {
+                OutputBuffer::Utf8(Box::new(GenericStringBuilder::with_capacity(len, 0)))
+            }
+            Some(StringType::LargeUtf8) => {
+                OutputBuffer::LargeUtf8(Box::new(GenericStringBuilder::with_capacity(len, 0)))
+            }
+            None => {
+                let width = native_type(data_type).unwrap().bytes() as usize;
+                OutputBuffer::Values(MutableBuffer::from_len_zeroed(len * width))
+            }
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            OutputBuffer::Values(buffer) => buffer.as_mut_ptr(),
+            OutputBuffer::Utf8(builder) => &mut **builder as *mut _ as *mut u8,
+            OutputBuffer::LargeUtf8(builder) => &mut **builder as *mut _ as *mut u8,
+        }
+    }
+}
+
+fn is_vectorizable(expr: &PhysicalExprRef, mode: ArithmeticMode) -> bool {
+    // overflow checks and saturation branch or select per row.
+    let checked_arithmetic = mode != ArithmeticMode::Wrapping
+        && expr
+            .as_any()
+            .downcast_ref::<BinaryExpr>()
+            .is_some_and(|binary| binary.op().is_arithmetic());
+    expr.vectorizable()
+        && !checked_arithmetic
+        && expr
+            .children()
+            .iter()
+            .all(|child| is_vectorizable(child, mode))
+}
+
+/// every node of the tree, not just its root, must produce values generated code can hold
+/// and be one `gen` emits code for.
+fn check_gen(expr: &PhysicalExprRef, schema: &SchemaRef) -> Result<()> {
+    try_gen_type(&expr.output_type(schema.clone()))?;
+    expr.check_gen(schema.clone())?;
+    expr.children()
+        .iter()
+        .try_for_each(|child| check_gen(child, schema))
+}
+
+/// register the native functions `expr` calls, so generated code can link to them.
+fn register_functions(
+    mut builder: CodegenContextBuilder,
+    expr: &PhysicalExprRef,
+    schema: &SchemaRef,
+) -> Result<CodegenContextBuilder> {
+    if let Some(call) = expr.as_any().downcast_ref::<ScalarFunctionExpr>() {
+        let overload = call.overload(schema)?;
+        builder = builder.try_register_func(FuncRegister::from(overload))?;
+    }
+    for child in expr.children() {
+        builder = register_functions(builder, &child, schema)?;
+    }
+    Ok(builder)
+}
+
+fn collect_columns(expr: &PhysicalExprRef, columns: &mut Vec<usize>) {
+    if let Some(column) = expr.as_any().downcast_ref::<ColumnExpr>() {
+        if !columns.contains(&column.index()) {
+            columns.push(column.index());
+        }
+    }
+    for child in expr.children() {
+        collect_columns(&child, columns);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use common::ServerError;
+    use core::{ir, ArithmeticMode, Backend};
+
+    use arrow::{
+        array::{
+            Array, ArrayRef, AsArray, BooleanArray, Decimal128Array, Float64Array, Int32Array,
+            Int64Array, LargeStringArray, StringArray, UInt8Array,
+        },
+        compute::filter,
+        datatypes::{DataType, Field, Float64Type, Int32Type, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            case::CaseExpr,
+            cast::CastExpr,
+            coalesce::CoalesceExpr,
+            column::ColumnExpr,
+            is_null::IsNullExpr,
+            literal::LiteralExpr,
+            not::NotExpr,
+        },
+        resolve_types, with_arithmetic_mode, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::{
+        compile, compile_exprs, compile_exprs_with, compile_filtered_exprs,
+        compile_filtered_exprs_with,
+    };
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn binary(op: Op, lhs: PhysicalExprRef, rhs: PhysicalExprRef) -> PhysicalExprRef {
+        Arc::new(BinaryExpr::new(op, lhs, rhs))
+    }
+
+    fn assert_compiled_matches_eval(expr: PhysicalExprRef, batch: &RecordBatch) {
+        let compiled = compile(&expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(batch).unwrap();
+        // compiling inserts the casts type resolution adds, evaluation takes them as given.
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(expected) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&result, &expected);
+    }
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+            Field::new("c", DataType::Boolean, true),
+        ]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None]);
+        let b = Int64Array::from(vec![5, 4, 3, 2, 1]);
+        let c = BooleanArray::from(vec![Some(true), None, Some(false), None, Some(true)]);
+        RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b), Arc::new(c)]).unwrap()
+    }
+
+    #[test]
+    fn test_compile_matches_eval() {
+        let batch = batch();
+        // a + b < 3 + a
+        let expr = binary(
+            Op::Lt,
+            binary(Op::Add, column("a", 0), column("b", 1)),
+            binary(
+                Op::Add,
+                Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(3)))),
+                column("a", 0),
+            ),
+        );
+        assert_compiled_matches_eval(expr.clone(), &batch);
+
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch)
+            .unwrap();
+        assert_eq!(result.null_count(), 2);
+    }
+
+    #[test]
+    fn test_compile_three_valued_logic() {
+        let batch = batch();
+        // c OR a IS NULL
+        assert_compiled_matches_eval(
+            binary(
+                Op::Or,
+                column("c", 2),
+                Arc::new(IsNullExpr::new(column("a", 0))),
+            ),
+            &batch,
+        );
+        // NOT c AND b < a
+        assert_compiled_matches_eval(
+            binary(
+                Op::And,
+                Arc::new(NotExpr::new(column("c", 2))),
+                binary(Op::Lt, column("b", 1), column("a", 0)),
+            ),
+            &batch,
+        );
+    }
+
+    #[test]
+    fn test_compile_coalesce() {
+        let batch = batch();
+        assert_compiled_matches_eval(
+            Arc::new(CoalesceExpr::new(vec![column("a", 0), column("b", 1)])),
+            &batch,
+        );
+    }
+
+    #[test]
+    fn test_compile_vectorized_odd_length() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let a = Int64Array::from(vec![1, 2, 3, 4, 5, 6, 7]);
+        let b = Int64Array::from(vec![7, 1, 5, 3, 4, 9, 2]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b)]).unwrap();
+        let sum = binary(
+            Op::Add,
+            column("a", 0),
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(3)))),
+        );
+        for expr in [sum.clone(), binary(Op::Lt, sum, column("b", 1))] {
+            // overflow checks would keep the addition out of the vector loop.
+            let compiled = compile(&expr, batch.schema(), ArithmeticMode::Wrapping).unwrap();
+            let Datum::Array(expected) = expr.eval(&batch).unwrap() else {
+                panic!("expected an array");
+            };
+            assert_eq!(&compiled.eval(&batch).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn test_compile_exprs_shares_inputs() {
+        let batch = batch();
+        let exprs = vec![
+            binary(Op::Add, column("a", 0), column("b", 1)),
+            binary(Op::Lt, column("b", 1), column("a", 0)),
+            Arc::new(IsNullExpr::new(column("a", 0))) as _,
+        ];
+        let compiled = compile_exprs(&exprs, batch.schema(), ArithmeticMode::Checked).unwrap();
+        assert_eq!(
+            compiled.output_types(),
+            vec![DataType::Int64, DataType::Boolean, DataType::Boolean]
+        );
+        let results = compiled.eval(&batch).unwrap();
+        for (expr, result) in exprs.iter().zip(results) {
+            let Datum::Array(expected) = expr.eval(&batch).unwrap() else {
+                panic!("expected an array");
+            };
+            assert_eq!(&result, &expected);
+        }
+    }
+
+    #[test]
+    fn test_compile_filtered_exprs() {
+        let batch = batch();
+        // SELECT a + b, NOT c WHERE b < 4 OR c
+        let predicate = binary(
+            Op::Or,
+            binary(
+                Op::Lt,
+                column("b", 1),
+                Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(4)))),
+            ),
+            column("c", 2),
+        );
+        let exprs = vec![
+            binary(Op::Add, column("a", 0), column("b", 1)),
+            Arc::new(NotExpr::new(column("c", 2))) as _,
+        ];
+        let compiled =
+            compile_filtered_exprs(&predicate, &exprs, batch.schema(), ArithmeticMode::Checked)
+                .unwrap();
+        let results = compiled.eval(&batch).unwrap();
+
+        let Datum::Array(keep) = predicate.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        for (expr, result) in exprs.iter().zip(results) {
+            let Datum::Array(expected) = expr.eval(&batch).unwrap() else {
+                panic!("expected an array");
+            };
+            assert_eq!(&result, &filter(&expected, keep.as_boolean()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_compile_operators() {
+        let decimal = |values: Vec<i128>| {
+            Decimal128Array::from(values)
+                .with_precision_and_scale(10, 2)
+                .unwrap()
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int32, true),
+            Field::new("y", DataType::Int32, false),
+            Field::new("f", DataType::Float64, false),
+            Field::new("g", DataType::Float64, false),
+            Field::new("h", DataType::Float64, false),
+            Field::new("u", DataType::UInt8, false),
+            Field::new("v", DataType::UInt8, false),
+            Field::new("d", DataType::Decimal128(10, 2), false),
+            Field::new("e", DataType::Decimal128(10, 2), false),
+        ]));
+        let columns = vec![
+            Arc::new(Int32Array::from(vec![
+                Some(7),
+                None,
+                Some(-9),
+                Some(i32::MIN + 1),
+                Some(0),
+            ])) as _,
+            Arc::new(Int32Array::from(vec![2, 3, -4, -1, 5])) as _,
+            Arc::new(Float64Array::from(vec![1.5, -0.0, f64::NAN, -2.0, 7.25])) as _,
+            Arc::new(Float64Array::from(vec![0.5, 0.25, f64::NAN, 3.0, -2.0])) as _,
+            Arc::new(Float64Array::from(vec![0.5, 0.0, 1.0, -2.0, f64::NAN])) as _,
+            Arc::new(UInt8Array::from(vec![200, 1, 17, 255, 0])) as _,
+            Arc::new(UInt8Array::from(vec![3, 7, 2, 1, 9])) as _,
+            Arc::new(decimal(vec![12345, -250, 1, 0, 99])) as _,
+            Arc::new(decimal(vec![100, 3, -7, 250, 1])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+
+        let arithmetic = [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Mod];
+        let comparisons = [Op::Eq, Op::NotEq, Op::Lt, Op::LtEq, Op::Gt, Op::GtEq];
+        let bitwise = [
+            Op::BitAnd,
+            Op::BitOr,
+            Op::BitXor,
+            Op::ShiftLeft,
+            Op::ShiftRight,
+        ];
+        let cases = [
+            ((0, 1), [&arithmetic[..], &comparisons, &bitwise].concat()),
+            ((2, 3), arithmetic.to_vec()),
+            // -0 < +0 and NaN is the largest float.
+            ((2, 4), comparisons.to_vec()),
+            // unsigned addition and multiplication of these would overflow.
+            ((5, 6), [&arithmetic[3..], &comparisons, &bitwise].concat()),
+            ((7, 8), [&arithmetic[..], &comparisons].concat()),
+        ];
+        let schema = batch.schema();
+        for ((lhs, rhs), ops) in cases {
+            let lhs = column(schema.field(lhs).name(), lhs);
+            let rhs = column(schema.field(rhs).name(), rhs);
+            for op in ops {
+                assert_compiled_matches_eval(binary(op, lhs.clone(), rhs.clone()), &batch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_arithmetic_modes() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+            Field::new("c", DataType::Int64, false),
+        ]));
+        let columns = vec![
+            Arc::new(Int32Array::from(vec![1, i32::MAX, -5, i32::MIN])) as _,
+            Arc::new(Int32Array::from(vec![2, 1, 3, -1])) as _,
+            Arc::new(Int64Array::from(vec![3, 1 << 32, -7, 5])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+        let eval = |expr: &PhysicalExprRef, mode| {
+            compile(expr, batch.schema(), mode).and_then(|compiled| compiled.eval(&batch))
+        };
+        let overflow_row = |expr: &PhysicalExprRef| match eval(expr, ArithmeticMode::Checked) {
+            Err(ServerError::ArithmeticOverflow { row }) => row,
+            other => panic!("expected an overflow, got {:?}", other),
+        };
+        let values = |expr: &PhysicalExprRef, mode| {
+            let result = eval(expr, mode).unwrap();
+            result.as_primitive::<Int32Type>().values().to_vec()
+        };
+        let (a, b, c) = (column("a", 0), column("b", 1), column("c", 2));
+
+        let sum = binary(Op::Add, a.clone(), b.clone());
+        assert_eq!(overflow_row(&sum), 1);
+        assert_eq!(
+            values(&sum, ArithmeticMode::Wrapping),
+            [3, i32::MIN, -2, i32::MAX]
+        );
+        assert_eq!(
+            values(&sum, ArithmeticMode::Saturating),
+            [3, i32::MAX, -2, i32::MIN]
+        );
+
+        let product = binary(Op::Mul, a.clone(), b.clone());
+        assert_eq!(overflow_row(&product), 3);
+        assert_eq!(
+            values(&product, ArithmeticMode::Wrapping),
+            [2, i32::MAX, -15, i32::MIN]
+        );
+        assert_eq!(
+            values(&product, ArithmeticMode::Saturating),
+            [2, i32::MAX, -15, i32::MAX]
+        );
+
+        let quotient = binary(Op::Div, a.clone(), b);
+        assert_eq!(overflow_row(&quotient), 3);
+        assert_eq!(
+            values(&quotient, ArithmeticMode::Wrapping),
+            [0, i32::MAX, -1, i32::MIN]
+        );
+        assert_eq!(
+            values(&quotient, ArithmeticMode::Saturating),
+            [0, i32::MAX, -1, i32::MAX]
+        );
+
+        let square = binary(Op::Mul, c.clone(), c);
+        assert_eq!(overflow_row(&square), 1);
+        let result = eval(&square, ArithmeticMode::Saturating).unwrap();
+        assert_eq!(
+            result.as_primitive::<Int64Type>().values(),
+            &[9, i64::MAX, 49, 25]
+        );
+
+        // a zero divisor fails when checked and gives null otherwise.
+        let zero = Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(0))));
+        let quotient = binary(Op::Div, a, zero);
+        assert!(matches!(
+            eval(&quotient, ArithmeticMode::Checked),
+            Err(ServerError::DivideByZero { row: 0 })
+        ));
+        let result = eval(&quotient, ArithmeticMode::Wrapping).unwrap();
+        assert_eq!(result.null_count(), batch.num_rows());
+    }
+
+    #[test]
+    fn test_interpreted_arithmetic_modes() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, false),
+            Field::new("d", DataType::Decimal128(10, 2), false),
+            Field::new("e", DataType::Decimal128(10, 2), false),
+            Field::new("f", DataType::Float64, false),
+        ]));
+        let columns = vec![
+            Arc::new(Int32Array::from(vec![
+                Some(1),
+                Some(i32::MAX),
+                None,
+                Some(i32::MIN),
+                Some(7),
+            ])) as _,
+            Arc::new(Int32Array::from(vec![2, 1, 3, -1, 0])) as _,
+            Arc::new(
+                Decimal128Array::from(vec![150, -700, 1, 99_999_999, 5])
+                    .with_precision_and_scale(10, 2)
+                    .unwrap(),
+            ) as _,
+            Arc::new(
+                Decimal128Array::from(vec![300, 200, 0, -1, 7])
+                    .with_precision_and_scale(10, 2)
+                    .unwrap(),
+            ) as _,
+            Arc::new(Float64Array::from(vec![1.5, 0.0, -0.0, 2.0, -3.0])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+        let (a, b) = (column("a", 0), column("b", 1));
+        let (d, e, f) = (column("d", 2), column("e", 3), column("f", 4));
+        let exprs = [
+            binary(Op::Add, a.clone(), b.clone()),
+            binary(Op::Sub, b.clone(), a.clone()),
+            binary(Op::Mul, a.clone(), b.clone()),
+            binary(Op::Div, a.clone(), b.clone()),
+            binary(Op::Mod, a, b),
+            binary(Op::Mul, d.clone(), e.clone()),
+            binary(Op::Div, d.clone(), e.clone()),
+            binary(Op::Mod, d, e),
+            binary(Op::Div, column("f", 4), f),
+        ];
+
+        // overflow and zero divisors stop neither, interpreted batches look like compiled.
+        for mode in [ArithmeticMode::Wrapping, ArithmeticMode::Saturating] {
+            for expr in &exprs {
+                let compiled = compile(expr, batch.schema(), mode).unwrap();
+                let result = compiled.eval(&batch).unwrap();
+                let expr = resolve_types(expr, &batch.schema()).unwrap();
+                let expr = with_arithmetic_mode(&expr, mode).unwrap();
+                let Datum::Array(expected) = expr.eval(&batch).unwrap() else {
+                    panic!("expected an array");
+                };
+                assert_eq!(&result, &expected, "{:?}", mode);
+            }
+        }
+        let sum = binary(Op::Add, column("a", 0), column("b", 1));
+        let sum = with_arithmetic_mode(&sum, ArithmeticMode::Saturating).unwrap();
+        let Datum::Array(result) = sum.eval(&batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(
+            result.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(3), Some(i32::MAX), None, Some(i32::MIN), Some(7)])
+        );
+    }
+
+    #[test]
+    fn test_compile_short_circuit() {
+        let batch = batch();
+        let int = |value| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value))))
+        };
+        let b = column("b", 1);
+        // 10 / (b - 3) divides by zero where b = 3, which neither form may evaluate.
+        let quotient = binary(Op::Div, int(10), binary(Op::Sub, b.clone(), int(3)));
+        let large = binary(Op::Gt, quotient, int(2));
+        let exprs = [
+            binary(Op::And, binary(Op::NotEq, b.clone(), int(3)), large.clone()),
+            binary(Op::Or, binary(Op::Eq, b.clone(), int(3)), large.clone()),
+            binary(Op::And, column("c", 2), large.clone()),
+        ];
+        for expr in exprs {
+            assert_compiled_matches_eval(expr, &batch);
+        }
+
+        let guarded = binary(Op::And, binary(Op::NotEq, b, int(3)), large);
+        let result = compile(&guarded, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch)
+            .unwrap();
+        let expected = BooleanArray::from(vec![true, true, false, false, false]);
+        assert_eq!(result.as_boolean(), &expected);
+    }
+
+    #[test]
+    fn test_checked_float_division_by_zero() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+        ]));
+        let columns = vec![
+            Arc::new(Float64Array::from(vec![1.0, -1.0, 0.0, 7.5])) as _,
+            Arc::new(Float64Array::from(vec![0.0, 0.0, 0.0, -0.0])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+        let div = binary(Op::Div, column("x", 0), column("y", 1));
+        let rem = binary(Op::Mod, column("x", 0), column("y", 1));
+
+        // only integer and decimal zero divisors fail, floats give inf or NaN.
+        for expr in [&div, &rem] {
+            let compiled = compile(expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+            let result = compiled.eval(&batch).unwrap();
+            let Datum::Array(expected) = expr.eval(&batch).unwrap() else {
+                panic!("expected an array");
+            };
+            let (result, expected) = (
+                result.as_primitive::<Float64Type>(),
+                expected.as_primitive::<Float64Type>(),
+            );
+            for (result, expected) in result.values().iter().zip(expected.values()) {
+                assert_eq!(result.to_bits(), expected.to_bits());
+            }
+        }
+        let compiled = compile(&div, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(&batch).unwrap();
+        let result = result.as_primitive::<Float64Type>();
+        assert_eq!(result.value(0), f64::INFINITY);
+        assert_eq!(result.value(1), f64::NEG_INFINITY);
+        assert!(result.value(2).is_nan());
+        assert_eq!(result.value(3), f64::NEG_INFINITY);
+        assert_eq!(result.null_count(), 0);
+    }
+
+    #[test]
+    fn test_compile_literals() {
+        let batch = batch();
+        let scalars = [
+            ScalarValue::Boolean(Some(true)),
+            ScalarValue::Int8(Some(-3)),
+            ScalarValue::UInt32(Some(u32::MAX)),
+            ScalarValue::Int64(None),
+            ScalarValue::Float64(Some(0.5)),
+            ScalarValue::Decimal128(Some(-12345), 10, 2),
+            ScalarValue::Date32(Some(19000)),
+            ScalarValue::TimestampMicrosecond(Some(1), Some(Arc::from("UTC"))),
+            ScalarValue::Utf8(Some("literal".to_string())),
+            ScalarValue::LargeUtf8(None),
+        ];
+        let exprs: Vec<PhysicalExprRef> = scalars
+            .iter()
+            .map(|scalar| Arc::new(LiteralExpr::new(scalar.clone())) as _)
+            .collect();
+        let results = compile_exprs(&exprs, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch)
+            .unwrap();
+        for (scalar, result) in scalars.iter().zip(results) {
+            assert_eq!(&result, &scalar.to_array_of_size(batch.num_rows()));
+        }
+
+        let binary: PhysicalExprRef = Arc::new(LiteralExpr::new(ScalarValue::Binary(None)));
+        assert!(compile(&binary, batch.schema(), ArithmeticMode::Checked).is_err());
+    }
+
+    fn string_batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("l", DataType::LargeUtf8, false),
+            Field::new("n", DataType::Int64, false),
+        ]));
+        let s = StringArray::from(vec![
+            Some("apple"),
+            None,
+            Some(""),
+            Some("banana"),
+            Some("42"),
+            Some("héllo"),
+        ]);
+        let l = LargeStringArray::from(vec!["apple", "cherry", "", "banan", "42", "hello"]);
+        let n = Int64Array::from(vec![1, 2, 3, 4, 5, 6]);
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(s), Arc::new(l), Arc::new(n)]).unwrap();
+        // offsets of a sliced array don't start at 0.
+        batch.slice(1, 5)
+    }
+
+    #[test]
+    fn test_compile_strings() {
+        let batch = string_batch();
+        let string = |value: &str| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Utf8(Some(value.to_string()))))
+        };
+        let (s, l, n) = (column("s", 0), column("l", 1), column("n", 2));
+
+        let exprs = [
+            s.clone(),
+            l.clone(),
+            binary(Op::Eq, s.clone(), l.clone()),
+            binary(Op::Lt, s.clone(), l.clone()),
+            binary(Op::GtEq, l.clone(), string("banana")),
+            binary(Op::NotEq, s.clone(), string("")),
+            Arc::new(CaseExpr::if_then_else(
+                binary(
+                    Op::Gt,
+                    n,
+                    Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(3)))),
+                ),
+                s.clone(),
+                string("small"),
+            )),
+            Arc::new(CastExpr::try_cast(s.clone(), DataType::Int32)),
+            Arc::new(CastExpr::try_cast(l.clone(), DataType::Float64)),
+        ];
+        for expr in exprs {
+            assert_compiled_matches_eval(expr, &batch);
+        }
+
+        // only rows the predicate keeps are appended.
+        let predicate = binary(Op::NotEq, l.clone(), string(""));
+        let compiled = compile_filtered_exprs(
+            &predicate,
+            &[s.clone(), l],
+            batch.schema(),
+            ArithmeticMode::Checked,
+        )
+        .unwrap();
+        let results = compiled.eval(&batch).unwrap();
+        let expected = StringArray::from(vec![None, Some("banana"), Some("42"), Some("héllo")]);
+        assert_eq!(results[0].as_string::<i32>(), &expected);
+        assert_eq!(results[1].len(), 4);
+
+        let parsed = Arc::new(CastExpr::new(s, DataType::Int64)) as PhysicalExprRef;
+        let result = compile(&parsed, batch.schema(), ArithmeticMode::Checked)
+            .and_then(|compiled| compiled.eval(&batch));
+        assert!(matches!(result, Err(ServerError::InvalidCast { row: 1 })));
+    }
+
+    // a column of every type backends generate code for, with nulls, zero divisors,
+    // overflowing rows, signed zeros and NaNs.
+    fn backend_batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, false),
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+            Field::new("c", DataType::Boolean, true),
+            Field::new("u", DataType::UInt8, false),
+        ]));
+        let columns = vec![
+            Arc::new(Int32Array::from(vec![
+                Some(7),
+                None,
+                Some(i32::MIN),
+                Some(i32::MAX),
+                Some(-9),
+            ])) as _,
+            Arc::new(Int32Array::from(vec![2, 0, -1, 1, 0])) as _,
+            Arc::new(Float64Array::from(vec![1.5, -0.0, f64::NAN, -2.0, 7.25])) as _,
+            Arc::new(Float64Array::from(vec![0.5, -0.0, 1.0, -2.0, f64::NAN])) as _,
+            Arc::new(BooleanArray::from(vec![
+                Some(true),
+                None,
+                Some(false),
+                None,
+                Some(true),
+            ])) as _,
+            Arc::new(UInt8Array::from(vec![200, 0, 255, 1, 100])) as _,
+        ];
+        RecordBatch::try_new(schema, columns).unwrap()
+    }
+
+    // code generated through `backend` agrees with cranelift on the values, nulls and
+    // first error of everything backends support.
+    fn assert_backend_matches_cranelift(backend: Backend) {
+        let batch = backend_batch();
+        let assert_agree = |exprs: &[PhysicalExprRef], mode| {
+            let eval = |backend| {
+                compile_exprs_with(exprs, batch.schema(), mode, backend)
+                    .and_then(|compiled| compiled.eval(&batch))
+            };
+            let (result, cranelift) = (eval(backend), eval(Backend::Cranelift));
+            match (&result, &cranelift) {
+                (Ok(result), Ok(cranelift)) => assert_eq!(result, cranelift),
+                _ => assert_eq!(format!("{:?}", result), format!("{:?}", cranelift)),
+            }
+        };
+        let (a, b, x, y, c, u) = (
+            column("a", 0),
+            column("b", 1),
+            column("x", 2),
+            column("y", 3),
+            column("c", 4),
+            column("u", 5),
+        );
+
+        let modes = [
+            ArithmeticMode::Wrapping,
+            ArithmeticMode::Checked,
+            ArithmeticMode::Saturating,
+        ];
+        let arithmetic = [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Mod];
+        let comparisons = [Op::Eq, Op::NotEq, Op::Lt, Op::LtEq, Op::Gt, Op::GtEq];
+        for mode in modes {
+            for op in arithmetic.into_iter().chain(comparisons) {
+                assert_agree(&[binary(op, a.clone(), b.clone())], mode);
+                assert_agree(&[binary(op, x.clone(), y.clone())], mode);
+                assert_agree(&[binary(op, u.clone(), u.clone())], mode);
+            }
+        }
+
+        // kleene logic, null tests, literals and widening casts.
+        let positive = binary(
+            Op::Gt,
+            a.clone(),
+            Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(0)))),
+        );
+        let exprs = vec![
+            binary(Op::And, c.clone(), positive.clone()),
+            binary(Op::Or, c.clone(), positive),
+            Arc::new(NotExpr::new(c)) as _,
+            Arc::new(IsNullExpr::new(a.clone())) as _,
+            binary(
+                Op::Eq,
+                a.clone(),
+                Arc::new(LiteralExpr::new(ScalarValue::Int32(None))),
+            ),
+            Arc::new(CastExpr::new(a.clone(), DataType::Float64)) as _,
+            Arc::new(CastExpr::new(a.clone(), DataType::Int64)) as _,
+            Arc::new(CastExpr::new(u, DataType::Int64)) as _,
+            binary(
+                Op::Add,
+                Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(i32::MAX)))),
+                Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(1)))),
+            ),
+        ];
+        for mode in modes {
+            assert_agree(&exprs, mode);
+        }
+
+        let predicate = binary(Op::Gt, x.clone(), y.clone());
+        let exprs = [a, binary(Op::Mul, x, y)];
+        let filtered = |backend| {
+            let mode = ArithmeticMode::Checked;
+            compile_filtered_exprs_with(&predicate, &exprs, batch.schema(), mode, backend)
+                .unwrap()
+                .eval(&batch)
+                .unwrap()
+        };
+        assert_eq!(filtered(backend), filtered(Backend::Cranelift));
+
+        // strings aren't generated through backends.
+        let batch = string_batch();
+        let result = compile_exprs_with(
+            &[column("s", 0)],
+            batch.schema(),
+            ArithmeticMode::Checked,
+            backend,
+        );
+        assert!(matches!(result, Err(ServerError::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_compile_llvm_backend() {
+        assert_backend_matches_cranelift(Backend::Llvm);
+
+        let batch = backend_batch();
+        let (a, b, c) = (column("a", 0), column("b", 1), column("c", 4));
+        let positive = binary(
+            Op::Gt,
+            a.clone(),
+            Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(0)))),
+        );
+        let result = compile_exprs_with(
+            &[binary(Op::And, c.clone(), positive)],
+            batch.schema(),
+            ArithmeticMode::Checked,
+            Backend::Llvm,
+        )
+        .unwrap()
+        .eval(&batch)
+        .unwrap();
+        let expected: ArrayRef = Arc::new(BooleanArray::from(vec![
+            Some(true),
+            None,
+            Some(false),
+            None,
+            Some(false),
+        ]));
+        assert_eq!(&result[0], &expected);
+
+        // checked rhs of AND would fail for rows lhs decides, and bitwise operations
+        // aren't generated by llvm.
+        let checked = binary(Op::Eq, binary(Op::Div, a.clone(), b.clone()), a.clone());
+        let unsupported = [binary(Op::And, c, checked), binary(Op::BitAnd, a, b)];
+        for expr in unsupported {
+            let result = compile_exprs_with(
+                &[expr],
+                batch.schema(),
+                ArithmeticMode::Checked,
+                Backend::Llvm,
+            );
+            assert!(matches!(result, Err(ServerError::NotSupported(_))));
+        }
+    }
+
+    #[test]
+    fn test_lower_to_ir() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, false),
+        ]));
+        let literal = |value| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int32(Some(value))))
+        };
+        let doubled = binary(Op::Mul, column("b", 1), literal(2));
+        let exprs = [
+            binary(Op::Add, doubled.clone(), doubled),
+            binary(Op::Add, literal(1), literal(2)),
+        ];
+        let mut func =
+            ir::lower_kernel(None, &exprs, schema, &[1], ArithmeticMode::Checked).unwrap();
+        let text = func.to_string();
+        assert_eq!(text.matches("= mul.i32").count(), 2);
+        assert_eq!(text.matches("fail.overflow").count(), 4);
+
+        // the repeated product and its check are dropped, the sum of literals can't
+        // overflow and is computed once, out of the loop.
+        ir::optimize(&mut func);
+        func.verify().unwrap();
+        let text = func.to_string();
+        assert_eq!(text.matches("= mul.i32").count(), 1, "{}", text);
+        assert_eq!(text.matches("fail.overflow").count(), 2, "{}", text);
+        let body = func
+            .body
+            .insts
+            .iter()
+            .find_map(|inst| match &inst.op {
+                ir::Op::Loop { body, .. } => Some(body),
+                _ => None,
+            })
+            .unwrap();
+        assert!(body
+            .insts
+            .iter()
+            .all(|inst| !matches!(inst.op, ir::Op::Const(_))));
+        assert!(func
+            .body
+            .insts
+            .iter()
+            .any(|inst| matches!(inst.op, ir::Op::Const(ir::Const::Int(3)))));
+        assert_eq!(func.outputs, vec![ir::Type::I32, ir::Type::I32]);
+        assert!(!func.writes_validity(0));
+
+        // a known branch is inlined, and an always false failure removed.
+        let mut builder = ir::Builder::new("branch", vec![]);
+        let zero = builder.iconst(ir::Type::I64, 0);
+        let no = builder.iconst(ir::Type::BOOL, 0);
+        builder.fail(core::KernelErrorCode::Overflow, no, zero);
+        let rows = builder.build_if(no, |b| vec![b.len()], |_| vec![zero]);
+        let mut func = builder.finish(rows[0]);
+        func.verify().unwrap();
+        ir::optimize(&mut func);
+        assert_eq!(
+            func.to_string(),
+            "function branch() -> () {\n    v0 = const.i64 0\n    yield v0\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_compile_ir_backend() {
+        assert_backend_matches_cranelift(Backend::Ir);
+    }
+}
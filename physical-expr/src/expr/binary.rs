@@ -1,51 +1,540 @@
-use core::ExprGen;
-use std::sync::Arc;
+use core::{
+    ArithOp, ArithmeticMode, BackendGenValue, CodegenBackend, CompareOp, ExprGen, FuncGenContext,
+    GenValue,
+};
+use std::{any::Any, fmt, sync::Arc};
 
 use arrow::{
-    compute::kernels::{cmp::lt, numeric::add},
-    datatypes::{DataType, SchemaRef},
+    array::{Array, ArrayRef, AsArray, BooleanArray, Decimal128Array, PrimitiveArray},
+    compute::kernels::{
+        bitwise::{bitwise_and, bitwise_or, bitwise_shift_left, bitwise_shift_right, bitwise_xor},
+        boolean::{and_kleene, or_kleene},
+        cmp::{eq, gt, gt_eq, lt, lt_eq, neq},
+        numeric::{add, div, mul, rem, sub},
+    },
+    datatypes::{
+        DataType, Decimal128Type, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type,
+        Int8Type, SchemaRef, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+    },
     record_batch::RecordBatch,
 };
+use common::{Result, ServerError};
+use cranelift::prelude::*;
 
-use crate::{Datum, PhysicalExpr};
+use crate::{
+    coercion::binary_signature, eval_selected, speculation_cost, take_children, Datum,
+    PhysicalExpr, PhysicalExprRef, ScalarValue, MAX_SPECULATION_COST,
+};
 
-enum Op {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
     Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    NotEq,
     Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Op {
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod)
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Op::Eq | Op::NotEq | Op::Lt | Op::LtEq | Op::Gt | Op::GtEq
+        )
+    }
+
+    pub fn is_bitwise(&self) -> bool {
+        matches!(
+            self,
+            Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight
+        )
+    }
 }
 
-struct BinaryExpr {
+pub struct BinaryExpr {
     lhs: Arc<dyn PhysicalExpr>,
     op: Op,
     rhs: Arc<dyn PhysicalExpr>,
+    mode: ArithmeticMode,
 }
 
 impl BinaryExpr {
-    fn new(op: Op, lhs: Arc<dyn PhysicalExpr>, rhs: Arc<dyn PhysicalExpr>) -> Self {
-        Self { lhs, op, rhs }
+    pub fn new(op: Op, lhs: Arc<dyn PhysicalExpr>, rhs: Arc<dyn PhysicalExpr>) -> Self {
+        Self {
+            lhs,
+            op,
+            rhs,
+            mode: ArithmeticMode::Checked,
+        }
+    }
+
+    /// interpret arithmetic in `mode`. Generated code takes the mode of its kernel
+    /// instead.
+    pub fn with_arithmetic_mode(self, mode: ArithmeticMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.mode
+    }
+
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    pub fn lhs(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.rhs
     }
 }
 
 impl PhysicalExpr for BinaryExpr {
-    fn output_type(&self, _: SchemaRef) -> DataType {
-        match self.op {
-            Op::Add => DataType::Int64,
-            Op::Lt => DataType::Boolean,
-        }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, schema: SchemaRef) -> DataType {
+        let lhs = self.lhs.output_type(schema.clone());
+        let rhs = self.rhs.output_type(schema);
+        // ill-typed operands are reported by type resolution.
+        binary_signature(self.op, &lhs, &rhs).map_or(lhs, |signature| signature.output)
     }
 
     fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
         vec![self.lhs.clone(), self.rhs.clone()]
     }
 
-    fn eval(&self, batch: &RecordBatch) -> Result<Datum, ()> {
-        let lhs = self.lhs.eval(batch).unwrap();
-        let rhs = self.rhs.eval(batch).unwrap();
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [lhs, rhs] = take_children(children)?;
+        Ok(Arc::new(
+            BinaryExpr::new(self.op, lhs, rhs).with_arithmetic_mode(self.mode),
+        ))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {:?}", self.op, self.mode)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let lhs = self.lhs.eval(batch)?;
+        let rhs = if matches!(self.op, Op::And | Op::Or) {
+            match self.eval_undecided_rhs(&lhs, batch)? {
+                Some(rhs) => rhs,
+                // lhs decides every row.
+                None => return Ok(lhs),
+            }
+        } else {
+            self.rhs.eval(batch)?
+        };
+        let scalar = matches!((&lhs, &rhs), (Datum::Scalar(_), Datum::Scalar(_)));
+        let len = if scalar { 1 } else { batch.num_rows() };
+        if self.op.is_arithmetic() && self.mode != ArithmeticMode::Checked {
+            let output = self.output_type(batch.schema());
+            let (lhs, rhs) = (lhs.into_array(len), rhs.into_array(len));
+            let lhs = match (self.op, &output) {
+                // both operands have the same scale, which the quotient loses, so the
+                // dividend is scaled up by the result's scale.
+                (Op::Div, DataType::Decimal128(_, output_scale)) => {
+                    let factor = 10i128.pow(*output_scale as u32);
+                    let factor: ArrayRef = Arc::new(
+                        Decimal128Array::from_value(factor, len)
+                            .with_data_type(lhs.data_type().clone()),
+                    );
+                    arith_unchecked(Op::Mul, self.mode, &lhs, &factor, lhs.data_type())?
+                }
+                _ => lhs,
+            };
+            let result = arith_unchecked(self.op, self.mode, &lhs, &rhs, &output)?;
+            return if scalar {
+                Ok(Datum::Scalar(ScalarValue::try_from_array(&result, 0)?))
+            } else {
+                Ok(Datum::Array(result))
+            };
+        }
+        let (l, r) = (lhs.as_ref(), rhs.as_ref());
+        let (l, r) = (&*l, &*r);
+        let result: ArrayRef = match self.op {
+            Op::Add => add(l, r)?,
+            Op::Sub => sub(l, r)?,
+            Op::Mul => mul(l, r)?,
+            Op::Div => div(l, r)?,
+            Op::Mod => rem(l, r)?,
+            Op::Eq => Arc::new(eq(l, r)?),
+            Op::NotEq => Arc::new(neq(l, r)?),
+            Op::Lt => Arc::new(lt(l, r)?),
+            Op::LtEq => Arc::new(lt_eq(l, r)?),
+            Op::Gt => Arc::new(gt(l, r)?),
+            Op::GtEq => Arc::new(gt_eq(l, r)?),
+            Op::And
+            | Op::Or
+            | Op::BitAnd
+            | Op::BitOr
+            | Op::BitXor
+            | Op::ShiftLeft
+            | Op::ShiftRight => {
+                let lhs = lhs.into_array(len);
+                let rhs = rhs.into_array(len);
+                match self.op {
+                    Op::And => Arc::new(and_kleene(lhs.as_boolean(), rhs.as_boolean())?),
+                    Op::Or => Arc::new(or_kleene(lhs.as_boolean(), rhs.as_boolean())?),
+                    op => bitwise(op, &lhs, &rhs)?,
+                }
+            }
+        };
+        if scalar {
+            Ok(Datum::Scalar(ScalarValue::try_from_array(&result, 0)?))
+        } else {
+            Ok(Datum::Array(result))
+        }
+    }
+}
+
+// arithmetic in a mode that doesn't stop on overflow or zero divisors, computed like
+// generated code does: integer overflow wraps or saturates and integer zero divisors give
+// null. Decimals compute on their unscaled values, `output` types the result.
+fn arith_unchecked(
+    op: Op,
+    mode: ArithmeticMode,
+    lhs: &ArrayRef,
+    rhs: &ArrayRef,
+    output: &DataType,
+) -> Result<ArrayRef> {
+    let saturate = mode == ArithmeticMode::Saturating;
+    macro_rules! int_op {
+        ($t:ty) => {{
+            let (lhs, rhs) = (lhs.as_primitive::<$t>(), rhs.as_primitive::<$t>());
+            let values: PrimitiveArray<$t> = lhs
+                .iter()
+                .zip(rhs.iter())
+                .map(|(l, r)| {
+                    let (l, r) = (l?, r?);
+                    match op {
+                        Op::Add if saturate => Some(l.saturating_add(r)),
+                        Op::Add => Some(l.wrapping_add(r)),
+                        Op::Sub if saturate => Some(l.saturating_sub(r)),
+                        Op::Sub => Some(l.wrapping_sub(r)),
+                        Op::Mul if saturate => Some(l.saturating_mul(r)),
+                        Op::Mul => Some(l.wrapping_mul(r)),
+                        _ if r == 0 => None,
+                        Op::Div if saturate => Some(l.saturating_div(r)),
+                        Op::Div => Some(l.wrapping_div(r)),
+                        _ => Some(l.wrapping_rem(r)),
+                    }
+                })
+                .collect();
+            Ok(Arc::new(values.with_data_type(output.clone())) as ArrayRef)
+        }};
+    }
+    // IEEE 754 never overflows, and zero divisors give inf or NaN.
+    macro_rules! float_op {
+        ($t:ty) => {{
+            let (lhs, rhs) = (lhs.as_primitive::<$t>(), rhs.as_primitive::<$t>());
+            let values: PrimitiveArray<$t> = lhs
+                .iter()
+                .zip(rhs.iter())
+                .map(|(l, r)| {
+                    let (l, r) = (l?, r?);
+                    match op {
+                        Op::Add => Some(l + r),
+                        Op::Sub => Some(l - r),
+                        Op::Mul => Some(l * r),
+                        Op::Div => Some(l / r),
+                        _ => Some(l % r),
+                    }
+                })
+                .collect();
+            Ok(Arc::new(values) as ArrayRef)
+        }};
+    }
+    match lhs.data_type() {
+        DataType::Int8 => int_op!(Int8Type),
+        DataType::Int16 => int_op!(Int16Type),
+        DataType::Int32 => int_op!(Int32Type),
+        DataType::Int64 => int_op!(Int64Type),
+        DataType::UInt8 => int_op!(UInt8Type),
+        DataType::UInt16 => int_op!(UInt16Type),
+        DataType::UInt32 => int_op!(UInt32Type),
+        DataType::UInt64 => int_op!(UInt64Type),
+        DataType::Float32 => float_op!(Float32Type),
+        DataType::Float64 => float_op!(Float64Type),
+        DataType::Decimal128(_, _) => int_op!(Decimal128Type),
+        other => Err(ServerError::TypeError(format!(
+            "{:?} can't be applied to {}",
+            op, other
+        ))),
+    }
+}
+
+// arrow's bitwise kernels are typed, both sides have the integer type of `lhs`.
+fn bitwise(op: Op, lhs: &ArrayRef, rhs: &ArrayRef) -> Result<ArrayRef> {
+    macro_rules! apply {
+        ($t:ty) => {{
+            let (lhs, rhs) = (lhs.as_primitive::<$t>(), rhs.as_primitive::<$t>());
+            let result = match op {
+                Op::BitAnd => bitwise_and(lhs, rhs),
+                Op::BitOr => bitwise_or(lhs, rhs),
+                Op::BitXor => bitwise_xor(lhs, rhs),
+                Op::ShiftLeft => bitwise_shift_left(lhs, rhs),
+                _ => bitwise_shift_right(lhs, rhs),
+            };
+            Ok(Arc::new(result?) as ArrayRef)
+        }};
+    }
+    match lhs.data_type() {
+        DataType::Int8 => apply!(Int8Type),
+        DataType::Int16 => apply!(Int16Type),
+        DataType::Int32 => apply!(Int32Type),
+        DataType::Int64 => apply!(Int64Type),
+        DataType::UInt8 => apply!(UInt8Type),
+        DataType::UInt16 => apply!(UInt16Type),
+        DataType::UInt32 => apply!(UInt32Type),
+        DataType::UInt64 => apply!(UInt64Type),
+        other => Err(ServerError::TypeError(format!(
+            "{:?} can't be applied to {}",
+            op, other
+        ))),
+    }
+}
+
+impl ExprGen for BinaryExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        // type resolution casts both operands to one type.
+        let data_type = self.lhs.output_type(ctx.schema());
+        let lhs = self.lhs.gen(ctx);
+        if matches!(self.op, Op::And | Op::Or) {
+            return self.gen_logical(ctx, lhs);
+        }
+        let rhs = self.rhs.gen(ctx);
+        let arith_op = match self.op {
+            Op::Add => ArithOp::Add,
+            Op::Sub => ArithOp::Sub,
+            Op::Mul => ArithOp::Mul,
+            Op::Div => ArithOp::Div,
+            Op::Mod => ArithOp::Mod,
+            _ => return self.gen_bits(ctx, &data_type, lhs, rhs),
+        };
+        let lhs = match (self.op, &data_type) {
+            // both operands have the same scale, which the quotient loses, so the
+            // dividend is scaled up by the result's scale.
+            (Op::Div, DataType::Decimal128(_, _)) => {
+                let DataType::Decimal128(_, output_scale) = self.output_type(ctx.schema()) else {
+                    unreachable!()
+                };
+                let factor = ctx.i128_const(10i128.pow(output_scale as u32));
+                ctx.arith(ArithOp::Mul, &data_type, lhs, GenValue::non_null(factor))
+            }
+            _ => lhs,
+        };
+        ctx.arith(arith_op, &data_type, lhs, rhs)
+    }
+
+    fn vectorizable(&self) -> bool {
+        // comparisons yield lane masks, which AND/OR combine bitwise. cranelift lacks
+        // vector multiplication of narrow lanes and division, and shifts take one amount
+        // for all lanes.
+        !matches!(
+            self.op,
+            Op::Mul | Op::Div | Op::Mod | Op::ShiftLeft | Op::ShiftRight
+        )
+    }
+
+    fn gen_cost(&self, ctx: &FuncGenContext) -> usize {
+        // divisions, and decimal products whose overflow check is a call, are slow.
         match self.op {
-            Op::Add => Ok(Datum::Array(add(&*lhs.as_ref(), &*rhs.as_ref()).unwrap())),
-            Op::Lt => Ok(Datum::Array(Arc::new(
-                lt(&*lhs.as_ref(), &*rhs.as_ref()).unwrap(),
-            ))),
+            Op::Div | Op::Mod => 20,
+            Op::Mul
+                if matches!(
+                    self.lhs.output_type(ctx.schema()),
+                    DataType::Decimal128(_, _)
+                ) =>
+            {
+                20
+            }
+            Op::Mul => 3,
+            _ => 1,
         }
     }
+
+    fn gen_may_fail(&self, ctx: &FuncGenContext) -> bool {
+        // overflows and zero divisors only stop checked arithmetic.
+        self.op.is_arithmetic() && ctx.arithmetic_mode() == ArithmeticMode::Checked
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        let data_type = self.lhs.output_type(backend.schema());
+        let lhs = self.lhs.gen_backend(backend)?;
+        if matches!(self.op, Op::And | Op::Or) {
+            // backends don't branch around rhs, so it runs for rows lhs decides too.
+            if backend_may_fail(&self.rhs, backend.arithmetic_mode()) {
+                return None;
+            }
+            let rhs = self.rhs.gen_backend(backend)?;
+            return Some(match self.op {
+                Op::And => backend.kleene_and(lhs, rhs),
+                _ => backend.kleene_or(lhs, rhs),
+            });
+        }
+        let rhs = self.rhs.gen_backend(backend)?;
+        let compare_op = match self.op {
+            Op::Eq => Some(CompareOp::Eq),
+            Op::NotEq => Some(CompareOp::NotEq),
+            Op::Lt => Some(CompareOp::Lt),
+            Op::LtEq => Some(CompareOp::LtEq),
+            Op::Gt => Some(CompareOp::Gt),
+            Op::GtEq => Some(CompareOp::GtEq),
+            _ => None,
+        };
+        if let Some(op) = compare_op {
+            let valid = backend.and_valid(lhs.valid, rhs.valid);
+            let value = backend.compare(op, &data_type, lhs.value, rhs.value)?;
+            return Some(BackendGenValue::new(value, valid));
+        }
+        let arith_op = match self.op {
+            Op::Add => ArithOp::Add,
+            Op::Sub => ArithOp::Sub,
+            Op::Mul => ArithOp::Mul,
+            Op::Div => ArithOp::Div,
+            Op::Mod => ArithOp::Mod,
+            // bitwise operations aren't generated through backends.
+            _ => return None,
+        };
+        let lhs = match (self.op, &data_type) {
+            // both operands have the same scale, which the quotient loses, so the
+            // dividend is scaled up by the result's scale.
+            (Op::Div, DataType::Decimal128(_, _)) => {
+                let DataType::Decimal128(_, output_scale) = self.output_type(backend.schema())
+                else {
+                    unreachable!()
+                };
+                let factor = 10i128.pow(output_scale as u32);
+                let factor = backend.int_const(&data_type, factor)?;
+                backend.arith(
+                    ArithOp::Mul,
+                    &data_type,
+                    lhs,
+                    BackendGenValue::non_null(factor),
+                )?
+            }
+            _ => lhs,
+        };
+        backend.arith(arith_op, &data_type, lhs, rhs)
+    }
+}
+
+// whether code generated through a backend for `expr` can stop the kernel, which only
+// checked arithmetic does.
+fn backend_may_fail(expr: &PhysicalExprRef, mode: ArithmeticMode) -> bool {
+    let fails = mode == ArithmeticMode::Checked
+        && expr
+            .as_any()
+            .downcast_ref::<BinaryExpr>()
+            .is_some_and(|binary| binary.op.is_arithmetic());
+    fails
+        || expr
+            .children()
+            .iter()
+            .any(|child| backend_may_fail(child, mode))
+}
+
+impl BinaryExpr {
+    // rhs of AND or OR, evaluated only for the rows lhs doesn't decide on its own, `None`
+    // when it decides all of them. Other rows are null in rhs.
+    fn eval_undecided_rhs(&self, lhs: &Datum, batch: &RecordBatch) -> Result<Option<Datum>> {
+        // false decides AND and true decides OR, null decides neither.
+        let decides = self.op == Op::Or;
+        let lhs = match lhs {
+            Datum::Scalar(ScalarValue::Boolean(Some(value))) if *value == decides => {
+                return Ok(None)
+            }
+            Datum::Scalar(_) => return Ok(Some(self.rhs.eval(batch)?)),
+            Datum::Array(lhs) => lhs.as_boolean(),
+        };
+        let decided = if decides {
+            lhs.values().clone()
+        } else {
+            !lhs.values()
+        };
+        let decided = match lhs.nulls() {
+            Some(nulls) => &decided & nulls.inner(),
+            None => decided,
+        };
+        let undecided = BooleanArray::new(!&decided, None);
+        match undecided.true_count() {
+            0 => Ok(None),
+            _ => Ok(Some(Datum::Array(eval_selected(
+                &self.rhs, batch, &undecided,
+            )?))),
+        }
+    }
+
+    // AND or OR, which skip rhs for rows lhs decides unless rhs is cheap and can't fail.
+    // vector lanes can't branch apart.
+    fn gen_logical(&self, ctx: &mut FuncGenContext, lhs: GenValue) -> GenValue {
+        let speculate = ctx.lanes() > 1
+            || speculation_cost(&self.rhs, ctx).is_some_and(|cost| cost <= MAX_SPECULATION_COST);
+        match (self.op, speculate) {
+            (Op::And, true) => {
+                let rhs = self.rhs.gen(ctx);
+                ctx.kleene_and(lhs, rhs)
+            }
+            (Op::Or, true) => {
+                let rhs = self.rhs.gen(ctx);
+                ctx.kleene_or(lhs, rhs)
+            }
+            (Op::And, false) => ctx.short_circuit_and(lhs, |ctx| self.rhs.gen(ctx)),
+            _ => ctx.short_circuit_or(lhs, |ctx| self.rhs.gen(ctx)),
+        }
+    }
+
+    // comparisons and bitwise operations, which can't fail.
+    fn gen_bits(
+        &self,
+        ctx: &mut FuncGenContext,
+        data_type: &DataType,
+        lhs: GenValue,
+        rhs: GenValue,
+    ) -> GenValue {
+        let valid = ctx.and_valid(lhs.valid, rhs.valid);
+        let (lhs, rhs) = (lhs.value, rhs.value);
+        let signed = !data_type.is_unsigned_integer();
+        let value = match self.op {
+            Op::Eq => ctx.compare(IntCC::Equal, data_type, lhs, rhs),
+            Op::NotEq => ctx.compare(IntCC::NotEqual, data_type, lhs, rhs),
+            Op::Lt => ctx.compare(IntCC::SignedLessThan, data_type, lhs, rhs),
+            Op::LtEq => ctx.compare(IntCC::SignedLessThanOrEqual, data_type, lhs, rhs),
+            Op::Gt => ctx.compare(IntCC::SignedGreaterThan, data_type, lhs, rhs),
+            Op::GtEq => ctx.compare(IntCC::SignedGreaterThanOrEqual, data_type, lhs, rhs),
+            Op::BitAnd => ctx.builder.ins().band(lhs, rhs),
+            Op::BitOr => ctx.builder.ins().bor(lhs, rhs),
+            Op::BitXor => ctx.builder.ins().bxor(lhs, rhs),
+            Op::ShiftLeft => ctx.builder.ins().ishl(lhs, rhs),
+            Op::ShiftRight if signed => ctx.builder.ins().sshr(lhs, rhs),
+            Op::ShiftRight => ctx.builder.ins().ushr(lhs, rhs),
+            _ => unreachable!(),
+        };
+        GenValue::new(value, valid)
+    }
 }
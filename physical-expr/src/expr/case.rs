@@ -0,0 +1,276 @@
+use core::{gen_type, ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::{new_null_array, ArrayRef, AsArray, BooleanArray},
+    compute::kernels::{
+        boolean::{and, not},
+        zip::zip,
+    },
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use cranelift::prelude::Type;
+
+use crate::{
+    coercion::common_type, eval_selected, speculation_cost, true_rows, Datum, PhysicalExpr,
+    PhysicalExprRef, MAX_SPECULATION_COST,
+};
+
+/// `CASE WHEN cond THEN value ... ELSE otherwise END`: the value of the first arm whose
+/// condition is true, `otherwise` when none is, or null without an ELSE. Conditions and
+/// values are only evaluated for the rows that reach them.
+pub struct CaseExpr {
+    when_then: Vec<(PhysicalExprRef, PhysicalExprRef)>,
+    else_expr: Option<PhysicalExprRef>,
+}
+
+impl CaseExpr {
+    pub fn new(
+        when_then: Vec<(PhysicalExprRef, PhysicalExprRef)>,
+        else_expr: Option<PhysicalExprRef>,
+    ) -> Self {
+        assert!(!when_then.is_empty(), "case needs at least one WHEN arm");
+        Self {
+            when_then,
+            else_expr,
+        }
+    }
+
+    /// `IF(cond, then, otherwise)`.
+    pub fn if_then_else(
+        cond: PhysicalExprRef,
+        then: PhysicalExprRef,
+        otherwise: PhysicalExprRef,
+    ) -> Self {
+        Self::new(vec![(cond, then)], Some(otherwise))
+    }
+
+    pub fn when_then(&self) -> &[(PhysicalExprRef, PhysicalExprRef)] {
+        &self.when_then
+    }
+
+    pub fn else_expr(&self) -> Option<&PhysicalExprRef> {
+        self.else_expr.as_ref()
+    }
+
+    // the values of every arm and the ELSE.
+    pub(crate) fn values(&self) -> impl Iterator<Item = &PhysicalExprRef> {
+        self.when_then
+            .iter()
+            .map(|(_, then)| then)
+            .chain(self.else_expr.iter())
+    }
+
+    // the value of the arms from `arm` on, computed for every row when they are cheap
+    // enough and can't fail, and otherwise only in the block of rows that reach them.
+    fn gen_arms(
+        &self,
+        ctx: &mut FuncGenContext,
+        arm: usize,
+        _type: Type,
+        rest_costs: &[Option<usize>],
+    ) -> GenValue {
+        let Some((when, then)) = self.when_then.get(arm) else {
+            return match &self.else_expr {
+                Some(otherwise) => otherwise.gen(ctx),
+                None => ctx.null_value(_type),
+            };
+        };
+        let cond = when.gen(ctx);
+        let cond = ctx.is_true(cond);
+        let select = match (speculation_cost(then, ctx), rest_costs[arm + 1]) {
+            (Some(then_cost), Some(rest_cost)) => then_cost + rest_cost <= MAX_SPECULATION_COST,
+            _ => false,
+        };
+        if select {
+            let then = then.gen(ctx);
+            let otherwise = self.gen_arms(ctx, arm + 1, _type, rest_costs);
+            ctx.select(cond, then, otherwise)
+        } else {
+            ctx.branch(
+                cond,
+                _type,
+                |ctx| then.gen(ctx),
+                |ctx| self.gen_arms(ctx, arm + 1, _type, rest_costs),
+            )
+        }
+    }
+}
+
+impl PhysicalExpr for CaseExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, schema: SchemaRef) -> DataType {
+        let value_types: Vec<DataType> = self
+            .values()
+            .map(|value| value.output_type(schema.clone()))
+            .collect();
+        common_type(&value_types).unwrap_or_else(|| value_types[0].clone())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        self.when_then
+            .iter()
+            .flat_map(|(when, then)| [when.clone(), then.clone()])
+            .chain(self.else_expr.clone())
+            .collect()
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let expected = self.when_then.len() * 2 + self.else_expr.is_some() as usize;
+        if children.len() != expected {
+            return Err(ServerError::ArgumentError(format!(
+                "expected {} children, got {}",
+                expected,
+                children.len()
+            )));
+        }
+        let mut children = children.into_iter();
+        let when_then = self
+            .when_then
+            .iter()
+            .map(|_| (children.next().unwrap(), children.next().unwrap()))
+            .collect();
+        Ok(Arc::new(CaseExpr::new(when_then, children.next())))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.when_then.len(), self.else_expr.is_some())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let len = batch.num_rows();
+        let mut result: ArrayRef = new_null_array(&self.output_type(batch.schema()), len);
+        // rows no arm has matched yet.
+        let mut remaining = BooleanArray::from(vec![true; len]);
+        for (when, then) in &self.when_then {
+            let cond = eval_selected(when, batch, &remaining)?;
+            let matched = and(&remaining, &true_rows(cond.as_boolean()))?;
+            if matched.true_count() == 0 {
+                continue;
+            }
+            let values = eval_selected(then, batch, &matched)?;
+            result = zip(&matched, &values, &result)?;
+            remaining = and(&remaining, &not(&matched)?)?;
+            if remaining.true_count() == 0 {
+                return Ok(Datum::Array(result));
+            }
+        }
+        if let Some(otherwise) = &self.else_expr {
+            let values = eval_selected(otherwise, batch, &remaining)?;
+            result = zip(&remaining, &values, &result)?;
+        }
+        Ok(Datum::Array(result))
+    }
+}
+
+impl ExprGen for CaseExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        // type resolution casts every value to the output type.
+        let _type = gen_type(&self.output_type(ctx.schema())).unwrap();
+        let mut rest_costs = vec![None; self.when_then.len() + 1];
+        rest_costs[self.when_then.len()] = match &self.else_expr {
+            Some(otherwise) => speculation_cost(otherwise, ctx),
+            None => Some(1),
+        };
+        for (arm, (when, then)) in self.when_then.iter().enumerate().rev() {
+            rest_costs[arm] = match (rest_costs[arm + 1], speculation_cost(when, ctx)) {
+                (Some(rest), Some(when)) => {
+                    speculation_cost(then, ctx).map(|then| rest + when + then)
+                }
+                _ => None,
+            };
+        }
+        self.gen_arms(ctx, 0, _type, &rest_costs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, BooleanArray, Int64Array},
+        datatypes::{DataType, Field, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        compile,
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::CaseExpr;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn binary(op: Op, lhs: PhysicalExprRef, rhs: PhysicalExprRef) -> PhysicalExprRef {
+        Arc::new(BinaryExpr::new(op, lhs, rhs))
+    }
+
+    fn assert_compiled_matches_eval(expr: PhysicalExprRef, batch: &RecordBatch) {
+        let compiled = compile(&expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(batch).unwrap();
+        let Datum::Array(expected) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&result, &expected);
+    }
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+            Field::new("c", DataType::Boolean, true),
+        ]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None]);
+        let b = Int64Array::from(vec![5, 4, 3, 2, 1]);
+        let c = BooleanArray::from(vec![Some(true), None, Some(false), None, Some(true)]);
+        RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b), Arc::new(c)]).unwrap()
+    }
+
+    #[test]
+    fn test_compile_case() {
+        let batch = batch();
+        let int = |value| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value))))
+        };
+        let (a, b, c) = (column("a", 0), column("b", 1), column("c", 2));
+
+        // cheap arms, computed for every row and selected from.
+        let large = binary(Op::Gt, b.clone(), int(3));
+        let case = CaseExpr::new(vec![(c.clone(), a.clone()), (large, b.clone())], None);
+        assert_compiled_matches_eval(Arc::new(case), &batch);
+        assert_compiled_matches_eval(Arc::new(CaseExpr::if_then_else(c, b.clone(), a)), &batch);
+
+        // a checked division only runs for the rows its arm guards.
+        let divisor = binary(Op::Sub, b.clone(), int(3));
+        let nonzero = binary(Op::NotEq, b, int(3));
+        let quotient = binary(Op::Div, int(10), divisor);
+        let case = Arc::new(CaseExpr::new(vec![(nonzero, quotient)], Some(int(0)))) as _;
+        assert_compiled_matches_eval(Arc::clone(&case), &batch);
+        let result = compile(&case, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch)
+            .unwrap();
+        assert_eq!(
+            result.as_primitive::<Int64Type>().values(),
+            &[5, 10, 0, -10, -5]
+        );
+    }
+}
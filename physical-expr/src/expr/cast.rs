@@ -0,0 +1,275 @@
+use core::{
+    can_gen_cast, cast_can_fail, BackendGenValue, CodegenBackend, ExprGen, FuncGenContext,
+    GenValue, KernelErrorCode,
+};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, AsArray},
+    compute::{can_cast_types, cast_with_options, CastOptions},
+    datatypes::{DataType, Decimal128Type, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use cranelift::prelude::InstBuilder;
+
+use crate::{take_children, Datum, PhysicalExpr, PhysicalExprRef, ScalarValue};
+
+/// Conversion of `expr` to `to_type`, inserted by type resolution wherever an operand
+/// must be widened to the type of the operation. A `CAST` fails on values `to_type`
+/// can't represent, a `TRY_CAST` makes them null.
+pub struct CastExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    to_type: DataType,
+    try_cast: bool,
+}
+
+impl CastExpr {
+    pub fn new(expr: Arc<dyn PhysicalExpr>, to_type: DataType) -> Self {
+        Self {
+            expr,
+            to_type,
+            try_cast: false,
+        }
+    }
+
+    pub fn try_cast(expr: Arc<dyn PhysicalExpr>, to_type: DataType) -> Self {
+        Self {
+            expr,
+            to_type,
+            try_cast: true,
+        }
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn to_type(&self) -> &DataType {
+        &self.to_type
+    }
+
+    pub fn is_try_cast(&self) -> bool {
+        self.try_cast
+    }
+
+    fn options(&self) -> CastOptions<'static> {
+        CastOptions {
+            safe: self.try_cast,
+            ..Default::default()
+        }
+    }
+
+    // arrow doesn't check that decimals it scales up keep to the target precision, values
+    // with too many digits fail like other values that don't fit.
+    fn cast_array(&self, array: &ArrayRef) -> Result<ArrayRef> {
+        let cast = cast_with_options(array, &self.to_type, &self.options())?;
+        let DataType::Decimal128(precision, _) = self.to_type else {
+            return Ok(cast);
+        };
+        let decimals = cast.as_primitive::<Decimal128Type>();
+        if self.try_cast {
+            let decimals = decimals.null_if_overflow_precision(precision);
+            return Ok(Arc::new(decimals.with_data_type(self.to_type.clone())));
+        }
+        decimals.validate_decimal_precision(precision)?;
+        Ok(cast)
+    }
+}
+
+impl PhysicalExpr for CastExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        self.to_type.clone()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.expr.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [expr] = take_children(children)?;
+        Ok(Arc::new(CastExpr {
+            expr,
+            to_type: self.to_type.clone(),
+            try_cast: self.try_cast,
+        }))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}", self.to_type, self.try_cast)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        match self.expr.eval(batch)? {
+            Datum::Array(array) => Ok(Datum::Array(self.cast_array(&array)?)),
+            Datum::Scalar(scalar) => {
+                let array = self.cast_array(&scalar.to_array_of_size(1))?;
+                Ok(Datum::Scalar(ScalarValue::try_from_array(&array, 0)?))
+            }
+        }
+    }
+
+    fn check_gen(&self, schema: SchemaRef) -> Result<()> {
+        let from = self.expr.output_type(schema);
+        if can_cast_types(&from, &self.to_type) && can_gen_cast(&from, &self.to_type) {
+            Ok(())
+        } else {
+            Err(ServerError::NotSupported(format!(
+                "cast from {} to {} in generated code",
+                from, self.to_type
+            )))
+        }
+    }
+}
+
+impl ExprGen for CastExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let from = self.expr.output_type(ctx.schema());
+        let input = self.expr.gen(ctx);
+        let (value, ok) = ctx.cast(input.value, &from, &self.to_type);
+        let Some(ok) = ok else {
+            return GenValue::new(value, input.valid);
+        };
+        if self.try_cast {
+            let valid = ctx.and_valid(input.valid, Some(ok));
+            GenValue::new(value, valid)
+        } else {
+            let failed = ctx.builder.ins().bxor_imm(ok, 1);
+            ctx.fail_if(failed, input.valid, KernelErrorCode::InvalidCast);
+            GenValue::new(value, input.valid)
+        }
+    }
+
+    fn gen_cost(&self, ctx: &FuncGenContext) -> usize {
+        // dropping decimal digits divides by a call.
+        match (self.expr.output_type(ctx.schema()), &self.to_type) {
+            (DataType::Decimal128(_, from_scale), DataType::Decimal128(_, to_scale)) => {
+                if *to_scale < from_scale {
+                    20
+                } else {
+                    2
+                }
+            }
+            (DataType::Decimal128(_, _), to_type) if !to_type.is_floating() => 20,
+            _ => 2,
+        }
+    }
+
+    fn gen_may_fail(&self, ctx: &FuncGenContext) -> bool {
+        !self.try_cast && cast_can_fail(&self.expr.output_type(ctx.schema()), &self.to_type)
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        // backends only convert values that always fit.
+        let from = self.expr.output_type(backend.schema());
+        let input = self.expr.gen_backend(backend)?;
+        let value = backend.cast(input.value, &from, &self.to_type)?;
+        Some(BackendGenValue::new(value, input.valid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{
+            Date32Array, Decimal128Array, Float64Array, Int64Array, TimestampMillisecondArray,
+        },
+        datatypes::{DataType, Field, Schema, TimeUnit},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+
+    use crate::{compile, expr::column::ColumnExpr, Datum, PhysicalExprRef};
+
+    use super::CastExpr;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn assert_compiled_matches_eval(expr: PhysicalExprRef, batch: &RecordBatch) {
+        let compiled = compile(&expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(batch).unwrap();
+        let Datum::Array(expected) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&result, &expected);
+    }
+
+    #[test]
+    fn test_compile_casts() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("i", DataType::Int64, true),
+            Field::new("f", DataType::Float64, false),
+            Field::new("d", DataType::Decimal128(10, 3), false),
+            Field::new("t", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+            Field::new("dt", DataType::Date32, false),
+        ]));
+        let columns = vec![
+            Arc::new(Int64Array::from(vec![
+                Some(1),
+                Some(300),
+                Some(-129),
+                None,
+                Some(i64::MIN),
+            ])) as _,
+            Arc::new(Float64Array::from(vec![1.5, 2.5, -2.5, f64::NAN, 1e20])) as _,
+            Arc::new(
+                Decimal128Array::from(vec![1500, 2500, -2500, 123456, -1])
+                    .with_precision_and_scale(10, 3)
+                    .unwrap(),
+            ) as _,
+            Arc::new(TimestampMillisecondArray::from(vec![
+                0,
+                -1,
+                86_400_001,
+                1_700_000_000_000,
+                -86_400_000,
+            ])) as _,
+            Arc::new(Date32Array::from(vec![0, -1, 19000, 1, -719162])) as _,
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+
+        let casts = [
+            ("i", 0, DataType::Int8),
+            ("i", 0, DataType::UInt32),
+            ("i", 0, DataType::Float32),
+            ("i", 0, DataType::Decimal128(5, 2)),
+            ("f", 1, DataType::Int32),
+            ("f", 1, DataType::UInt8),
+            ("f", 1, DataType::Decimal128(6, 0)),
+            ("d", 2, DataType::Int16),
+            ("d", 2, DataType::Float64),
+            ("d", 2, DataType::Decimal128(6, 1)),
+            ("d", 2, DataType::Decimal128(5, 4)),
+            ("t", 3, DataType::Date32),
+            ("t", 3, DataType::Date64),
+            ("t", 3, DataType::Timestamp(TimeUnit::Second, None)),
+            ("t", 3, DataType::Timestamp(TimeUnit::Microsecond, None)),
+            ("t", 3, DataType::Int64),
+            ("dt", 4, DataType::Date64),
+            ("dt", 4, DataType::Timestamp(TimeUnit::Second, None)),
+        ];
+        for (name, index, to_type) in casts {
+            let expr = Arc::new(CastExpr::try_cast(column(name, index), to_type));
+            assert_compiled_matches_eval(expr, &batch);
+        }
+
+        // a cast fails where a try_cast gives null.
+        let narrowed = Arc::new(CastExpr::new(column("i", 0), DataType::Int8)) as PhysicalExprRef;
+        assert!(narrowed.eval(&batch).is_err());
+        let result = compile(&narrowed, batch.schema(), ArithmeticMode::Checked)
+            .and_then(|compiled| compiled.eval(&batch));
+        assert!(matches!(result, Err(ServerError::InvalidCast { row: 1 })));
+    }
+}
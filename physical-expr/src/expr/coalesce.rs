@@ -0,0 +1,79 @@
+use core::{ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::Array,
+    compute::kernels::{boolean::is_not_null, zip::zip},
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+
+use crate::{coercion::common_type, Datum, PhysicalExpr, PhysicalExprRef};
+
+pub struct CoalesceExpr {
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl CoalesceExpr {
+    pub fn new(args: Vec<Arc<dyn PhysicalExpr>>) -> Self {
+        assert!(!args.is_empty(), "coalesce needs at least one argument");
+        Self { args }
+    }
+}
+
+impl PhysicalExpr for CoalesceExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, schema: SchemaRef) -> DataType {
+        let arg_types: Vec<DataType> = self
+            .args
+            .iter()
+            .map(|arg| arg.output_type(schema.clone()))
+            .collect();
+        common_type(&arg_types).unwrap_or_else(|| arg_types[0].clone())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        self.args.clone()
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        if children.is_empty() {
+            return Err(ServerError::ArgumentError(
+                "coalesce needs at least one argument".to_string(),
+            ));
+        }
+        Ok(Arc::new(CoalesceExpr::new(children)))
+    }
+
+    fn fingerprint(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let len = batch.num_rows();
+        let mut result = self.args[0].eval(batch)?.into_array(len);
+        for arg in &self.args[1..] {
+            if result.null_count() == 0 {
+                break;
+            }
+            let next = arg.eval(batch)?.into_array(len);
+            let mask = is_not_null(&result)?;
+            result = zip(&mask, &result, &next)?;
+        }
+        Ok(Datum::Array(result))
+    }
+}
+
+impl ExprGen for CoalesceExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let inputs: Vec<GenValue> = self.args.iter().map(|arg| arg.gen(ctx)).collect();
+        ctx.coalesce(&inputs)
+    }
+}
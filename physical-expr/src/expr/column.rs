@@ -1,26 +1,40 @@
-use std::{ops::Index, sync::Arc};
+use std::{any::Any, fmt, ops::Index, sync::Arc};
 
 use arrow::{
     datatypes::{DataType, SchemaRef},
     record_batch::RecordBatch,
 };
+use common::Result;
+use core::{BackendGenValue, CodegenBackend, ExprGen, FuncGenContext, GenValue};
 
-use crate::PhysicalExpr;
+use crate::{take_children, PhysicalExpr, PhysicalExprRef};
 
 use crate::Datum;
 
-struct ColumnExpr {
+pub struct ColumnExpr {
     name: String,
     index: usize,
 }
 
 impl ColumnExpr {
-    fn new(name: String, index: usize) -> Self {
+    pub fn new(name: String, index: usize) -> Self {
         Self { name, index }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
 }
 
 impl PhysicalExpr for ColumnExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn output_type(&self, schema: SchemaRef) -> DataType {
         schema.field(self.index).data_type().clone()
     }
@@ -29,7 +43,33 @@ impl PhysicalExpr for ColumnExpr {
         vec![]
     }
 
-    fn eval(&self, batch: &RecordBatch) -> Result<Datum, ()> {
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [] = take_children(children)?;
+        Ok(self)
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
         Ok(Datum::Array(batch.index(&self.name).clone()))
     }
 }
+
+impl ExprGen for ColumnExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        ctx.column(self.index)
+    }
+
+    fn vectorizable(&self) -> bool {
+        true
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        backend.column(self.index)
+    }
+}
@@ -0,0 +1,480 @@
+use core::{float32_key, float_key, ExprGen, FuncGenContext, GenValue, ValueSet};
+use std::{any::Any, collections::HashSet, fmt, sync::Arc};
+
+use arrow::{
+    array::BooleanArray,
+    compute::kernels::{
+        boolean::{not, or_kleene},
+        cmp::eq,
+    },
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use cranelift::prelude::*;
+
+use crate::{expr::literal::LiteralExpr, Datum, PhysicalExpr, PhysicalExprRef, ScalarValue};
+
+/// lists up to this long are compared one item after another.
+const MAX_UNROLLED: usize = 8;
+
+/// integer lists spanning fewer than this many values per item become a jump table.
+const MAX_SWITCH_SPREAD: i128 = 4;
+
+/// `expr IN (list)`, or `NOT IN` when negated. Like a chain of `=` joined by `OR`, it is
+/// null when no item matches and `expr` or any item is null.
+pub struct InListExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    list: Vec<Arc<dyn PhysicalExpr>>,
+    negated: bool,
+    search: Search,
+}
+
+// How generated code looks for a value in the list, chosen when the expression is built.
+enum Search {
+    /// compare with every item.
+    Unrolled,
+    /// switch over the distinct integer constants of the list.
+    Switch { values: Vec<i128>, has_null: bool },
+    /// look the value up in a set of the list's constants.
+    Set { set: Arc<ValueSet>, has_null: bool },
+}
+
+impl InListExpr {
+    pub fn new(
+        expr: Arc<dyn PhysicalExpr>,
+        list: Vec<Arc<dyn PhysicalExpr>>,
+        negated: bool,
+    ) -> Self {
+        assert!(!list.is_empty(), "IN needs at least one list item");
+        let search = choose_search(&list);
+        Self {
+            expr,
+            list,
+            negated,
+            search,
+        }
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn list(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.list
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+}
+
+fn choose_search(list: &[PhysicalExprRef]) -> Search {
+    if list.len() <= MAX_UNROLLED {
+        return Search::Unrolled;
+    }
+    let Some(constants) = list
+        .iter()
+        .map(|item| {
+            let literal = item.as_any().downcast_ref::<LiteralExpr>()?;
+            Some(literal.value())
+        })
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Search::Unrolled;
+    };
+    // items only share a type once type resolution cast them.
+    let data_type = constants[0].data_type();
+    if constants
+        .iter()
+        .any(|constant| constant.data_type() != data_type)
+    {
+        return Search::Unrolled;
+    }
+    let has_null = constants.iter().any(|constant| constant.is_null());
+    let values: Vec<&ScalarValue> = constants
+        .into_iter()
+        .filter(|constant| !constant.is_null())
+        .collect();
+    if values.is_empty() {
+        return Search::Unrolled;
+    }
+
+    if let Some(ints) = values
+        .iter()
+        .map(|value| int_value(value))
+        .collect::<Option<Vec<_>>>()
+    {
+        let min = ints.iter().min().unwrap();
+        let max = ints.iter().max().unwrap();
+        if max - min < ints.len() as i128 * MAX_SWITCH_SPREAD {
+            return Search::Switch {
+                values: ints,
+                has_null,
+            };
+        }
+        // unsigned values keep their bits, like generated code widens them.
+        let keys = ints.iter().map(|value| *value as i64).collect();
+        return Search::Set {
+            set: Arc::new(ValueSet::Int64(keys)),
+            has_null,
+        };
+    }
+    let set = match data_type {
+        DataType::Float32 | DataType::Float64 => {
+            let keys = values
+                .iter()
+                .map(|value| match value {
+                    ScalarValue::Float32(Some(value)) => float32_key(*value),
+                    ScalarValue::Float64(Some(value)) => float_key(*value),
+                    _ => unreachable!(),
+                })
+                .collect();
+            ValueSet::Int64(keys)
+        }
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            let strings: HashSet<String> = values
+                .iter()
+                .map(|value| match value {
+                    ScalarValue::Utf8(Some(value)) | ScalarValue::LargeUtf8(Some(value)) => {
+                        value.clone()
+                    }
+                    _ => unreachable!(),
+                })
+                .collect();
+            ValueSet::Utf8(strings)
+        }
+        _ => return Search::Unrolled,
+    };
+    Search::Set {
+        set: Arc::new(set),
+        has_null,
+    }
+}
+
+// the value of a non-null integer, date or timestamp constant.
+fn int_value(value: &ScalarValue) -> Option<i128> {
+    match value {
+        ScalarValue::Int8(value) => value.map(i128::from),
+        ScalarValue::Int16(value) => value.map(i128::from),
+        ScalarValue::Int32(value) | ScalarValue::Date32(value) => value.map(i128::from),
+        ScalarValue::Int64(value)
+        | ScalarValue::Date64(value)
+        | ScalarValue::TimestampSecond(value, _)
+        | ScalarValue::TimestampMillisecond(value, _)
+        | ScalarValue::TimestampMicrosecond(value, _)
+        | ScalarValue::TimestampNanosecond(value, _) => value.map(i128::from),
+        ScalarValue::UInt8(value) => value.map(i128::from),
+        ScalarValue::UInt16(value) => value.map(i128::from),
+        ScalarValue::UInt32(value) => value.map(i128::from),
+        ScalarValue::UInt64(value) => value.map(i128::from),
+        _ => None,
+    }
+}
+
+impl PhysicalExpr for InListExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        let mut children = vec![self.expr.clone()];
+        children.extend(self.list.iter().cloned());
+        children
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        if children.len() < 2 {
+            return Err(ServerError::ArgumentError(
+                "IN needs an expression and at least one list item".to_string(),
+            ));
+        }
+        let list = children.split_off(1);
+        let expr = children.pop().unwrap();
+        Ok(Arc::new(InListExpr::new(expr, list, self.negated)))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.negated)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.expr.eval(batch)?.into_array(batch.num_rows());
+        let mut found = BooleanArray::from(vec![false; input.len()]);
+        for item in &self.list {
+            let item = item.eval(batch)?.as_ref();
+            let equal = eq(&input, &*item)?;
+            found = or_kleene(&found, &equal)?;
+        }
+        let result = if self.negated { not(&found)? } else { found };
+        Ok(Datum::Array(Arc::new(result)))
+    }
+}
+
+impl ExprGen for InListExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let data_type = self.expr.output_type(ctx.schema());
+        let input = self.expr.gen(ctx);
+        let (found, has_null) = match &self.search {
+            Search::Unrolled => return self.gen_unrolled(ctx, &data_type, input),
+            Search::Switch { values, has_null } => {
+                (ctx.switch_contains(input.value, values), *has_null)
+            }
+            Search::Set { set, has_null } => (ctx.in_set(set, &data_type, input.value), *has_null),
+        };
+        // with a null in the list, values it doesn't find may or may not be in it.
+        let valid = if has_null {
+            ctx.and_valid(input.valid, Some(found))
+        } else {
+            input.valid
+        };
+        let value = if self.negated {
+            ctx.builder.ins().bxor_imm(found, 1)
+        } else {
+            found
+        };
+        GenValue::new(value, valid)
+    }
+
+    fn gen_cost(&self, _ctx: &FuncGenContext) -> usize {
+        match self.search {
+            Search::Unrolled => 2 * self.list.len(),
+            Search::Switch { .. } => 4,
+            Search::Set { .. } => 20,
+        }
+    }
+}
+
+impl InListExpr {
+    fn gen_unrolled(
+        &self,
+        ctx: &mut FuncGenContext,
+        data_type: &DataType,
+        input: GenValue,
+    ) -> GenValue {
+        let mut found: Option<GenValue> = None;
+        for item in &self.list {
+            let item = item.gen(ctx);
+            let equal = ctx.compare(IntCC::Equal, data_type, input.value, item.value);
+            let valid = ctx.and_valid(input.valid, item.valid);
+            let equal = GenValue::new(equal, valid);
+            found = Some(match found {
+                Some(found) => ctx.kleene_or(found, equal),
+                None => equal,
+            });
+        }
+        let found = found.unwrap();
+        if self.negated {
+            ctx.kleene_not(found)
+        } else {
+            found
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, BooleanArray, Int64Array, LargeStringArray, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        compile,
+        expr::{cast::CastExpr, column::ColumnExpr, literal::LiteralExpr},
+        resolve_types, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::InListExpr;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn assert_compiled_matches_eval(expr: PhysicalExprRef, batch: &RecordBatch) {
+        let compiled = compile(&expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(batch).unwrap();
+        // compiling inserts the casts type resolution adds, evaluation takes them as given.
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(expected) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&result, &expected);
+    }
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None]);
+        let b = Int64Array::from(vec![5, 4, 3, 2, 1]);
+        RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b)]).unwrap()
+    }
+
+    fn string_batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("l", DataType::LargeUtf8, false),
+        ]));
+        let s = StringArray::from(vec![
+            Some("apple"),
+            None,
+            Some(""),
+            Some("banana"),
+            Some("42"),
+            Some("héllo"),
+        ]);
+        let l = LargeStringArray::from(vec!["apple", "cherry", "", "banan", "42", "hello"]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(s), Arc::new(l)]).unwrap();
+        // offsets of a sliced array don't start at 0.
+        batch.slice(1, 5)
+    }
+
+    #[test]
+    fn test_compile_in_list() {
+        let int = |value: i64| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value))))
+        };
+        let null: PhysicalExprRef = Arc::new(LiteralExpr::new(ScalarValue::Int64(None)));
+        let in_list = |expr: &PhysicalExprRef, list: Vec<PhysicalExprRef>, negated| {
+            Arc::new(InListExpr::new(expr.clone(), list, negated)) as PhysicalExprRef
+        };
+        let check = |expr: PhysicalExprRef, batch: &RecordBatch, expected: Vec<Option<bool>>| {
+            let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+                .unwrap()
+                .eval(batch)
+                .unwrap();
+            assert_eq!(result.as_boolean(), &BooleanArray::from(expected));
+            assert_compiled_matches_eval(expr, batch);
+        };
+
+        // a is 1, null, 3, 4, null and b is 5, 4, 3, 2, 1.
+        let batch = batch();
+        let (a, b) = (column("a", 0), column("b", 1));
+        // short lists compare item by item.
+        check(
+            in_list(&a, vec![int(3), int(4)], false),
+            &batch,
+            vec![Some(false), None, Some(true), Some(true), None],
+        );
+        check(
+            in_list(&a, vec![int(3), null.clone()], false),
+            &batch,
+            vec![None, None, Some(true), None, None],
+        );
+        check(
+            in_list(&b, vec![int(1), int(2), int(3)], true),
+            &batch,
+            vec![
+                Some(true),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(false),
+            ],
+        );
+        // dense integers switch over their values.
+        let dense = |range: std::ops::RangeInclusive<i64>| range.map(int).collect::<Vec<_>>();
+        check(
+            in_list(&b, dense(-6..=3), false),
+            &batch,
+            vec![Some(false), Some(false), Some(true), Some(true), Some(true)],
+        );
+        check(
+            in_list(&b, dense(-6..=3), true),
+            &batch,
+            vec![
+                Some(true),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(false),
+            ],
+        );
+        let mut with_null = dense(0..=9);
+        with_null.push(null.clone());
+        check(
+            in_list(&a, with_null, false),
+            &batch,
+            vec![Some(true), None, Some(true), Some(true), None],
+        );
+        let mut with_null = dense(5..=14);
+        with_null.push(null);
+        check(
+            in_list(&a, with_null, true),
+            &batch,
+            vec![None, None, None, None, None],
+        );
+        // sparse integers and floats are looked up in a set.
+        let sparse: Vec<PhysicalExprRef> = (0..10).map(|i| int(i * 100 + 1)).collect();
+        check(
+            in_list(&b, sparse.clone(), false),
+            &batch,
+            vec![
+                Some(false),
+                Some(false),
+                Some(false),
+                Some(false),
+                Some(true),
+            ],
+        );
+        check(
+            in_list(&b, sparse, true),
+            &batch,
+            vec![Some(true), Some(true), Some(true), Some(true), Some(false)],
+        );
+        let float = Arc::new(CastExpr::new(b, DataType::Float64)) as PhysicalExprRef;
+        let halves = (0..10)
+            .map(|i| {
+                Arc::new(LiteralExpr::new(ScalarValue::Float64(Some(i as f64 / 2.0))))
+                    as PhysicalExprRef
+            })
+            .collect();
+        check(
+            in_list(&float, halves, false),
+            &batch,
+            vec![Some(false), Some(true), Some(true), Some(true), Some(true)],
+        );
+
+        // s is null, "", "banana", "42", "héllo" and l is "cherry", "", "banan", "42", "hello".
+        let batch = string_batch();
+        let strings = |values: &[&str], large: bool| -> Vec<PhysicalExprRef> {
+            let fillers = (0..8).map(|i| format!("x{}", i));
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .chain(fillers)
+                .map(|value| {
+                    let value = if large {
+                        ScalarValue::LargeUtf8(Some(value))
+                    } else {
+                        ScalarValue::Utf8(Some(value))
+                    };
+                    Arc::new(LiteralExpr::new(value)) as PhysicalExprRef
+                })
+                .collect()
+        };
+        check(
+            in_list(&column("s", 0), strings(&["", "42"], false), false),
+            &batch,
+            vec![None, Some(true), Some(false), Some(true), Some(false)],
+        );
+        check(
+            in_list(&column("l", 1), strings(&["apple", "cherry"], true), true),
+            &batch,
+            vec![Some(false), Some(true), Some(true), Some(true), Some(true)],
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use core::{BackendGenValue, CodegenBackend, ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    compute::kernels::boolean::{is_not_null, is_null},
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::Result;
+
+use crate::{take_children, Datum, PhysicalExpr, PhysicalExprRef};
+
+pub struct IsNullExpr {
+    input: Arc<dyn PhysicalExpr>,
+}
+
+impl IsNullExpr {
+    pub fn new(input: Arc<dyn PhysicalExpr>) -> Self {
+        Self { input }
+    }
+}
+
+impl PhysicalExpr for IsNullExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(IsNullExpr::new(input)))
+    }
+
+    fn fingerprint(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.input.eval(batch)?.into_array(batch.num_rows());
+        Ok(Datum::Array(Arc::new(is_null(&input)?)))
+    }
+}
+
+impl ExprGen for IsNullExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let input = self.input.gen(ctx);
+        ctx.is_null(input)
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        let input = self.input.gen_backend(backend)?;
+        Some(backend.is_null(input))
+    }
+}
+
+pub struct IsNotNullExpr {
+    input: Arc<dyn PhysicalExpr>,
+}
+
+impl IsNotNullExpr {
+    pub fn new(input: Arc<dyn PhysicalExpr>) -> Self {
+        Self { input }
+    }
+}
+
+impl PhysicalExpr for IsNotNullExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(IsNotNullExpr::new(input)))
+    }
+
+    fn fingerprint(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.input.eval(batch)?.into_array(batch.num_rows());
+        Ok(Datum::Array(Arc::new(is_not_null(&input)?)))
+    }
+}
+
+impl ExprGen for IsNotNullExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let input = self.input.gen(ctx);
+        ctx.is_not_null(input)
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        let input = self.input.gen_backend(backend)?;
+        Some(backend.is_not_null(input))
+    }
+}
@@ -0,0 +1,338 @@
+use core::{ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, BooleanArray},
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use cranelift::prelude::*;
+use regex::Regex;
+
+use crate::{take_children, Datum, PhysicalExpr, PhysicalExprRef};
+
+/// How a constant pattern is matched against strings, decided when the expression is
+/// built. Literal patterns compare bytes in generated code, anything else runs a
+/// precompiled regex.
+#[derive(Clone, Debug)]
+pub(crate) enum StringMatcher {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    Regex(Arc<Regex>),
+}
+
+impl StringMatcher {
+    /// the matcher for literal text between optional anchors, where `text` must start at
+    /// the beginning of the string when `start` and end at its end when `end`.
+    pub(crate) fn literal(text: String, start: bool, end: bool) -> Self {
+        match (start, end) {
+            (true, true) => StringMatcher::Exact(text),
+            (true, false) => StringMatcher::Prefix(text),
+            (false, true) => StringMatcher::Suffix(text),
+            (false, false) => StringMatcher::Contains(text),
+        }
+    }
+
+    pub(crate) fn matches(&self, string: &str) -> bool {
+        match self {
+            StringMatcher::Exact(text) => string == text,
+            StringMatcher::Prefix(text) => string.starts_with(text.as_str()),
+            StringMatcher::Suffix(text) => string.ends_with(text.as_str()),
+            StringMatcher::Contains(text) => string.contains(text.as_str()),
+            StringMatcher::Regex(regex) => regex.is_match(string),
+        }
+    }
+
+    /// match every string of a Utf8 or LargeUtf8 array, nulls stay null.
+    pub(crate) fn eval(&self, array: &ArrayRef) -> Result<BooleanArray> {
+        match array.data_type() {
+            DataType::Utf8 => Ok(array
+                .as_string::<i32>()
+                .iter()
+                .map(|string| string.map(|string| self.matches(string)))
+                .collect()),
+            DataType::LargeUtf8 => Ok(array
+                .as_string::<i64>()
+                .iter()
+                .map(|string| string.map(|string| self.matches(string)))
+                .collect()),
+            other => Err(ServerError::TypeError(format!(
+                "patterns can't be matched against {}",
+                other
+            ))),
+        }
+    }
+
+    pub(crate) fn gen(&self, ctx: &mut FuncGenContext, string: Value) -> Value {
+        match self {
+            StringMatcher::Exact(text) => {
+                let len = ctx.string_len(string);
+                let same_len = ctx
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, len, text.len() as i64);
+                let prefix = ctx.starts_with(string, text);
+                ctx.builder.ins().band(same_len, prefix)
+            }
+            StringMatcher::Prefix(text) => ctx.starts_with(string, text),
+            StringMatcher::Suffix(text) => ctx.ends_with(string, text),
+            StringMatcher::Contains(text) => ctx.string_contains(string, text),
+            StringMatcher::Regex(regex) => ctx.regex_match(regex, string),
+        }
+    }
+
+    pub(crate) fn gen_cost(&self) -> usize {
+        // inline comparisons load 8 bytes at a time, the rest are calls.
+        match self {
+            StringMatcher::Exact(text)
+            | StringMatcher::Prefix(text)
+            | StringMatcher::Suffix(text) => 2 + text.len().div_ceil(8) * 2,
+            StringMatcher::Contains(_) | StringMatcher::Regex(_) => 20,
+        }
+    }
+}
+
+/// SQL `LIKE` and `ILIKE` against a constant pattern, where `%` matches any characters,
+/// `_` matches one character and `\` escapes the character after it. `NOT LIKE` is a
+/// [`NotExpr`](crate::expr::not::NotExpr) of this.
+pub struct LikeExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    pattern: String,
+    case_insensitive: bool,
+    matcher: StringMatcher,
+}
+
+impl LikeExpr {
+    pub fn like(expr: Arc<dyn PhysicalExpr>, pattern: &str) -> Self {
+        Self::new(expr, pattern, false)
+    }
+
+    pub fn ilike(expr: Arc<dyn PhysicalExpr>, pattern: &str) -> Self {
+        Self::new(expr, pattern, true)
+    }
+
+    fn new(expr: Arc<dyn PhysicalExpr>, pattern: &str, case_insensitive: bool) -> Self {
+        Self {
+            expr,
+            pattern: pattern.to_string(),
+            case_insensitive,
+            matcher: like_matcher(pattern, case_insensitive),
+        }
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+}
+
+// literal text of the pattern between `%`s, or a regex where that isn't enough.
+fn like_matcher(pattern: &str, case_insensitive: bool) -> StringMatcher {
+    let mut parts = vec![String::new()];
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => parts.push(String::new()),
+            '_' => return like_regex(pattern, case_insensitive),
+            '\\' => parts.last_mut().unwrap().push(chars.next().unwrap_or('\\')),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    // letters are only compared bytewise when their case matters.
+    let cased = |part: &String| part.chars().any(|c| c.is_lowercase() || c.is_uppercase());
+    if case_insensitive && parts.iter().any(cased) {
+        return like_regex(pattern, case_insensitive);
+    }
+    if parts.len() == 1 {
+        return StringMatcher::Exact(parts.pop().unwrap());
+    }
+    let start = !parts[0].is_empty();
+    let end = !parts[parts.len() - 1].is_empty();
+    let mut literals: Vec<String> = parts.into_iter().filter(|part| !part.is_empty()).collect();
+    match literals.len() {
+        0 => StringMatcher::Prefix(String::new()),
+        1 => StringMatcher::literal(literals.pop().unwrap(), start, end),
+        _ => like_regex(pattern, case_insensitive),
+    }
+}
+
+fn like_regex(pattern: &str, case_insensitive: bool) -> StringMatcher {
+    // `.` matches newlines like `_` does.
+    let mut source = String::from(if case_insensitive { "(?is)^" } else { "(?s)^" });
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => source.push_str(".*"),
+            '_' => source.push('.'),
+            '\\' => {
+                let escaped = chars.next().unwrap_or('\\');
+                source.push_str(&regex::escape(escaped.encode_utf8(&mut [0; 4])));
+            }
+            c => source.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    source.push('$');
+    StringMatcher::Regex(Arc::new(
+        Regex::new(&source).expect("escaped LIKE patterns are valid regexes"),
+    ))
+}
+
+impl PhysicalExpr for LikeExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.expr.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [expr] = take_children(children)?;
+        Ok(Arc::new(LikeExpr {
+            expr,
+            pattern: self.pattern.clone(),
+            case_insensitive: self.case_insensitive,
+            matcher: self.matcher.clone(),
+        }))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}", self.pattern, self.case_insensitive)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.expr.eval(batch)?.into_array(batch.num_rows());
+        Ok(Datum::Array(Arc::new(self.matcher.eval(&input)?)))
+    }
+}
+
+impl ExprGen for LikeExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let input = self.expr.gen(ctx);
+        let matched = self.matcher.gen(ctx, input.value);
+        GenValue::new(matched, input.valid)
+    }
+
+    fn gen_cost(&self, _ctx: &FuncGenContext) -> usize {
+        self.matcher.gen_cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, BooleanArray, LargeStringArray, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        compile,
+        expr::{column::ColumnExpr, not::NotExpr, regexp::RegexpMatchExpr},
+        Datum, PhysicalExprRef,
+    };
+
+    use super::LikeExpr;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn assert_compiled_matches_eval(expr: PhysicalExprRef, batch: &RecordBatch) {
+        let compiled = compile(&expr, batch.schema(), ArithmeticMode::Checked).unwrap();
+        let result = compiled.eval(batch).unwrap();
+        let Datum::Array(expected) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&result, &expected);
+    }
+
+    fn string_batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("l", DataType::LargeUtf8, false),
+        ]));
+        let s = StringArray::from(vec![
+            Some("apple"),
+            None,
+            Some(""),
+            Some("banana"),
+            Some("42"),
+            Some("héllo"),
+        ]);
+        let l = LargeStringArray::from(vec!["apple", "cherry", "", "banan", "42", "hello"]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(s), Arc::new(l)]).unwrap();
+        // offsets of a sliced array don't start at 0.
+        batch.slice(1, 5)
+    }
+
+    #[test]
+    fn test_compile_pattern_matching() {
+        let batch = string_batch();
+        let (s, l) = (column("s", 0), column("l", 1));
+        let like = |pattern| -> PhysicalExprRef { Arc::new(LikeExpr::like(l.clone(), pattern)) };
+        let regexp = |pattern, case_insensitive| -> PhysicalExprRef {
+            Arc::new(RegexpMatchExpr::try_new(l.clone(), pattern, case_insensitive).unwrap())
+        };
+        // l is "cherry", "", "banan", "42", "hello".
+        let cases = [
+            (like("42"), [false, false, false, true, false]),
+            (like(""), [false, true, false, false, false]),
+            (like("ban%"), [false, false, true, false, false]),
+            (like("%an"), [false, false, true, false, false]),
+            (like("%err%"), [true, false, false, false, false]),
+            (like("%"), [true, true, true, true, true]),
+            (like("_____"), [false, false, true, false, true]),
+            (like("h%l_o"), [false, false, false, false, true]),
+            (
+                Arc::new(LikeExpr::ilike(l.clone(), "HEL%")) as PhysicalExprRef,
+                [false, false, false, false, true],
+            ),
+            (regexp("^ch", false), [true, false, false, false, false]),
+            (regexp("an$", false), [false, false, true, false, false]),
+            (regexp("^$", false), [false, true, false, false, false]),
+            (regexp("[0-9]+", false), [false, false, false, true, false]),
+            (regexp("^B.N", true), [false, false, true, false, false]),
+        ];
+        for (expr, expected) in cases {
+            let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+                .unwrap()
+                .eval(&batch)
+                .unwrap();
+            assert_eq!(result.as_boolean(), &BooleanArray::from(expected.to_vec()));
+            assert_compiled_matches_eval(expr, &batch);
+        }
+
+        // nulls stay null, NOT LIKE negates the rest.
+        let not_like: PhysicalExprRef = Arc::new(NotExpr::new(Arc::new(LikeExpr::like(s, "%l%"))));
+        let result = compile(&not_like, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch)
+            .unwrap();
+        let expected =
+            BooleanArray::from(vec![None, Some(true), Some(true), Some(true), Some(false)]);
+        assert_eq!(result.as_boolean(), &expected);
+
+        assert!(RegexpMatchExpr::try_new(l, "(", false).is_err());
+    }
+}
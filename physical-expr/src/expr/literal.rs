@@ -1,42 +1,165 @@
-use crate::{Datum, PhysicalExpr, ScalarValue};
+use crate::{take_children, Datum, PhysicalExpr, PhysicalExprRef, ScalarValue};
 use arrow::{
     datatypes::{DataType, SchemaRef},
     record_batch::RecordBatch,
 };
-use core::{ExprGen, FuncGenContext};
+use common::Result;
+use core::{BackendGenValue, CodegenBackend, ExprGen, FuncGenContext, GenValue};
 use cranelift::prelude::*;
-use std::sync::Arc;
+use std::{any::Any, fmt, sync::Arc};
 
-struct LiteralExpr {
+pub struct LiteralExpr {
     scalar: ScalarValue,
 }
 
 impl LiteralExpr {
-    fn new(scalar: ScalarValue) -> Self {
+    pub fn new(scalar: ScalarValue) -> Self {
         Self { scalar }
     }
+
+    pub fn value(&self) -> &ScalarValue {
+        &self.scalar
+    }
 }
 
 impl PhysicalExpr for LiteralExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn output_type(&self, _: SchemaRef) -> DataType {
-        match self.scalar {
-            ScalarValue::Int64(_) => DataType::Int64,
-        }
+        self.scalar.data_type()
     }
 
     fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
         vec![]
     }
 
-    fn eval(&self, _: &RecordBatch) -> Result<Datum, ()> {
-        Ok(Datum::Scalar(self.scalar))
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [] = take_children(children)?;
+        Ok(self)
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.scalar)
+    }
+
+    fn eval(&self, _: &RecordBatch) -> Result<Datum> {
+        Ok(Datum::Scalar(self.scalar.clone()))
     }
 }
 
 impl ExprGen for LiteralExpr {
-    fn gen(&self, ctx: &mut FuncGenContext) -> Value {
-        match self.scalar {
-            ScalarValue::Int64(value) => ctx.builder.ins().iconst(types::I64, value),
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        // narrow immediates are passed zero-extended, nulls are a zero of their type.
+        let ins = ctx.builder.ins();
+        let value = match &self.scalar {
+            ScalarValue::Boolean(value) => ins.iconst(types::I8, value.unwrap_or_default() as i64),
+            ScalarValue::Int8(value) => {
+                ins.iconst(types::I8, value.unwrap_or_default() as u8 as i64)
+            }
+            ScalarValue::Int16(value) => {
+                ins.iconst(types::I16, value.unwrap_or_default() as u16 as i64)
+            }
+            ScalarValue::Int32(value) | ScalarValue::Date32(value) => {
+                ins.iconst(types::I32, value.unwrap_or_default() as u32 as i64)
+            }
+            ScalarValue::Int64(value)
+            | ScalarValue::Date64(value)
+            | ScalarValue::TimestampSecond(value, _)
+            | ScalarValue::TimestampMillisecond(value, _)
+            | ScalarValue::TimestampMicrosecond(value, _)
+            | ScalarValue::TimestampNanosecond(value, _) => {
+                ins.iconst(types::I64, value.unwrap_or_default())
+            }
+            ScalarValue::UInt8(value) => ins.iconst(types::I8, value.unwrap_or_default() as i64),
+            ScalarValue::UInt16(value) => ins.iconst(types::I16, value.unwrap_or_default() as i64),
+            ScalarValue::UInt32(value) => ins.iconst(types::I32, value.unwrap_or_default() as i64),
+            ScalarValue::UInt64(value) => ins.iconst(types::I64, value.unwrap_or_default() as i64),
+            ScalarValue::Float32(value) => ins.f32const(value.unwrap_or_default()),
+            ScalarValue::Float64(value) => ins.f64const(value.unwrap_or_default()),
+            ScalarValue::Decimal128(value, _, _) => ctx.i128_const(value.unwrap_or_default()),
+            ScalarValue::Utf8(value) | ScalarValue::LargeUtf8(value) => match value {
+                Some(value) => ctx.string_const(value),
+                None => ctx.i128_const(0),
+            },
+            ScalarValue::Null
+            | ScalarValue::Binary(_)
+            | ScalarValue::IntervalYearMonth(_)
+            | ScalarValue::IntervalDayTime(_)
+            | ScalarValue::IntervalMonthDayNano(_) => {
+                panic!("{} literals can't be generated", self.scalar.data_type())
+            }
+        };
+        let value = ctx.broadcast(value);
+        if self.scalar.is_null() {
+            let valid = ctx.builder.ins().iconst(types::I8, 0);
+            GenValue::new(value, Some(valid))
+        } else {
+            GenValue::non_null(value)
+        }
+    }
+
+    fn vectorizable(&self) -> bool {
+        // vector loops don't track validity, and strings have no vector form.
+        !self.scalar.is_null()
+            && !matches!(
+                self.scalar,
+                ScalarValue::Utf8(_) | ScalarValue::LargeUtf8(_)
+            )
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        let data_type = self.scalar.data_type();
+        let value = match &self.scalar {
+            ScalarValue::Boolean(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default() as i128)
+            }
+            ScalarValue::Int8(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::Int16(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::Int32(value) | ScalarValue::Date32(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::Int64(value)
+            | ScalarValue::Date64(value)
+            | ScalarValue::TimestampSecond(value, _)
+            | ScalarValue::TimestampMillisecond(value, _)
+            | ScalarValue::TimestampMicrosecond(value, _)
+            | ScalarValue::TimestampNanosecond(value, _) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::UInt8(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::UInt16(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::UInt32(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::UInt64(value) => {
+                backend.int_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::Float32(value) => {
+                backend.float_const(&data_type, value.unwrap_or_default().into())
+            }
+            ScalarValue::Float64(value) => {
+                backend.float_const(&data_type, value.unwrap_or_default())
+            }
+            _ => None,
+        }?;
+        if self.scalar.is_null() {
+            let valid = backend.int_const(&DataType::Boolean, 0)?;
+            Some(BackendGenValue::new(value, Some(valid)))
+        } else {
+            Some(BackendGenValue::non_null(value))
         }
     }
 }
@@ -1,3 +1,12 @@
 pub mod binary;
+pub mod case;
+pub mod cast;
+pub mod coalesce;
 pub mod column;
+pub mod in_list;
+pub mod is_null;
+pub mod like;
 pub mod literal;
+pub mod not;
+pub mod regexp;
+pub mod scalar_function;
@@ -0,0 +1,65 @@
+use core::{BackendGenValue, CodegenBackend, ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::AsArray,
+    compute::kernels::boolean::not,
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::Result;
+
+use crate::{take_children, Datum, PhysicalExpr, PhysicalExprRef};
+
+pub struct NotExpr {
+    input: Arc<dyn PhysicalExpr>,
+}
+
+impl NotExpr {
+    pub fn new(input: Arc<dyn PhysicalExpr>) -> Self {
+        Self { input }
+    }
+}
+
+impl PhysicalExpr for NotExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(NotExpr::new(input)))
+    }
+
+    fn fingerprint(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.input.eval(batch)?.into_array(batch.num_rows());
+        Ok(Datum::Array(Arc::new(not(input.as_boolean())?)))
+    }
+}
+
+impl ExprGen for NotExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let input = self.input.gen(ctx);
+        ctx.kleene_not(input)
+    }
+
+    fn gen_backend(&self, backend: &mut dyn CodegenBackend) -> Option<BackendGenValue> {
+        let input = self.input.gen_backend(backend)?;
+        Some(backend.kleene_not(input))
+    }
+}
@@ -0,0 +1,117 @@
+use core::{ExprGen, FuncGenContext, GenValue};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use regex::RegexBuilder;
+
+use crate::{expr::like::StringMatcher, take_children, Datum, PhysicalExpr, PhysicalExprRef};
+
+/// Whether a regular expression matches anywhere in a string, `~` and `~*` in postgres.
+/// Patterns that are literal text, optionally anchored by `^` and `$`, are compared
+/// without running the regex.
+pub struct RegexpMatchExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    pattern: String,
+    case_insensitive: bool,
+    matcher: StringMatcher,
+}
+
+impl RegexpMatchExpr {
+    /// fails if `pattern` isn't a valid regular expression.
+    pub fn try_new(
+        expr: Arc<dyn PhysicalExpr>,
+        pattern: &str,
+        case_insensitive: bool,
+    ) -> Result<Self> {
+        Ok(Self {
+            expr,
+            pattern: pattern.to_string(),
+            case_insensitive,
+            matcher: regexp_matcher(pattern, case_insensitive)?,
+        })
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+}
+
+fn regexp_matcher(pattern: &str, case_insensitive: bool) -> Result<StringMatcher> {
+    let (start, rest) = match pattern.strip_prefix('^') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let (end, text) = match rest.strip_suffix('$') {
+        Some(text) => (true, text),
+        None => (false, rest),
+    };
+    let is_literal = !text.chars().any(|c| r"\.+*?()|[]{}^$".contains(c));
+    if is_literal && !case_insensitive {
+        return Ok(StringMatcher::literal(text.to_string(), start, end));
+    }
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| ServerError::ArgumentError(format!("invalid regex {}: {}", pattern, err)))?;
+    Ok(StringMatcher::Regex(Arc::new(regex)))
+}
+
+impl PhysicalExpr for RegexpMatchExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, _: SchemaRef) -> DataType {
+        DataType::Boolean
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.expr.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        let [expr] = take_children(children)?;
+        Ok(Arc::new(RegexpMatchExpr {
+            expr,
+            pattern: self.pattern.clone(),
+            case_insensitive: self.case_insensitive,
+            matcher: self.matcher.clone(),
+        }))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}", self.pattern, self.case_insensitive)
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let input = self.expr.eval(batch)?.into_array(batch.num_rows());
+        Ok(Datum::Array(Arc::new(self.matcher.eval(&input)?)))
+    }
+}
+
+impl ExprGen for RegexpMatchExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let input = self.expr.gen(ctx);
+        let matched = self.matcher.gen(ctx, input.value);
+        GenValue::new(matched, input.valid)
+    }
+
+    fn gen_cost(&self, _ctx: &FuncGenContext) -> usize {
+        self.matcher.gen_cost()
+    }
+}
@@ -0,0 +1,123 @@
+use core::{ExprGen, FuncGenContext, GenValue, NativeFunction};
+use std::{any::Any, fmt, sync::Arc};
+
+use arrow::{
+    array::ArrayRef,
+    compute::cast,
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::Result;
+
+use crate::{function::ScalarFunction, Datum, PhysicalExpr, PhysicalExprRef};
+
+/// A call of a [`ScalarFunction`], null where any argument is null. Type resolution
+/// picks the overload and casts the arguments to its types.
+pub struct ScalarFunctionExpr {
+    func: Arc<ScalarFunction>,
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl ScalarFunctionExpr {
+    pub fn new(func: Arc<ScalarFunction>, args: Vec<Arc<dyn PhysicalExpr>>) -> Self {
+        Self { func, args }
+    }
+
+    pub fn func(&self) -> &Arc<ScalarFunction> {
+        &self.func
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+
+    /// the overload called with the arguments' types in `schema`.
+    pub fn overload(&self, schema: &SchemaRef) -> Result<&NativeFunction> {
+        let arg_types: Vec<DataType> = self
+            .args
+            .iter()
+            .map(|arg| arg.output_type(schema.clone()))
+            .collect();
+        self.func.resolve(&arg_types)
+    }
+}
+
+impl PhysicalExpr for ScalarFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn output_type(&self, schema: SchemaRef) -> DataType {
+        // calls no overload takes are reported by type resolution.
+        match self.overload(&schema) {
+            Ok(overload) => overload.return_type().clone(),
+            Err(_) => self.func.overloads()[0].return_type().clone(),
+        }
+    }
+
+    fn children(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        self.args.clone()
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        Ok(Arc::new(ScalarFunctionExpr::new(
+            self.func.clone(),
+            children,
+        )))
+    }
+
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the same name may be registered for different native code.
+        write!(f, "{}", self.func.name())?;
+        for overload in self.func.overloads() {
+            write!(f, " {}@{:?}", overload.name(), overload.address())?;
+        }
+        Ok(())
+    }
+
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum> {
+        let overload = self.overload(&batch.schema())?;
+        let args = self
+            .args
+            .iter()
+            .zip(overload.arg_types())
+            .map(|(arg, data_type)| {
+                let array = arg.eval(batch)?.into_array(batch.num_rows());
+                Ok(cast(&array, data_type)?)
+            })
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        Ok(Datum::Array(self.func.eval(&args)?))
+    }
+
+    fn check_gen(&self, schema: SchemaRef) -> Result<()> {
+        self.overload(&schema).map(|_| ())
+    }
+}
+
+impl ExprGen for ScalarFunctionExpr {
+    fn gen(&self, ctx: &mut FuncGenContext) -> GenValue {
+        let overload = self
+            .overload(&ctx.schema())
+            .expect("overloads are resolved before generating code");
+        let mut values = vec![];
+        let mut valid = None;
+        for arg in &self.args {
+            let arg = arg.gen(ctx);
+            values.push(arg.value);
+            valid = ctx.and_valid(valid, arg.valid);
+        }
+        ctx.call_function(overload, &values, valid)
+    }
+
+    fn gen_cost(&self, _ctx: &FuncGenContext) -> usize {
+        20
+    }
+
+    fn gen_may_fail(&self, ctx: &FuncGenContext) -> bool {
+        self.overload(&ctx.schema())
+            .map_or(true, |overload| overload.error().is_some())
+    }
+}
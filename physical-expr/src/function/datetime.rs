@@ -0,0 +1,267 @@
+use core::{KernelErrorCode, NativeResult};
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, PrimitiveArray},
+    datatypes::{
+        ArrowTimestampType, DataType, TimeUnit, TimestampMicrosecondType, TimestampMillisecondType,
+        TimestampNanosecondType, TimestampSecondType,
+    },
+};
+use common::{Result, ServerError};
+
+use super::{math::unsupported, native, string::as_str, ScalarFunction};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// `date_trunc(unit, timestamp)`: the timestamp rounded down to the start of its
+/// `microsecond`, `millisecond`, `second`, `minute`, `hour`, `day`, `week` (starting on
+/// monday), `month`, `quarter` or `year`. Timestamps are taken to be in UTC, other units
+/// are invalid arguments.
+pub(super) fn date_trunc() -> ScalarFunction {
+    let units: [(TimeUnit, *const u8); 4] = [
+        (
+            TimeUnit::Second,
+            date_trunc_native::<TimestampSecondType> as *const u8,
+        ),
+        (
+            TimeUnit::Millisecond,
+            date_trunc_native::<TimestampMillisecondType> as *const u8,
+        ),
+        (
+            TimeUnit::Microsecond,
+            date_trunc_native::<TimestampMicrosecondType> as *const u8,
+        ),
+        (
+            TimeUnit::Nanosecond,
+            date_trunc_native::<TimestampNanosecondType> as *const u8,
+        ),
+    ];
+    let overloads = units
+        .into_iter()
+        .map(|(unit, address)| {
+            let name = format!("date_trunc_{:?}", unit).to_lowercase();
+            let data_type = DataType::Timestamp(unit, None);
+            native(
+                &name,
+                address,
+                vec![DataType::Utf8, data_type.clone()],
+                data_type,
+            )
+            .with_error(KernelErrorCode::InvalidArgument)
+            .unwrap()
+        })
+        .collect();
+    ScalarFunction::new(
+        "date_trunc",
+        overloads,
+        Arc::new(|args: &[ArrayRef]| match args[1].data_type() {
+            DataType::Timestamp(TimeUnit::Second, None) => {
+                eval_date_trunc::<TimestampSecondType>(args)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, None) => {
+                eval_date_trunc::<TimestampMillisecondType>(args)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, None) => {
+                eval_date_trunc::<TimestampMicrosecondType>(args)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+                eval_date_trunc::<TimestampNanosecondType>(args)
+            }
+            other => Err(unsupported("date_trunc", other)),
+        }),
+    )
+}
+
+fn eval_date_trunc<T: ArrowTimestampType>(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let units = args[0].as_string::<i32>();
+    let timestamps = args[1].as_primitive::<T>();
+    let mut values = Vec::with_capacity(timestamps.len());
+    for row in 0..timestamps.len() {
+        if units.is_null(row) || timestamps.is_null(row) {
+            values.push(None);
+            continue;
+        }
+        let value = trunc(
+            units.value(row),
+            timestamps.value(row),
+            ticks_per_second::<T>(),
+        )
+        .ok_or(ServerError::InvalidArgument { row })?;
+        values.push(Some(value));
+    }
+    Ok(Arc::new(values.into_iter().collect::<PrimitiveArray<T>>()))
+}
+
+extern "C" fn date_trunc_native<T: ArrowTimestampType>(
+    ptr: *const u8,
+    len: i64,
+    value: i64,
+) -> NativeResult {
+    match trunc(unsafe { as_str(ptr, len) }, value, ticks_per_second::<T>()) {
+        Some(value) => NativeResult::ok(value as u64),
+        None => NativeResult::FAILED,
+    }
+}
+
+fn ticks_per_second<T: ArrowTimestampType>() -> i64 {
+    match T::UNIT {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    }
+}
+
+const UNITS: [&str; 10] = [
+    "microsecond",
+    "millisecond",
+    "second",
+    "minute",
+    "hour",
+    "day",
+    "week",
+    "month",
+    "quarter",
+    "year",
+];
+
+// `value` ticks since the epoch truncated to `unit`, none for unknown units or results
+// out of range.
+fn trunc(unit: &str, value: i64, ticks_per_second: i64) -> Option<i64> {
+    let unit = UNITS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(unit))?;
+    let step = |ticks: i64| value.checked_sub(value.rem_euclid(ticks.max(1)));
+    let ticks_per_day = SECONDS_PER_DAY * ticks_per_second;
+    let days = value.div_euclid(ticks_per_day);
+    let start_of_day = |days: i64| days.checked_mul(ticks_per_day);
+    let (year, month, _) = civil_from_days(days);
+    match *unit {
+        "microsecond" => step(ticks_per_second / 1_000_000),
+        "millisecond" => step(ticks_per_second / 1_000),
+        "second" => step(ticks_per_second),
+        "minute" => step(60 * ticks_per_second),
+        "hour" => step(3_600 * ticks_per_second),
+        "day" => step(ticks_per_day),
+        // 1970-01-01 was a thursday.
+        "week" => start_of_day(days - (days + 3).rem_euclid(7)),
+        "month" => start_of_day(days_from_civil(year, month, 1)),
+        "quarter" => start_of_day(days_from_civil(year, month - (month - 1) % 3, 1)),
+        _ => start_of_day(days_from_civil(year, 1, 1)),
+    }
+}
+
+// the proleptic gregorian (year, month, day) of days since 1970-01-01, after
+// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// days since 1970-01-01 of a proleptic gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, TimestampMillisecondArray},
+        datatypes::{DataType, Field, Schema, TimeUnit},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+
+    use crate::{
+        compile,
+        expr::{column::ColumnExpr, literal::LiteralExpr, scalar_function::ScalarFunctionExpr},
+        function::ScalarFunction,
+        resolve_types, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::date_trunc;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn call(func: ScalarFunction, args: Vec<PhysicalExprRef>) -> PhysicalExprRef {
+        Arc::new(ScalarFunctionExpr::new(Arc::new(func), args))
+    }
+
+    // compiled and interpreted evaluation both give `expected`.
+    fn check(expr: PhysicalExprRef, batch: &RecordBatch, expected: ArrayRef) {
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(batch)
+            .unwrap();
+        assert_eq!(&result, &expected);
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(interpreted) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&interpreted, &expected);
+    }
+
+    #[test]
+    fn test_date_trunc() {
+        let string = |value: &str| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Utf8(Some(value.to_string()))))
+        };
+        // 2024-02-29T13:45:30.500 and 1969-12-31T23:00:00.
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "t",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        )]));
+        let t = TimestampMillisecondArray::from(vec![1709214330500, -3600000]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(t)]).unwrap();
+        let cases = [
+            ("hour", [1709211600000, -3600000]),
+            ("DAY", [1709164800000, -86400000]),
+            ("week", [1708905600000, -259200000]),
+            ("month", [1706745600000, -2678400000]),
+            ("quarter", [1704067200000, -7948800000]),
+            ("year", [1704067200000, -31536000000]),
+        ];
+        for (unit, expected) in cases {
+            check(
+                call(date_trunc(), vec![string(unit), column("t", 0)]),
+                &batch,
+                Arc::new(TimestampMillisecondArray::from(expected.to_vec())),
+            );
+        }
+
+        // both modes fail at the same row.
+        let expr = call(date_trunc(), vec![string("fortnight"), column("t", 0)]);
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch);
+        assert!(matches!(
+            result,
+            Err(ServerError::InvalidArgument { row: 0 })
+        ));
+        assert!(matches!(
+            expr.eval(&batch),
+            Err(ServerError::InvalidArgument { row: 0 })
+        ));
+    }
+}
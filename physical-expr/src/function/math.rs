@@ -0,0 +1,290 @@
+use core::{KernelErrorCode, NativeResult};
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, AsArray, Float64Array},
+    compute::kernels::arity::binary,
+    datatypes::{DataType, Float32Type, Float64Type, Int32Type, Int64Type},
+};
+use common::{Result, ServerError};
+
+use super::{native, try_unary_rows, ScalarFunction};
+
+/// `abs(x)` of an integer or float. The smallest integer of a type has no absolute value
+/// in it and overflows.
+pub(super) fn abs() -> ScalarFunction {
+    let overloads = vec![
+        native(
+            "abs_int32",
+            abs_int32 as *const u8,
+            vec![DataType::Int32],
+            DataType::Int32,
+        )
+        .with_error(KernelErrorCode::Overflow)
+        .unwrap(),
+        native(
+            "abs_int64",
+            abs_int64 as *const u8,
+            vec![DataType::Int64],
+            DataType::Int64,
+        )
+        .with_error(KernelErrorCode::Overflow)
+        .unwrap(),
+        native(
+            "abs_float32",
+            abs_float32 as *const u8,
+            vec![DataType::Float32],
+            DataType::Float32,
+        ),
+        native(
+            "abs_float64",
+            abs_float64 as *const u8,
+            vec![DataType::Float64],
+            DataType::Float64,
+        ),
+    ];
+    ScalarFunction::new("abs", overloads, Arc::new(eval_abs))
+}
+
+fn eval_abs(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let overflow = |row: usize| ServerError::ArithmeticOverflow { row };
+    let array = &args[0];
+    Ok(match array.data_type() {
+        DataType::Int32 => Arc::new(try_unary_rows::<_, Int32Type, _>(
+            array.as_primitive::<Int32Type>(),
+            i32::checked_abs,
+            overflow,
+        )?),
+        DataType::Int64 => Arc::new(try_unary_rows::<_, Int64Type, _>(
+            array.as_primitive::<Int64Type>(),
+            i64::checked_abs,
+            overflow,
+        )?),
+        DataType::Float32 => Arc::new(
+            array
+                .as_primitive::<Float32Type>()
+                .unary::<_, Float32Type>(f32::abs),
+        ),
+        DataType::Float64 => Arc::new(
+            array
+                .as_primitive::<Float64Type>()
+                .unary::<_, Float64Type>(f64::abs),
+        ),
+        other => return Err(unsupported("abs", other)),
+    })
+}
+
+extern "C" fn abs_int32(value: i32) -> NativeResult {
+    match value.checked_abs() {
+        Some(value) => NativeResult::ok(value as u64),
+        None => NativeResult::FAILED,
+    }
+}
+
+extern "C" fn abs_int64(value: i64) -> NativeResult {
+    match value.checked_abs() {
+        Some(value) => NativeResult::ok(value as u64),
+        None => NativeResult::FAILED,
+    }
+}
+
+extern "C" fn abs_float32(value: f32) -> f32 {
+    value.abs()
+}
+
+extern "C" fn abs_float64(value: f64) -> f64 {
+    value.abs()
+}
+
+/// `round(x)` to the nearest integer and `round(x, digits)` to `digits` decimal places,
+/// or to a multiple of a power of ten for negative `digits`. Halfway values round away
+/// from zero.
+pub(super) fn round() -> ScalarFunction {
+    let overloads = vec![
+        native(
+            "round_float64",
+            round_float64 as *const u8,
+            vec![DataType::Float64],
+            DataType::Float64,
+        ),
+        native(
+            "round_digits_float64",
+            round_digits_float64 as *const u8,
+            vec![DataType::Float64, DataType::Int64],
+            DataType::Float64,
+        ),
+    ];
+    ScalarFunction::new("round", overloads, Arc::new(eval_round))
+}
+
+fn eval_round(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = args[0].as_primitive::<Float64Type>();
+    let rounded: Float64Array = match args.get(1) {
+        None => values.unary(f64::round),
+        Some(digits) => binary(values, digits.as_primitive::<Int64Type>(), round_digits)?,
+    };
+    Ok(Arc::new(rounded))
+}
+
+extern "C" fn round_float64(value: f64) -> f64 {
+    value.round()
+}
+
+extern "C" fn round_digits_float64(value: f64, digits: i64) -> f64 {
+    round_digits(value, digits)
+}
+
+fn round_digits(value: f64, digits: i64) -> f64 {
+    // a float64 has no more than 308 decimal digits on either side of the point.
+    let digits = digits.clamp(-308, 309) as i32;
+    if digits < 0 {
+        let scale = 10f64.powi(-digits);
+        return (value / scale).round() * scale;
+    }
+    let scale = 10f64.powi(digits);
+    let scaled = value * scale;
+    // values this large have no digits that far right of the point.
+    if scaled.is_finite() {
+        scaled.round() / scale
+    } else {
+        value
+    }
+}
+
+/// `sqrt(x)`, NaN for negative numbers.
+pub(super) fn sqrt() -> ScalarFunction {
+    let overloads = vec![native(
+        "sqrt_float64",
+        sqrt_float64 as *const u8,
+        vec![DataType::Float64],
+        DataType::Float64,
+    )];
+    ScalarFunction::new(
+        "sqrt",
+        overloads,
+        Arc::new(|args: &[ArrayRef]| {
+            let values = args[0].as_primitive::<Float64Type>();
+            Ok(Arc::new(values.unary::<_, Float64Type>(f64::sqrt)) as ArrayRef)
+        }),
+    )
+}
+
+extern "C" fn sqrt_float64(value: f64) -> f64 {
+    value.sqrt()
+}
+
+pub(super) fn unsupported(name: &str, data_type: &DataType) -> ServerError {
+    ServerError::TypeError(format!("{} of {}", name, data_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Float64Array, Int64Array},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+
+    use crate::{
+        compile,
+        expr::{
+            binary::{BinaryExpr, Op},
+            cast::CastExpr,
+            column::ColumnExpr,
+            literal::LiteralExpr,
+            scalar_function::ScalarFunctionExpr,
+        },
+        function::ScalarFunction,
+        resolve_types, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::{abs, round, sqrt};
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn binary(op: Op, lhs: PhysicalExprRef, rhs: PhysicalExprRef) -> PhysicalExprRef {
+        Arc::new(BinaryExpr::new(op, lhs, rhs))
+    }
+
+    fn call(func: ScalarFunction, args: Vec<PhysicalExprRef>) -> PhysicalExprRef {
+        Arc::new(ScalarFunctionExpr::new(Arc::new(func), args))
+    }
+
+    // compiled and interpreted evaluation both give `expected`.
+    fn check(expr: PhysicalExprRef, batch: &RecordBatch, expected: ArrayRef) {
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(batch)
+            .unwrap();
+        assert_eq!(&result, &expected);
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(interpreted) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&interpreted, &expected);
+    }
+
+    #[test]
+    fn test_math_functions() {
+        let float = |value: f64| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Float64(Some(value))))
+        };
+        let int = |value: i64| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value))))
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None]);
+        let b = Int64Array::from(vec![5, 4, 3, 2, 1]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(a), Arc::new(b)]).unwrap();
+
+        let difference = binary(Op::Sub, column("a", 0), column("b", 1));
+        check(
+            call(abs(), vec![difference]),
+            &batch,
+            Arc::new(Int64Array::from(vec![
+                Some(4),
+                None,
+                Some(0),
+                Some(2),
+                None,
+            ])),
+        );
+        let b = Arc::new(CastExpr::new(column("b", 1), DataType::Float64)) as PhysicalExprRef;
+        check(
+            call(round(), vec![binary(Op::Div, b.clone(), float(2.0))]),
+            &batch,
+            Arc::new(Float64Array::from(vec![3.0, 2.0, 2.0, 1.0, 1.0])),
+        );
+        check(
+            call(
+                round(),
+                vec![binary(Op::Div, b.clone(), float(3.0)), int(1)],
+            ),
+            &batch,
+            Arc::new(Float64Array::from(vec![1.7, 1.3, 1.0, 0.7, 0.3])),
+        );
+        check(
+            call(sqrt(), vec![binary(Op::Mul, b.clone(), b)]),
+            &batch,
+            Arc::new(Float64Array::from(vec![5.0, 4.0, 3.0, 2.0, 1.0])),
+        );
+
+        let expr = call(abs(), vec![int(i64::MIN)]);
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch);
+        assert!(matches!(
+            result,
+            Err(ServerError::ArithmeticOverflow { row: 0 })
+        ));
+    }
+}
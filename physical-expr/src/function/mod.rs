@@ -0,0 +1,291 @@
+use core::{NativeFunction, TypedNativeFn};
+use std::{fmt, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, PrimitiveArray},
+    datatypes::{ArrowPrimitiveType, DataType},
+};
+use common::{Result, ServerError};
+
+use crate::comparison_coercion;
+
+mod datetime;
+mod math;
+mod string;
+
+/// Computes a scalar function over arrays of one overload's argument types, null where
+/// any argument is null.
+pub type ScalarFunctionImpl = Arc<dyn Fn(&[ArrayRef]) -> Result<ArrayRef> + Send + Sync>;
+
+/// A function of one row's values. Each overload is a [`NativeFunction`] compiled code
+/// calls, and interpreted evaluation runs the arrow implementation for all of them.
+pub struct ScalarFunction {
+    name: String,
+    overloads: Vec<NativeFunction>,
+    eval: ScalarFunctionImpl,
+}
+
+impl ScalarFunction {
+    pub fn new(
+        name: impl Into<String>,
+        overloads: Vec<NativeFunction>,
+        eval: ScalarFunctionImpl,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            overloads,
+            eval,
+        }
+    }
+
+    /// a user-defined function of the single overload `func`, whose types are those of
+    /// its rust signature. Interpreted evaluation calls it row by row, e.g.
+    /// `ScalarFunction::from_native("geohash", geohash as extern "C" fn(f64, f64) -> i64)`.
+    pub fn from_native<F: TypedNativeFn>(name: impl Into<String>, func: F) -> Result<Self> {
+        let name = name.into();
+        let overload = NativeFunction::typed(name.clone(), func)?;
+        Ok(Self::new(
+            name,
+            vec![overload],
+            Arc::new(move |args: &[ArrayRef]| func.eval(args)),
+        ))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn overloads(&self) -> &[NativeFunction] {
+        &self.overloads
+    }
+
+    /// the overload called with arguments of `arg_types`: the one taking exactly those
+    /// types, or else the first they can all be cast to without losing values.
+    pub fn resolve(&self, arg_types: &[DataType]) -> Result<&NativeFunction> {
+        let takes = |overload: &&NativeFunction, exact: bool| {
+            overload.arg_types().len() == arg_types.len()
+                && overload
+                    .arg_types()
+                    .iter()
+                    .zip(arg_types)
+                    .all(|(param, arg)| {
+                        param == arg
+                            || (!exact && comparison_coercion(arg, param).as_ref() == Some(param))
+                    })
+        };
+        self.overloads
+            .iter()
+            .find(|overload| takes(overload, true))
+            .or_else(|| {
+                self.overloads
+                    .iter()
+                    .find(|overload| takes(overload, false))
+            })
+            .ok_or_else(|| {
+                ServerError::TypeError(format!(
+                    "no overload of {} takes {:?}",
+                    self.name, arg_types
+                ))
+            })
+    }
+
+    /// evaluate over arrays of the argument types of one overload.
+    pub fn eval(&self, args: &[ArrayRef]) -> Result<ArrayRef> {
+        (self.eval)(args)
+    }
+}
+
+impl fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalarFunction")
+            .field("name", &self.name)
+            .field("overloads", &self.overloads)
+            .finish()
+    }
+}
+
+/// abs, round, sqrt, upper, substr and date_trunc.
+pub fn builtin_functions() -> Vec<ScalarFunction> {
+    vec![
+        math::abs(),
+        math::round(),
+        math::sqrt(),
+        string::upper(),
+        string::substr(),
+        datetime::date_trunc(),
+    ]
+}
+
+// a native function of a builtin, whose types are known to be valid.
+fn native(
+    name: &str,
+    address: *const u8,
+    arg_types: Vec<DataType>,
+    return_type: DataType,
+) -> NativeFunction {
+    NativeFunction::try_new(name, address, arg_types, return_type)
+        .expect("builtin functions take types generated code can pass")
+}
+
+/// map every non-null value of `array` with `op`, failing with the error `error` makes
+/// for the first row `op` gives `None` for.
+pub(crate) fn try_unary_rows<I, O, F>(
+    array: &PrimitiveArray<I>,
+    op: F,
+    error: fn(usize) -> ServerError,
+) -> Result<PrimitiveArray<O>>
+where
+    I: ArrowPrimitiveType,
+    O: ArrowPrimitiveType,
+    F: Fn(I::Native) -> Option<O::Native>,
+{
+    let mut values = Vec::with_capacity(array.len());
+    for (row, value) in array.values().iter().enumerate() {
+        if array.is_null(row) {
+            values.push(O::Native::default());
+            continue;
+        }
+        values.push(op(*value).ok_or_else(|| error(row))?);
+    }
+    Ok(PrimitiveArray::new(values.into(), array.nulls().cloned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        return_string, ArithmeticMode, CodegenContext, Fallible, NativeStr, StringResult,
+        TypedNativeFn,
+    };
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Float64Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+
+    use crate::{
+        compile,
+        expr::{column::ColumnExpr, literal::LiteralExpr, scalar_function::ScalarFunctionExpr},
+        resolve_types, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::ScalarFunction;
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    // compiled and interpreted evaluation both give `expected`.
+    fn check(expr: PhysicalExprRef, batch: &RecordBatch, expected: ArrayRef) {
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(batch)
+            .unwrap();
+        assert_eq!(&result, &expected);
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(interpreted) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&interpreted, &expected);
+    }
+
+    extern "C" fn scale(value: i64, factor: f64) -> f64 {
+        value as f64 * factor
+    }
+
+    extern "C" fn repeat(string: NativeStr, count: i64) -> StringResult {
+        return_string(string.as_str().repeat(count.max(0) as usize))
+    }
+
+    extern "C" fn parse_int(string: NativeStr) -> Fallible<i64> {
+        string
+            .as_str()
+            .parse()
+            .map_or_else(|_| Fallible::failed(), Fallible::ok)
+    }
+
+    fn native_call<F: TypedNativeFn>(
+        name: &str,
+        func: F,
+        args: Vec<PhysicalExprRef>,
+    ) -> PhysicalExprRef {
+        let func = ScalarFunction::from_native(name, func).unwrap();
+        Arc::new(ScalarFunctionExpr::new(Arc::new(func), args))
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let scale = scale as extern "C" fn(i64, f64) -> f64;
+        let repeat = repeat as extern "C" fn(NativeStr, i64) -> StringResult;
+        let parse_int = parse_int as extern "C" fn(NativeStr) -> Fallible<i64>;
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(a)]).unwrap();
+        let half = Arc::new(LiteralExpr::new(ScalarValue::Float64(Some(0.5))));
+        check(
+            native_call("scale", scale, vec![column("a", 0), half]),
+            &batch,
+            Arc::new(Float64Array::from(vec![
+                Some(0.5),
+                None,
+                Some(1.5),
+                Some(2.0),
+                None,
+            ])),
+        );
+
+        let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
+        let s = StringArray::from(vec![
+            None,
+            Some(""),
+            Some("banana"),
+            Some("42"),
+            Some("héllo"),
+        ]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(s)]).unwrap();
+        let two = Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(2))));
+        check(
+            native_call("repeat", repeat, vec![column("s", 0), two]),
+            &batch,
+            Arc::new(StringArray::from(vec![
+                None,
+                Some(""),
+                Some("bananabanana"),
+                Some("4242"),
+                Some("héllohéllo"),
+            ])),
+        );
+
+        // "" isn't a number in either mode.
+        let expr = native_call("parse_int", parse_int, vec![column("s", 0)]);
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(&batch);
+        assert!(matches!(
+            result,
+            Err(ServerError::InvalidArgument { row: 1 })
+        ));
+        assert!(matches!(
+            expr.eval(&batch),
+            Err(ServerError::InvalidArgument { row: 1 })
+        ));
+        let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
+        let s = StringArray::from(vec![Some("7"), None, Some("-12")]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(s)]).unwrap();
+        check(
+            native_call("parse_int", parse_int, vec![column("s", 0)]),
+            &batch,
+            Arc::new(Int64Array::from(vec![Some(7), None, Some(-12)])),
+        );
+
+        // a name links to one function only.
+        let builder = CodegenContext::builder()
+            .register_native("scale", scale)
+            .unwrap();
+        let builder = builder.register_native("scale", scale).unwrap();
+        assert!(builder.register_native("scale", parse_int).is_err());
+    }
+}
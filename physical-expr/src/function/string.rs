@@ -0,0 +1,247 @@
+use core::{return_string, StringResult};
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, GenericStringArray, OffsetSizeTrait},
+    datatypes::{DataType, Int64Type},
+};
+
+use super::{math::unsupported, native, ScalarFunction};
+
+/// `upper(s)`, with every character converted to its unicode uppercase.
+pub(super) fn upper() -> ScalarFunction {
+    let overloads = [DataType::Utf8, DataType::LargeUtf8]
+        .into_iter()
+        .map(|data_type| {
+            let name = format!("upper_{}", data_type).to_lowercase();
+            native(
+                &name,
+                upper_native as *const u8,
+                vec![data_type.clone()],
+                data_type,
+            )
+        })
+        .collect();
+    ScalarFunction::new(
+        "upper",
+        overloads,
+        Arc::new(|args: &[ArrayRef]| match args[0].data_type() {
+            DataType::Utf8 => Ok(map_strings::<i32>(&args[0], str::to_uppercase)),
+            DataType::LargeUtf8 => Ok(map_strings::<i64>(&args[0], str::to_uppercase)),
+            other => Err(unsupported("upper", other)),
+        }),
+    )
+}
+
+fn map_strings<O: OffsetSizeTrait>(array: &ArrayRef, op: fn(&str) -> String) -> ArrayRef {
+    let strings: GenericStringArray<O> = array
+        .as_string::<O>()
+        .iter()
+        .map(|string| string.map(op))
+        .collect();
+    Arc::new(strings)
+}
+
+extern "C" fn upper_native(ptr: *const u8, len: i64) -> StringResult {
+    return_string(unsafe { as_str(ptr, len) }.to_uppercase())
+}
+
+/// `substr(s, start)` and `substr(s, start, count)`: the characters of `s` from the
+/// 1-based position `start` on, at most `count` of them counted from `start` even where
+/// it is before the first character. A negative `count` gives an empty string.
+pub(super) fn substr() -> ScalarFunction {
+    let mut overloads = vec![];
+    for data_type in [DataType::Utf8, DataType::LargeUtf8] {
+        let prefix = format!("substr_{}", data_type).to_lowercase();
+        overloads.push(native(
+            &prefix,
+            substr_native as *const u8,
+            vec![data_type.clone(), DataType::Int64],
+            data_type.clone(),
+        ));
+        overloads.push(native(
+            &format!("{}_count", prefix),
+            substr_count_native as *const u8,
+            vec![data_type.clone(), DataType::Int64, DataType::Int64],
+            data_type,
+        ));
+    }
+    ScalarFunction::new(
+        "substr",
+        overloads,
+        Arc::new(|args: &[ArrayRef]| match args[0].data_type() {
+            DataType::Utf8 => Ok(eval_substr::<i32>(args)),
+            DataType::LargeUtf8 => Ok(eval_substr::<i64>(args)),
+            other => Err(unsupported("substr", other)),
+        }),
+    )
+}
+
+fn eval_substr<O: OffsetSizeTrait>(args: &[ArrayRef]) -> ArrayRef {
+    let strings = args[0].as_string::<O>();
+    let starts = args[1].as_primitive::<Int64Type>();
+    let counts = args.get(2).map(|counts| counts.as_primitive::<Int64Type>());
+    let result: GenericStringArray<O> = (0..strings.len())
+        .map(|row| {
+            let count = match counts {
+                Some(counts) if counts.is_null(row) => return None,
+                Some(counts) => Some(counts.value(row)),
+                None => None,
+            };
+            if strings.is_null(row) || starts.is_null(row) {
+                return None;
+            }
+            Some(substr_of(strings.value(row), starts.value(row), count))
+        })
+        .collect();
+    Arc::new(result)
+}
+
+fn substr_of(string: &str, start: i64, count: Option<i64>) -> &str {
+    let end = match count {
+        Some(count) if count < 0 => return "",
+        Some(count) => Some(start.saturating_add(count)),
+        None => None,
+    };
+    // byte offset of the character at a 1-based position, clamped to the string.
+    let offset = |position: i64| {
+        if position <= 1 {
+            return 0;
+        }
+        string
+            .char_indices()
+            .nth((position - 1) as usize)
+            .map_or(string.len(), |(offset, _)| offset)
+    };
+    let from = offset(start);
+    let to = end.map_or(string.len(), offset);
+    if from >= to {
+        ""
+    } else {
+        &string[from..to]
+    }
+}
+
+extern "C" fn substr_native(ptr: *const u8, len: i64, start: i64) -> StringResult {
+    // a slice of the argument lives as long as it does.
+    StringResult::borrowed(substr_of(unsafe { as_str(ptr, len) }, start, None))
+}
+
+extern "C" fn substr_count_native(
+    ptr: *const u8,
+    len: i64,
+    start: i64,
+    count: i64,
+) -> StringResult {
+    StringResult::borrowed(substr_of(unsafe { as_str(ptr, len) }, start, Some(count)))
+}
+
+/// the utf8 string of `len` bytes at `ptr`, passed by generated code.
+pub(super) unsafe fn as_str<'a>(ptr: *const u8, len: i64) -> &'a str {
+    if len == 0 {
+        return "";
+    }
+    std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, LargeStringArray, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    use crate::{
+        compile,
+        expr::{column::ColumnExpr, literal::LiteralExpr, scalar_function::ScalarFunctionExpr},
+        function::ScalarFunction,
+        resolve_types, Datum, PhysicalExprRef, ScalarValue,
+    };
+
+    use super::{substr, upper};
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    fn call(func: ScalarFunction, args: Vec<PhysicalExprRef>) -> PhysicalExprRef {
+        Arc::new(ScalarFunctionExpr::new(Arc::new(func), args))
+    }
+
+    // compiled and interpreted evaluation both give `expected`.
+    fn check(expr: PhysicalExprRef, batch: &RecordBatch, expected: ArrayRef) {
+        let result = compile(&expr, batch.schema(), ArithmeticMode::Checked)
+            .unwrap()
+            .eval(batch)
+            .unwrap();
+        assert_eq!(&result, &expected);
+        let expr = resolve_types(&expr, &batch.schema()).unwrap();
+        let Datum::Array(interpreted) = expr.eval(batch).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(&interpreted, &expected);
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let int = |value: i64| -> PhysicalExprRef {
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(value))))
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("l", DataType::LargeUtf8, false),
+        ]));
+        let s = StringArray::from(vec![
+            Some("apple"),
+            None,
+            Some(""),
+            Some("banana"),
+            Some("42"),
+            Some("héllo"),
+        ]);
+        let l = LargeStringArray::from(vec!["apple", "cherry", "", "banan", "42", "hello"]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(s), Arc::new(l)]).unwrap();
+        // offsets of a sliced array don't start at 0.
+        let batch = batch.slice(1, 5);
+
+        check(
+            call(upper(), vec![column("s", 0)]),
+            &batch,
+            Arc::new(StringArray::from(vec![
+                None,
+                Some(""),
+                Some("BANANA"),
+                Some("42"),
+                Some("HÉLLO"),
+            ])),
+        );
+        check(
+            call(substr(), vec![column("l", 1), int(2), int(3)]),
+            &batch,
+            Arc::new(LargeStringArray::from(vec!["her", "", "ana", "2", "ell"])),
+        );
+        check(
+            call(substr(), vec![column("s", 0), int(0), int(3)]),
+            &batch,
+            Arc::new(StringArray::from(vec![
+                None,
+                Some(""),
+                Some("ba"),
+                Some("42"),
+                Some("hé"),
+            ])),
+        );
+        check(
+            call(substr(), vec![call(upper(), vec![column("l", 1)]), int(4)]),
+            &batch,
+            Arc::new(LargeStringArray::from(vec!["RRY", "", "AN", "", "LO"])),
+        );
+
+        let expr = call(upper(), vec![int(1)]);
+        assert!(compile(&expr, batch.schema(), ArithmeticMode::Checked).is_err());
+    }
+}
@@ -1,11 +1,30 @@
 use arrow::{
-    array::{ArrayRef, Int64Array},
+    array::{Array, ArrayRef, BooleanArray, UInt32Array},
+    compute::{filter_record_batch, prep_null_mask_filter, take},
     datatypes::{DataType, SchemaRef},
     record_batch::RecordBatch,
 };
-use std::sync::Arc;
+use common::{Result, ServerError};
+use core::{ArithmeticMode, ExprGen, FuncGenContext};
+use expr::binary::BinaryExpr;
+use std::{any::Any, fmt, sync::Arc};
 
+mod adaptive;
+mod cache;
+mod coercion;
+mod compile;
 pub mod expr;
+pub mod function;
+mod scalar;
+
+pub use adaptive::{AdaptiveExprs, ExecutionMode, TieringPolicy};
+pub use cache::KernelCache;
+pub use coercion::{cast_to, comparison_coercion, resolve_types};
+pub use compile::{
+    compile, compile_exprs, compile_exprs_with, compile_filtered_exprs,
+    compile_filtered_exprs_with, CompiledExpr, CompiledExprs,
+};
+pub use scalar::ScalarValue;
 
 #[derive(Clone, Debug)]
 pub enum Datum {
@@ -17,23 +36,150 @@ impl Datum {
     pub fn as_ref(&self) -> Arc<dyn arrow::array::Datum> {
         match self {
             Datum::Array(array) => Arc::new(array.clone()),
-            Datum::Scalar(scalar_value) => match scalar_value {
-                ScalarValue::Int64(value) => Arc::new(Int64Array::new_scalar(*value)),
-            },
+            Datum::Scalar(scalar_value) => Arc::new(scalar_value.to_scalar()),
         }
     }
-}
 
-#[derive(Copy, Clone, Debug)]
-pub enum ScalarValue {
-    Int64(i64),
+    /// materialize as an array of `len` rows, repeating scalars.
+    pub fn into_array(self, len: usize) -> ArrayRef {
+        match self {
+            Datum::Array(array) => array,
+            Datum::Scalar(scalar_value) => scalar_value.to_array_of_size(len),
+        }
+    }
 }
 
 pub type PhysicalExprRef = Arc<dyn PhysicalExpr>;
 
-pub trait PhysicalExpr: Send + Sync {
+pub trait PhysicalExpr: ExprGen + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
     fn output_type(&self, schema: SchemaRef) -> DataType;
     fn children(&self) -> Vec<Arc<dyn PhysicalExpr>>;
+
+    /// the same expression over `children`, used by rewrites such as type resolution.
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef>;
+
+    /// write what sets this node apart from others of its type over the same children,
+    /// such as its operator or constant. Expressions with equal [`fingerprint`]s must
+    /// compute the same values.
+    fn fingerprint(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
     // ArrayRef can represent both array and scalar value.
-    fn eval(&self, batch: &RecordBatch) -> Result<Datum, ()>;
+    fn eval(&self, batch: &RecordBatch) -> Result<Datum>;
+
+    /// fail if `gen` can't emit code for this node, checked before any code is generated.
+    fn check_gen(&self, _schema: SchemaRef) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// a canonical key of `expr`, equal for separately built trees of the same nodes.
+pub fn fingerprint(expr: &PhysicalExprRef) -> String {
+    struct Node<'a>(&'a dyn PhysicalExpr);
+
+    impl fmt::Display for Node<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:?}(", Any::type_id(self.0.as_any()))?;
+            self.0.fingerprint(f)?;
+            write!(f, ")[")?;
+            for child in self.0.children() {
+                write!(f, "{},", Node(child.as_ref()))?;
+            }
+            write!(f, "]")
+        }
+    }
+
+    Node(expr.as_ref()).to_string()
+}
+
+/// `expr` with its arithmetic interpreted in `mode`, the way kernels compiled in `mode`
+/// compute it. Interpreted arithmetic is checked otherwise.
+pub fn with_arithmetic_mode(
+    expr: &PhysicalExprRef,
+    mode: ArithmeticMode,
+) -> Result<PhysicalExprRef> {
+    let children = expr.children();
+    let expr = if children.is_empty() {
+        expr.clone()
+    } else {
+        let children = children
+            .iter()
+            .map(|child| with_arithmetic_mode(child, mode))
+            .collect::<Result<_>>()?;
+        expr.clone().with_new_children(children)?
+    };
+    match expr.as_any().downcast_ref::<BinaryExpr>() {
+        Some(binary) => Ok(Arc::new(
+            BinaryExpr::new(binary.op(), binary.lhs().clone(), binary.rhs().clone())
+                .with_arithmetic_mode(mode),
+        )),
+        None => Ok(expr),
+    }
+}
+
+/// unpack the children given to [`PhysicalExpr::with_new_children`].
+pub(crate) fn take_children<const N: usize>(
+    children: Vec<PhysicalExprRef>,
+) -> Result<[PhysicalExprRef; N]> {
+    let len = children.len();
+    children
+        .try_into()
+        .map_err(|_| ServerError::ArgumentError(format!("expected {} children, got {}", N, len)))
+}
+
+/// rows where `cond` is true, null rows are false.
+pub(crate) fn true_rows(cond: &BooleanArray) -> BooleanArray {
+    match cond.nulls() {
+        Some(_) => prep_null_mask_filter(cond),
+        None => cond.clone(),
+    }
+}
+
+/// evaluate `expr` only for the rows of `batch` where `selection`, which has no nulls, is
+/// true. Other rows are null in the result, and never see errors the expression would
+/// raise for them.
+pub(crate) fn eval_selected(
+    expr: &PhysicalExprRef,
+    batch: &RecordBatch,
+    selection: &BooleanArray,
+) -> Result<ArrayRef> {
+    let len = batch.num_rows();
+    if selection.true_count() == len {
+        return Ok(expr.eval(batch)?.into_array(len));
+    }
+    let selected = filter_record_batch(batch, selection)?;
+    let values = expr.eval(&selected)?.into_array(selected.num_rows());
+    // the n-th selected row takes the n-th value.
+    let mut next = 0u32;
+    let indices: UInt32Array = selection
+        .values()
+        .iter()
+        .map(|selected| {
+            selected.then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect();
+    Ok(take(&values, &indices, None)?)
+}
+
+/// expressions costing up to this many instructions are computed for every row rather
+/// than branched around, which is cheaper than a mispredicted branch.
+pub(crate) const MAX_SPECULATION_COST: usize = 8;
+
+/// instructions spent computing `expr` for a row that may not need it, `None` when that
+/// could fail the kernel.
+pub(crate) fn speculation_cost(expr: &PhysicalExprRef, ctx: &FuncGenContext) -> Option<usize> {
+    if expr.gen_may_fail(ctx) {
+        return None;
+    }
+    expr.children()
+        .iter()
+        .try_fold(expr.gen_cost(ctx), |cost, child| {
+            Some(cost + speculation_cost(child, ctx)?)
+        })
 }
@@ -0,0 +1,308 @@
+use std::{iter, sync::Arc};
+
+use arrow::{
+    array::{
+        new_null_array, Array, ArrayRef, AsArray, BinaryArray, BooleanArray, Date32Array,
+        Date64Array, Decimal128Array, Float32Array, Float64Array, Int16Array, Int32Array,
+        Int64Array, Int8Array, IntervalDayTimeArray, IntervalMonthDayNanoArray,
+        IntervalYearMonthArray, LargeStringArray, Scalar, StringArray, TimestampMicrosecondArray,
+        TimestampMillisecondArray, TimestampNanosecondArray, TimestampSecondArray, UInt16Array,
+        UInt32Array, UInt64Array, UInt8Array,
+    },
+    datatypes::{
+        DataType, Date32Type, Date64Type, Decimal128Type, Float32Type, Float64Type, Int16Type,
+        Int32Type, Int64Type, Int8Type, IntervalDayTimeType, IntervalMonthDayNanoType,
+        IntervalUnit, IntervalYearMonthType, TimeUnit, TimestampMicrosecondType,
+        TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+};
+use common::{Result, ServerError};
+
+/// A single value of an arrow type, `None` being a null of that type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalarValue {
+    Null,
+    Boolean(Option<bool>),
+    Int8(Option<i8>),
+    Int16(Option<i16>),
+    Int32(Option<i32>),
+    Int64(Option<i64>),
+    UInt8(Option<u8>),
+    UInt16(Option<u16>),
+    UInt32(Option<u32>),
+    UInt64(Option<u64>),
+    Float32(Option<f32>),
+    Float64(Option<f64>),
+    Utf8(Option<String>),
+    LargeUtf8(Option<String>),
+    Binary(Option<Vec<u8>>),
+    /// unscaled value, precision, scale
+    Decimal128(Option<i128>, u8, i8),
+    /// days since the epoch
+    Date32(Option<i32>),
+    /// milliseconds since the epoch
+    Date64(Option<i64>),
+    TimestampSecond(Option<i64>, Option<Arc<str>>),
+    TimestampMillisecond(Option<i64>, Option<Arc<str>>),
+    TimestampMicrosecond(Option<i64>, Option<Arc<str>>),
+    TimestampNanosecond(Option<i64>, Option<Arc<str>>),
+    /// months
+    IntervalYearMonth(Option<i32>),
+    /// days in the high and milliseconds in the low 32 bits
+    IntervalDayTime(Option<i64>),
+    /// months, days and nanoseconds packed as by arrow
+    IntervalMonthDayNano(Option<i128>),
+}
+
+impl ScalarValue {
+    pub fn data_type(&self) -> DataType {
+        match self {
+            ScalarValue::Null => DataType::Null,
+            ScalarValue::Boolean(_) => DataType::Boolean,
+            ScalarValue::Int8(_) => DataType::Int8,
+            ScalarValue::Int16(_) => DataType::Int16,
+            ScalarValue::Int32(_) => DataType::Int32,
+            ScalarValue::Int64(_) => DataType::Int64,
+            ScalarValue::UInt8(_) => DataType::UInt8,
+            ScalarValue::UInt16(_) => DataType::UInt16,
+            ScalarValue::UInt32(_) => DataType::UInt32,
+            ScalarValue::UInt64(_) => DataType::UInt64,
+            ScalarValue::Float32(_) => DataType::Float32,
+            ScalarValue::Float64(_) => DataType::Float64,
+            ScalarValue::Utf8(_) => DataType::Utf8,
+            ScalarValue::LargeUtf8(_) => DataType::LargeUtf8,
+            ScalarValue::Binary(_) => DataType::Binary,
+            ScalarValue::Decimal128(_, precision, scale) => {
+                DataType::Decimal128(*precision, *scale)
+            }
+            ScalarValue::Date32(_) => DataType::Date32,
+            ScalarValue::Date64(_) => DataType::Date64,
+            ScalarValue::TimestampSecond(_, tz) => {
+                DataType::Timestamp(TimeUnit::Second, tz.clone())
+            }
+            ScalarValue::TimestampMillisecond(_, tz) => {
+                DataType::Timestamp(TimeUnit::Millisecond, tz.clone())
+            }
+            ScalarValue::TimestampMicrosecond(_, tz) => {
+                DataType::Timestamp(TimeUnit::Microsecond, tz.clone())
+            }
+            ScalarValue::TimestampNanosecond(_, tz) => {
+                DataType::Timestamp(TimeUnit::Nanosecond, tz.clone())
+            }
+            ScalarValue::IntervalYearMonth(_) => DataType::Interval(IntervalUnit::YearMonth),
+            ScalarValue::IntervalDayTime(_) => DataType::Interval(IntervalUnit::DayTime),
+            ScalarValue::IntervalMonthDayNano(_) => DataType::Interval(IntervalUnit::MonthDayNano),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            ScalarValue::Null => true,
+            ScalarValue::Boolean(value) => value.is_none(),
+            ScalarValue::Int8(value) => value.is_none(),
+            ScalarValue::Int16(value) => value.is_none(),
+            ScalarValue::Int32(value) => value.is_none(),
+            ScalarValue::Int64(value) => value.is_none(),
+            ScalarValue::UInt8(value) => value.is_none(),
+            ScalarValue::UInt16(value) => value.is_none(),
+            ScalarValue::UInt32(value) => value.is_none(),
+            ScalarValue::UInt64(value) => value.is_none(),
+            ScalarValue::Float32(value) => value.is_none(),
+            ScalarValue::Float64(value) => value.is_none(),
+            ScalarValue::Utf8(value) | ScalarValue::LargeUtf8(value) => value.is_none(),
+            ScalarValue::Binary(value) => value.is_none(),
+            ScalarValue::Decimal128(value, _, _) => value.is_none(),
+            ScalarValue::Date32(value) | ScalarValue::IntervalYearMonth(value) => value.is_none(),
+            ScalarValue::IntervalMonthDayNano(value) => value.is_none(),
+            ScalarValue::Date64(value)
+            | ScalarValue::IntervalDayTime(value)
+            | ScalarValue::TimestampSecond(value, _)
+            | ScalarValue::TimestampMillisecond(value, _)
+            | ScalarValue::TimestampMicrosecond(value, _)
+            | ScalarValue::TimestampNanosecond(value, _) => value.is_none(),
+        }
+    }
+
+    /// an arrow array of `len` rows all holding this value.
+    pub fn to_array_of_size(&self, len: usize) -> ArrayRef {
+        macro_rules! repeat {
+            ($array:ty, $value:expr) => {
+                Arc::new(<$array>::from_iter(iter::repeat_n($value, len)))
+            };
+        }
+
+        match self {
+            ScalarValue::Null => new_null_array(&DataType::Null, len),
+            ScalarValue::Boolean(value) => repeat!(BooleanArray, *value),
+            ScalarValue::Int8(value) => repeat!(Int8Array, *value),
+            ScalarValue::Int16(value) => repeat!(Int16Array, *value),
+            ScalarValue::Int32(value) => repeat!(Int32Array, *value),
+            ScalarValue::Int64(value) => repeat!(Int64Array, *value),
+            ScalarValue::UInt8(value) => repeat!(UInt8Array, *value),
+            ScalarValue::UInt16(value) => repeat!(UInt16Array, *value),
+            ScalarValue::UInt32(value) => repeat!(UInt32Array, *value),
+            ScalarValue::UInt64(value) => repeat!(UInt64Array, *value),
+            ScalarValue::Float32(value) => repeat!(Float32Array, *value),
+            ScalarValue::Float64(value) => repeat!(Float64Array, *value),
+            ScalarValue::Utf8(value) => repeat!(StringArray, value.as_deref()),
+            ScalarValue::LargeUtf8(value) => repeat!(LargeStringArray, value.as_deref()),
+            ScalarValue::Binary(value) => repeat!(BinaryArray, value.as_deref()),
+            ScalarValue::Decimal128(value, precision, scale) => Arc::new(
+                Decimal128Array::from_iter(iter::repeat_n(*value, len))
+                    .with_data_type(DataType::Decimal128(*precision, *scale)),
+            ),
+            ScalarValue::Date32(value) => repeat!(Date32Array, *value),
+            ScalarValue::Date64(value) => repeat!(Date64Array, *value),
+            ScalarValue::TimestampSecond(value, tz) => Arc::new(
+                TimestampSecondArray::from_iter(iter::repeat_n(*value, len))
+                    .with_timezone_opt(tz.clone()),
+            ),
+            ScalarValue::TimestampMillisecond(value, tz) => Arc::new(
+                TimestampMillisecondArray::from_iter(iter::repeat_n(*value, len))
+                    .with_timezone_opt(tz.clone()),
+            ),
+            ScalarValue::TimestampMicrosecond(value, tz) => Arc::new(
+                TimestampMicrosecondArray::from_iter(iter::repeat_n(*value, len))
+                    .with_timezone_opt(tz.clone()),
+            ),
+            ScalarValue::TimestampNanosecond(value, tz) => Arc::new(
+                TimestampNanosecondArray::from_iter(iter::repeat_n(*value, len))
+                    .with_timezone_opt(tz.clone()),
+            ),
+            ScalarValue::IntervalYearMonth(value) => repeat!(IntervalYearMonthArray, *value),
+            ScalarValue::IntervalDayTime(value) => repeat!(IntervalDayTimeArray, *value),
+            ScalarValue::IntervalMonthDayNano(value) => repeat!(IntervalMonthDayNanoArray, *value),
+        }
+    }
+
+    /// the value at `index` of `array`.
+    pub fn try_from_array(array: &dyn Array, index: usize) -> Result<Self> {
+        let valid = array.is_valid(index);
+        macro_rules! primitive {
+            ($arrow_type:ty) => {
+                valid.then(|| array.as_primitive::<$arrow_type>().value(index))
+            };
+        }
+
+        Ok(match array.data_type() {
+            DataType::Null => ScalarValue::Null,
+            DataType::Boolean => {
+                ScalarValue::Boolean(valid.then(|| array.as_boolean().value(index)))
+            }
+            DataType::Int8 => ScalarValue::Int8(primitive!(Int8Type)),
+            DataType::Int16 => ScalarValue::Int16(primitive!(Int16Type)),
+            DataType::Int32 => ScalarValue::Int32(primitive!(Int32Type)),
+            DataType::Int64 => ScalarValue::Int64(primitive!(Int64Type)),
+            DataType::UInt8 => ScalarValue::UInt8(primitive!(UInt8Type)),
+            DataType::UInt16 => ScalarValue::UInt16(primitive!(UInt16Type)),
+            DataType::UInt32 => ScalarValue::UInt32(primitive!(UInt32Type)),
+            DataType::UInt64 => ScalarValue::UInt64(primitive!(UInt64Type)),
+            DataType::Float32 => ScalarValue::Float32(primitive!(Float32Type)),
+            DataType::Float64 => ScalarValue::Float64(primitive!(Float64Type)),
+            DataType::Utf8 => {
+                ScalarValue::Utf8(valid.then(|| array.as_string::<i32>().value(index).to_string()))
+            }
+            DataType::LargeUtf8 => ScalarValue::LargeUtf8(
+                valid.then(|| array.as_string::<i64>().value(index).to_string()),
+            ),
+            DataType::Binary => {
+                ScalarValue::Binary(valid.then(|| array.as_binary::<i32>().value(index).to_vec()))
+            }
+            DataType::Decimal128(precision, scale) => {
+                ScalarValue::Decimal128(primitive!(Decimal128Type), *precision, *scale)
+            }
+            DataType::Date32 => ScalarValue::Date32(primitive!(Date32Type)),
+            DataType::Date64 => ScalarValue::Date64(primitive!(Date64Type)),
+            DataType::Timestamp(TimeUnit::Second, tz) => {
+                ScalarValue::TimestampSecond(primitive!(TimestampSecondType), tz.clone())
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, tz) => {
+                ScalarValue::TimestampMillisecond(primitive!(TimestampMillisecondType), tz.clone())
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, tz) => {
+                ScalarValue::TimestampMicrosecond(primitive!(TimestampMicrosecondType), tz.clone())
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, tz) => {
+                ScalarValue::TimestampNanosecond(primitive!(TimestampNanosecondType), tz.clone())
+            }
+            DataType::Interval(IntervalUnit::YearMonth) => {
+                ScalarValue::IntervalYearMonth(primitive!(IntervalYearMonthType))
+            }
+            DataType::Interval(IntervalUnit::DayTime) => {
+                ScalarValue::IntervalDayTime(primitive!(IntervalDayTimeType))
+            }
+            DataType::Interval(IntervalUnit::MonthDayNano) => {
+                ScalarValue::IntervalMonthDayNano(primitive!(IntervalMonthDayNanoType))
+            }
+            other => {
+                return Err(ServerError::NotSupported(format!("{} scalars", other)));
+            }
+        })
+    }
+
+    /// this value as an arrow scalar, for use with the arrow compute kernels.
+    pub fn to_scalar(&self) -> Scalar<ArrayRef> {
+        Scalar::new(self.to_array_of_size(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, AsArray},
+        datatypes::{DataType, Date32Type, TimeUnit, TimestampMillisecondType},
+    };
+
+    use super::ScalarValue;
+
+    #[test]
+    fn test_to_array_of_size() {
+        let scalars = [
+            ScalarValue::Null,
+            ScalarValue::Boolean(Some(true)),
+            ScalarValue::Int8(Some(-1)),
+            ScalarValue::UInt16(None),
+            ScalarValue::Float64(Some(1.5)),
+            ScalarValue::Utf8(Some("abc".to_string())),
+            ScalarValue::LargeUtf8(None),
+            ScalarValue::Binary(Some(vec![1, 2])),
+            ScalarValue::Decimal128(Some(12345), 10, 2),
+            ScalarValue::Date32(Some(19000)),
+            ScalarValue::TimestampNanosecond(Some(1), Some(Arc::from("+08:00"))),
+            ScalarValue::IntervalDayTime(Some(1 << 32)),
+        ];
+        for scalar in scalars {
+            let array = scalar.to_array_of_size(3);
+            assert_eq!(ScalarValue::try_from_array(&array, 2).unwrap(), scalar);
+            assert_eq!(array.len(), 3);
+            assert_eq!(array.data_type(), &scalar.data_type());
+            let null_count = array.logical_nulls().map_or(0, |nulls| nulls.null_count());
+            assert_eq!(null_count, if scalar.is_null() { 3 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let date = ScalarValue::Date32(Some(19000)).to_array_of_size(2);
+        assert_eq!(date.as_primitive::<Date32Type>().value(1), 19000);
+
+        let timestamp =
+            ScalarValue::TimestampMillisecond(Some(42), Some(Arc::from("UTC"))).to_array_of_size(1);
+        assert_eq!(
+            timestamp.data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, Some(Arc::from("UTC")))
+        );
+        assert_eq!(
+            timestamp
+                .as_primitive::<TimestampMillisecondType>()
+                .value(0),
+            42
+        );
+
+        let string = ScalarValue::Utf8(Some("abc".to_string())).to_array_of_size(2);
+        assert_eq!(string.as_string::<i32>().value(1), "abc");
+    }
+}
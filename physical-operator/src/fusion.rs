@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use common::Result;
+
+use crate::{
+    operator::{
+        filter::FilterOperator, filter_projection::FilterProjectionOperator,
+        projection::ProjectionOperator,
+    },
+    PhysicalOperatorRef,
+};
+
+/// Rewrite `plan` bottom-up, replacing every projection directly above a filter with a
+/// [`FilterProjectionOperator`] so each pair runs as a single generated function.
+pub fn fuse_operators(plan: PhysicalOperatorRef) -> Result<PhysicalOperatorRef> {
+    let children = plan.children();
+    let plan = if children.is_empty() {
+        plan
+    } else {
+        let children = children
+            .into_iter()
+            .map(fuse_operators)
+            .collect::<Result<_>>()?;
+        plan.with_new_children(children)?
+    };
+
+    let fused = plan
+        .as_any()
+        .downcast_ref::<ProjectionOperator>()
+        .and_then(|projection| {
+            let filter = projection
+                .input()
+                .as_any()
+                .downcast_ref::<FilterOperator>()?;
+            let fused = FilterProjectionOperator::new(
+                filter.input().clone(),
+                filter.predicate().clone(),
+                projection.exprs().to_vec(),
+            );
+            Some(Arc::new(fused) as PhysicalOperatorRef)
+        });
+    Ok(fused.unwrap_or(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Int64Array},
+        datatypes::{DataType, Field, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+    use execution::context::ExecContext;
+    use physical_expr::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        PhysicalExprRef, ScalarValue,
+    };
+
+    use crate::{
+        operator::{
+            filter::FilterOperator, filter_projection::FilterProjectionOperator,
+            projection::ProjectionOperator,
+        },
+        source::mem::MemSourceScan,
+        PhysicalOperatorRef,
+    };
+
+    use super::fuse_operators;
+
+    fn collect(plan: &PhysicalOperatorRef) -> Vec<Option<i64>> {
+        plan.exec(ExecContext::new().as_ref())
+            .unwrap()
+            .flat_map(|batch| {
+                let batch = batch.unwrap();
+                let column = batch.column(0).as_primitive::<Int64Type>();
+                column.iter().collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fuse_filter_projection() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let batches = [
+            (vec![Some(1), None, Some(5)], vec![1, 2, 3]),
+            (vec![Some(2), Some(7), None], vec![4, 5, 6]),
+        ]
+        .into_iter()
+        .map(|(a, b)| {
+            let columns = vec![
+                Arc::new(Int64Array::from(a)) as _,
+                Arc::new(Int64Array::from(b)) as _,
+            ];
+            RecordBatch::try_new(schema.clone(), columns).unwrap()
+        })
+        .collect();
+        let source = Arc::new(MemSourceScan::new(schema, batches));
+
+        let a: PhysicalExprRef = Arc::new(ColumnExpr::new("a".to_string(), 0));
+        let b: PhysicalExprRef = Arc::new(ColumnExpr::new("b".to_string(), 1));
+        // SELECT a + b FROM t WHERE b < a
+        let filter = Arc::new(FilterOperator::new(
+            source,
+            Arc::new(BinaryExpr::new(Op::Lt, b.clone(), a.clone())),
+        ));
+        let projection: PhysicalOperatorRef = Arc::new(ProjectionOperator::new(
+            filter,
+            vec![(
+                Arc::new(BinaryExpr::new(Op::Add, a, b)) as _,
+                "sum".to_string(),
+            )],
+        ));
+
+        let fused = fuse_operators(projection.clone()).unwrap();
+        assert!(fused
+            .as_any()
+            .downcast_ref::<FilterProjectionOperator>()
+            .is_some());
+        assert_eq!(fused.schema(), projection.schema());
+        assert_eq!(collect(&fused), vec![Some(8), Some(12)]);
+        assert_eq!(collect(&fused), collect(&projection));
+
+        // the pattern is fused below other operators too.
+        let literal: PhysicalExprRef = Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(1))));
+        let plan: PhysicalOperatorRef = Arc::new(ProjectionOperator::new(
+            projection,
+            vec![(literal, "one".to_string())],
+        ));
+        let plan = fuse_operators(plan).unwrap();
+        let projection = plan.as_any().downcast_ref::<ProjectionOperator>().unwrap();
+        assert!(projection
+            .input()
+            .as_any()
+            .downcast_ref::<FilterProjectionOperator>()
+            .is_some());
+    }
+}
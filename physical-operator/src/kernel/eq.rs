@@ -0,0 +1,146 @@
+use common::Result;
+use core::{try_native_type, CodegenContext, JitCode, KernelArgs};
+use std::mem;
+
+use arrow::{array::ArrayRef, datatypes::DataType};
+use cranelift::prelude::*;
+
+/// (build key pointers, build key validity pointers, build row indices,
+/// probe key pointers, probe key validity pointers, probe row indices, output, pair count)
+type EqFn = extern "C" fn(
+    *const *const u8,
+    *const *const u8,
+    *const u32,
+    *const *const u8,
+    *const *const u8,
+    *const u32,
+    *mut u8,
+    i64,
+);
+
+/// Generated function comparing the multi-column keys of candidate row pairs.
+///
+/// Null keys never compare equal, as in SQL.
+pub struct EqKernel {
+    kernel: EqFn,
+    // the memory `kernel` lives in.
+    _code: JitCode,
+    key_types: Vec<DataType>,
+}
+
+impl EqKernel {
+    pub fn compile(key_types: &[DataType]) -> Result<Self> {
+        for key_type in key_types {
+            try_native_type(key_type)?;
+        }
+
+        let mut ctx = CodegenContext::builder().finish();
+        let ptype = ctx.ptype();
+        let mut func_ctx = ctx.create_func_gen_ctx(
+            "eq_keys",
+            vec![
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(types::I64),
+            ],
+            vec![],
+        )?;
+        let entry_block = func_ctx.builder.create_block();
+        func_ctx.builder.switch_to_block(entry_block);
+        func_ctx
+            .builder
+            .append_block_params_for_function_params(entry_block);
+        let params = func_ctx.builder.block_params(entry_block).to_vec();
+        let build_ptrs = func_ctx.load_ptrs(params[0], key_types.len());
+        let build_validity_ptrs = func_ctx.load_ptrs(params[1], key_types.len());
+        let build_indices = params[2];
+        let probe_ptrs = func_ctx.load_ptrs(params[3], key_types.len());
+        let probe_validity_ptrs = func_ctx.load_ptrs(params[4], key_types.len());
+        let probe_indices = params[5];
+        let output_ptr = params[6];
+        let len = params[7];
+
+        let start = func_ctx.builder.ins().iconst(types::I64, 0);
+        func_ctx.build_row_loop(start, len, |ctx, row| {
+            let build_row = ctx.load_element(types::I32, build_indices, row);
+            let build_row = ctx.builder.ins().uextend(types::I64, build_row);
+            let probe_row = ctx.load_element(types::I32, probe_indices, row);
+            let probe_row = ctx.builder.ins().uextend(types::I64, probe_row);
+            let mut eq = ctx.builder.ins().iconst(types::I8, 1);
+            for (i, key_type) in key_types.iter().enumerate() {
+                let build = ctx.load_array_value(
+                    key_type,
+                    build_ptrs[i],
+                    Some(build_validity_ptrs[i]),
+                    build_row,
+                );
+                let probe = ctx.load_array_value(
+                    key_type,
+                    probe_ptrs[i],
+                    Some(probe_validity_ptrs[i]),
+                    probe_row,
+                );
+                let key_eq = if key_type.is_floating() {
+                    ctx.builder
+                        .ins()
+                        .fcmp(FloatCC::Equal, build.value, probe.value)
+                } else {
+                    ctx.builder
+                        .ins()
+                        .icmp(IntCC::Equal, build.value, probe.value)
+                };
+                let valid = ctx.and_valid(build.valid, probe.valid).unwrap();
+                let key_eq = ctx.builder.ins().band(key_eq, valid);
+                eq = ctx.builder.ins().band(eq, key_eq);
+            }
+            ctx.store_element(eq, output_ptr, row);
+        });
+
+        let func_id = func_ctx.finalize(&[])?;
+        let code = ctx.finalize_code(func_id)?;
+        Ok(Self {
+            kernel: unsafe { mem::transmute::<*const u8, EqFn>(code.address()) },
+            _code: code,
+            key_types: key_types.to_vec(),
+        })
+    }
+
+    /// compare `build_keys[build_indices[i]]` with `probe_keys[probe_indices[i]]` for
+    /// every candidate pair `i`.
+    pub fn eq(
+        &self,
+        build_keys: &[ArrayRef],
+        build_indices: &[u32],
+        probe_keys: &[ArrayRef],
+        probe_indices: &[u32],
+    ) -> Vec<bool> {
+        assert_eq!(build_keys.len(), self.key_types.len());
+        assert_eq!(probe_keys.len(), self.key_types.len());
+        assert_eq!(build_indices.len(), probe_indices.len());
+        let len = build_indices.len();
+        let build_values = KernelArgs::values(build_keys);
+        let build_validity = KernelArgs::validity(build_keys);
+        let probe_values = KernelArgs::values(probe_keys);
+        let probe_validity = KernelArgs::validity(probe_keys);
+        let mut output: Vec<bool> = Vec::with_capacity(len);
+        (self.kernel)(
+            build_values.as_ptr(),
+            build_validity.as_ptr(),
+            build_indices.as_ptr(),
+            probe_values.as_ptr(),
+            probe_validity.as_ptr(),
+            probe_indices.as_ptr(),
+            output.as_mut_ptr() as *mut u8,
+            len as i64,
+        );
+        unsafe {
+            output.set_len(len);
+        }
+        output
+    }
+}
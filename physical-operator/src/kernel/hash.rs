@@ -0,0 +1,86 @@
+use common::Result;
+use core::{try_native_type, CodegenContext, JitCode, KernelArgs, HASH_SEED};
+use std::mem;
+
+use arrow::{
+    array::{Array, ArrayRef},
+    datatypes::DataType,
+};
+use cranelift::prelude::*;
+
+/// (key pointers, key validity pointers, output hashes, row count)
+type HashFn = extern "C" fn(*const *const u8, *const *const u8, *mut u64, i64);
+
+/// Generated function hashing the multi-column keys of every row.
+pub struct HashKernel {
+    kernel: HashFn,
+    // the memory `kernel` lives in.
+    _code: JitCode,
+    key_types: Vec<DataType>,
+}
+
+impl HashKernel {
+    pub fn compile(key_types: &[DataType]) -> Result<Self> {
+        for key_type in key_types {
+            try_native_type(key_type)?;
+        }
+
+        let mut ctx = CodegenContext::builder().finish();
+        let ptype = ctx.ptype();
+        let mut func_ctx = ctx.create_func_gen_ctx(
+            "hash_keys",
+            vec![
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(ptype),
+                AbiParam::new(types::I64),
+            ],
+            vec![],
+        )?;
+        let entry_block = func_ctx.builder.create_block();
+        func_ctx.builder.switch_to_block(entry_block);
+        func_ctx
+            .builder
+            .append_block_params_for_function_params(entry_block);
+        let key_ptrs = func_ctx.builder.block_params(entry_block)[0];
+        let validity_ptrs = func_ctx.builder.block_params(entry_block)[1];
+        let hashes_ptr = func_ctx.builder.block_params(entry_block)[2];
+        let len = func_ctx.builder.block_params(entry_block)[3];
+        let key_ptrs = func_ctx.load_ptrs(key_ptrs, key_types.len());
+        let validity_ptrs = func_ctx.load_ptrs(validity_ptrs, key_types.len());
+
+        let start = func_ctx.builder.ins().iconst(types::I64, 0);
+        func_ctx.build_row_loop(start, len, |ctx, row| {
+            let mut hash = ctx.builder.ins().iconst(types::I64, HASH_SEED);
+            for (i, key_type) in key_types.iter().enumerate() {
+                let key = ctx.load_array_value(key_type, key_ptrs[i], Some(validity_ptrs[i]), row);
+                hash = ctx.hash_combine(hash, key);
+            }
+            ctx.store_element(hash, hashes_ptr, row);
+        });
+
+        let func_id = func_ctx.finalize(&[])?;
+        let code = ctx.finalize_code(func_id)?;
+        Ok(Self {
+            kernel: unsafe { mem::transmute::<*const u8, HashFn>(code.address()) },
+            _code: code,
+            key_types: key_types.to_vec(),
+        })
+    }
+
+    /// hash the rows of `keys` into `hashes`, replacing its content.
+    pub fn hash(&self, keys: &[ArrayRef], hashes: &mut Vec<u64>) {
+        assert_eq!(keys.len(), self.key_types.len());
+        let len = keys.first().map_or(0, |key| key.len());
+        let values = KernelArgs::values(keys);
+        let validity = KernelArgs::validity(keys);
+        hashes.clear();
+        hashes.resize(len, 0);
+        (self.kernel)(
+            values.as_ptr(),
+            validity.as_ptr(),
+            hashes.as_mut_ptr(),
+            len as i64,
+        );
+    }
+}
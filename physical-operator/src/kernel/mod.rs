@@ -0,0 +1,2 @@
+pub mod eq;
+pub mod hash;
@@ -1,11 +1,43 @@
+use std::{any::Any, sync::Arc};
+
 use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use common::{Result, ServerError};
 use execution::context::ExecContextRef;
 
+pub mod fusion;
+pub mod kernel;
 pub mod operator;
 pub mod source;
 
-trait PhysicalOperator {
+/// Batches pulled one at a time from an operator, so only the batches in flight are
+/// held in memory.
+pub type BatchStream = Box<dyn Iterator<Item = Result<RecordBatch>> + Send>;
+
+pub type PhysicalOperatorRef = Arc<dyn PhysicalOperator>;
+
+pub trait PhysicalOperator: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
     fn schema(&self) -> SchemaRef;
 
-    fn exec(&self, ctx: ExecContextRef) -> Result<RecordBatch, ()>;
+    fn children(&self) -> Vec<PhysicalOperatorRef>;
+
+    /// the same operator reading from `children`, used by plan rewrites.
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef>;
+
+    /// start executing, batches are produced lazily as the stream is pulled.
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream>;
+}
+
+/// unpack the children given to [`PhysicalOperator::with_new_children`].
+pub(crate) fn take_children<const N: usize>(
+    children: Vec<PhysicalOperatorRef>,
+) -> Result<[PhysicalOperatorRef; N]> {
+    let len = children.len();
+    children
+        .try_into()
+        .map_err(|_| ServerError::ArgumentError(format!("expected {} children, got {}", N, len)))
 }
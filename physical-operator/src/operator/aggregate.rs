@@ -0,0 +1,861 @@
+use core::{
+    native_type, validity_buffer, values_buffer, ArithOp, ArithmeticMode, CodegenContext,
+    FuncGenContext, GenValue, JitCode, KernelArgs, KernelError,
+};
+use std::{any::Any, collections::HashMap, iter, mem, sync::Arc};
+
+use arrow::{
+    array::{make_array, ArrayData, ArrayRef, AsArray, Float64Array, Int64Array},
+    buffer::{BooleanBuffer, Buffer, MutableBuffer, NullBuffer, ScalarBuffer},
+    datatypes::{DataType, Field, Float32Type, Float64Type, Schema, SchemaRef},
+    record_batch::RecordBatch,
+    row::{OwnedRow, RowConverter, SortField},
+};
+use common::{Result, ServerError};
+use cranelift::prelude::*;
+use execution::context::ExecContextRef;
+use physical_expr::{resolve_types, with_arithmetic_mode, PhysicalExprRef};
+
+use crate::{
+    kernel::hash::HashKernel, take_children, BatchStream, PhysicalOperator, PhysicalOperatorRef,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Clone)]
+pub struct AggregateExpr {
+    func: AggregateFunction,
+    // `None` for COUNT(*).
+    arg: Option<PhysicalExprRef>,
+    name: String,
+}
+
+impl AggregateExpr {
+    pub fn try_new(
+        func: AggregateFunction,
+        arg: Option<PhysicalExprRef>,
+        name: String,
+    ) -> Result<Self> {
+        if arg.is_none() && func != AggregateFunction::Count {
+            return Err(ServerError::ArgumentError(format!(
+                "{:?} needs an argument, only COUNT can omit it",
+                func
+            )));
+        }
+        Ok(Self { func, arg, name })
+    }
+
+    fn arg_type(&self, schema: &SchemaRef) -> Option<DataType> {
+        self.arg.as_ref().map(|arg| arg.output_type(schema.clone()))
+    }
+
+    /// type of both the per-group accumulator and the final result.
+    fn output_type(&self, schema: &SchemaRef) -> Result<DataType> {
+        use AggregateFunction::*;
+        match (self.func, self.arg_type(schema)) {
+            (Count, _) => Ok(DataType::Int64),
+            (_, None) | (_, Some(DataType::Boolean)) => Err(ServerError::TypeError(format!(
+                "{:?} needs a numeric argument",
+                self.func
+            ))),
+            (_, Some(arg_type)) if native_type(&arg_type).is_none() => Err(
+                ServerError::NotSupported(format!("{:?} over {}", self.func, arg_type)),
+            ),
+            (Sum | Avg, Some(arg_type)) if !arg_type.is_integer() && !arg_type.is_floating() => {
+                Err(ServerError::NotSupported(format!(
+                    "{:?} over {}",
+                    self.func, arg_type
+                )))
+            }
+            (Sum, Some(arg_type)) if arg_type.is_floating() => Ok(DataType::Float64),
+            (Sum, Some(arg_type)) if arg_type.is_unsigned_integer() => Ok(DataType::UInt64),
+            (Sum, Some(_)) => Ok(DataType::Int64),
+            (Min | Max, Some(arg_type)) => Ok(arg_type),
+            (Avg, Some(_)) => Ok(DataType::Float64),
+        }
+    }
+}
+
+pub struct HashAggregateOperator {
+    input: Arc<dyn PhysicalOperator>,
+    group_by: Vec<(PhysicalExprRef, String)>,
+    aggregates: Vec<AggregateExpr>,
+    schema: SchemaRef,
+}
+
+impl HashAggregateOperator {
+    pub fn try_new(
+        input: Arc<dyn PhysicalOperator>,
+        group_by: Vec<(PhysicalExprRef, String)>,
+        aggregates: Vec<AggregateExpr>,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let group_by = group_by
+            .into_iter()
+            .map(|(expr, name)| Ok((resolve_types(&expr, &input_schema)?, name)))
+            .collect::<Result<Vec<_>>>()?;
+        let aggregates = aggregates
+            .into_iter()
+            .map(|aggregate| {
+                let arg = aggregate
+                    .arg
+                    .map(|arg| resolve_types(&arg, &input_schema))
+                    .transpose()?;
+                Ok(AggregateExpr { arg, ..aggregate })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut fields = vec![];
+        for (expr, name) in &group_by {
+            fields.push(Field::new(
+                name,
+                expr.output_type(input_schema.clone()),
+                true,
+            ));
+        }
+        for aggregate in &aggregates {
+            let output_type = aggregate.output_type(&input_schema)?;
+            let nullable = aggregate.func != AggregateFunction::Count;
+            fields.push(Field::new(&aggregate.name, output_type, nullable));
+        }
+        Ok(Self {
+            input,
+            group_by,
+            aggregates,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+}
+
+impl PhysicalOperator for HashAggregateOperator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        let [input] = take_children(children)?;
+        let operator =
+            HashAggregateOperator::try_new(input, self.group_by.clone(), self.aggregates.clone())?;
+        Ok(Arc::new(operator))
+    }
+
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream> {
+        let input_schema = self.input.schema();
+        let mode = ctx.arithmetic_mode();
+        let input = self.input.exec(ctx)?;
+        let mut state = AggregateState::try_new(self, &input_schema, mode)?;
+        // aggregation is a pipeline breaker, the input is drained on the first pull.
+        Ok(Box::new(iter::once_with(move || {
+            for batch in input {
+                state.update(&batch?)?;
+            }
+            state.finish()
+        })))
+    }
+}
+
+struct AggregateSpec {
+    func: AggregateFunction,
+    arg_type: Option<DataType>,
+    state_type: DataType,
+}
+
+struct AggregateState {
+    group_by: Vec<PhysicalExprRef>,
+    aggregates: Vec<AggregateExpr>,
+    specs: Vec<AggregateSpec>,
+    schema: SchemaRef,
+    // `None` without GROUP BY, every row then belongs to group 0.
+    grouping: Option<Grouping>,
+    update: UpdateFn,
+    // the memory `update` lives in.
+    _update_code: JitCode,
+    num_groups: usize,
+    group_ids: Vec<u32>,
+    accumulators: Vec<MutableBuffer>,
+    // non-null values seen per group, which also decides whether a result is null.
+    counts: Vec<MutableBuffer>,
+}
+
+impl AggregateState {
+    fn try_new(
+        operator: &HashAggregateOperator,
+        input_schema: &SchemaRef,
+        mode: ArithmeticMode,
+    ) -> Result<Self> {
+        let specs = operator
+            .aggregates
+            .iter()
+            .map(|aggregate| {
+                Ok(AggregateSpec {
+                    func: aggregate.func,
+                    arg_type: aggregate.arg_type(input_schema),
+                    state_type: aggregate.output_type(input_schema)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let grouping = if operator.group_by.is_empty() {
+            None
+        } else {
+            let key_types: Vec<DataType> = operator
+                .group_by
+                .iter()
+                .map(|(expr, _)| expr.output_type(input_schema.clone()))
+                .collect();
+            Some(Grouping::try_new(&key_types)?)
+        };
+        let num_groups = if grouping.is_some() { 0 } else { 1 };
+        let (update, update_code) = compile_update(&specs, mode)?;
+        let mut state = Self {
+            group_by: operator
+                .group_by
+                .iter()
+                .map(|(expr, _)| with_arithmetic_mode(expr, mode))
+                .collect::<Result<_>>()?,
+            aggregates: operator
+                .aggregates
+                .iter()
+                .map(|aggregate| {
+                    let arg = aggregate
+                        .arg
+                        .as_ref()
+                        .map(|arg| with_arithmetic_mode(arg, mode))
+                        .transpose()?;
+                    Ok(AggregateExpr {
+                        arg,
+                        ..aggregate.clone()
+                    })
+                })
+                .collect::<Result<_>>()?,
+            update,
+            _update_code: update_code,
+            specs,
+            schema: operator.schema.clone(),
+            grouping,
+            num_groups,
+            group_ids: vec![],
+            accumulators: operator
+                .aggregates
+                .iter()
+                .map(|_| MutableBuffer::default())
+                .collect(),
+            counts: operator
+                .aggregates
+                .iter()
+                .map(|_| MutableBuffer::default())
+                .collect(),
+        };
+        state.grow();
+        Ok(state)
+    }
+
+    fn update(&mut self, batch: &RecordBatch) -> Result<()> {
+        let len = batch.num_rows();
+        match &mut self.grouping {
+            Some(grouping) => {
+                let keys = self
+                    .group_by
+                    .iter()
+                    .map(|expr| Ok(expr.eval(batch)?.into_array(len)))
+                    .collect::<Result<Vec<_>>>()?;
+                grouping.group_ids(&keys, &mut self.group_ids)?;
+                self.num_groups = grouping.keys.len();
+            }
+            None => {
+                self.group_ids.clear();
+                self.group_ids.resize(len, 0);
+            }
+        }
+        self.grow();
+
+        let mut values = vec![];
+        let mut validity = vec![];
+        for aggregate in &self.aggregates {
+            match &aggregate.arg {
+                Some(arg) => {
+                    let array = arg.eval(batch)?.into_array(len);
+                    // COUNT only reads the validity of its argument.
+                    values.push(match aggregate.func {
+                        AggregateFunction::Count => Buffer::from_vec(Vec::<u8>::new()),
+                        _ => values_buffer(&array),
+                    });
+                    validity.push(validity_buffer(&array));
+                }
+                None => {
+                    values.push(Buffer::from_vec(Vec::<u8>::new()));
+                    validity.push(Buffer::from_vec(Vec::<u8>::new()));
+                }
+            }
+        }
+        let values = KernelArgs::new(values);
+        let validity = KernelArgs::new(validity);
+        let accumulators: Vec<*mut u8> = self
+            .accumulators
+            .iter_mut()
+            .map(|buffer| buffer.as_mut_ptr())
+            .collect();
+        let counts: Vec<*mut u8> = self
+            .counts
+            .iter_mut()
+            .map(|buffer| buffer.as_mut_ptr())
+            .collect();
+        let mut error = KernelError::default();
+        let updated = (self.update)(
+            self.group_ids.as_ptr(),
+            values.as_ptr(),
+            validity.as_ptr(),
+            accumulators.as_ptr(),
+            counts.as_ptr(),
+            &mut error,
+            len as i64,
+        );
+        if updated < 0 {
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    /// make room in every accumulator for groups created by the last batch.
+    fn grow(&mut self) {
+        for (i, spec) in self.specs.iter().enumerate() {
+            let width = native_type(&spec.state_type).unwrap().bytes() as usize;
+            self.accumulators[i].resize(self.num_groups * width, 0);
+            self.counts[i].resize(self.num_groups * mem::size_of::<i64>(), 0);
+        }
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch> {
+        let num_groups = self.num_groups;
+        let mut columns = match &self.grouping {
+            Some(grouping) => grouping
+                .converter
+                .convert_rows(grouping.keys.iter().map(|key| key.row()))?,
+            None => vec![],
+        };
+        for (i, spec) in self.specs.iter().enumerate() {
+            let accumulator: Buffer = mem::take(&mut self.accumulators[i]).into();
+            let counts: Buffer = mem::take(&mut self.counts[i]).into();
+            let counts = ScalarBuffer::<i64>::new(counts, 0, num_groups);
+            let column: ArrayRef = match spec.func {
+                AggregateFunction::Count => Arc::new(Int64Array::new(counts, None)),
+                AggregateFunction::Avg => {
+                    let sums = ScalarBuffer::<f64>::new(accumulator, 0, num_groups);
+                    let averages = sums
+                        .iter()
+                        .zip(counts.iter())
+                        .map(|(sum, count)| (*count > 0).then(|| sum / *count as f64));
+                    Arc::new(averages.collect::<Float64Array>())
+                }
+                _ => {
+                    let nulls = BooleanBuffer::from_iter(counts.iter().map(|count| *count > 0));
+                    let data = ArrayData::builder(spec.state_type.clone())
+                        .len(num_groups)
+                        .add_buffer(accumulator)
+                        .nulls(Some(NullBuffer::new(nulls)))
+                        .build()?;
+                    make_array(data)
+                }
+            };
+            columns.push(column);
+        }
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+/// Assigns dense group ids to the distinct keys seen so far.
+struct Grouping {
+    hash: HashKernel,
+    converter: RowConverter,
+    // hash of a key -> ids of the groups whose key has that hash.
+    table: HashMap<u64, Vec<u32>>,
+    // key of every group, indexed by group id.
+    keys: Vec<OwnedRow>,
+    hashes: Vec<u64>,
+}
+
+impl Grouping {
+    fn try_new(key_types: &[DataType]) -> Result<Self> {
+        let fields = key_types
+            .iter()
+            .map(|key_type| SortField::new(key_type.clone()))
+            .collect();
+        Ok(Self {
+            hash: HashKernel::compile(key_types)?,
+            converter: RowConverter::new(fields)?,
+            table: HashMap::new(),
+            keys: vec![],
+            hashes: vec![],
+        })
+    }
+
+    fn group_ids(&mut self, keys: &[ArrayRef], group_ids: &mut Vec<u32>) -> Result<()> {
+        let keys: Vec<ArrayRef> = keys.iter().map(normalize_key).collect();
+        self.hash.hash(&keys, &mut self.hashes);
+        let rows = self.converter.convert_columns(&keys)?;
+        group_ids.clear();
+        for (row, hash) in self.hashes.iter().enumerate() {
+            let key = rows.row(row);
+            let candidates = self.table.entry(*hash).or_default();
+            let found = candidates
+                .iter()
+                .copied()
+                .find(|id| self.keys[*id as usize].row() == key);
+            let id = match found {
+                Some(id) => id,
+                None => {
+                    let id = self.keys.len() as u32;
+                    self.keys.push(key.owned());
+                    candidates.push(id);
+                    id
+                }
+            };
+            group_ids.push(id);
+        }
+        Ok(())
+    }
+}
+
+/// Rows compare floats by bit pattern, while -0 and +0 belong to one group and so does
+/// every NaN. Rewrite both to a single representation before keys are converted.
+fn normalize_key(key: &ArrayRef) -> ArrayRef {
+    match key.data_type() {
+        DataType::Float32 => Arc::new(
+            key.as_primitive::<Float32Type>()
+                .unary::<_, Float32Type>(|v| if v.is_nan() { f32::NAN } else { v + 0.0 }),
+        ),
+        DataType::Float64 => Arc::new(
+            key.as_primitive::<Float64Type>()
+                .unary::<_, Float64Type>(|v| if v.is_nan() { f64::NAN } else { v + 0.0 }),
+        ),
+        _ => key.clone(),
+    }
+}
+
+/// (group ids, argument pointers, argument validity pointers, accumulator pointers,
+/// count pointers, error record, row count) -> rows updated, or -1 after an error.
+type UpdateFn = extern "C" fn(
+    *const u32,
+    *const *const u8,
+    *const *const u8,
+    *const *mut u8,
+    *const *mut u8,
+    *mut KernelError,
+    i64,
+) -> i64;
+
+/// Generate one loop updating every aggregate for every row, so there is no dispatch
+/// per aggregate per row. Integer sums overflow as `mode` says.
+fn compile_update(specs: &[AggregateSpec], mode: ArithmeticMode) -> Result<(UpdateFn, JitCode)> {
+    let mut ctx = CodegenContext::builder().finish();
+    let ptype = ctx.ptype();
+    let mut func_ctx = ctx.create_func_gen_ctx(
+        "aggregate_update",
+        vec![
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(ptype),
+            AbiParam::new(types::I64),
+        ],
+        vec![AbiParam::new(types::I64)],
+    )?;
+    let entry_block = func_ctx.builder.create_block();
+    func_ctx.builder.switch_to_block(entry_block);
+    func_ctx
+        .builder
+        .append_block_params_for_function_params(entry_block);
+    let params = func_ctx.builder.block_params(entry_block).to_vec();
+    let group_ids = params[0];
+    let arg_ptrs = func_ctx.load_ptrs(params[1], specs.len());
+    let validity_ptrs = func_ctx.load_ptrs(params[2], specs.len());
+    let accumulator_ptrs = func_ctx.load_ptrs(params[3], specs.len());
+    let count_ptrs = func_ctx.load_ptrs(params[4], specs.len());
+    func_ctx.bind_error_record(params[5]);
+    func_ctx.set_arithmetic_mode(mode);
+    let len = params[6];
+
+    let start = func_ctx.builder.ins().iconst(types::I64, 0);
+    func_ctx.build_row_loop(start, len, |ctx, row| {
+        ctx.bind_row(row);
+        let group = ctx.load_element(types::I32, group_ids, row);
+        let group = ctx.builder.ins().uextend(types::I64, group);
+        for (i, spec) in specs.iter().enumerate() {
+            let valid = spec
+                .arg_type
+                .as_ref()
+                .map(|_| ctx.load_bit(validity_ptrs[i], row));
+            let value = match (spec.func, &spec.arg_type) {
+                (AggregateFunction::Count, _) | (_, None) => None,
+                (_, Some(arg_type)) => {
+                    Some(ctx.load_array_value(arg_type, arg_ptrs[i], None, row).value)
+                }
+            };
+            let ptrs = (accumulator_ptrs[i], count_ptrs[i]);
+            gen_update(ctx, spec, value, valid, ptrs, group);
+        }
+    });
+
+    let func_id = func_ctx.finalize(&[len])?;
+    let code = ctx.finalize_code(func_id)?;
+    let update = unsafe { mem::transmute::<*const u8, UpdateFn>(code.address()) };
+    Ok((update, code))
+}
+
+fn gen_update(
+    ctx: &mut FuncGenContext,
+    spec: &AggregateSpec,
+    value: Option<Value>,
+    valid: Option<Value>,
+    (accumulator_ptr, count_ptr): (Value, Value),
+    group: Value,
+) {
+    let count = ctx.load_element(types::I64, count_ptr, group);
+    let increment = match valid {
+        Some(valid) => ctx.builder.ins().uextend(types::I64, valid),
+        None => ctx.builder.ins().iconst(types::I64, 1),
+    };
+    let new_count = ctx.builder.ins().iadd(count, increment);
+    ctx.store_element(new_count, count_ptr, group);
+    let Some(value) = value else {
+        return;
+    };
+
+    let arg_type = spec.arg_type.as_ref().unwrap();
+    let state_type = &spec.state_type;
+    let old = ctx.load_element(native_type(state_type).unwrap(), accumulator_ptr, group);
+    let new = match spec.func {
+        AggregateFunction::Sum | AggregateFunction::Avg => {
+            let value = widen(ctx, value, arg_type, state_type);
+            if state_type.is_floating() {
+                ctx.builder.ins().fadd(old, value)
+            } else {
+                // null rows don't add, so they can't overflow either.
+                let value = GenValue::new(value, valid);
+                ctx.arith(ArithOp::Add, state_type, GenValue::non_null(old), value)
+                    .value
+            }
+        }
+        AggregateFunction::Min | AggregateFunction::Max => {
+            let min = spec.func == AggregateFunction::Min;
+            let ins = ctx.builder.ins();
+            let better = if arg_type.is_floating() {
+                // NaN is greater than every other value: MAX keeps the first NaN it sees,
+                // MIN only returns NaN when a group has nothing else.
+                if min {
+                    let less = ins.fcmp(FloatCC::UnorderedOrLessThan, value, old);
+                    let value_is_number = ctx.builder.ins().fcmp(FloatCC::Ordered, value, value);
+                    ctx.builder.ins().band(less, value_is_number)
+                } else {
+                    let greater = ins.fcmp(FloatCC::UnorderedOrGreaterThan, value, old);
+                    let old_is_number = ctx.builder.ins().fcmp(FloatCC::Ordered, old, old);
+                    ctx.builder.ins().band(greater, old_is_number)
+                }
+            } else {
+                let cc = match (min, arg_type.is_unsigned_integer()) {
+                    (true, true) => IntCC::UnsignedLessThan,
+                    (true, false) => IntCC::SignedLessThan,
+                    (false, true) => IntCC::UnsignedGreaterThan,
+                    (false, false) => IntCC::SignedGreaterThan,
+                };
+                ins.icmp(cc, value, old)
+            };
+            // the first value of a group replaces the zeroed accumulator.
+            let first = ctx.builder.ins().icmp_imm(IntCC::Equal, count, 0);
+            let take = ctx.builder.ins().bor(better, first);
+            ctx.builder.ins().select(take, value, old)
+        }
+        AggregateFunction::Count => unreachable!(),
+    };
+    let new = match valid {
+        Some(valid) => ctx.builder.ins().select(valid, new, old),
+        None => new,
+    };
+    ctx.store_element(new, accumulator_ptr, group);
+}
+
+/// convert an argument to the wider type its sum is accumulated in.
+fn widen(ctx: &mut FuncGenContext, value: Value, from: &DataType, to: &DataType) -> Value {
+    let from_native = native_type(from).unwrap();
+    let to_native = native_type(to).unwrap();
+    let ins = ctx.builder.ins();
+    match (from.is_floating(), to.is_floating()) {
+        _ if from_native == to_native => value,
+        (true, true) => ins.fpromote(to_native, value),
+        (false, true) if from.is_unsigned_integer() => ins.fcvt_from_uint(to_native, value),
+        (false, true) => ins.fcvt_from_sint(to_native, value),
+        _ if from.is_unsigned_integer() => ins.uextend(to_native, value),
+        _ => ins.sextend(to_native, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ArithmeticMode;
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Float64Array, Int32Array, Int64Array},
+        compute::{sort_to_indices, take},
+        datatypes::{DataType, Field, Float64Type, Int32Type, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+    use execution::context::ExecContext;
+    use physical_expr::{expr::column::ColumnExpr, PhysicalExprRef};
+
+    use crate::{source::mem::MemSourceScan, PhysicalOperator};
+
+    use super::{AggregateExpr, AggregateFunction, HashAggregateOperator};
+
+    fn column(name: &str, index: usize) -> PhysicalExprRef {
+        Arc::new(ColumnExpr::new(name.to_string(), index))
+    }
+
+    #[test]
+    fn test_hash_aggregate() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Int64, true),
+            Field::new("value", DataType::Int32, true),
+            Field::new("price", DataType::Float64, false),
+        ]));
+        let batch = |keys: Vec<Option<i64>>, values: Vec<Option<i32>>, prices: Vec<f64>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(keys)),
+                    Arc::new(Int32Array::from(values)),
+                    Arc::new(Float64Array::from(prices)),
+                ],
+            )
+            .unwrap()
+        };
+        let batches = vec![
+            batch(
+                vec![Some(1), Some(2), None, Some(1)],
+                vec![Some(10), None, Some(7), Some(-4)],
+                vec![1.0, 2.0, 3.0, 4.0],
+            ),
+            batch(vec![Some(2), Some(1)], vec![Some(5), None], vec![5.0, 6.0]),
+        ];
+        let source = MemSourceScan::new(schema, batches);
+        let aggregates = vec![
+            AggregateExpr::try_new(AggregateFunction::Count, None, "count".to_string()).unwrap(),
+            AggregateExpr::try_new(
+                AggregateFunction::Sum,
+                Some(column("value", 1)),
+                "sum".to_string(),
+            )
+            .unwrap(),
+            AggregateExpr::try_new(
+                AggregateFunction::Min,
+                Some(column("value", 1)),
+                "min".to_string(),
+            )
+            .unwrap(),
+            AggregateExpr::try_new(
+                AggregateFunction::Max,
+                Some(column("price", 2)),
+                "max".to_string(),
+            )
+            .unwrap(),
+            AggregateExpr::try_new(
+                AggregateFunction::Avg,
+                Some(column("value", 1)),
+                "avg".to_string(),
+            )
+            .unwrap(),
+        ];
+        let aggregate = HashAggregateOperator::try_new(
+            Arc::new(source),
+            vec![(column("key", 0), "key".to_string())],
+            aggregates,
+        )
+        .unwrap();
+
+        let result: Vec<RecordBatch> = aggregate
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        // sort by key, nulls first.
+        let indices = sort_to_indices(result[0].column(0), None, None).unwrap();
+        let sorted = |i: usize| take(result[0].column(i), &indices, None).unwrap();
+
+        assert_eq!(
+            sorted(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![None, Some(1), Some(2)])
+        );
+        assert_eq!(
+            sorted(1).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![1, 3, 2])
+        );
+        assert_eq!(
+            sorted(2).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![7, 6, 5])
+        );
+        assert_eq!(
+            sorted(3).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![7, -4, 5])
+        );
+        assert_eq!(
+            sorted(4).as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![3.0, 6.0, 5.0])
+        );
+        assert_eq!(
+            sorted(5).as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![7.0, 3.0, 5.0])
+        );
+    }
+
+    #[test]
+    fn test_aggregate_without_group_by() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "value",
+            DataType::Int64,
+            true,
+        )]));
+        let source = MemSourceScan::new(schema, vec![]);
+        let aggregate = HashAggregateOperator::try_new(
+            Arc::new(source),
+            vec![],
+            vec![
+                AggregateExpr::try_new(AggregateFunction::Count, None, "count".to_string())
+                    .unwrap(),
+                AggregateExpr::try_new(
+                    AggregateFunction::Sum,
+                    Some(column("value", 0)),
+                    "sum".to_string(),
+                )
+                .unwrap(),
+            ],
+        )
+        .unwrap();
+
+        let mut result = aggregate.exec(ExecContext::new().as_ref()).unwrap();
+        let batch = result.next().unwrap().unwrap();
+        assert!(result.next().is_none());
+        assert_eq!(
+            batch.column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![0])
+        );
+        assert_eq!(
+            batch.column(1).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![None])
+        );
+    }
+
+    #[test]
+    fn test_float_keys_and_nan_min_max() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Float64, false),
+            Field::new("value", DataType::Float64, false),
+        ]));
+        // -0 and +0 form one group, and so does every NaN whatever its bits.
+        let keys = Float64Array::from(vec![
+            0.0,
+            -0.0,
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff8_0000_0000_0001),
+        ]);
+        let values = Float64Array::from(vec![f64::NAN, 1.0, 2.0, f64::NAN, 3.0]);
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(keys), Arc::new(values)]).unwrap();
+        let source = MemSourceScan::new(schema, vec![batch]);
+        let aggregate = HashAggregateOperator::try_new(
+            Arc::new(source),
+            vec![(column("key", 0), "key".to_string())],
+            vec![
+                AggregateExpr::try_new(
+                    AggregateFunction::Min,
+                    Some(column("value", 1)),
+                    "min".to_string(),
+                )
+                .unwrap(),
+                AggregateExpr::try_new(
+                    AggregateFunction::Max,
+                    Some(column("value", 1)),
+                    "max".to_string(),
+                )
+                .unwrap(),
+            ],
+        )
+        .unwrap();
+
+        let batch = aggregate
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let keys = batch.column(0).as_primitive::<Float64Type>();
+        let zero = (0..keys.len()).find(|i| keys.value(*i) == 0.0).unwrap();
+        let nan = 1 - zero;
+        assert!(keys.value(nan).is_nan());
+        let min = batch.column(1).as_primitive::<Float64Type>();
+        let max = batch.column(2).as_primitive::<Float64Type>();
+        // NaN sorts above every number, wherever it shows up in the group.
+        assert_eq!(min.value(zero), 1.0);
+        assert!(max.value(zero).is_nan());
+        assert_eq!(min.value(nan), 2.0);
+        assert!(max.value(nan).is_nan());
+    }
+
+    #[test]
+    fn test_only_count_omits_its_argument() {
+        let sum = AggregateExpr::try_new(AggregateFunction::Sum, None, "sum".to_string());
+        assert!(matches!(sum, Err(ServerError::ArgumentError(_))));
+    }
+
+    #[test]
+    fn test_sum_overflow_follows_arithmetic_mode() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "value",
+            DataType::Int64,
+            true,
+        )]));
+        let values = Int64Array::from(vec![Some(i64::MAX), None, Some(1)]);
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap();
+        let sum = |mode| {
+            let source = MemSourceScan::new(schema.clone(), vec![batch.clone()]);
+            let sum = AggregateExpr::try_new(
+                AggregateFunction::Sum,
+                Some(column("value", 0)),
+                "sum".to_string(),
+            )
+            .unwrap();
+            let aggregate =
+                HashAggregateOperator::try_new(Arc::new(source), vec![], vec![sum]).unwrap();
+            let ctx = ExecContext::new().with_arithmetic_mode(mode).as_ref();
+            let batch = aggregate.exec(ctx).unwrap().next().unwrap()?;
+            Ok::<_, ServerError>(batch.column(0).as_primitive::<Int64Type>().value(0))
+        };
+        assert_eq!(sum(ArithmeticMode::Wrapping).unwrap(), i64::MIN);
+        assert_eq!(sum(ArithmeticMode::Saturating).unwrap(), i64::MAX);
+        assert!(matches!(
+            sum(ArithmeticMode::Checked),
+            Err(ServerError::ArithmeticOverflow { row: 2 })
+        ));
+    }
+}
@@ -1,44 +1,154 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
-use crate::PhysicalOperator;
+use crate::{take_children, BatchStream, PhysicalOperator, PhysicalOperatorRef};
 use arrow::array::AsArray;
-use arrow::{
-    compute::filter,
-    datatypes::SchemaRef,
-    record_batch::{RecordBatch, RecordBatchOptions},
-};
-use execution::context::ExecContext;
-use physical_expr::PhysicalExpr;
-
-struct FilterOperator {
+use arrow::{compute::filter_record_batch, datatypes::SchemaRef, record_batch::RecordBatch};
+use common::{Result, ServerError};
+use execution::context::ExecContextRef;
+use physical_expr::{resolve_types, with_arithmetic_mode, PhysicalExpr};
+
+pub struct FilterOperator {
     input: Arc<dyn PhysicalOperator>,
     predicate: Arc<dyn PhysicalExpr>,
 }
 
 impl FilterOperator {
-    fn new(input: Arc<dyn PhysicalOperator>, predicate: Arc<dyn PhysicalExpr>) -> Self {
+    pub fn new(input: Arc<dyn PhysicalOperator>, predicate: Arc<dyn PhysicalExpr>) -> Self {
         Self { input, predicate }
     }
+
+    pub fn input(&self) -> &Arc<dyn PhysicalOperator> {
+        &self.input
+    }
+
+    pub fn predicate(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.predicate
+    }
 }
 
 impl PhysicalOperator for FilterOperator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn schema(&self) -> SchemaRef {
         self.input.schema()
     }
 
-    fn exec(&self, ctx: std::sync::Arc<ExecContext>) -> Result<RecordBatch, ()> {
-        let input = self.input.exec(ctx.clone()).unwrap();
-        let predicate = self.predicate.eval(&input).unwrap();
-        let bind = predicate.as_ref();
-        let (predicate_array, _) = bind.get();
-        let bool_array = predicate_array.as_boolean();
-        let columns = input
-            .columns()
-            .iter()
-            .map(|column| filter(column, bool_array).unwrap())
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(FilterOperator::new(input, self.predicate.clone())))
+    }
+
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream> {
+        let predicate = resolve_types(&self.predicate, &self.input.schema())?;
+        let predicate = with_arithmetic_mode(&predicate, ctx.arithmetic_mode())?;
+        let input = self.input.exec(ctx)?;
+        Ok(Box::new(
+            input.map(move |batch| filter_batch(&batch?, &*predicate)),
+        ))
+    }
+}
+
+fn filter_batch(input: &RecordBatch, predicate: &dyn PhysicalExpr) -> Result<RecordBatch> {
+    // a scalar predicate keeps or drops every row.
+    let predicate = predicate.eval(input)?.into_array(input.num_rows());
+    let predicate = predicate
+        .as_boolean_opt()
+        .ok_or_else(|| ServerError::TypeError("filter predicate must be boolean".to_string()))?;
+    // rows the predicate is null for are dropped like false ones.
+    Ok(filter_record_batch(input, predicate)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{BooleanArray, Int64Array},
+        buffer::{BooleanBuffer, NullBuffer},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use execution::context::ExecContext;
+    use physical_expr::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        ScalarValue,
+    };
+
+    use crate::{source::mem::MemSourceScan, PhysicalOperator};
+
+    use super::FilterOperator;
+
+    #[test]
+    fn test_filter_streams_batches() {
+        let schema = Arc::new(Schema::new(vec![Field::new("num", DataType::Int64, false)]));
+        let batches = [vec![1, 2, 3], vec![4, 5], vec![0, 6, 2]]
+            .into_iter()
+            .map(|values| {
+                let array = Arc::new(Int64Array::from(values));
+                RecordBatch::try_new(schema.clone(), vec![array]).unwrap()
+            })
+            .collect();
+        let source = MemSourceScan::new(schema, batches);
+        let filter = FilterOperator::new(
+            Arc::new(source),
+            Arc::new(BinaryExpr::new(
+                Op::Lt,
+                Arc::new(ColumnExpr::new(String::from("num"), 0)),
+                Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(3)))),
+            )),
+        );
+
+        let row_counts: Vec<usize> = filter
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
             .collect();
-        let options = RecordBatchOptions::default()
-            .with_row_count(Some(bool_array.values().count_set_bits()));
-        Ok(RecordBatch::try_new_with_options(input.schema(), columns, &options).unwrap())
+        assert_eq!(row_counts, vec![2, 0, 2]);
+    }
+
+    #[test]
+    fn test_filter_null_and_scalar_predicates() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("num", DataType::Int64, false),
+            Field::new("keep", DataType::Boolean, true),
+        ]));
+        // the null row's value bit is set, it is still dropped.
+        let keep = BooleanArray::new(
+            BooleanBuffer::from(vec![true, true, false]),
+            Some(NullBuffer::from(vec![true, false, true])),
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3])), Arc::new(keep)],
+        )
+        .unwrap();
+        let exec = |predicate| {
+            let source = MemSourceScan::new(schema.clone(), vec![batch.clone()]);
+            FilterOperator::new(Arc::new(source), predicate)
+                .exec(ExecContext::new().as_ref())
+                .unwrap()
+                .map(|batch| batch.unwrap().num_rows())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            exec(Arc::new(ColumnExpr::new(String::from("keep"), 1))),
+            vec![1]
+        );
+        let literal = |value| Arc::new(LiteralExpr::new(ScalarValue::Boolean(Some(value))));
+        assert_eq!(exec(literal(true)), vec![3]);
+        assert_eq!(exec(literal(false)), vec![0]);
     }
 }
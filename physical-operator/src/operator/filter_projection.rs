@@ -0,0 +1,81 @@
+use std::{any::Any, sync::Arc};
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use common::Result;
+use execution::context::ExecContextRef;
+use physical_expr::{AdaptiveExprs, PhysicalExpr, PhysicalExprRef};
+
+use crate::{take_children, BatchStream, PhysicalOperator, PhysicalOperatorRef};
+
+/// A filter and the projection above it generated as one function: the projections are
+/// only computed for rows passing the predicate, and written straight to the output
+/// without materializing the filtered input in between.
+pub struct FilterProjectionOperator {
+    input: Arc<dyn PhysicalOperator>,
+    predicate: Arc<dyn PhysicalExpr>,
+    exprs: Vec<(PhysicalExprRef, String)>,
+    schema: SchemaRef,
+}
+
+impl FilterProjectionOperator {
+    pub fn new(
+        input: Arc<dyn PhysicalOperator>,
+        predicate: Arc<dyn PhysicalExpr>,
+        exprs: Vec<(PhysicalExprRef, String)>,
+    ) -> Self {
+        let input_schema = input.schema();
+        let fields: Vec<Field> = exprs
+            .iter()
+            .map(|(expr, name)| Field::new(name, expr.output_type(input_schema.clone()), true))
+            .collect();
+        Self {
+            input,
+            predicate,
+            exprs,
+            schema: Arc::new(Schema::new(fields)),
+        }
+    }
+}
+
+impl PhysicalOperator for FilterProjectionOperator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(FilterProjectionOperator::new(
+            input,
+            self.predicate.clone(),
+            self.exprs.clone(),
+        )))
+    }
+
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream> {
+        let exprs: Vec<PhysicalExprRef> = self.exprs.iter().map(|(expr, _)| expr.clone()).collect();
+        let mut exprs = AdaptiveExprs::try_new(
+            Some(self.predicate.clone()),
+            exprs,
+            self.input.schema(),
+            ctx.arithmetic_mode(),
+            ctx.execution_mode(),
+            ctx.kernel_cache().clone(),
+        )?;
+        let input = self.input.exec(ctx)?;
+        let schema = self.schema.clone();
+        Ok(Box::new(input.map(move |batch| {
+            exprs.eval_batch(&batch?, schema.clone())
+        })))
+    }
+}
@@ -0,0 +1,482 @@
+use core::try_native_type;
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use arrow::{
+    array::{new_null_array, Array, ArrayRef, UInt32Array},
+    compute::{concat_batches, take},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use common::{Result, ServerError};
+use execution::context::ExecContextRef;
+use physical_expr::{
+    cast_to, comparison_coercion, resolve_types, with_arithmetic_mode, PhysicalExprRef,
+};
+
+use crate::{
+    kernel::{eq::EqKernel, hash::HashKernel},
+    take_children, BatchStream, PhysicalOperator, PhysicalOperatorRef,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    /// keep left rows without a match, padded with nulls.
+    Left,
+    /// keep right rows without a match, padded with nulls.
+    Right,
+    /// left rows with at least one match.
+    LeftSemi,
+    /// left rows without any match.
+    LeftAnti,
+    /// right rows with at least one match.
+    RightSemi,
+    /// right rows without any match.
+    RightAnti,
+}
+
+/// Equi-join building a hash table from the left input and probing it with the right one.
+///
+/// Keys match as `=` compares them: null keys never match, float keys -0 and +0 do, and
+/// NaN keys don't. Keys are hashed and compared by generated code on their native
+/// values, so string and binary keys are rejected.
+pub struct HashJoinOperator {
+    left: Arc<dyn PhysicalOperator>,
+    right: Arc<dyn PhysicalOperator>,
+    on: Vec<(PhysicalExprRef, PhysicalExprRef)>,
+    join_type: JoinType,
+    key_types: Vec<DataType>,
+    schema: SchemaRef,
+}
+
+impl HashJoinOperator {
+    pub fn try_new(
+        left: Arc<dyn PhysicalOperator>,
+        right: Arc<dyn PhysicalOperator>,
+        on: Vec<(PhysicalExprRef, PhysicalExprRef)>,
+        join_type: JoinType,
+    ) -> Result<Self> {
+        if on.is_empty() {
+            return Err(ServerError::ArgumentError(
+                "hash join needs at least one key".to_string(),
+            ));
+        }
+        // both sides of each key are cast to one type so they hash and compare alike.
+        let (left_schema, right_schema) = (left.schema(), right.schema());
+        let mut key_types = vec![];
+        let mut keys = vec![];
+        for (left_key, right_key) in &on {
+            let left_key = resolve_types(left_key, &left_schema)?;
+            let right_key = resolve_types(right_key, &right_schema)?;
+            let left_type = left_key.output_type(left_schema.clone());
+            let right_type = right_key.output_type(right_schema.clone());
+            let key_type = comparison_coercion(&left_type, &right_type).ok_or_else(|| {
+                ServerError::TypeError(format!(
+                    "join keys of incompatible types: {} and {}",
+                    left_type, right_type
+                ))
+            })?;
+            try_native_type(&key_type)?;
+            keys.push((
+                cast_to(&left_key, &key_type, &left_schema)?,
+                cast_to(&right_key, &key_type, &right_schema)?,
+            ));
+            key_types.push(key_type);
+        }
+
+        let left_fields = left_schema.fields().iter().cloned();
+        let right_fields = right_schema.fields().iter().cloned();
+        let nullable = |field: Arc<Field>| Arc::new(field.as_ref().clone().with_nullable(true));
+        let fields: Vec<Arc<Field>> = match join_type {
+            JoinType::Inner => left_fields.chain(right_fields).collect(),
+            JoinType::Left => left_fields.chain(right_fields.map(nullable)).collect(),
+            JoinType::Right => left_fields.map(nullable).chain(right_fields).collect(),
+            JoinType::LeftSemi | JoinType::LeftAnti => left_fields.collect(),
+            JoinType::RightSemi | JoinType::RightAnti => right_fields.collect(),
+        };
+        Ok(Self {
+            left,
+            right,
+            on: keys,
+            join_type,
+            key_types,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+}
+
+impl PhysicalOperator for HashJoinOperator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        let [left, right] = take_children(children)?;
+        let operator = HashJoinOperator::try_new(left, right, self.on.clone(), self.join_type)?;
+        Ok(Arc::new(operator))
+    }
+
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream> {
+        let mode = ctx.arithmetic_mode();
+        let build_input = self.left.exec(ctx.clone())?;
+        let probe_input = self.right.exec(ctx)?;
+        Ok(Box::new(HashJoinStream {
+            join_type: self.join_type,
+            schema: self.schema.clone(),
+            build_schema: self.left.schema(),
+            build_keys: self
+                .on
+                .iter()
+                .map(|(left, _)| with_arithmetic_mode(left, mode))
+                .collect::<Result<_>>()?,
+            probe_keys: self
+                .on
+                .iter()
+                .map(|(_, right)| with_arithmetic_mode(right, mode))
+                .collect::<Result<_>>()?,
+            hash: HashKernel::compile(&self.key_types)?,
+            eq: EqKernel::compile(&self.key_types)?,
+            build_input: Some(build_input),
+            probe_input,
+            table: None,
+            hashes: vec![],
+            finished: false,
+        }))
+    }
+}
+
+struct JoinTable {
+    batch: RecordBatch,
+    keys: Vec<ArrayRef>,
+    // hash of a key -> build rows whose key has that hash.
+    rows: HashMap<u64, Vec<u32>>,
+    // build rows matched by any probe row so far.
+    visited: Vec<bool>,
+}
+
+struct HashJoinStream {
+    join_type: JoinType,
+    schema: SchemaRef,
+    build_schema: SchemaRef,
+    build_keys: Vec<PhysicalExprRef>,
+    probe_keys: Vec<PhysicalExprRef>,
+    hash: HashKernel,
+    eq: EqKernel,
+    // taken once the build side has been drained into the table.
+    build_input: Option<BatchStream>,
+    probe_input: BatchStream,
+    table: Option<JoinTable>,
+    hashes: Vec<u64>,
+    finished: bool,
+}
+
+impl HashJoinStream {
+    fn build(&mut self, input: BatchStream) -> Result<()> {
+        let batches = input.collect::<Result<Vec<_>>>()?;
+        let batch = concat_batches(&self.build_schema, &batches)?;
+        let keys = eval_keys(&self.build_keys, &batch)?;
+        self.hash.hash(&keys, &mut self.hashes);
+        let mut rows: HashMap<u64, Vec<u32>> = HashMap::new();
+        for (row, hash) in self.hashes.iter().enumerate() {
+            // null keys never match, so they stay out of the table.
+            if keys.iter().any(|key| key.is_null(row)) {
+                continue;
+            }
+            rows.entry(*hash).or_default().push(row as u32);
+        }
+        self.table = Some(JoinTable {
+            visited: vec![false; batch.num_rows()],
+            batch,
+            keys,
+            rows,
+        });
+        Ok(())
+    }
+
+    fn probe(&mut self, batch: &RecordBatch) -> Result<Option<RecordBatch>> {
+        let table = self.table.as_mut().unwrap();
+        let keys = eval_keys(&self.probe_keys, batch)?;
+        self.hash.hash(&keys, &mut self.hashes);
+        let mut build_indices = vec![];
+        let mut probe_indices = vec![];
+        for (row, hash) in self.hashes.iter().enumerate() {
+            if let Some(rows) = table.rows.get(hash) {
+                build_indices.extend_from_slice(rows);
+                probe_indices.extend(std::iter::repeat_n(row as u32, rows.len()));
+            }
+        }
+
+        // hashes only select candidates, the generated comparison decides.
+        let eq = self
+            .eq
+            .eq(&table.keys, &build_indices, &keys, &probe_indices);
+        let mut matched_build = vec![];
+        let mut matched_probe = vec![];
+        for (i, eq) in eq.into_iter().enumerate() {
+            if eq {
+                table.visited[build_indices[i] as usize] = true;
+                matched_build.push(Some(build_indices[i]));
+                matched_probe.push(Some(probe_indices[i]));
+            }
+        }
+
+        let mut probe_matched = vec![false; batch.num_rows()];
+        for row in matched_probe.iter().flatten() {
+            probe_matched[*row as usize] = true;
+        }
+        match self.join_type {
+            JoinType::LeftSemi | JoinType::LeftAnti => return Ok(None),
+            JoinType::RightSemi | JoinType::RightAnti => {
+                let keep_matched = self.join_type == JoinType::RightSemi;
+                let probe_indices: UInt32Array = (0..batch.num_rows() as u32)
+                    .filter(|row| probe_matched[*row as usize] == keep_matched)
+                    .map(Some)
+                    .collect();
+                let columns = take_columns(batch, &probe_indices)?;
+                return Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?));
+            }
+            JoinType::Right => {
+                for (row, matched) in probe_matched.into_iter().enumerate() {
+                    if !matched {
+                        matched_build.push(None);
+                        matched_probe.push(Some(row as u32));
+                    }
+                }
+            }
+            JoinType::Inner | JoinType::Left => {}
+        }
+        let mut columns = take_columns(&table.batch, &UInt32Array::from(matched_build))?;
+        columns.extend(take_columns(batch, &UInt32Array::from(matched_probe))?);
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+
+    /// build rows emitted after the probe side is exhausted.
+    fn finish(&mut self) -> Result<Option<RecordBatch>> {
+        let Some(table) = &self.table else {
+            return Ok(None);
+        };
+        let keep_visited = match self.join_type {
+            JoinType::Inner | JoinType::Right | JoinType::RightSemi | JoinType::RightAnti => {
+                return Ok(None)
+            }
+            JoinType::Left | JoinType::LeftAnti => false,
+            JoinType::LeftSemi => true,
+        };
+        let build_indices: UInt32Array = (0..table.visited.len() as u32)
+            .filter(|row| table.visited[*row as usize] == keep_visited)
+            .map(Some)
+            .collect();
+        let mut columns = take_columns(&table.batch, &build_indices)?;
+        if self.join_type == JoinType::Left {
+            let probe_fields = &self.schema.fields()[columns.len()..];
+            for field in probe_fields {
+                columns.push(new_null_array(field.data_type(), build_indices.len()));
+            }
+        }
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+}
+
+impl Iterator for HashJoinStream {
+    type Item = Result<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(build_input) = self.build_input.take() {
+            if let Err(err) = self.build(build_input) {
+                self.finished = true;
+                return Some(Err(err));
+            }
+        }
+        if self.finished {
+            return None;
+        }
+        while let Some(batch) = self.probe_input.next() {
+            match batch.and_then(|batch| self.probe(&batch)) {
+                Ok(Some(output)) => return Some(Ok(output)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.finished = true;
+        self.finish().transpose()
+    }
+}
+
+fn eval_keys(keys: &[PhysicalExprRef], batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+    keys.iter()
+        .map(|key| Ok(key.eval(batch)?.into_array(batch.num_rows())))
+        .collect()
+}
+
+fn take_columns(batch: &RecordBatch, indices: &UInt32Array) -> Result<Vec<ArrayRef>> {
+    batch
+        .columns()
+        .iter()
+        .map(|column| Ok(take(column, indices, None)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Decimal128Array, Float64Array, Int64Array},
+        datatypes::{DataType, Field, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+    use common::ServerError;
+    use execution::context::ExecContext;
+    use physical_expr::expr::column::ColumnExpr;
+
+    use crate::{source::mem::MemSourceScan, PhysicalOperator};
+
+    use super::{HashJoinOperator, JoinType};
+
+    fn source(name: &str, batches: Vec<Vec<Option<i64>>>) -> Arc<dyn PhysicalOperator> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int64, true)]));
+        let batches = batches
+            .into_iter()
+            .map(|values| {
+                let array = Arc::new(Int64Array::from(values));
+                RecordBatch::try_new(schema.clone(), vec![array]).unwrap()
+            })
+            .collect();
+        Arc::new(MemSourceScan::new(schema, batches))
+    }
+
+    fn join(join_type: JoinType) -> Vec<RecordBatch> {
+        let left = source(
+            "l",
+            vec![vec![Some(1), Some(2)], vec![Some(2), Some(3), None]],
+        );
+        let right = source(
+            "r",
+            vec![vec![Some(2), Some(3), Some(4)], vec![None, Some(1)]],
+        );
+        let on = vec![(
+            Arc::new(ColumnExpr::new("l".to_string(), 0)) as _,
+            Arc::new(ColumnExpr::new("r".to_string(), 0)) as _,
+        )];
+        HashJoinOperator::try_new(left, right, on, join_type)
+            .unwrap()
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn num_rows(batches: &[RecordBatch]) -> usize {
+        batches.iter().map(|batch| batch.num_rows()).sum()
+    }
+
+    #[test]
+    fn test_hash_join_types() {
+        assert_eq!(num_rows(&join(JoinType::Inner)), 4);
+        assert_eq!(num_rows(&join(JoinType::Left)), 5);
+        assert_eq!(num_rows(&join(JoinType::Right)), 6);
+        assert_eq!(num_rows(&join(JoinType::LeftSemi)), 4);
+        assert_eq!(num_rows(&join(JoinType::LeftAnti)), 1);
+        assert_eq!(num_rows(&join(JoinType::RightSemi)), 3);
+        assert_eq!(num_rows(&join(JoinType::RightAnti)), 2);
+    }
+
+    #[test]
+    fn test_float_keys_match_as_equal() {
+        let source = |name: &str, values: Vec<f64>| -> Arc<dyn PhysicalOperator> {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                name,
+                DataType::Float64,
+                false,
+            )]));
+            let array = Arc::new(Float64Array::from(values));
+            let batch = RecordBatch::try_new(schema.clone(), vec![array]).unwrap();
+            Arc::new(MemSourceScan::new(schema, vec![batch]))
+        };
+        let left = source("l", vec![0.0, f64::NAN, 1.5]);
+        let right = source("r", vec![-0.0, f64::NAN, 1.5]);
+        let on = vec![(
+            Arc::new(ColumnExpr::new("l".to_string(), 0)) as _,
+            Arc::new(ColumnExpr::new("r".to_string(), 0)) as _,
+        )];
+        let batches: Vec<RecordBatch> = HashJoinOperator::try_new(left, right, on, JoinType::Inner)
+            .unwrap()
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // -0 matches +0, NaN matches nothing.
+        assert_eq!(num_rows(&batches), 2);
+    }
+
+    #[test]
+    fn test_decimal_keys_match() {
+        let source = |name: &str, values: Vec<i128>| -> Arc<dyn PhysicalOperator> {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                name,
+                DataType::Decimal128(10, 2),
+                false,
+            )]));
+            let array = Decimal128Array::from(values)
+                .with_precision_and_scale(10, 2)
+                .unwrap();
+            let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(array)]).unwrap();
+            Arc::new(MemSourceScan::new(schema, vec![batch]))
+        };
+        let left = source("l", vec![100, 250, -1]);
+        let right = source("r", vec![250, 7, -1]);
+        let on = vec![(
+            Arc::new(ColumnExpr::new("l".to_string(), 0)) as _,
+            Arc::new(ColumnExpr::new("r".to_string(), 0)) as _,
+        )];
+        let batches: Vec<RecordBatch> = HashJoinOperator::try_new(left, right, on, JoinType::Inner)
+            .unwrap()
+            .exec(ExecContext::new().as_ref())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(num_rows(&batches), 2);
+    }
+
+    #[test]
+    fn test_non_native_keys_are_rejected() {
+        for key_type in [DataType::Utf8, DataType::LargeUtf8, DataType::Binary] {
+            let source = |name: &str| -> Arc<dyn PhysicalOperator> {
+                let schema = Arc::new(Schema::new(vec![Field::new(
+                    name,
+                    key_type.clone(),
+                    true,
+                )]));
+                Arc::new(MemSourceScan::new(schema, vec![]))
+            };
+            let on = vec![(
+                Arc::new(ColumnExpr::new("l".to_string(), 0)) as _,
+                Arc::new(ColumnExpr::new("r".to_string(), 0)) as _,
+            )];
+            let join = HashJoinOperator::try_new(source("l"), source("r"), on, JoinType::Inner);
+            assert!(matches!(join, Err(ServerError::NotSupported(_))));
+        }
+    }
+
+    #[test]
+    fn test_inner_join_matches_keys() {
+        for batch in join(JoinType::Inner) {
+            assert_eq!(batch.num_columns(), 2);
+            assert_eq!(
+                batch.column(0).as_primitive::<Int64Type>(),
+                batch.column(1).as_primitive::<Int64Type>()
+            );
+        }
+    }
+}
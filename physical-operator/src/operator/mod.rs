@@ -1 +1,5 @@
+pub mod aggregate;
 pub mod filter;
+pub mod filter_projection;
+pub mod join;
+pub mod projection;
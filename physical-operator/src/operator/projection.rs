@@ -0,0 +1,165 @@
+use std::{any::Any, sync::Arc};
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use common::Result;
+use execution::context::ExecContextRef;
+use physical_expr::{AdaptiveExprs, PhysicalExprRef};
+
+use crate::{take_children, BatchStream, PhysicalOperator, PhysicalOperatorRef};
+
+/// Computes one output column per expression, all of them in a single generated kernel.
+pub struct ProjectionOperator {
+    input: Arc<dyn PhysicalOperator>,
+    exprs: Vec<(PhysicalExprRef, String)>,
+    schema: SchemaRef,
+}
+
+impl ProjectionOperator {
+    pub fn new(input: Arc<dyn PhysicalOperator>, exprs: Vec<(PhysicalExprRef, String)>) -> Self {
+        let input_schema = input.schema();
+        let fields: Vec<Field> = exprs
+            .iter()
+            .map(|(expr, name)| Field::new(name, expr.output_type(input_schema.clone()), true))
+            .collect();
+        Self {
+            input,
+            exprs,
+            schema: Arc::new(Schema::new(fields)),
+        }
+    }
+
+    pub fn input(&self) -> &Arc<dyn PhysicalOperator> {
+        &self.input
+    }
+
+    /// projected expressions with their output column names.
+    pub fn exprs(&self) -> &[(PhysicalExprRef, String)] {
+        &self.exprs
+    }
+}
+
+impl PhysicalOperator for ProjectionOperator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        let [input] = take_children(children)?;
+        Ok(Arc::new(ProjectionOperator::new(input, self.exprs.clone())))
+    }
+
+    fn exec(&self, ctx: ExecContextRef) -> Result<BatchStream> {
+        let exprs: Vec<PhysicalExprRef> = self.exprs.iter().map(|(expr, _)| expr.clone()).collect();
+        let mut exprs = AdaptiveExprs::try_new(
+            None,
+            exprs,
+            self.input.schema(),
+            ctx.arithmetic_mode(),
+            ctx.execution_mode(),
+            ctx.kernel_cache().clone(),
+        )?;
+        let input = self.input.exec(ctx)?;
+        let schema = self.schema.clone();
+        Ok(Box::new(input.map(move |batch| {
+            exprs.eval_batch(&batch?, schema.clone())
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, AsArray, BooleanArray, Int64Array},
+        datatypes::{DataType, Field, Int64Type, Schema},
+        record_batch::RecordBatch,
+    };
+    use execution::context::ExecContext;
+    use physical_expr::{
+        expr::{
+            binary::{BinaryExpr, Op},
+            column::ColumnExpr,
+            literal::LiteralExpr,
+        },
+        ExecutionMode, ScalarValue, TieringPolicy,
+    };
+
+    use crate::{source::mem::MemSourceScan, PhysicalOperator};
+
+    use super::ProjectionOperator;
+
+    #[test]
+    fn test_projection() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let a = Int64Array::from(vec![Some(1), None, Some(3)]);
+        let b = Int64Array::from(vec![2, 2, 2]);
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(a), Arc::new(b)]).unwrap();
+        let source = MemSourceScan::new(schema, vec![batch.clone(), batch]);
+
+        let a = Arc::new(ColumnExpr::new("a".to_string(), 0));
+        let b = Arc::new(ColumnExpr::new("b".to_string(), 1));
+        let projection = ProjectionOperator::new(
+            Arc::new(source),
+            vec![
+                (
+                    Arc::new(BinaryExpr::new(
+                        Op::Add,
+                        a.clone(),
+                        Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(10)))),
+                    )) as _,
+                    "a_plus_10".to_string(),
+                ),
+                (
+                    Arc::new(BinaryExpr::new(Op::Lt, b, a)) as _,
+                    "b_lt_a".to_string(),
+                ),
+            ],
+        );
+        let output_schema = projection.schema();
+        assert_eq!(output_schema.field(0).name(), "a_plus_10");
+        assert_eq!(output_schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(output_schema.field(1).data_type(), &DataType::Boolean);
+
+        // interpreted batches look like compiled ones.
+        let modes = [
+            ExecutionMode::Compiled,
+            ExecutionMode::Interpreted,
+            ExecutionMode::Adaptive(TieringPolicy::default()),
+        ];
+        for mode in modes {
+            let ctx = ExecContext::new().with_execution_mode(mode);
+            let batches: Vec<RecordBatch> = projection
+                .exec(ctx.as_ref())
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(batches.len(), 2);
+            for batch in batches {
+                assert_eq!(
+                    batch.column(0).as_primitive::<Int64Type>(),
+                    &Int64Array::from(vec![Some(11), None, Some(13)])
+                );
+                assert_eq!(
+                    batch.column(1).as_boolean(),
+                    &BooleanArray::from(vec![Some(false), None, Some(true)])
+                );
+                assert_eq!(batch.column(1).null_count(), 1);
+            }
+        }
+    }
+}
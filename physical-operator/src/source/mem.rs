@@ -1,24 +1,43 @@
+use std::{any::Any, sync::Arc};
+
 use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use common::Result;
 use execution::context::ExecContextRef;
 
-use crate::PhysicalOperator;
+use crate::{BatchStream, PhysicalOperator, PhysicalOperatorRef};
 
-struct MemSourceScan {
-    batch: RecordBatch,
+pub struct MemSourceScan {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
 }
 
 impl MemSourceScan {
-    fn new(batch: RecordBatch) -> Self {
-        Self { batch }
+    pub fn new(schema: SchemaRef, batches: Vec<RecordBatch>) -> Self {
+        Self { schema, batches }
     }
 }
 
 impl PhysicalOperator for MemSourceScan {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn schema(&self) -> SchemaRef {
-        self.batch.schema().clone()
+        self.schema.clone()
+    }
+
+    fn children(&self) -> Vec<PhysicalOperatorRef> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<PhysicalOperatorRef>,
+    ) -> Result<PhysicalOperatorRef> {
+        Ok(self)
     }
 
-    fn exec(&self, ctx: ExecContextRef) -> Result<RecordBatch, ()> {
-        Ok(self.batch.clone())
+    fn exec(&self, _: ExecContextRef) -> Result<BatchStream> {
+        Ok(Box::new(self.batches.clone().into_iter().map(Ok)))
     }
 }
@@ -24,7 +24,7 @@ fn main() {
         Arc::new(BinaryExpr::new(
             Op::Lt,
             Arc::new(ColumnExpr::new(String::from("num"), 0)),
-            Arc::new(LiteralExpr::new(ScalarValue::Int64(3))),
+            Arc::new(LiteralExpr::new(ScalarValue::Int64(Some(3)))),
         )),
     );
 